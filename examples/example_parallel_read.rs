@@ -84,6 +84,9 @@ pub fn main() {
                 ReadError::Other(err) => {
                     eprintln!("Error: {:?}", err);
                 }
+                err => {
+                    eprintln!("Error: {:?}", err);
+                }
             }
         }
     }
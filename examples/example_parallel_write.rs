@@ -43,7 +43,7 @@ pub fn main() {
         2
     };
     // Avoid overwriting existing file: exit if output file exists.
-    if let Ok(_) = std::fs::metadata(&filename) {
+    if std::fs::metadata(&filename).is_ok() {
         eprintln!("Error: file '{}' exists", filename);
         std::process::exit(1);
     }
@@ -74,14 +74,36 @@ pub fn main() {
             std::fs::remove_file(&filename).expect("Cannot delete file");
         }
         Err(err) => {
-            use par_io::write::{ProducerError, WriteError};
+            use par_io::write::{ConsumerError, ProducerError, WriteError};
             match err {
                 WriteError::Producer(ProducerError { msg, offset }) => {
                     eprintln!("Producer error: {} at {}", msg, offset);
                 }
+                WriteError::Consumer(ConsumerError { msg, offset }) => {
+                    eprintln!("Consumer error: {} at {}", msg, offset);
+                }
                 WriteError::IO(err) => {
                     eprintln!("I/O error: {:?}", err);
                 }
+                WriteError::Cancelled { written } => {
+                    eprintln!("Cancelled after writing {} bytes", written);
+                }
+                WriteError::VerifyFailed { offset } => {
+                    eprintln!("Verification failed at {}", offset);
+                }
+                WriteError::Locked { filename } => {
+                    eprintln!("{} is locked", filename);
+                }
+                WriteError::RetryExhausted {
+                    offset,
+                    attempts,
+                    error,
+                } => {
+                    eprintln!("Write at {} failed {} times: {:?}", offset, attempts, error);
+                }
+                WriteError::Timeout { offset } => {
+                    eprintln!("No progress, stalled at {:?}", offset);
+                }
                 WriteError::Other(err) => {
                     eprintln!("Error: {}", err);
                 }
@@ -0,0 +1,60 @@
+//! Allocation of the buffers used to transfer chunks.
+//!
+//! Each operation allocates its buffers once when producers and consumers
+//! start and recycles them until the operation completes. Setting `ReadOptions::allocator` or
+//! `WriteOptions::allocator` replaces the default allocation with a custom
+//! `BufferAlloc`, e.g. to back buffers with huge pages, or to lock or register
+//! them for DMA, without changing the buffer type.
+//!
+//! Buffers are plain `Vec<u8>` instances and are freed through the global
+//! allocator when the operation completes: the memory returned by `alloc`
+//! must therefore come from the global allocator, e.g. a vector allocated
+//! normally and then advised or locked, or from a global allocator
+//! registered with `#[global_allocator]`. Buffers are requested with enough
+//! capacity for the largest chunk and are never reallocated, except when read
+//! chunks are extended beyond their initial size; reads and writes fail with
+//! an error when a buffer has less capacity than requested.
+use std::sync::Arc;
+
+/// Buffer allocator.
+pub trait BufferAlloc: Send + Sync {
+    /// Return an empty vector with a capacity of at least `capacity` bytes.
+    fn alloc(&self, capacity: usize) -> Vec<u8>;
+}
+
+/// Default allocator: `Vec::with_capacity`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DefaultAlloc;
+
+impl BufferAlloc for DefaultAlloc {
+    fn alloc(&self, capacity: usize) -> Vec<u8> {
+        Vec::with_capacity(capacity)
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Allocate an empty buffer with `alloc`, or `DefaultAlloc` if `None`.
+pub(crate) fn allocate(alloc: Option<&Arc<dyn BufferAlloc>>, capacity: usize) -> Vec<u8> {
+    let mut buffer = match alloc {
+        Some(a) => a.alloc(capacity),
+        None => DefaultAlloc.alloc(capacity),
+    };
+    buffer.clear();
+    buffer
+}
+
+// -----------------------------------------------------------------------------
+/// Return an error if `buffer` cannot hold `size` bytes without being
+/// reallocated, i.e. if the buffer size was miscalculated or a custom
+/// `BufferAlloc` returned less capacity than requested.
+pub(crate) fn check_capacity(buffer: &Vec<u8>, size: usize) -> Result<(), String> {
+    if buffer.capacity() < size {
+        Err(format!(
+            "Buffer capacity is {} bytes, chunk size is {}",
+            buffer.capacity(),
+            size
+        ))
+    } else {
+        Ok(())
+    }
+}
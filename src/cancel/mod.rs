@@ -0,0 +1,109 @@
+//! Cooperative cancellation of read and write operations.
+//!
+//! A `CancelToken` is checked by producers before generating each chunk: after
+//! cancellation no new chunk is produced, chunks already produced are written
+//! to file and the write function returns `WriteError::Cancelled` with the
+//! number of bytes written, unless all the chunks had already been produced.
+//!
+//! When reading, producers stop reading chunks and consumers return the
+//! chunks already read to the producers without invoking the callback; all
+//! threads are joined before `ReadError::Cancelled` is returned.
+//!
+//! With the `sigint` feature enabled `CancelToken::on_sigint` returns a token
+//! cancelled when the process receives `SIGINT` after the token is created;
+//! signals received earlier do not affect new tokens. Signal handling is
+//! opt-in: installing a handler replaces the default behaviour of terminating
+//! the process, which library users might not expect.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// -----------------------------------------------------------------------------
+/// Cancellation flag shared between the caller and worker threads.
+#[derive(Debug, Default)]
+pub struct CancelToken {
+    cancelled: AtomicBool,
+    // cancelled when the parent is cancelled
+    parent: Option<Arc<CancelToken>>,
+    // number of SIGINT signals received when the token was created
+    #[cfg(feature = "sigint")]
+    sigint: Option<usize>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Return a token also cancelled when `parent` is; cancelling the new
+    /// token does not cancel the parent.
+    pub(crate) fn linked(parent: Option<Arc<CancelToken>>) -> Self {
+        CancelToken {
+            parent,
+            ..Self::default()
+        }
+    }
+    /// Request cancellation.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+    /// Return `true` if cancellation was requested.
+    pub fn is_cancelled(&self) -> bool {
+        #[cfg(feature = "sigint")]
+        if self.sigint.map_or(false, |n| sigint::received() != n) {
+            return true;
+        }
+        self.cancelled.load(Ordering::SeqCst)
+            || self.parent.as_ref().map_or(false, |p| p.is_cancelled())
+    }
+}
+
+#[cfg(feature = "sigint")]
+impl CancelToken {
+    /// Install a `SIGINT` handler and return a token cancelled when the
+    /// signal is received after this call; the process is not terminated by
+    /// `SIGINT` after this function is called.
+    pub fn on_sigint() -> Self {
+        sigint::install();
+        CancelToken {
+            cancelled: AtomicBool::new(false),
+            parent: None,
+            sigint: Some(sigint::received()),
+        }
+    }
+}
+
+#[cfg(feature = "sigint")]
+mod sigint {
+    use std::os::raw::c_int;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Once;
+
+    const SIGINT: c_int = 2;
+    // number of signals received, tokens compare it with the value read when
+    // they were created
+    static RECEIVED: AtomicUsize = AtomicUsize::new(0);
+    static INSTALL: Once = Once::new();
+
+    extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    }
+
+    // only async-signal-safe operations allowed here
+    extern "C" fn handler(_signum: c_int) {
+        RECEIVED.fetch_add(1, Ordering::SeqCst);
+        // the C runtime restores the default handler before invoking it
+        #[cfg(windows)]
+        unsafe {
+            signal(SIGINT, handler);
+        }
+    }
+
+    pub(super) fn install() {
+        INSTALL.call_once(|| unsafe {
+            signal(SIGINT, handler);
+        });
+    }
+
+    pub(super) fn received() -> usize {
+        RECEIVED.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,141 @@
+//! Durable record of the chunks written to file.
+//!
+//! When `WriteOptions::checkpoint` is set each consumer, after receiving all
+//! its chunks, flushes the file data to disk and appends the offset and size
+//! of the chunks it wrote to the checkpoint file, one `offset size` pair per
+//! line. After a crash the checkpoint lists the chunks known to be durably
+//! written and `resume_write` only generates the missing ones.
+//!
+//! `WriteOptions::crash_after` simulates a crash for testing.
+use crate::write::{
+    generated, write_to_file_with_chunks, ChunkProducer, OpenMode, Producer, Region, WriteError,
+    WriteOptions,
+};
+use core::fmt::Debug;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// Chunk recorded in checkpoint file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheckpointEntry {
+    pub offset: u64,
+    pub size: u64,
+}
+
+// -----------------------------------------------------------------------------
+/// Checkpoint file shared by consumers.
+pub(crate) struct Checkpoint {
+    log: Mutex<File>,
+}
+
+impl Checkpoint {
+    /// Open checkpoint file for appending, creating it if it does not exist.
+    pub(crate) fn open(path: &str) -> Result<Self, WriteError> {
+        let log = File::options()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(WriteError::IO)?;
+        Ok(Checkpoint {
+            log: Mutex::new(log),
+        })
+    }
+    /// Flush `file` to disk then durably record `entries`.
+    pub(crate) fn record(
+        &self,
+        file: &File,
+        entries: &[CheckpointEntry],
+    ) -> Result<(), WriteError> {
+        file.sync_data().map_err(WriteError::IO)?;
+        let text: String = entries
+            .iter()
+            .map(|e| format!("{} {}\n", e.offset, e.size))
+            .collect();
+        let mut log = match self.log.lock() {
+            Ok(l) => l,
+            Err(err) => err.into_inner(),
+        };
+        log.write_all(text.as_bytes()).map_err(WriteError::IO)?;
+        log.sync_data().map_err(WriteError::IO)
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Return the chunks recorded in checkpoint file `path` sorted by offset;
+/// empty if the file does not exist.
+pub fn read_checkpoint(path: &str) -> Result<Vec<CheckpointEntry>, WriteError> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(t) => t,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(WriteError::IO(err)),
+    };
+    let mut entries = Vec::new();
+    for line in text.lines().filter(|l| !l.trim().is_empty()) {
+        let mut fields = line.split_whitespace().map(|f| f.parse::<u64>());
+        match (fields.next(), fields.next()) {
+            (Some(Ok(offset)), Some(Ok(size))) => entries.push(CheckpointEntry { offset, size }),
+            _ => {
+                return Err(WriteError::Other(format!(
+                    "Invalid checkpoint entry '{}'",
+                    line
+                )))
+            }
+        }
+    }
+    entries.sort_by_key(|e| e.offset);
+    Ok(entries)
+}
+
+// -----------------------------------------------------------------------------
+/// Resume a write interrupted after recording chunks in checkpoint file
+/// `checkpoint`: chunks in the checkpoint are left untouched and the producer
+/// callback is only invoked for the other chunks, which are then appended to
+/// the checkpoint.
+///
+/// The number of producers and chunks per producer must be the same as the
+/// interrupted write so that chunks have the same offsets; the file is never
+/// truncated, `options.open_mode` and `options.checkpoint` are ignored.
+/// Returns the number of bytes written.
+#[allow(clippy::too_many_arguments)]
+pub fn resume_write<T: 'static + Clone + Send, E: 'static + Send + Debug>(
+    filename: &str,
+    checkpoint: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    producer: Arc<Producer<T, E>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    total_size: usize,
+    options: WriteOptions,
+) -> Result<usize, WriteError> {
+    let done: HashSet<u64> = read_checkpoint(checkpoint)?
+        .iter()
+        .map(|e| e.offset)
+        .collect();
+    let skip_done: Arc<ChunkProducer<T, E>> =
+        Arc::new(move |buffer: &mut Vec<u8>, data: &T, offset: u64| {
+            if done.contains(&offset) {
+                return Ok(Some(vec![Region::Keep(buffer.len() as u64)]));
+            }
+            let len = buffer.len();
+            producer(buffer, data, offset).map(|_| generated(buffer, len))
+        });
+    write_to_file_with_chunks(
+        filename,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        skip_done,
+        client_data,
+        num_buffers_per_producer,
+        total_size,
+        WriteOptions {
+            open_mode: OpenMode::CreateOrKeep,
+            checkpoint: Some(checkpoint.to_string()),
+            ..options
+        },
+    )
+}
@@ -0,0 +1,203 @@
+//! Files made of blocks followed by their CRC-32C, to detect corrupted data,
+//! e.g. because of bit rot, when reading the file back.
+//!
+//! Data is split into blocks of `block_size` bytes, the last block being
+//! shorter when the data size is not a multiple of the block size. Each block
+//! is stored followed by the 4 byte CRC-32C of its content, little-endian:
+//!
+//! ```text
+//! | block 0 | crc 0 | block 1 | crc 1 | ... | block n-1 | crc n-1 |
+//! ```
+//!
+//! Block `k` is stored at file offset `k * (block_size + CRC_SIZE)` and holds
+//! the data at offset `k * block_size`; the file size is the data size plus
+//! `CRC_SIZE` bytes per block, see `checksummed_size`. The layout only depends
+//! on the data size and the block size, which must be the same when writing
+//! and reading the file, not on the number of producers or chunks. Producer
+//! chunks contain whole blocks, offsets passed to callbacks are data offsets.
+use crate::hash::crc32c;
+use crate::lock::LockPolicy;
+use crate::read::{aligned_tasks, read_tasks, Consumer, ReadAt, ReadError, ReadOptions};
+use crate::write::{
+    aligned_ranges, create_file, write_ranges, ChunkProducer, OpenMode, Producer, WriteError,
+    WriteOptions,
+};
+use core::fmt::Debug;
+use std::fs::File;
+use std::sync::Arc;
+
+#[cfg(unix)]
+use crate::io::io_at_unix::*;
+
+#[cfg(windows)]
+use crate::io::io_at_windows::*;
+
+/// Size of the checksum stored after each block.
+pub const CRC_SIZE: u64 = 4;
+
+// -----------------------------------------------------------------------------
+/// Size of the file holding `data_size` bytes of data in blocks of
+/// `block_size` bytes.
+pub fn checksummed_size(data_size: u64, block_size: u64) -> u64 {
+    data_size + (data_size + block_size - 1) / block_size * CRC_SIZE
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `write_to_file` but each block of `block_size` bytes is followed by
+/// its checksum, see the module documentation for the file layout; chunks
+/// contain whole blocks.
+/// Returns the number of bytes written to file, including checksums.
+#[allow(clippy::too_many_arguments)]
+pub fn write_to_file_checksummed<T: 'static + Clone + Send, E: 'static + Send + Debug>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    producer: Arc<Producer<T, E>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    total_size: usize,
+    block_size: u64,
+) -> Result<usize, WriteError> {
+    if block_size == 0 {
+        return Err(WriteError::Other("Block size must be positive".to_string()));
+    }
+    let stored_size = checksummed_size(total_size as u64, block_size);
+    let stored_block_size = block_size + CRC_SIZE;
+    let ranges = aligned_ranges(
+        stored_size,
+        num_producers,
+        chunks_per_producer,
+        stored_block_size,
+    );
+    let seal: Arc<ChunkProducer<T, E>> =
+        Arc::new(move |buffer: &mut Vec<u8>, data: &T, offset: u64| {
+            let first = offset / stored_block_size;
+            let num_blocks = (buffer.len() as u64 + stored_block_size - 1) / stored_block_size;
+            let data_size = (buffer.len() as u64 - num_blocks * CRC_SIZE) as usize;
+            // generate the data at the start of the buffer, then move the
+            // blocks to their stored position starting from the last one
+            let stored_len = buffer.len();
+            buffer.truncate(data_size);
+            producer(buffer, data, first * block_size)?;
+            buffer.resize(stored_len, 0);
+            let (block_size, stored_block_size) = (block_size as usize, stored_block_size as usize);
+            for k in (0..num_blocks as usize).rev() {
+                let begin = k * block_size;
+                let end = (begin + block_size).min(data_size);
+                let dst = k * stored_block_size;
+                buffer.copy_within(begin..end, dst);
+                let crc = crc32c(&buffer[dst..dst + end - begin]);
+                buffer[dst + end - begin..dst + end - begin + CRC_SIZE as usize]
+                    .copy_from_slice(&crc.to_le_bytes());
+            }
+            Ok(None)
+        });
+    let file = create_file(
+        filename,
+        stored_size,
+        OpenMode::Truncate,
+        LockPolicy::NoLock,
+    )?;
+    write_ranges(
+        &file,
+        ranges,
+        num_consumers,
+        seal,
+        client_data,
+        num_buffers_per_producer,
+        stored_size as usize,
+        &WriteOptions::default(),
+    )
+    .map(|r| r.bytes_written)
+}
+
+// -----------------------------------------------------------------------------
+/// Checksummed file, the checksums of the blocks are verified when read.
+struct Verify {
+    file: File,
+    block_size: u64,
+}
+
+impl ReadAt for Verify {
+    fn read_at(&self, buffer: &mut Vec<u8>, offset: u64) -> Result<(), ReadError> {
+        read_bytes_at(buffer, &self.file, offset)?;
+        let stored_block_size = (self.block_size + CRC_SIZE) as usize;
+        let first = offset / stored_block_size as u64;
+        for (k, block) in buffer.chunks(stored_block_size).enumerate() {
+            let (data, crc) = block.split_at(block.len() - CRC_SIZE as usize);
+            if crc32c(data).to_le_bytes() != crc {
+                return Err(ReadError::ChecksumMismatch {
+                    offset: (first + k as u64) * self.block_size,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Read file written by `write_to_file_checksummed` with the same
+/// `block_size`: checksums are verified by producers, consumers receive the
+/// data without checksums and data offsets.
+///
+/// Returns `ReadError::ChecksumMismatch` with the data offset of the first
+/// corrupted block found.
+#[allow(clippy::too_many_arguments)]
+pub fn read_file_checksummed<T: 'static + Clone + Send, R: 'static + Clone + Sync + Send>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    consumer: Arc<Consumer<T, R>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    block_size: u64,
+) -> Result<Vec<(u64, R)>, ReadError> {
+    if block_size == 0 {
+        return Err(ReadError::Other("Block size must be positive".to_string()));
+    }
+    let stored_size = std::fs::metadata(filename).map_err(ReadError::IO)?.len();
+    let stored_block_size = block_size + CRC_SIZE;
+    let last = stored_size % stored_block_size;
+    if last != 0 && last <= CRC_SIZE {
+        return Err(ReadError::Other(format!(
+            "Invalid checksummed file size {}",
+            stored_size
+        )));
+    }
+    let tasks = aligned_tasks(
+        stored_size,
+        num_producers,
+        chunks_per_producer,
+        stored_block_size,
+    );
+    let strip: Arc<Consumer<T, R>> = Arc::new(
+        move |buffer: &[u8], data: &T, chunk_id, num_chunks, offset| {
+            let plain: Vec<u8> = buffer
+                .chunks(stored_block_size as usize)
+                .flat_map(|b| &b[..b.len() - CRC_SIZE as usize])
+                .copied()
+                .collect();
+            let data_offset = offset / stored_block_size * block_size;
+            consumer(&plain, data, chunk_id, num_chunks, data_offset)
+        },
+    );
+    let source = Verify {
+        file: File::open(filename).map_err(ReadError::IO)?,
+        block_size,
+    };
+    read_tasks(
+        filename,
+        tasks,
+        num_producers * chunks_per_producer,
+        num_consumers,
+        strip,
+        client_data,
+        num_buffers_per_producer,
+        &ReadOptions {
+            source: Some(Arc::new(source)),
+            ..Default::default()
+        },
+    )
+}
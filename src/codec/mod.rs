@@ -0,0 +1,215 @@
+//! Record decoding applied on consumer threads.
+//!
+//! A `Codec` knows where records begin and how to turn raw bytes into records.
+//! Chunk boundaries are moved forward to the next record boundary before the
+//! read starts so that no record is split between two chunks, then each chunk is
+//! decoded by the consumer thread receiving it.
+use crate::read::{producer_tasks, read_tasks, Chunk, Consumer, ReadError, ReadOptions, Tasks};
+use std::fs::File;
+use std::sync::Arc;
+
+#[cfg(unix)]
+use crate::io::io_at_unix::*;
+
+#[cfg(windows)]
+use crate::io::io_at_windows::*;
+
+// -----------------------------------------------------------------------------
+/// Record framing and decoding.
+pub trait Codec: Send + Sync {
+    /// Decoded record type.
+    type Record;
+    /// Number of bytes to read when searching for a record boundary; `0` if
+    /// boundaries can be computed from the offset only.
+    fn probe_size(&self) -> usize {
+        4096
+    }
+    /// Check the codec parameters before reading, return a description of
+    /// the problem if they are invalid.
+    fn validate(&self) -> Result<(), String> {
+        Ok(())
+    }
+    /// Return the position relative to `offset` of the first record boundary
+    /// at or after `offset`, or `None` if no boundary is found in `data`, which
+    /// contains file data starting at `offset`.
+    fn boundary(&self, offset: u64, data: &[u8]) -> Option<usize>;
+    /// Decode all the records in `data`, which always starts at a record boundary
+    /// and ends at a record boundary or at the end of the file.
+    fn decode(&self, data: &[u8]) -> Vec<Self::Record>;
+}
+
+// -----------------------------------------------------------------------------
+/// Records of the same size in bytes.
+pub struct FixedSizeRecords(pub usize);
+
+impl Codec for FixedSizeRecords {
+    type Record = Vec<u8>;
+    fn probe_size(&self) -> usize {
+        0
+    }
+    fn validate(&self) -> Result<(), String> {
+        if self.0 == 0 {
+            return Err("Record size must be greater than zero".to_string());
+        }
+        Ok(())
+    }
+    fn boundary(&self, offset: u64, _data: &[u8]) -> Option<usize> {
+        let size = self.0 as u64;
+        Some((((offset + size - 1) / size) * size - offset) as usize)
+    }
+    fn decode(&self, data: &[u8]) -> Vec<Self::Record> {
+        data.chunks(self.0).map(|r| r.to_vec()).collect()
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Newline delimited text; records do not include the trailing `'\n'`.
+/// Invalid UTF-8 sequences are replaced with `U+FFFD`.
+pub struct NewlineDelimited;
+
+impl Codec for NewlineDelimited {
+    type Record = String;
+    fn boundary(&self, _offset: u64, data: &[u8]) -> Option<usize> {
+        data.iter().position(|b| *b == b'\n').map(|p| p + 1)
+    }
+    fn decode(&self, data: &[u8]) -> Vec<Self::Record> {
+        let data = data.strip_suffix(b"\n").unwrap_or(data);
+        if data.is_empty() {
+            return Vec::new();
+        }
+        data.split(|b| *b == b'\n')
+            .map(|l| String::from_utf8_lossy(l).into_owned())
+            .collect()
+    }
+}
+
+// Moving a generic Fn instance requires customization
+type RecordConsumer<Rec, T, R> = dyn Fn(
+    &[Rec], // records decoded from file data
+    &T,     // client data
+    u64,    // chunk id
+    u64,    // number of chunks
+    u64,    // file offset (where data is read from)
+) -> R;
+
+// -----------------------------------------------------------------------------
+/// Read file in parallel and pass decoded records to consumer callback.
+///
+/// Same as `read_file` except that chunk boundaries are aligned to record
+/// boundaries and the consumer callback receives the records decoded from
+/// each chunk instead of raw bytes. Each record is decoded exactly once.
+///
+/// Chunks can be empty when a record spans more than one chunk.
+///
+/// `ReadError::Other` is returned if the codec parameters are invalid, e.g.
+/// a `FixedSizeRecords` record size of zero.
+///
+/// Callback signature:
+///
+/// ```ignore
+/// type RecordConsumer<Rec, T, R> = dyn Fn(&[Rec], // records decoded from chunk
+///                                         &T,     // client data
+///                                         u64,    // chunk id
+///                                         u64,    // number of chunks
+///                                         u64     // file offset (where data is read from)
+///                                        ) -> R;
+/// ```
+#[allow(clippy::too_many_arguments)]
+pub fn read_records<
+    C: 'static + Codec,
+    T: 'static + Clone + Send,
+    R: 'static + Clone + Sync + Send,
+>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    codec: Arc<C>,
+    consumer: Arc<RecordConsumer<C::Record, T, R>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+) -> Result<Vec<(u64, R)>, ReadError> {
+    codec.validate().map_err(ReadError::Other)?;
+    let file = File::open(filename).map_err(ReadError::IO)?;
+    let total_size = file.metadata().map_err(ReadError::IO)?.len();
+    let tasks = producer_tasks(total_size, num_producers, chunks_per_producer);
+    let tasks = align_tasks(&file, codec.as_ref(), tasks, total_size)?;
+    drop(file);
+    let decode: Arc<Consumer<T, R>> = Arc::new(
+        move |buffer: &[u8], data: &T, chunk_id: u64, num_chunks: u64, offset: u64| -> R {
+            let records = codec.decode(buffer);
+            consumer(&records, data, chunk_id, num_chunks, offset)
+        },
+    );
+    read_tasks(
+        filename,
+        tasks,
+        chunks_per_producer * num_producers,
+        num_consumers,
+        decode,
+        client_data,
+        num_buffers_per_producer,
+        &ReadOptions::default(),
+    )
+}
+
+// -----------------------------------------------------------------------------
+/// Move the start of each chunk forward to the next record boundary.
+fn align_tasks<C: Codec>(
+    file: &File,
+    codec: &C,
+    tasks: Tasks,
+    total_size: u64,
+) -> Result<Tasks, ReadError> {
+    let mut boundaries = Vec::new();
+    let mut prev = 0;
+    for c in tasks.iter().flatten().skip(1) {
+        prev = find_boundary(file, codec, c.offset.max(prev), total_size)?;
+        boundaries.push(prev);
+    }
+    boundaries.push(total_size);
+    let mut begin = 0;
+    let mut ends = boundaries.into_iter();
+    Ok(tasks
+        .into_iter()
+        .map(|chunks| {
+            chunks
+                .into_iter()
+                .map(|c| {
+                    let end = ends.next().unwrap_or(total_size);
+                    let chunk = Chunk {
+                        id: c.id,
+                        offset: begin,
+                        size: end - begin,
+                    };
+                    begin = end;
+                    chunk
+                })
+                .collect()
+        })
+        .collect())
+}
+
+// -----------------------------------------------------------------------------
+/// Return the offset of the first record boundary at or after `offset`, or
+/// `total_size` if there is none.
+fn find_boundary<C: Codec>(
+    file: &File,
+    codec: &C,
+    mut offset: u64,
+    total_size: u64,
+) -> Result<u64, ReadError> {
+    let mut probe = Vec::new();
+    loop {
+        let size = (codec.probe_size() as u64).min(total_size - offset);
+        probe.resize(size as usize, 0);
+        read_bytes_at(&mut probe, file, offset)?;
+        if let Some(b) = codec.boundary(offset, &probe) {
+            return Ok((offset + b as u64).min(total_size));
+        }
+        if size == 0 {
+            return Ok(total_size);
+        }
+        offset += size;
+    }
+}
@@ -0,0 +1,1472 @@
+//! Seekable compressed files: each chunk is compressed independently as a
+//! zstd frame (RFC 8878) and an index mapping data offsets to frames is
+//! stored after the frames.
+//!
+//! File layout, all integers big-endian:
+//!
+//! ```text
+//! | frame 0 | frame 1 | ... | frame n-1 | index entry 0 | ... | index entry n-1 | trailer |
+//! ```
+//!
+//! | field          | size | content                                 |
+//! |----------------|------|-----------------------------------------|
+//! | index entry    | 32   | data offset, data size, frame offset and frame size, 8 bytes each |
+//! | trailer        | 24   | number of entries (8), data size (8), magic `PARIOZST` (8) |
+//!
+//! Frames are written one after the other in data offset order, empty chunks
+//! have no frame nor index entry. Reading a range of data only requires
+//! reading the frames listed in the index for that range.
+//!
+//! No compression library is used: blocks are compressed with LZ matches
+//! found through a hash table, encoded with the predefined FSE distributions,
+//! and literals are stored raw; each block is stored compressed, raw or as a
+//! run of identical bytes, whichever is smallest. The decoder supports all the
+//! block and literal types of RFC 8878, so frames written by other zstd
+//! encoders can be read, except frames requiring a dictionary. Content
+//! checksums are verified when present.
+use crate::config::check_counts;
+use crate::read::{read_file_chunks, read_tasks, Chunk, Consumer, ReadError, ReadOptions, Tasks};
+use crate::write::{write_to_file_variable, Producer, VariableProducer, WriteError, WriteOptions};
+use core::fmt::Debug;
+use std::fs::File;
+use std::sync::{Arc, Mutex};
+
+#[cfg(unix)]
+use crate::io::io_at_unix::*;
+
+#[cfg(windows)]
+use crate::io::io_at_windows::*;
+
+/// Identifier stored at the end of the file.
+pub const MAGIC: [u8; 8] = *b"PARIOZST";
+/// Size of each index entry.
+pub const INDEX_ENTRY_SIZE: u64 = 32;
+/// Size of the trailer following the index.
+pub const TRAILER_SIZE: u64 = 24;
+
+// -----------------------------------------------------------------------------
+// zstd frames
+
+const ZSTD_MAGIC: u32 = 0xFD2F_B528;
+// skippable frames use magic numbers 0x184D2A50 to 0x184D2A5F
+const SKIPPABLE_MAGIC: u32 = 0x184D_2A50;
+const MAX_BLOCK_SIZE: usize = 128 * 1024;
+const RAW_BLOCK: u32 = 0;
+const RLE_BLOCK: u32 = 1;
+const COMPRESSED_BLOCK: u32 = 2;
+// magic, frame header descriptor and 8 byte content size
+const FRAME_HEADER_SIZE: usize = 13;
+const BLOCK_HEADER_SIZE: usize = 3;
+const MIN_MATCH: usize = 4;
+// offsets must be encodable with the predefined offset code distribution
+const MAX_OFFSET: usize = (1 << 28) - 1;
+const HASH_LOG: u32 = 14;
+
+// baselines and number of extra bits of literal length codes
+const LL_BASE: [u32; 36] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 18, 20, 22, 24, 28, 32, 40, 48, 64,
+    128, 256, 512, 1024, 2048, 4096, 8192, 16384, 32768, 65536,
+];
+const LL_BITS: [u8; 36] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 3, 3, 4, 6, 7, 8, 9, 10, 11,
+    12, 13, 14, 15, 16,
+];
+// baselines and number of extra bits of match length codes
+const ML_BASE: [u32; 53] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27,
+    28, 29, 30, 31, 32, 33, 34, 35, 37, 39, 41, 43, 47, 51, 59, 67, 83, 99, 131, 259, 515, 1027,
+    2051, 4099, 8195, 16387, 32771, 65539,
+];
+const ML_BITS: [u8; 53] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    1, 1, 1, 1, 2, 2, 3, 3, 4, 4, 5, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16,
+];
+// predefined distributions of literal length, match length and offset codes
+const LL_DEFAULT: [i16; 36] = [
+    4, 3, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 3, 2, 1, 1, 1, 1, 1,
+    -1, -1, -1, -1,
+];
+const ML_DEFAULT: [i16; 53] = [
+    1, 4, 3, 2, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1, -1, -1,
+];
+const OF_DEFAULT: [i16; 29] = [
+    1, 1, 1, 1, 1, 1, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1,
+];
+const LL_DEFAULT_LOG: u32 = 6;
+const ML_DEFAULT_LOG: u32 = 6;
+const OF_DEFAULT_LOG: u32 = 5;
+
+/// Index of the highest bit set, `x` must be positive.
+fn highbit(x: u64) -> u32 {
+    63 - x.leading_zeros()
+}
+
+fn le16(b: &[u8]) -> usize {
+    u16::from_le_bytes([b[0], b[1]]) as usize
+}
+
+fn le32(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+}
+
+fn le64(b: &[u8]) -> u64 {
+    let mut v = [0_u8; 8];
+    v.copy_from_slice(&b[..8]);
+    u64::from_le_bytes(v)
+}
+
+/// Load up to 8 bytes at `pos` as a little-endian integer, missing bytes
+/// are zero.
+fn load(data: &[u8], pos: usize) -> u64 {
+    let mut v = [0_u8; 8];
+    if pos < data.len() {
+        let n = (data.len() - pos).min(8);
+        v[..n].copy_from_slice(&data[pos..pos + n]);
+    }
+    u64::from_le_bytes(v)
+}
+
+fn mask(bits: u32) -> u64 {
+    if bits >= 64 {
+        u64::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Bitstreams
+
+/// Bitstream written forward and read backward, as used by FSE and Huffman
+/// coded data.
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        BitWriter {
+            out: Vec::new(),
+            acc: 0,
+            bits: 0,
+        }
+    }
+    /// Append the `bits` lowest bits of `value`, `bits` <= 32.
+    fn write(&mut self, value: u64, bits: u32) {
+        self.acc |= (value & mask(bits)) << self.bits;
+        self.bits += bits;
+        while self.bits >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.bits -= 8;
+        }
+    }
+    /// Append the end marker and return the bytes written.
+    fn finish(mut self) -> Vec<u8> {
+        self.write(1, 1);
+        if self.bits > 0 {
+            self.out.push(self.acc as u8);
+        }
+        self.out
+    }
+}
+
+/// Reader of a bitstream from its end; bits read past the beginning are
+/// zeros.
+struct BackwardBits<'a> {
+    data: &'a [u8],
+    // number of bits left, negative after reading past the beginning
+    pos: i64,
+}
+
+impl<'a> BackwardBits<'a> {
+    fn new(data: &'a [u8]) -> Result<Self, String> {
+        match data.last() {
+            Some(last) if *last != 0 => Ok(BackwardBits {
+                data,
+                pos: (data.len() as i64 - 1) * 8 + highbit(*last as u64) as i64,
+            }),
+            _ => Err("missing bitstream end marker".to_string()),
+        }
+    }
+    /// Return the next `bits` bits without consuming them, `bits` <= 56.
+    fn peek(&self, bits: u32) -> u64 {
+        if bits == 0 || self.pos <= 0 {
+            return 0;
+        }
+        let low = self.pos - bits as i64;
+        let start = low.max(0) as usize;
+        let n = (self.pos as usize - start) as u32;
+        let v = (load(self.data, start / 8) >> (start % 8)) & mask(n);
+        v << (start as i64 - low)
+    }
+    fn consume(&mut self, bits: u32) {
+        self.pos -= bits as i64;
+    }
+    fn read(&mut self, bits: u32) -> u64 {
+        let v = self.peek(bits);
+        self.consume(bits);
+        v
+    }
+    fn overflowed(&self) -> bool {
+        self.pos < 0
+    }
+}
+
+/// Reader of a bitstream from its beginning; bits read past the end are
+/// zeros.
+struct ForwardBits<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ForwardBits<'a> {
+    /// Return the next `bits` bits without consuming them, `bits` <= 56.
+    fn peek(&self, bits: u32) -> u64 {
+        (load(self.data, self.pos / 8) >> (self.pos % 8)) & mask(bits)
+    }
+    fn read(&mut self, bits: u32) -> u64 {
+        let v = self.peek(bits);
+        self.pos += bits as usize;
+        v
+    }
+    /// Number of bytes holding the bits read so far.
+    fn bytes(&self) -> usize {
+        (self.pos + 7) / 8
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Finite state entropy tables
+
+#[derive(Clone, Copy, Default)]
+struct FseEntry {
+    symbol: u8,
+    bits: u8,
+    // next state is base + bits read
+    base: u16,
+}
+
+/// FSE decoding table.
+#[derive(Clone)]
+struct Fse {
+    log: u32,
+    entries: Vec<FseEntry>,
+}
+
+impl Fse {
+    /// Build the decoding table of the normalized distribution `norm`, where
+    /// -1 marks symbols with a probability lower than 1.
+    fn new(norm: &[i16], log: u32) -> Result<Self, String> {
+        let size = 1_usize << log;
+        let total: usize = norm.iter().map(|p| p.unsigned_abs() as usize).sum();
+        if total != size || norm.len() > 256 {
+            return Err("invalid FSE distribution".to_string());
+        }
+        let mut symbols = vec![0_u8; size];
+        let mut next = vec![0_u64; norm.len()];
+        // symbols with probability lower than 1 go at the end of the table
+        let mut high = size - 1;
+        for (s, p) in norm.iter().enumerate() {
+            if *p == -1 {
+                symbols[high] = s as u8;
+                high = high.wrapping_sub(1);
+                next[s] = 1;
+            } else {
+                next[s] = *p as u64;
+            }
+        }
+        let step = (size >> 1) + (size >> 3) + 3;
+        let mut pos = 0;
+        for (s, p) in norm.iter().enumerate() {
+            for _ in 0..(*p).max(0) {
+                symbols[pos] = s as u8;
+                loop {
+                    pos = (pos + step) & (size - 1);
+                    if pos <= high {
+                        break;
+                    }
+                }
+            }
+        }
+        if pos != 0 {
+            return Err("invalid FSE distribution".to_string());
+        }
+        let entries = symbols
+            .iter()
+            .map(|s| {
+                let x = next[*s as usize];
+                next[*s as usize] += 1;
+                let bits = log - highbit(x);
+                FseEntry {
+                    symbol: *s,
+                    bits: bits as u8,
+                    base: ((x << bits) - size as u64) as u16,
+                }
+            })
+            .collect();
+        Ok(Fse { log, entries })
+    }
+    /// Table always decoding `symbol`.
+    fn rle(symbol: u8) -> Self {
+        Fse {
+            log: 0,
+            entries: vec![FseEntry {
+                symbol,
+                bits: 0,
+                base: 0,
+            }],
+        }
+    }
+    /// Read a table description, return the table and the number of bytes
+    /// read.
+    fn read(data: &[u8], max_symbol: usize, max_log: u32) -> Result<(Self, usize), String> {
+        let err = || Err("invalid FSE table description".to_string());
+        let mut r = ForwardBits { data, pos: 0 };
+        let log = r.read(4) as u32 + 5;
+        if log > max_log {
+            return err();
+        }
+        let mut remaining = (1_i32 << log) + 1;
+        let mut threshold = 1_i32 << log;
+        let mut bits = log + 1;
+        let mut norm: Vec<i16> = Vec::new();
+        let mut previous0 = false;
+        while remaining > 1 {
+            if previous0 {
+                // repeat flags: number of following zero probabilities
+                loop {
+                    let n = r.read(2);
+                    norm.extend((0..n).map(|_| 0));
+                    if n != 3 {
+                        break;
+                    }
+                }
+            }
+            if norm.len() > max_symbol || r.bytes() > data.len() {
+                return err();
+            }
+            let max = (2 * threshold - 1) - remaining;
+            let v = r.peek(bits) as i32;
+            let count = if v & (threshold - 1) < max {
+                r.read(bits - 1);
+                v & (threshold - 1)
+            } else {
+                r.read(bits);
+                let c = v & (2 * threshold - 1);
+                if c >= threshold {
+                    c - max
+                } else {
+                    c
+                }
+            };
+            let p = count - 1;
+            remaining -= p.abs();
+            norm.push(p as i16);
+            previous0 = p == 0;
+            while remaining < threshold && threshold > 1 {
+                bits -= 1;
+                threshold >>= 1;
+            }
+        }
+        if remaining != 1 || r.bytes() > data.len() {
+            return err();
+        }
+        Ok((Fse::new(&norm, log)?, r.bytes()))
+    }
+}
+
+/// FSE encoding table, computed from the decoding table.
+struct FseEncoder {
+    fse: Fse,
+    // state decoding each symbol and leading to each next state
+    states: Vec<u16>,
+}
+
+impl FseEncoder {
+    fn new(norm: &[i16], log: u32) -> Self {
+        // predefined distributions are valid
+        let fse = Fse::new(norm, log).unwrap_or_else(|_| Fse::rle(0));
+        let size = fse.entries.len();
+        let mut states = vec![0_u16; norm.len() * size];
+        for (s, e) in fse.entries.iter().enumerate() {
+            let begin = e.base as usize;
+            let end = (begin + (1 << e.bits)).min(size);
+            for next in begin..end {
+                states[e.symbol as usize * size + next] = s as u16;
+            }
+        }
+        FseEncoder { fse, states }
+    }
+    /// State decoding `symbol` followed by state `next`.
+    fn state(&self, symbol: u8, next: usize) -> usize {
+        self.states[symbol as usize * self.fse.entries.len() + next] as usize
+    }
+    /// Bits to write for a transition from `state` to `next`.
+    fn transition(&self, state: usize, next: usize) -> (u64, u32) {
+        let e = &self.fse.entries[state];
+        ((next - e.base as usize) as u64, e.bits as u32)
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Encoder
+
+/// Literal length, match length and offset of a match.
+struct Sequence {
+    literals: usize,
+    length: usize,
+    offset: usize,
+}
+
+/// Return the code of `value`, its extra bits and their number.
+fn code(base: &[u32], bits: &[u8], value: u32) -> (u8, u64, u32) {
+    let c = base.iter().rposition(|b| *b <= value).unwrap_or(0);
+    (c as u8, (value - base[c]) as u64, bits[c] as u32)
+}
+
+/// Sequence encoders, predefined distributions only.
+struct SequenceEncoder {
+    ll: FseEncoder,
+    ml: FseEncoder,
+    of: FseEncoder,
+}
+
+impl SequenceEncoder {
+    fn new() -> Self {
+        SequenceEncoder {
+            ll: FseEncoder::new(&LL_DEFAULT, LL_DEFAULT_LOG),
+            ml: FseEncoder::new(&ML_DEFAULT, ML_DEFAULT_LOG),
+            of: FseEncoder::new(&OF_DEFAULT, OF_DEFAULT_LOG),
+        }
+    }
+    /// Append the sequences section; sequences are written in reverse order
+    /// since the decoder reads the bitstream backward.
+    fn encode(&self, sequences: &[Sequence], out: &mut Vec<u8>) {
+        let n = sequences.len();
+        if n < 128 {
+            out.push(n as u8);
+        } else if n < 0x7F00 {
+            out.push((n >> 8) as u8 + 128);
+            out.push(n as u8);
+        } else {
+            out.push(255);
+            out.extend_from_slice(&((n - 0x7F00) as u16).to_le_bytes());
+        }
+        if n == 0 {
+            return;
+        }
+        // predefined mode for all codes
+        out.push(0);
+        let codes: Vec<_> = sequences
+            .iter()
+            .map(|s| {
+                let ll = code(&LL_BASE, &LL_BITS, s.literals as u32);
+                let ml = code(&ML_BASE, &ML_BITS, s.length as u32);
+                let value = s.offset as u64 + 3;
+                let of_bits = highbit(value);
+                (ll, ml, (of_bits as u8, value - (1 << of_bits), of_bits))
+            })
+            .collect();
+        let mut w = BitWriter::new();
+        let mut states = (0, 0, 0);
+        for (k, (ll, ml, of)) in codes.iter().enumerate().rev() {
+            if k + 1 == n {
+                states = (
+                    self.ll.state(ll.0, 0),
+                    self.ml.state(ml.0, 0),
+                    self.of.state(of.0, 0),
+                );
+            } else {
+                // the decoder updates states in literal length, match length,
+                // offset order
+                let next = (
+                    self.ll.state(ll.0, states.0),
+                    self.ml.state(ml.0, states.1),
+                    self.of.state(of.0, states.2),
+                );
+                let (v, b) = self.of.transition(next.2, states.2);
+                w.write(v, b);
+                let (v, b) = self.ml.transition(next.1, states.1);
+                w.write(v, b);
+                let (v, b) = self.ll.transition(next.0, states.0);
+                w.write(v, b);
+                states = next;
+            }
+            // extra bits are read in offset, match length, literal length order
+            w.write(ll.1, ll.2);
+            w.write(ml.1, ml.2);
+            w.write(of.1, of.2);
+        }
+        // initial states are read in literal length, offset, match length order
+        w.write(states.1 as u64, ML_DEFAULT_LOG);
+        w.write(states.2 as u64, OF_DEFAULT_LOG);
+        w.write(states.0 as u64, LL_DEFAULT_LOG);
+        out.extend_from_slice(&w.finish());
+    }
+}
+
+fn hash(data: &[u8], pos: usize) -> usize {
+    (le32(&data[pos..]).wrapping_mul(0x9E37_79B1) >> (32 - HASH_LOG)) as usize
+}
+
+/// Find matches in `data[begin..end]` with earlier data, return the sequences
+/// and the position of the trailing literals. `table` holds the last
+/// position of each hash of `MIN_MATCH` bytes.
+fn find_matches(
+    data: &[u8],
+    begin: usize,
+    end: usize,
+    table: &mut [usize],
+) -> (Vec<Sequence>, usize) {
+    let mut sequences = Vec::new();
+    let mut literals = begin;
+    let mut i = begin;
+    while i + MIN_MATCH <= end {
+        let h = hash(data, i);
+        let candidate = table[h];
+        table[h] = i;
+        if candidate == usize::MAX
+            || i - candidate > MAX_OFFSET
+            || data[candidate..candidate + MIN_MATCH] != data[i..i + MIN_MATCH]
+        {
+            i += 1;
+            continue;
+        }
+        let mut length = MIN_MATCH;
+        while i + length < end && data[candidate + length] == data[i + length] {
+            length += 1;
+        }
+        // extend the match backward over pending literals
+        let (mut start, mut from) = (i, candidate);
+        while start > literals && from > 0 && data[start - 1] == data[from - 1] {
+            start -= 1;
+            from -= 1;
+        }
+        length += i - start;
+        sequences.push(Sequence {
+            literals: start - literals,
+            length,
+            offset: start - from,
+        });
+        for p in i + 1..start + length {
+            if p + MIN_MATCH <= data.len() {
+                table[hash(data, p)] = p;
+            }
+        }
+        i = start + length;
+        literals = i;
+    }
+    (sequences, literals)
+}
+
+/// Append the content of a compressed block holding `data[begin..end]`:
+/// literals are stored raw, sequences use the predefined distributions.
+fn compress_block(
+    data: &[u8],
+    begin: usize,
+    end: usize,
+    table: &mut [usize],
+    encoder: &SequenceEncoder,
+    out: &mut Vec<u8>,
+) {
+    let (sequences, trailing) = find_matches(data, begin, end, table);
+    let mut literals = Vec::with_capacity(end - begin);
+    let mut pos = begin;
+    for s in &sequences {
+        literals.extend_from_slice(&data[pos..pos + s.literals]);
+        pos += s.literals + s.length;
+    }
+    literals.extend_from_slice(&data[trailing..end]);
+    // raw literals header, 1 to 3 bytes depending on the size
+    let n = literals.len();
+    if n < 32 {
+        out.push((n << 3) as u8);
+    } else if n < 4096 {
+        out.extend_from_slice(&((n << 4) as u16 | 0b0100).to_le_bytes());
+    } else {
+        out.extend_from_slice(&((n << 4) as u32 | 0b1100).to_le_bytes()[..3]);
+    }
+    out.extend_from_slice(&literals);
+    encoder.encode(&sequences, out);
+}
+
+/// Maximum size of the frame encoding `size` bytes: blocks are never larger
+/// than their data plus a block header.
+pub fn max_frame_size(size: usize) -> usize {
+    FRAME_HEADER_SIZE + size + BLOCK_HEADER_SIZE * (size / MAX_BLOCK_SIZE + 2)
+}
+
+/// Write the header of a block of `size` bytes at `pos`.
+fn set_block_header(out: &mut [u8], pos: usize, block_type: u32, size: usize, last: bool) {
+    let h = u32::from(last) | block_type << 1 | (size as u32) << 3;
+    out[pos..pos + BLOCK_HEADER_SIZE].copy_from_slice(&h.to_le_bytes()[..3]);
+}
+
+/// Append the zstd frame encoding `data` to `out`.
+///
+/// Data are split into blocks of at most 128 KiB; each block is stored
+/// compressed, as a run of identical bytes or uncompressed, whichever is
+/// smaller. Compressed blocks hold matches found with a hash table over the
+/// whole frame, encoded with the predefined FSE distributions, and
+/// uncompressed literals.
+pub fn encode_frame(data: &[u8], out: &mut Vec<u8>) {
+    out.extend_from_slice(&ZSTD_MAGIC.to_le_bytes());
+    // 8 byte content size, single segment: no window descriptor
+    out.push(0b1110_0000);
+    out.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    let mut table = vec![usize::MAX; 1 << HASH_LOG];
+    let encoder = SequenceEncoder::new();
+    let mut begin = 0;
+    loop {
+        let end = (begin + MAX_BLOCK_SIZE).min(data.len());
+        let last = end == data.len();
+        let block = &data[begin..end];
+        let header = out.len();
+        out.extend_from_slice(&[0; BLOCK_HEADER_SIZE]);
+        if block.len() > 1 && block.iter().all(|b| *b == block[0]) {
+            out.push(block[0]);
+            set_block_header(out, header, RLE_BLOCK, block.len(), last);
+        } else {
+            compress_block(data, begin, end, &mut table, &encoder, out);
+            let size = out.len() - header - BLOCK_HEADER_SIZE;
+            if size < block.len() {
+                set_block_header(out, header, COMPRESSED_BLOCK, size, last);
+            } else {
+                out.truncate(header + BLOCK_HEADER_SIZE);
+                out.extend_from_slice(block);
+                set_block_header(out, header, RAW_BLOCK, block.len(), last);
+            }
+        }
+        if last {
+            break;
+        }
+        begin = end;
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Decoder
+
+/// Huffman decoding table indexed by the next `bits` bits of the stream.
+#[derive(Clone)]
+struct Huffman {
+    bits: u32,
+    // (symbol, number of bits)
+    entries: Vec<(u8, u8)>,
+}
+
+impl Huffman {
+    /// Read a Huffman tree description, return the table and the number of
+    /// bytes read.
+    fn read(data: &[u8]) -> Result<(Self, usize), String> {
+        let err = |msg: &str| Err(format!("invalid Huffman table: {}", msg));
+        let header = *data.first().ok_or("missing Huffman table")? as usize;
+        let mut weights = Vec::new();
+        let used = if header < 128 {
+            // FSE compressed weights, decoded with two interleaved states
+            let end = 1 + header;
+            if end > data.len() {
+                return err("truncated weights");
+            }
+            let (fse, n) = Fse::read(&data[1..end], 255, 6)?;
+            let mut r = BackwardBits::new(&data[1 + n..end])?;
+            let mut states = [r.read(fse.log) as usize, r.read(fse.log) as usize];
+            let mut k = 0;
+            loop {
+                let e = fse.entries[states[k]];
+                weights.push(e.symbol);
+                states[k] = e.base as usize + r.read(e.bits as u32) as usize;
+                if r.overflowed() {
+                    weights.push(fse.entries[states[1 - k]].symbol);
+                    break;
+                }
+                if weights.len() > 255 {
+                    return err("too many weights");
+                }
+                k = 1 - k;
+            }
+            end
+        } else {
+            // 4 bit weights
+            let n = header - 127;
+            let end = 1 + (n + 1) / 2;
+            if end > data.len() {
+                return err("truncated weights");
+            }
+            weights.extend((0..n).map(|i| {
+                let b = data[1 + i / 2];
+                if i % 2 == 0 {
+                    b >> 4
+                } else {
+                    b & 0xF
+                }
+            }));
+            end
+        };
+        if weights.iter().any(|w| *w > 11) {
+            return err("weight too large");
+        }
+        // the weight of the last symbol completes the sum to a power of two
+        let sum: u64 = weights
+            .iter()
+            .filter(|w| **w > 0)
+            .map(|w| 1 << (w - 1))
+            .sum();
+        if sum == 0 {
+            return err("no symbol");
+        }
+        let bits = highbit(sum) + 1;
+        let rest = (1 << bits) - sum;
+        if bits > 11 || !rest.is_power_of_two() || weights.len() > 255 {
+            return err("invalid weights");
+        }
+        weights.push(highbit(rest) as u8 + 1);
+        // codes are assigned by increasing weight, then symbol
+        let mut entries = Vec::with_capacity(1 << bits);
+        for w in 1..=bits as u8 {
+            for (s, _) in weights.iter().enumerate().filter(|(_, x)| **x == w) {
+                let n = 1_usize << (w - 1);
+                entries.extend((0..n).map(|_| (s as u8, (bits + 1) as u8 - w)));
+            }
+        }
+        Ok((Huffman { bits, entries }, used))
+    }
+    /// Decode `count` symbols from `stream` to `out`.
+    fn decode(&self, stream: &[u8], count: usize, out: &mut Vec<u8>) -> Result<(), String> {
+        let mut r = BackwardBits::new(stream)?;
+        for _ in 0..count {
+            let (s, bits) = self.entries[r.peek(self.bits) as usize];
+            r.consume(bits as u32);
+            out.push(s);
+        }
+        if r.pos != 0 {
+            return Err("corrupted Huffman stream".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Decoding state carried across the blocks of a frame.
+struct Frame {
+    // repeated offsets
+    offsets: [u64; 3],
+    huffman: Option<Huffman>,
+    // last literal length, offset and match length tables
+    tables: [Option<Fse>; 3],
+    defaults: [Fse; 3],
+}
+
+/// Decode the literals section of a compressed block, return the literals
+/// and the size of the section.
+fn decode_literals(block: &[u8], frame: &mut Frame) -> Result<(Vec<u8>, usize), String> {
+    let err = |msg: &str| Err(format!("invalid literals section: {}", msg));
+    let header = load(block, 0);
+    let block_type = header & 3;
+    let size_format = (header >> 2) & 3;
+    if block_type < 2 {
+        // raw or RLE literals
+        let (size, len) = match size_format {
+            0 | 2 => ((header >> 3) & 0x1F, 1),
+            1 => ((header >> 4) & 0xFFF, 2),
+            _ => ((header >> 4) & 0xFFFFF, 3),
+        };
+        let size = size as usize;
+        if block_type == 0 {
+            if len + size > block.len() {
+                return err("truncated literals");
+            }
+            Ok((block[len..len + size].to_vec(), len + size))
+        } else {
+            if len >= block.len() || size > MAX_BLOCK_SIZE {
+                return err("truncated literals");
+            }
+            Ok((vec![block[len]; size], len + 1))
+        }
+    } else {
+        // Huffman coded literals, with a new table or the previous one
+        let (regenerated, compressed, len) = match size_format {
+            0 | 1 => ((header >> 4) & 0x3FF, (header >> 14) & 0x3FF, 3),
+            2 => ((header >> 4) & 0x3FFF, (header >> 18) & 0x3FFF, 4),
+            _ => ((header >> 4) & 0x3FFFF, (header >> 22) & 0x3FFFF, 5),
+        };
+        let (regenerated, compressed) = (regenerated as usize, compressed as usize);
+        if len + compressed > block.len() || regenerated > MAX_BLOCK_SIZE {
+            return err("truncated literals");
+        }
+        let data = &block[len..len + compressed];
+        let mut pos = 0;
+        if block_type == 2 {
+            let (huffman, n) = Huffman::read(data)?;
+            frame.huffman = Some(huffman);
+            pos = n;
+        }
+        let huffman = match &frame.huffman {
+            Some(h) => h,
+            None => return err("missing Huffman table"),
+        };
+        let mut literals = Vec::with_capacity(regenerated);
+        if size_format == 0 {
+            huffman.decode(&data[pos..], regenerated, &mut literals)?;
+        } else {
+            // four streams preceded by the sizes of the first three
+            if pos + 6 > data.len() {
+                return err("truncated jump table");
+            }
+            let sizes = [
+                le16(&data[pos..]),
+                le16(&data[pos + 2..]),
+                le16(&data[pos + 4..]),
+            ];
+            let segment = (regenerated + 3) / 4;
+            if sizes.iter().sum::<usize>() > data.len() - pos - 6 || 3 * segment > regenerated {
+                return err("invalid jump table");
+            }
+            let mut begin = pos + 6;
+            for size in sizes {
+                huffman.decode(&data[begin..begin + size], segment, &mut literals)?;
+                begin += size;
+            }
+            huffman.decode(&data[begin..], regenerated - 3 * segment, &mut literals)?;
+        }
+        Ok((literals, len + compressed))
+    }
+}
+
+/// Return the table of `mode` read at `data[*pos..]`, update `pos`.
+fn sequence_table(
+    mode: u8,
+    data: &[u8],
+    pos: &mut usize,
+    default: &Fse,
+    previous: &mut Option<Fse>,
+    max_symbol: usize,
+    max_log: u32,
+) -> Result<Fse, String> {
+    let table = match mode {
+        0 => default.clone(),
+        1 => {
+            let s = *data.get(*pos).ok_or("truncated sequences section")?;
+            if s as usize > max_symbol {
+                return Err("invalid RLE symbol".to_string());
+            }
+            *pos += 1;
+            Fse::rle(s)
+        }
+        2 => {
+            let (fse, n) = Fse::read(&data[*pos..], max_symbol, max_log)?;
+            *pos += n;
+            fse
+        }
+        _ => previous.clone().ok_or("missing repeated table")?,
+    };
+    *previous = Some(table.clone());
+    Ok(table)
+}
+
+/// Decode a compressed block, appending its data to `out`; `start` is the
+/// position of the frame data in `out`.
+fn decode_block(
+    block: &[u8],
+    out: &mut Vec<u8>,
+    start: usize,
+    frame: &mut Frame,
+) -> Result<(), String> {
+    let block_start = out.len();
+    let (literals, mut pos) = decode_literals(block, frame)?;
+    let err = |msg: &str| Err(format!("invalid sequences section: {}", msg));
+    let b0 = *block.get(pos).ok_or("missing sequences section")? as usize;
+    let (count, len) = match b0 {
+        0..=127 => (b0, 1),
+        128..=254 => (((b0 - 128) << 8) + load(block, pos + 1) as u8 as usize, 2),
+        _ => (
+            le16(&[load(block, pos + 1) as u8, load(block, pos + 2) as u8]) + 0x7F00,
+            3,
+        ),
+    };
+    pos += len;
+    if pos > block.len() {
+        return err("truncated header");
+    }
+    if count == 0 {
+        if pos != block.len() || literals.len() > MAX_BLOCK_SIZE {
+            return err("trailing data");
+        }
+        out.extend_from_slice(&literals);
+        return Ok(());
+    }
+    let modes = *block.get(pos).ok_or("missing compression modes")?;
+    pos += 1;
+    if modes & 3 != 0 {
+        return err("reserved bits set");
+    }
+    let ll = sequence_table(
+        modes >> 6,
+        block,
+        &mut pos,
+        &frame.defaults[0],
+        &mut frame.tables[0],
+        35,
+        9,
+    )?;
+    let of = sequence_table(
+        (modes >> 4) & 3,
+        block,
+        &mut pos,
+        &frame.defaults[1],
+        &mut frame.tables[1],
+        31,
+        8,
+    )?;
+    let ml = sequence_table(
+        (modes >> 2) & 3,
+        block,
+        &mut pos,
+        &frame.defaults[2],
+        &mut frame.tables[2],
+        52,
+        9,
+    )?;
+    let mut r = BackwardBits::new(&block[pos..])?;
+    let mut ll_state = r.read(ll.log) as usize;
+    let mut of_state = r.read(of.log) as usize;
+    let mut ml_state = r.read(ml.log) as usize;
+    let mut lit = 0;
+    for k in 0..count {
+        let (ll_e, of_e, ml_e) = (
+            ll.entries[ll_state],
+            of.entries[of_state],
+            ml.entries[ml_state],
+        );
+        if ll_e.symbol > 35 || ml_e.symbol > 52 {
+            return err("invalid code");
+        }
+        let of_code = of_e.symbol as u32;
+        let value = (1_u64 << of_code) + r.read(of_code);
+        let ml_code = ml_e.symbol as usize;
+        let length = ML_BASE[ml_code] as usize + r.read(ML_BITS[ml_code] as u32) as usize;
+        let ll_code = ll_e.symbol as usize;
+        let literal_length = LL_BASE[ll_code] as usize + r.read(LL_BITS[ll_code] as u32) as usize;
+        if k + 1 < count {
+            ll_state = ll_e.base as usize + r.read(ll_e.bits as u32) as usize;
+            ml_state = ml_e.base as usize + r.read(ml_e.bits as u32) as usize;
+            of_state = of_e.base as usize + r.read(of_e.bits as u32) as usize;
+        }
+        let reps = &mut frame.offsets;
+        let offset = if value > 3 {
+            let o = value - 3;
+            *reps = [o, reps[0], reps[1]];
+            o
+        } else {
+            match value as usize - usize::from(literal_length > 0) {
+                0 => reps[0],
+                1 => {
+                    let o = reps[1];
+                    *reps = [o, reps[0], reps[2]];
+                    o
+                }
+                2 => {
+                    let o = reps[2];
+                    *reps = [o, reps[0], reps[1]];
+                    o
+                }
+                _ => {
+                    let o = reps[0].wrapping_sub(1);
+                    *reps = [o, reps[0], reps[1]];
+                    o
+                }
+            }
+        } as usize;
+        if lit + literal_length > literals.len() {
+            return err("not enough literals");
+        }
+        out.extend_from_slice(&literals[lit..lit + literal_length]);
+        lit += literal_length;
+        if offset == 0 || offset > out.len() - start {
+            return err("offset out of range");
+        }
+        if out.len() + length - block_start > MAX_BLOCK_SIZE {
+            return err("block too large");
+        }
+        let from = out.len() - offset;
+        if offset >= length {
+            out.extend_from_within(from..from + length);
+        } else {
+            // overlapping copy
+            for i in 0..length {
+                out.push(out[from + i]);
+            }
+        }
+    }
+    if r.pos != 0 {
+        return err("corrupted bitstream");
+    }
+    out.extend_from_slice(&literals[lit..]);
+    if out.len() - block_start > MAX_BLOCK_SIZE {
+        return err("block too large");
+    }
+    Ok(())
+}
+
+/// Return the XXH64 hash of `data` with seed zero.
+fn xxh64(data: &[u8]) -> u64 {
+    const P1: u64 = 0x9E37_79B1_85EB_CA87;
+    const P2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+    const P3: u64 = 0x1656_67B1_9E37_79F9;
+    const P4: u64 = 0x85EB_CA77_C2B2_AE63;
+    const P5: u64 = 0x27D4_EB2F_1656_67C5;
+    let round = |acc: u64, input: u64| {
+        acc.wrapping_add(input.wrapping_mul(P2))
+            .rotate_left(31)
+            .wrapping_mul(P1)
+    };
+    let merge = |acc: u64, v: u64| (acc ^ round(0, v)).wrapping_mul(P1).wrapping_add(P4);
+    let mut p = 0;
+    let mut h = if data.len() >= 32 {
+        let mut v = [P1.wrapping_add(P2), P2, 0, 0_u64.wrapping_sub(P1)];
+        while p + 32 <= data.len() {
+            for (k, x) in v.iter_mut().enumerate() {
+                *x = round(*x, le64(&data[p + 8 * k..]));
+            }
+            p += 32;
+        }
+        let h = v[0]
+            .rotate_left(1)
+            .wrapping_add(v[1].rotate_left(7))
+            .wrapping_add(v[2].rotate_left(12))
+            .wrapping_add(v[3].rotate_left(18));
+        v.iter().fold(h, |h, x| merge(h, *x))
+    } else {
+        P5
+    };
+    h = h.wrapping_add(data.len() as u64);
+    while p + 8 <= data.len() {
+        h ^= round(0, le64(&data[p..]));
+        h = h.rotate_left(27).wrapping_mul(P1).wrapping_add(P4);
+        p += 8;
+    }
+    if p + 4 <= data.len() {
+        h ^= (le32(&data[p..]) as u64).wrapping_mul(P1);
+        h = h.rotate_left(23).wrapping_mul(P2).wrapping_add(P3);
+        p += 4;
+    }
+    for b in &data[p..] {
+        h ^= (*b as u64).wrapping_mul(P5);
+        h = h.rotate_left(11).wrapping_mul(P1);
+    }
+    h ^= h >> 33;
+    h = h.wrapping_mul(P2);
+    h ^= h >> 29;
+    h = h.wrapping_mul(P3);
+    h ^ (h >> 32)
+}
+
+/// Decode the zstd frame in `frame`, appending the data to `out`; skippable
+/// frames produce no data. Frames requiring a dictionary are not supported;
+/// the content checksum is verified when present.
+pub fn decode_frame(frame: &[u8], out: &mut Vec<u8>) -> Result<(), String> {
+    let start = out.len();
+    let r = decode(frame, out);
+    if r.is_err() {
+        out.truncate(start);
+    }
+    r.map_err(|err| format!("Invalid zstd frame: {}", err))
+}
+
+fn decode(frame: &[u8], out: &mut Vec<u8>) -> Result<(), String> {
+    let err = |msg: &str| Err(msg.to_string());
+    if frame.len() < 8 {
+        return err("truncated frame header");
+    }
+    let magic = le32(frame);
+    if magic & 0xFFFF_FFF0 == SKIPPABLE_MAGIC {
+        if le32(&frame[4..]) as usize != frame.len() - 8 {
+            return err("wrong skippable frame size");
+        }
+        return Ok(());
+    }
+    if magic != ZSTD_MAGIC {
+        return err("wrong magic number");
+    }
+    let fhd = frame[4];
+    if fhd & 0x08 != 0 {
+        return err("reserved bit set");
+    }
+    let single_segment = fhd & 0x20 != 0;
+    let has_checksum = fhd & 0x04 != 0;
+    let dict_id_size = [0, 1, 2, 4][(fhd & 3) as usize];
+    let content_size_size = match fhd >> 6 {
+        0 if single_segment => 1,
+        0 => 0,
+        1 => 2,
+        2 => 4,
+        _ => 8,
+    };
+    let mut pos = 5 + usize::from(!single_segment);
+    if pos + dict_id_size + content_size_size > frame.len() {
+        return err("truncated frame header");
+    }
+    if load(frame, pos) & mask(8 * dict_id_size as u32) != 0 {
+        return err("dictionaries are not supported");
+    }
+    pos += dict_id_size;
+    let content_size = match content_size_size {
+        0 => None,
+        2 => Some(le16(&frame[pos..]) as u64 + 256),
+        n => Some(load(frame, pos) & mask(8 * n as u32)),
+    };
+    pos += content_size_size;
+    let start = out.len();
+    let mut state = Frame {
+        offsets: [1, 4, 8],
+        huffman: None,
+        tables: [None, None, None],
+        defaults: [
+            Fse::new(&LL_DEFAULT, LL_DEFAULT_LOG)?,
+            Fse::new(&OF_DEFAULT, OF_DEFAULT_LOG)?,
+            Fse::new(&ML_DEFAULT, ML_DEFAULT_LOG)?,
+        ],
+    };
+    loop {
+        if pos + BLOCK_HEADER_SIZE > frame.len() {
+            return err("truncated block header");
+        }
+        let h = load(frame, pos) as u32 & 0xFF_FFFF;
+        pos += BLOCK_HEADER_SIZE;
+        let size = (h >> 3) as usize;
+        if size > MAX_BLOCK_SIZE {
+            return err("block too large");
+        }
+        match (h >> 1) & 3 {
+            RAW_BLOCK => {
+                if pos + size > frame.len() {
+                    return err("truncated raw block");
+                }
+                out.extend_from_slice(&frame[pos..pos + size]);
+                pos += size;
+            }
+            RLE_BLOCK => {
+                if pos >= frame.len() {
+                    return err("truncated RLE block");
+                }
+                out.resize(out.len() + size, frame[pos]);
+                pos += 1;
+            }
+            COMPRESSED_BLOCK => {
+                if pos + size > frame.len() {
+                    return err("truncated compressed block");
+                }
+                decode_block(&frame[pos..pos + size], out, start, &mut state)?;
+                pos += size;
+            }
+            _ => return err("reserved block type"),
+        }
+        if h & 1 != 0 {
+            break;
+        }
+    }
+    if has_checksum {
+        if pos + 4 > frame.len() {
+            return err("truncated checksum");
+        }
+        if le32(&frame[pos..]) != xxh64(&out[start..]) as u32 {
+            return err("checksum mismatch");
+        }
+        pos += 4;
+    }
+    if pos != frame.len() {
+        return err("trailing data");
+    }
+    if content_size.map_or(false, |s| s != (out.len() - start) as u64) {
+        return err("wrong content size");
+    }
+    Ok(())
+}
+
+// -----------------------------------------------------------------------------
+/// Location of the frame holding a range of data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// Offset of the data in the uncompressed data.
+    pub offset: u64,
+    /// Size of the uncompressed data.
+    pub size: u64,
+    /// File offset of the frame.
+    pub frame_offset: u64,
+    /// Size of the frame.
+    pub frame_size: u64,
+}
+
+fn be64(b: &[u8]) -> u64 {
+    let mut v = [0_u8; 8];
+    v.copy_from_slice(&b[..8]);
+    u64::from_be_bytes(v)
+}
+
+/// Read the index of file written by `write_compressed`, return the index
+/// entries and the data size.
+pub fn read_index(filename: &str) -> Result<(Vec<IndexEntry>, u64), ReadError> {
+    let file = File::open(filename).map_err(ReadError::IO)?;
+    let file_size = file.metadata().map_err(ReadError::IO)?.len();
+    let invalid = || ReadError::Other("Not a compressed file".to_string());
+    if file_size < TRAILER_SIZE {
+        return Err(invalid());
+    }
+    let mut trailer = vec![0_u8; TRAILER_SIZE as usize];
+    read_bytes_at(&mut trailer, &file, file_size - TRAILER_SIZE)?;
+    if trailer[16..] != MAGIC {
+        return Err(invalid());
+    }
+    let num_entries = be64(&trailer);
+    let data_size = be64(&trailer[8..]);
+    let index_size = num_entries
+        .checked_mul(INDEX_ENTRY_SIZE)
+        .filter(|s| *s <= file_size - TRAILER_SIZE)
+        .ok_or_else(invalid)?;
+    let mut index = vec![0_u8; index_size as usize];
+    read_bytes_at(&mut index, &file, file_size - TRAILER_SIZE - index_size)?;
+    let entries = index
+        .chunks(INDEX_ENTRY_SIZE as usize)
+        .map(|e| IndexEntry {
+            offset: be64(e),
+            size: be64(&e[8..]),
+            frame_offset: be64(&e[16..]),
+            frame_size: be64(&e[24..]),
+        })
+        .collect();
+    Ok((entries, data_size))
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `write_to_file` but each chunk is compressed into a zstd frame by
+/// producer threads and an index is written after the frames, see the module
+/// documentation. Returns the size of the file.
+#[allow(clippy::too_many_arguments)]
+pub fn write_compressed<T: 'static + Clone + Send, E: 'static + Send + Debug>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    producer: Arc<Producer<T, E>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    total_size: usize,
+) -> Result<usize, WriteError> {
+    check_counts(
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        num_buffers_per_producer,
+    )
+    .map_err(WriteError::Other)?;
+    let num_chunks = num_producers * chunks_per_producer;
+    let total_size = total_size as u64;
+    let chunk_size = ((total_size + num_chunks - 1) / num_chunks).max(1);
+    // frame size of each chunk
+    let frame_sizes = Arc::new(Mutex::new(vec![0_u64; num_chunks as usize]));
+    let sizes = frame_sizes.clone();
+    // one buffer per producer thread, chunk k is generated by producer
+    // k % num_producers
+    let staging: Vec<Mutex<Vec<u8>>> = (0..num_producers).map(|_| Mutex::new(Vec::new())).collect();
+    let compress: Arc<VariableProducer<T, E>> =
+        Arc::new(move |buffer: &mut Vec<u8>, data: &T, index: u64| {
+            let offset = index * chunk_size;
+            let len = chunk_size.min(total_size.saturating_sub(offset));
+            if len == 0 {
+                return Ok(0);
+            }
+            let mut plain = match staging[(index % num_producers) as usize].lock() {
+                Ok(p) => p,
+                Err(err) => err.into_inner(),
+            };
+            plain.resize(len as usize, 0);
+            producer(&mut plain, data, offset)?;
+            // capacity is at least the maximum frame size, no reallocation
+            buffer.clear();
+            encode_frame(&plain, buffer);
+            match sizes.lock() {
+                Ok(mut s) => s[index as usize] = buffer.len() as u64,
+                Err(err) => err.into_inner()[index as usize] = buffer.len() as u64,
+            }
+            Ok(buffer.len())
+        });
+    let frames_size = write_to_file_variable(
+        filename,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        compress,
+        client_data,
+        num_buffers_per_producer,
+        max_frame_size(chunk_size as usize),
+        WriteOptions::default(),
+    )? as u64;
+    let frame_sizes = match frame_sizes.lock() {
+        Ok(s) => s.clone(),
+        Err(err) => err.into_inner().clone(),
+    };
+    let mut footer = Vec::new();
+    let mut frame_offset = 0_u64;
+    let mut num_entries = 0_u64;
+    for (k, frame_size) in frame_sizes.into_iter().enumerate() {
+        if frame_size == 0 {
+            continue;
+        }
+        let offset = k as u64 * chunk_size;
+        footer.extend_from_slice(&offset.to_be_bytes());
+        footer.extend_from_slice(&chunk_size.min(total_size - offset).to_be_bytes());
+        footer.extend_from_slice(&frame_offset.to_be_bytes());
+        footer.extend_from_slice(&frame_size.to_be_bytes());
+        frame_offset += frame_size;
+        num_entries += 1;
+    }
+    footer.extend_from_slice(&num_entries.to_be_bytes());
+    footer.extend_from_slice(&total_size.to_be_bytes());
+    footer.extend_from_slice(&MAGIC);
+    let file = File::options()
+        .write(true)
+        .open(filename)
+        .map_err(WriteError::IO)?;
+    write_bytes_at(&footer, &file, frames_size)?;
+    Ok((frames_size + footer.len() as u64) as usize)
+}
+
+// -----------------------------------------------------------------------------
+/// Read file written by `write_compressed`: frames listed in the index are
+/// read by producers, decompressed by consumers and passed to the callback
+/// with their data offset. Each frame is one chunk, frames are split evenly
+/// among producers; the number of chunks passed to the callback is the
+/// number of frames.
+pub fn read_compressed<T: 'static + Clone + Send, R: 'static + Clone + Sync + Send>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    consumer: Arc<Consumer<T, R>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+) -> Result<Vec<(u64, R)>, ReadError> {
+    check_counts(num_producers, num_consumers, 1, num_buffers_per_producer)
+        .map_err(ReadError::Other)?;
+    read_compressed_with_options(
+        filename,
+        num_producers,
+        num_consumers,
+        consumer,
+        client_data,
+        num_buffers_per_producer,
+        &ReadOptions::default(),
+    )
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `read_compressed` with read options, invoked by
+/// `read::read_file_with_options` when `ReadOptions::compressed` is set;
+/// counts must already be validated.
+pub(crate) fn read_compressed_with_options<
+    T: 'static + Clone + Send,
+    R: 'static + Clone + Sync + Send,
+>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    consumer: Arc<Consumer<T, R>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    options: &ReadOptions,
+) -> Result<Vec<(u64, R)>, ReadError> {
+    let (entries, _) = read_index(filename)?;
+    let num_chunks = entries.len() as u64;
+    let per_producer = ((num_chunks + num_producers - 1) / num_producers).max(1);
+    let tasks: Tasks = (0..num_producers)
+        .map(|i| {
+            let begin = (i * per_producer).min(num_chunks);
+            let end = (begin + per_producer).min(num_chunks);
+            (begin..end)
+                .map(|k| {
+                    let e = &entries[k as usize];
+                    Chunk {
+                        id: k + 1,
+                        offset: e.frame_offset,
+                        size: e.frame_size,
+                    }
+                })
+                .collect::<Vec<_>>()
+        })
+        .filter(|t| !t.is_empty())
+        .collect();
+    let entries = Arc::new(entries);
+    let decompress: Arc<Consumer<T, Result<R, String>>> = Arc::new(
+        move |buffer: &[u8], data: &T, chunk_id, num_chunks, _offset| {
+            let entry = &entries[chunk_id as usize - 1];
+            let mut plain = Vec::with_capacity(entry.size as usize);
+            decode_frame(buffer, &mut plain)?;
+            if plain.len() as u64 != entry.size {
+                return Err(format!(
+                    "Frame at offset {} holds {} bytes, expected {}",
+                    entry.frame_offset,
+                    plain.len(),
+                    entry.size
+                ));
+            }
+            Ok(consumer(&plain, data, chunk_id, num_chunks, entry.offset))
+        },
+    );
+    read_tasks(
+        filename,
+        tasks,
+        num_chunks,
+        num_consumers,
+        decompress,
+        client_data,
+        num_buffers_per_producer,
+        options,
+    )?
+    .into_iter()
+    .map(|(chunk_id, r)| r.map(|r| (chunk_id, r)).map_err(ReadError::Other))
+    .collect()
+}
+
+// -----------------------------------------------------------------------------
+/// Read the zstd frames at the `(offset, size)` ranges in `frames`, e.g.
+/// frames produced by another tool and concatenated in a file without an
+/// index: each frame is one chunk read by a producer and decompressed by a
+/// consumer, see `read::read_file_chunks` for how frames are assigned to
+/// producers. The callback receives the decompressed data and the file offset
+/// of the frame; chunk `k` in `frames` has id `k + 1`.
+///
+/// Frames requiring a dictionary cannot be decoded, see the module
+/// documentation; they fail with `ReadError::Other`, as do corrupted frames.
+pub fn read_frames<T: 'static + Clone + Send, R: 'static + Clone + Sync + Send>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    frames: &[(u64, u64)],
+    consumer: Arc<Consumer<T, R>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+) -> Result<Vec<(u64, R)>, ReadError> {
+    let decompress: Arc<Consumer<T, Result<R, String>>> = Arc::new(
+        move |buffer: &[u8], data: &T, chunk_id, num_chunks, offset| {
+            let mut plain = Vec::new();
+            decode_frame(buffer, &mut plain)
+                .map_err(|err| format!("Frame at offset {}: {}", offset, err))?;
+            Ok(consumer(&plain, data, chunk_id, num_chunks, offset))
+        },
+    );
+    read_file_chunks(
+        filename,
+        num_producers,
+        num_consumers,
+        frames,
+        decompress,
+        client_data,
+        num_buffers_per_producer,
+        &ReadOptions::default(),
+    )?
+    .into_iter()
+    .map(|(chunk_id, r)| r.map(|r| (chunk_id, r)).map_err(ReadError::Other))
+    .collect()
+}
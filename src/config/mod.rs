@@ -0,0 +1,54 @@
+//! Thread and buffer configuration shared by the read and write builders.
+
+/// Number of threads, chunks and buffers; unset fields are filled in with
+/// defaults.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ParConfig {
+    pub num_producers: u64,
+    /// Same as `num_producers` if `None`.
+    pub num_consumers: Option<u64>,
+    pub chunks_per_producer: u64,
+    pub num_buffers_per_producer: u64,
+}
+
+impl Default for ParConfig {
+    /// One producer per available CPU, one chunk and two buffers per
+    /// producer.
+    fn default() -> Self {
+        ParConfig {
+            num_producers: std::thread::available_parallelism()
+                .map(|n| n.get() as u64)
+                .unwrap_or(1),
+            num_consumers: None,
+            chunks_per_producer: 1,
+            num_buffers_per_producer: 2,
+        }
+    }
+}
+
+impl ParConfig {
+    /// Return the number of consumers, equal to the number of producers
+    /// unless set explicitly.
+    pub fn num_consumers(&self) -> u64 {
+        self.num_consumers.unwrap_or(self.num_producers)
+    }
+}
+
+/// Return an error message naming the first zero argument if any of the
+/// thread, chunk and buffer counts is zero.
+pub(crate) fn check_counts(
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    num_buffers_per_producer: u64,
+) -> Result<(), String> {
+    [
+        ("num_producers", num_producers),
+        ("num_consumers", num_consumers),
+        ("chunks_per_producer", chunks_per_producer),
+        ("num_buffers_per_producer", num_buffers_per_producer),
+    ]
+    .iter()
+    .find(|(_, n)| *n == 0)
+    .map_or(Ok(()), |(name, _)| Err(format!("{} must be >= 1", name)))
+}
@@ -0,0 +1,166 @@
+//! Portable container: versioned header followed by data written and read in
+//! parallel.
+//!
+//! Header layout, all integers big-endian (network byte order) so that files
+//! written on one architecture can be read on any other:
+//!
+//! | offset | size | content                               |
+//! |--------|------|---------------------------------------|
+//! | 0      | 8    | magic `PARIOCNT`                      |
+//! | 8      | 4    | format version, currently 1           |
+//! | 12     | 4    | reserved, zero                        |
+//! | 16     | 8    | data size in bytes                    |
+//!
+//! Data starts at offset `HEADER_SIZE`; offsets passed to callbacks are
+//! relative to the start of the data.
+use crate::read::{producer_tasks, read_tasks, shift_tasks, Consumer, ReadError, ReadOptions};
+use crate::write::{write_to_file_with_options, Producer, WriteError, WriteOptions};
+use core::fmt::Debug;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+/// Container identifier stored at the start of the file.
+pub const MAGIC: [u8; 8] = *b"PARIOCNT";
+/// Format version written by `write_container`.
+pub const VERSION: u32 = 1;
+/// Size of the header preceding the data.
+pub const HEADER_SIZE: u64 = 24;
+
+/// Container header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub version: u32,
+    /// Data size in bytes.
+    pub data_size: u64,
+}
+
+impl Header {
+    /// Serialise header.
+    pub fn to_bytes(&self) -> [u8; HEADER_SIZE as usize] {
+        let mut b = [0_u8; HEADER_SIZE as usize];
+        b[..8].copy_from_slice(&MAGIC);
+        b[8..12].copy_from_slice(&self.version.to_be_bytes());
+        b[16..24].copy_from_slice(&self.data_size.to_be_bytes());
+        b
+    }
+    /// Parse and validate header.
+    pub fn from_bytes(b: &[u8]) -> Result<Header, ReadError> {
+        if b.len() < HEADER_SIZE as usize || b[..8] != MAGIC {
+            return Err(ReadError::Other("Not a container file".to_string()));
+        }
+        let mut version = [0_u8; 4];
+        version.copy_from_slice(&b[8..12]);
+        let version = u32::from_be_bytes(version);
+        if version != VERSION {
+            return Err(ReadError::Other(format!(
+                "Unsupported container version {}",
+                version
+            )));
+        }
+        let mut data_size = [0_u8; 8];
+        data_size.copy_from_slice(&b[16..24]);
+        Ok(Header {
+            version,
+            data_size: u64::from_be_bytes(data_size),
+        })
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Write container with `total_size` bytes of data generated in parallel as in
+/// `write_to_file`; the header is written after the data.
+/// Returns the number of data bytes written.
+#[allow(clippy::too_many_arguments)]
+pub fn write_container<T: 'static + Clone + Send, E: 'static + Send + Debug>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    producer: Arc<Producer<T, E>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    total_size: usize,
+) -> Result<usize, WriteError> {
+    let written = write_to_file_with_options(
+        filename,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        producer,
+        client_data,
+        num_buffers_per_producer,
+        total_size,
+        WriteOptions {
+            data_offset: HEADER_SIZE,
+            ..Default::default()
+        },
+    )?;
+    let header = Header {
+        version: VERSION,
+        data_size: total_size as u64,
+    };
+    let mut file = File::options()
+        .write(true)
+        .open(filename)
+        .map_err(WriteError::IO)?;
+    file.write_all(&header.to_bytes()).map_err(WriteError::IO)?;
+    Ok(written)
+}
+
+// -----------------------------------------------------------------------------
+/// Validate the container header then read the data in parallel as in
+/// `read_file`.
+///
+/// Returns `ReadError::Other` if the file is not a container, has an
+/// unsupported version or a size not matching the header.
+pub fn read_container<T: 'static + Clone + Send, R: 'static + Clone + Sync + Send>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    consumer: Arc<Consumer<T, R>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+) -> Result<Vec<(u64, R)>, ReadError> {
+    let header = read_header(filename)?;
+    let file_size = std::fs::metadata(filename).map_err(ReadError::IO)?.len();
+    if file_size != HEADER_SIZE + header.data_size {
+        return Err(ReadError::Other(format!(
+            "Container data size {} does not match header, expected {}",
+            file_size.saturating_sub(HEADER_SIZE),
+            header.data_size
+        )));
+    }
+    let mut tasks = producer_tasks(header.data_size, num_producers, chunks_per_producer);
+    shift_tasks(&mut tasks, HEADER_SIZE);
+    let data_consumer: Arc<Consumer<T, R>> = Arc::new(
+        move |buffer: &[u8], data: &T, chunk_id, num_chunks, offset| {
+            consumer(buffer, data, chunk_id, num_chunks, offset - HEADER_SIZE)
+        },
+    );
+    read_tasks(
+        filename,
+        tasks,
+        num_producers * chunks_per_producer,
+        num_consumers,
+        data_consumer,
+        client_data,
+        num_buffers_per_producer,
+        &ReadOptions::default(),
+    )
+}
+
+// -----------------------------------------------------------------------------
+/// Read and validate the header of container `filename`.
+pub fn read_header(filename: &str) -> Result<Header, ReadError> {
+    let mut b = [0_u8; HEADER_SIZE as usize];
+    let mut file = File::open(filename).map_err(ReadError::IO)?;
+    match file.read_exact(&mut b) {
+        Ok(()) => Header::from_bytes(&b),
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+            Err(ReadError::Other("Not a container file".to_string()))
+        }
+        Err(err) => Err(ReadError::IO(err)),
+    }
+}
@@ -0,0 +1,232 @@
+//! Parallel copy of multiple files sharing the same worker threads.
+//!
+//! Copying many small files one at a time spends most of the time spawning
+//! and joining threads. `copy_files` instead reads all the source files as a
+//! single sequence of bytes, the files being laid out one after the other:
+//! the sequence is split into chunks as when reading a single file, so that
+//! producers read chunks of different files at the same time and a chunk can
+//! span the end of a file and the beginning of the next one. Consumers write
+//! each part of a chunk to the destination file at the offset it was read
+//! from.
+//!
+//! One set of producer and consumer threads is spawned for the whole copy;
+//! memory usage is the same as when reading a single file.
+use crate::cancel::CancelToken;
+use crate::lock::LockPolicy;
+use crate::read::{producer_tasks, read_tasks, Consumer, ReadAt, ReadError, ReadOptions};
+use crate::write::{create_file, OpenMode, WriteError};
+use std::fs::File;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+#[cfg(unix)]
+use crate::io::io_at_unix::*;
+
+#[cfg(windows)]
+use crate::io::io_at_windows::*;
+
+/// Copy configuration.
+#[derive(Debug, Clone)]
+pub struct CopyConfig {
+    pub num_producers: u64,
+    pub num_consumers: u64,
+    pub chunks_per_producer: u64,
+    pub num_buffers_per_producer: u64,
+}
+
+impl Default for CopyConfig {
+    fn default() -> Self {
+        CopyConfig {
+            num_producers: 4,
+            num_consumers: 2,
+            chunks_per_producer: 2,
+            num_buffers_per_producer: 2,
+        }
+    }
+}
+
+/// Error type returned by `copy_files`.
+#[derive(Debug)]
+pub enum CopyError {
+    /// Error opening a source file.
+    Open {
+        filename: String,
+        error: std::io::Error,
+    },
+    /// Error reading source files, I/O errors include the file name.
+    Read(ReadError),
+    /// Error writing a destination file.
+    Write { filename: String, error: WriteError },
+}
+
+// -----------------------------------------------------------------------------
+/// Files laid out one after the other in a single offset space.
+struct Files {
+    files: Vec<File>,
+    names: Vec<String>,
+    // offset of the first byte of each file, followed by the total size
+    starts: Vec<u64>,
+}
+
+impl Files {
+    /// Call `f` with the index of the file, the file offset and the range of
+    /// the chunk of `len` bytes at `offset` stored in each file.
+    fn parts<E>(
+        &self,
+        offset: u64,
+        len: usize,
+        mut f: impl FnMut(usize, u64, Range<usize>) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let end = offset + len as u64;
+        // last file starting at or before offset; empty files are skipped
+        // since they start where the next file starts
+        let mut i = self.starts.partition_point(|s| *s <= offset) - 1;
+        while i < self.files.len() && self.starts[i] < end {
+            let begin = self.starts[i].max(offset);
+            let part_end = self.starts[i + 1].min(end);
+            if part_end > begin {
+                let r = (begin - offset) as usize..(part_end - offset) as usize;
+                f(i, begin - self.starts[i], r)?;
+            }
+            i += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Source files read as a single data source.
+struct Sources(Files);
+
+impl ReadAt for Sources {
+    fn read_at(&self, buffer: &mut Vec<u8>, offset: u64) -> Result<(), ReadError> {
+        let files = &self.0;
+        files.parts(offset, buffer.len(), |i, file_offset, r| {
+            read_bytes_at(&mut buffer[r], &files.files[i], file_offset)
+                .map_err(|err| ReadError::Other(format!("{}: {:?}", files.names[i], err)))
+        })
+    }
+}
+
+/// Destination files and first write error, consumers stop writing after an
+/// error.
+struct Destinations {
+    files: Files,
+    error: Mutex<Option<CopyError>>,
+    cancel: Arc<CancelToken>,
+}
+
+impl Destinations {
+    fn write_chunk(&self, buffer: &[u8], offset: u64) -> usize {
+        let files = &self.files;
+        let r = files.parts(offset, buffer.len(), |i, file_offset, r| {
+            write_bytes_at(&buffer[r], &files.files[i], file_offset).map_err(|error| {
+                CopyError::Write {
+                    filename: files.names[i].clone(),
+                    error,
+                }
+            })
+        });
+        match r {
+            Ok(()) => buffer.len(),
+            Err(err) => {
+                let mut error = match self.error.lock() {
+                    Ok(e) => e,
+                    Err(e) => e.into_inner(),
+                };
+                error.get_or_insert(err);
+                self.cancel.cancel();
+                0
+            }
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Copy each `(source, destination)` pair of files in parallel, using the
+/// same producer and consumer threads for all the files, see the module
+/// documentation; return the total number of bytes copied.
+///
+/// Destination files are created or truncated before copying starts. After
+/// the first write error no more chunks are read and the error is returned,
+/// destination files are then partially written.
+pub fn copy_files(pairs: &[(&str, &str)], config: CopyConfig) -> Result<usize, CopyError> {
+    let mut sources = Vec::with_capacity(pairs.len());
+    let mut destinations = Vec::with_capacity(pairs.len());
+    let mut starts = vec![0];
+    let mut total_size = 0;
+    for (src, dst) in pairs {
+        let read_err = |error| CopyError::Open {
+            filename: src.to_string(),
+            error,
+        };
+        let file = File::open(src).map_err(read_err)?;
+        let size = file.metadata().map_err(read_err)?.len();
+        sources.push(file);
+        let file =
+            create_file(dst, size, OpenMode::Truncate, LockPolicy::NoLock).map_err(|error| {
+                CopyError::Write {
+                    filename: dst.to_string(),
+                    error,
+                }
+            })?;
+        destinations.push(file);
+        total_size += size;
+        starts.push(total_size);
+    }
+    if total_size == 0 {
+        return Ok(0);
+    }
+    let sources = Sources(Files {
+        files: sources,
+        names: pairs.iter().map(|p| p.0.to_string()).collect(),
+        starts: starts.clone(),
+    });
+    let cancel = Arc::new(CancelToken::new());
+    let destinations = Arc::new(Destinations {
+        files: Files {
+            files: destinations,
+            names: pairs.iter().map(|p| p.1.to_string()).collect(),
+            starts,
+        },
+        error: Mutex::new(None),
+        cancel: cancel.clone(),
+    });
+    let consume: Arc<Consumer<Arc<Destinations>, usize>> = Arc::new(
+        |buffer: &[u8], dst: &Arc<Destinations>, _chunk_id, _num_chunks, offset| {
+            dst.write_chunk(buffer, offset)
+        },
+    );
+    let tasks = producer_tasks(total_size, config.num_producers, config.chunks_per_producer);
+    let read = read_tasks(
+        pairs[0].0,
+        tasks,
+        config.num_producers * config.chunks_per_producer,
+        config.num_consumers,
+        consume,
+        destinations.clone(),
+        config.num_buffers_per_producer,
+        &ReadOptions {
+            source: Some(Arc::new(sources)),
+            cancel: Some(cancel),
+            ..Default::default()
+        },
+    );
+    let write_err = match destinations.error.lock() {
+        Ok(mut e) => e.take(),
+        Err(e) => e.into_inner().take(),
+    };
+    if let Some(err) = write_err {
+        return Err(err);
+    }
+    let read = read.map_err(CopyError::Read)?;
+    Ok(read.iter().map(|(_, n)| n).sum())
+}
+
+// -----------------------------------------------------------------------------
+/// Copy `src` to `dst` in parallel, the parallel equivalent of
+/// `std::fs::copy`: chunks are read into buffers and written from the same
+/// buffers at the same offset, no data is copied in memory. Return the number
+/// of bytes copied.
+pub fn copy_file(src: &str, dst: &str, config: CopyConfig) -> Result<usize, CopyError> {
+    copy_files(&[(src, dst)], config)
+}
@@ -0,0 +1,163 @@
+//! Diagnostics recording the CPU each worker thread runs on, and CPU
+//! affinity of worker threads.
+//!
+//! Useful to verify that thread placement, e.g. CPU affinity, has the intended
+//! effect. The CPU is only known on Linux, where it is retrieved through
+//! `sched_getcpu`; it is reported as unknown (`None`) elsewhere.
+//!
+//! Setting `ReadOptions::affinity` or `WriteOptions::affinity` pins each
+//! producer and consumer thread to a single CPU when it starts, through
+//! `sched_setaffinity`, avoiding migrations across sockets on NUMA machines.
+//! Producers take CPUs from the start of the list of CPUs and consumers from
+//! the end, wrapping around when there are more threads than CPUs, so that
+//! producers and consumers do not share CPUs as long as there are enough of
+//! them. Pinning is only supported on Linux and is a no-op elsewhere; it is
+//! not applied to tasks run on a `ParIoPool` and failures are ignored.
+use std::sync::Mutex;
+
+#[cfg(target_os = "linux")]
+extern "C" {
+    fn sched_getcpu() -> i32;
+    fn sched_getaffinity(pid: i32, cpusetsize: usize, mask: *mut u64) -> i32;
+    fn sched_setaffinity(pid: i32, cpusetsize: usize, mask: *const u64) -> i32;
+}
+
+// CPU set of 1024 CPUs, same as the glibc cpu_set_t
+#[cfg(target_os = "linux")]
+const CPU_SET_WORDS: usize = 16;
+
+// -----------------------------------------------------------------------------
+/// Return the CPU the calling thread is running on, `None` if unknown.
+pub fn current_cpu() -> Option<usize> {
+    #[cfg(target_os = "linux")]
+    {
+        let cpu = unsafe { sched_getcpu() };
+        if cpu >= 0 {
+            return Some(cpu as usize);
+        }
+    }
+    None
+}
+
+/// CPU affinity policy for producer and consumer threads, see the module
+/// documentation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Affinity {
+    /// Spread threads across all the CPUs the process is allowed to run on.
+    Spread,
+    /// Spread threads across the listed CPUs.
+    Cores(Vec<usize>),
+}
+
+// -----------------------------------------------------------------------------
+/// Return the CPUs the calling thread is allowed to run on, empty if unknown.
+fn allowed_cpus() -> Vec<usize> {
+    #[cfg(target_os = "linux")]
+    {
+        let mut mask = [0_u64; CPU_SET_WORDS];
+        let r = unsafe { sched_getaffinity(0, std::mem::size_of_val(&mask), mask.as_mut_ptr()) };
+        if r == 0 {
+            return (0..CPU_SET_WORDS * 64)
+                .filter(|cpu| mask[cpu / 64] & (1 << (cpu % 64)) != 0)
+                .collect();
+        }
+    }
+    Vec::new()
+}
+
+/// Pin the calling thread to `cpu`, errors are ignored.
+fn pin_current_thread(cpu: usize) {
+    #[cfg(target_os = "linux")]
+    if cpu < CPU_SET_WORDS * 64 {
+        let mut mask = [0_u64; CPU_SET_WORDS];
+        mask[cpu / 64] |= 1 << (cpu % 64);
+        unsafe {
+            sched_setaffinity(0, std::mem::size_of_val(&mask), mask.as_ptr());
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = cpu;
+}
+
+// -----------------------------------------------------------------------------
+/// CPUs assigned to worker threads, computed once per operation from an
+/// `Affinity` policy.
+#[derive(Debug, Clone)]
+pub(crate) struct Placement {
+    cpus: Vec<usize>,
+}
+
+impl Placement {
+    /// Return `None` if no policy is set or no CPU is available.
+    pub fn new(affinity: Option<&Affinity>) -> Option<Self> {
+        let cpus = match affinity? {
+            Affinity::Spread => allowed_cpus(),
+            Affinity::Cores(cpus) => cpus.clone(),
+        };
+        if cpus.is_empty() {
+            None
+        } else {
+            Some(Placement { cpus })
+        }
+    }
+    /// Pin the calling thread to the CPU assigned to `worker`.
+    pub fn pin(&self, worker: Worker) {
+        let n = self.cpus.len();
+        let cpu = match worker {
+            Worker::Producer(i) => self.cpus[i as usize % n],
+            Worker::Consumer(i) => self.cpus[n - 1 - i as usize % n],
+        };
+        pin_current_thread(cpu);
+    }
+}
+
+/// Pin the calling thread if a placement is set.
+pub(crate) fn pin(placement: Option<&Placement>, worker: Worker) {
+    if let Some(p) = placement {
+        p.pin(worker);
+    }
+}
+
+/// Worker thread identifier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Worker {
+    Producer(u64),
+    Consumer(u64),
+}
+
+/// CPU observed on a worker thread; `None` if unknown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuSample {
+    pub worker: Worker,
+    pub cpu: Option<usize>,
+}
+
+// -----------------------------------------------------------------------------
+/// CPU samples recorded by worker threads when they start and before they exit.
+#[derive(Debug, Default)]
+pub struct CpuReport {
+    samples: Mutex<Vec<CpuSample>>,
+}
+
+impl CpuReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Return recorded samples.
+    pub fn samples(&self) -> Vec<CpuSample> {
+        match self.samples.lock() {
+            Ok(s) => s.clone(),
+            Err(err) => err.into_inner().clone(),
+        }
+    }
+    /// Record the CPU the calling thread is running on.
+    pub(crate) fn record(&self, worker: Worker) {
+        let sample = CpuSample {
+            worker,
+            cpu: current_cpu(),
+        };
+        if let Ok(mut s) = self.samples.lock() {
+            s.push(sample);
+        }
+    }
+}
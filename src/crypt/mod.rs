@@ -0,0 +1,471 @@
+//! Authenticated encryption of file data with ChaCha20-Poly1305 (RFC 8439).
+//!
+//! Data is split into records of `record_size` bytes, the last record being
+//! shorter when the data size is not a multiple of the record size. Each
+//! record is encrypted separately and stored followed by its 16 byte
+//! authentication tag:
+//!
+//! ```text
+//! | ciphertext 0 | tag 0 | ciphertext 1 | tag 1 | ... | ciphertext n-1 | tag n-1 |
+//! ```
+//!
+//! Record `k` is stored at file offset `k * (record_size + TAG_SIZE)` and
+//! holds the data at offset `k * record_size`; the file size is the data size
+//! plus `TAG_SIZE` bytes per record, see `encrypted_size`. Empty data are
+//! stored as a single empty record. Producer chunks contain whole records,
+//! offsets passed to callbacks are data offsets.
+//!
+//! The 96 bit nonce of each record is the 32 bit `file_id` followed by the
+//! 64 bit data offset of the record, both little-endian. The data size, 64 bit
+//! little-endian, is authenticated as additional data of every record: a file
+//! truncated or extended by whole records fails authentication. Nonces must
+//! never be reused with the same key: use a different key or `file_id` for
+//! each file and each version of a file.
+use crate::config::check_counts;
+use crate::lock::LockPolicy;
+use crate::read::{aligned_tasks, read_tasks, Consumer, ReadAt, ReadError, ReadOptions};
+use crate::write::{
+    aligned_ranges, create_file, write_ranges, ChunkProducer, OpenMode, Producer, WriteError,
+    WriteOptions,
+};
+use core::fmt::Debug;
+use std::fs::File;
+use std::sync::Arc;
+
+#[cfg(unix)]
+use crate::io::io_at_unix::*;
+
+#[cfg(windows)]
+use crate::io::io_at_windows::*;
+
+/// Size of the authentication tag stored after each record.
+pub const TAG_SIZE: u64 = 16;
+
+// -----------------------------------------------------------------------------
+// ChaCha20
+
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
+
+fn le32(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+}
+
+/// Return key stream block `counter`.
+fn chacha20_block(key: &[u8; 32], counter: u32, nonce: &[u8; 12]) -> [u8; 64] {
+    let mut init = [0_u32; 16];
+    init[..4].copy_from_slice(&[0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574]);
+    for i in 0..8 {
+        init[4 + i] = le32(&key[4 * i..]);
+    }
+    init[12] = counter;
+    for i in 0..3 {
+        init[13 + i] = le32(&nonce[4 * i..]);
+    }
+    let mut s = init;
+    for _ in 0..10 {
+        quarter_round(&mut s, 0, 4, 8, 12);
+        quarter_round(&mut s, 1, 5, 9, 13);
+        quarter_round(&mut s, 2, 6, 10, 14);
+        quarter_round(&mut s, 3, 7, 11, 15);
+        quarter_round(&mut s, 0, 5, 10, 15);
+        quarter_round(&mut s, 1, 6, 11, 12);
+        quarter_round(&mut s, 2, 7, 8, 13);
+        quarter_round(&mut s, 3, 4, 9, 14);
+    }
+    let mut out = [0_u8; 64];
+    for i in 0..16 {
+        out[4 * i..4 * i + 4].copy_from_slice(&s[i].wrapping_add(init[i]).to_le_bytes());
+    }
+    out
+}
+
+/// XOR `data` with the key stream starting at block `counter`.
+fn chacha20_xor(key: &[u8; 32], counter: u32, nonce: &[u8; 12], data: &mut [u8]) {
+    for (i, block) in data.chunks_mut(64).enumerate() {
+        let ks = chacha20_block(key, counter.wrapping_add(i as u32), nonce);
+        block.iter_mut().zip(ks.iter()).for_each(|(b, k)| *b ^= k);
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Poly1305, 26 bit limbs
+
+struct Poly1305 {
+    r: [u32; 5],
+    s: [u32; 4],
+    h: [u32; 5],
+}
+
+impl Poly1305 {
+    fn new(key: &[u8]) -> Self {
+        Poly1305 {
+            r: [
+                le32(&key[0..]) & 0x03ff_ffff,
+                (le32(&key[3..]) >> 2) & 0x03ff_ff03,
+                (le32(&key[6..]) >> 4) & 0x03ff_c0ff,
+                (le32(&key[9..]) >> 6) & 0x03f0_3fff,
+                (le32(&key[12..]) >> 8) & 0x000f_ffff,
+            ],
+            s: [
+                le32(&key[16..]),
+                le32(&key[20..]),
+                le32(&key[24..]),
+                le32(&key[28..]),
+            ],
+            h: [0; 5],
+        }
+    }
+    /// Process one full 16 byte block.
+    fn block(&mut self, m: &[u8]) {
+        let [r0, r1, r2, r3, r4] = self.r.map(u64::from);
+        let (s1, s2, s3, s4) = (r1 * 5, r2 * 5, r3 * 5, r4 * 5);
+        let h = &mut self.h;
+        let h0 = (h[0] + (le32(&m[0..]) & 0x03ff_ffff)) as u64;
+        let h1 = (h[1] + ((le32(&m[3..]) >> 2) & 0x03ff_ffff)) as u64;
+        let h2 = (h[2] + ((le32(&m[6..]) >> 4) & 0x03ff_ffff)) as u64;
+        let h3 = (h[3] + ((le32(&m[9..]) >> 6) & 0x03ff_ffff)) as u64;
+        let h4 = (h[4] + ((le32(&m[12..]) >> 8) | (1 << 24))) as u64;
+        let d0 = h0 * r0 + h1 * s4 + h2 * s3 + h3 * s2 + h4 * s1;
+        let mut d1 = h0 * r1 + h1 * r0 + h2 * s4 + h3 * s3 + h4 * s2;
+        let mut d2 = h0 * r2 + h1 * r1 + h2 * r0 + h3 * s4 + h4 * s3;
+        let mut d3 = h0 * r3 + h1 * r2 + h2 * r1 + h3 * r0 + h4 * s4;
+        let mut d4 = h0 * r4 + h1 * r3 + h2 * r2 + h3 * r1 + h4 * r0;
+        let mask = 0x03ff_ffff;
+        d1 += d0 >> 26;
+        h[0] = (d0 & mask) as u32;
+        d2 += d1 >> 26;
+        h[1] = (d1 & mask) as u32;
+        d3 += d2 >> 26;
+        h[2] = (d2 & mask) as u32;
+        d4 += d3 >> 26;
+        h[3] = (d3 & mask) as u32;
+        h[4] = (d4 & mask) as u32;
+        h[0] += (d4 >> 26) as u32 * 5;
+        h[1] += h[0] >> 26;
+        h[0] &= mask as u32;
+    }
+    /// Process `data` padded with zeros to a multiple of 16 bytes.
+    fn update_padded(&mut self, data: &[u8]) {
+        let mut blocks = data.chunks_exact(16);
+        for b in &mut blocks {
+            self.block(b);
+        }
+        let rest = blocks.remainder();
+        if !rest.is_empty() {
+            let mut last = [0_u8; 16];
+            last[..rest.len()].copy_from_slice(rest);
+            self.block(&last);
+        }
+    }
+    fn finish(self) -> [u8; 16] {
+        let mask = 0x03ff_ffff;
+        let mut h = self.h;
+        let mut c;
+        c = h[1] >> 26;
+        h[1] &= mask;
+        h[2] += c;
+        c = h[2] >> 26;
+        h[2] &= mask;
+        h[3] += c;
+        c = h[3] >> 26;
+        h[3] &= mask;
+        h[4] += c;
+        c = h[4] >> 26;
+        h[4] &= mask;
+        h[0] += c * 5;
+        c = h[0] >> 26;
+        h[0] &= mask;
+        h[1] += c;
+        // compute h - p and select it if non negative
+        let mut g = [0_u32; 5];
+        g[0] = h[0].wrapping_add(5);
+        c = g[0] >> 26;
+        g[0] &= mask;
+        for i in 1..4 {
+            g[i] = h[i].wrapping_add(c);
+            c = g[i] >> 26;
+            g[i] &= mask;
+        }
+        g[4] = h[4].wrapping_add(c).wrapping_sub(1 << 26);
+        let select = (g[4] >> 31).wrapping_sub(1);
+        for i in 0..5 {
+            h[i] = (h[i] & !select) | (g[i] & select);
+        }
+        let h0 = h[0] | (h[1] << 26);
+        let h1 = (h[1] >> 6) | (h[2] << 20);
+        let h2 = (h[2] >> 12) | (h[3] << 14);
+        let h3 = (h[3] >> 18) | (h[4] << 8);
+        let mut out = [0_u8; 16];
+        let mut f = 0_u64;
+        for (i, v) in [h0, h1, h2, h3].iter().enumerate() {
+            f = *v as u64 + self.s[i] as u64 + (f >> 32);
+            out[4 * i..4 * i + 4].copy_from_slice(&(f as u32).to_le_bytes());
+        }
+        out
+    }
+}
+
+/// Authentication tag of `aad` and `ciphertext`.
+fn tag(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], ciphertext: &[u8]) -> [u8; 16] {
+    let otk = chacha20_block(key, 0, nonce);
+    let mut mac = Poly1305::new(&otk[..32]);
+    mac.update_padded(aad);
+    mac.update_padded(ciphertext);
+    let mut lengths = [0_u8; 16];
+    lengths[..8].copy_from_slice(&(aad.len() as u64).to_le_bytes());
+    lengths[8..].copy_from_slice(&(ciphertext.len() as u64).to_le_bytes());
+    mac.block(&lengths);
+    mac.finish()
+}
+
+// -----------------------------------------------------------------------------
+/// Encrypt `data` in place and return the authentication tag of `aad` and the
+/// encrypted data (RFC 8439 AEAD_CHACHA20_POLY1305).
+pub fn encrypt(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], data: &mut [u8]) -> [u8; 16] {
+    chacha20_xor(key, 1, nonce, data);
+    tag(key, nonce, aad, data)
+}
+
+// -----------------------------------------------------------------------------
+/// Verify `tag` and decrypt `data` in place; return `false`, leaving `data`
+/// untouched, if authentication fails.
+pub fn decrypt(
+    key: &[u8; 32],
+    nonce: &[u8; 12],
+    aad: &[u8],
+    data: &mut [u8],
+    tag: &[u8; 16],
+) -> bool {
+    let expected = self::tag(key, nonce, aad, data);
+    // constant time comparison
+    if expected.iter().zip(tag).fold(0, |d, (a, b)| d | (a ^ b)) != 0 {
+        return false;
+    }
+    chacha20_xor(key, 1, nonce, data);
+    true
+}
+
+/// Nonce of the record at data offset `offset`.
+fn record_nonce(file_id: u32, offset: u64) -> [u8; 12] {
+    let mut nonce = [0_u8; 12];
+    nonce[..4].copy_from_slice(&file_id.to_le_bytes());
+    nonce[4..].copy_from_slice(&offset.to_le_bytes());
+    nonce
+}
+
+// -----------------------------------------------------------------------------
+/// Size of the encrypted file holding `data_size` bytes of data.
+pub fn encrypted_size(data_size: u64, record_size: u64) -> u64 {
+    data_size + ((data_size + record_size - 1) / record_size).max(1) * TAG_SIZE
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `write_to_file` but data are encrypted, see the module
+/// documentation for the file layout; chunks contain whole records.
+/// Returns the number of bytes written to file, including tags.
+#[allow(clippy::too_many_arguments)]
+pub fn write_encrypted<T: 'static + Clone + Send, E: 'static + Send + Debug>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    producer: Arc<Producer<T, E>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    total_size: usize,
+    record_size: u64,
+    key: [u8; 32],
+    file_id: u32,
+) -> Result<usize, WriteError> {
+    check_counts(
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        num_buffers_per_producer,
+    )
+    .map_err(WriteError::Other)?;
+    if record_size == 0 {
+        return Err(WriteError::Other(
+            "Record size must be positive".to_string(),
+        ));
+    }
+    let stored_size = encrypted_size(total_size as u64, record_size);
+    let stored_record_size = record_size + TAG_SIZE;
+    let ranges = aligned_ranges(
+        stored_size,
+        num_producers,
+        chunks_per_producer,
+        stored_record_size,
+    );
+    let aad = (total_size as u64).to_le_bytes();
+    let seal: Arc<ChunkProducer<T, E>> =
+        Arc::new(move |buffer: &mut Vec<u8>, data: &T, offset: u64| {
+            let first = offset / stored_record_size;
+            let num_records = (buffer.len() as u64 + stored_record_size - 1) / stored_record_size;
+            let mut plain = vec![0_u8; (buffer.len() as u64 - num_records * TAG_SIZE) as usize];
+            // no data to generate for the single record of empty data
+            if !plain.is_empty() {
+                producer(&mut plain, data, first * record_size)?;
+            }
+            for k in 0..num_records as usize {
+                let begin = k * record_size as usize;
+                let p = &plain[begin..(begin + record_size as usize).min(plain.len())];
+                let b = k * stored_record_size as usize;
+                let record = &mut buffer[b..b + p.len() + TAG_SIZE as usize];
+                record[..p.len()].copy_from_slice(p);
+                let nonce = record_nonce(file_id, (first + k as u64) * record_size);
+                let t = encrypt(&key, &nonce, &aad, &mut record[..p.len()]);
+                record[p.len()..].copy_from_slice(&t);
+            }
+            Ok(None)
+        });
+    let file = create_file(
+        filename,
+        stored_size,
+        OpenMode::Truncate,
+        LockPolicy::NoLock,
+    )?;
+    write_ranges(
+        &file,
+        ranges,
+        num_consumers,
+        seal,
+        client_data,
+        num_buffers_per_producer,
+        stored_size as usize,
+        &WriteOptions::default(),
+    )
+    .map(|r| r.bytes_written)
+}
+
+// -----------------------------------------------------------------------------
+/// Encrypted file, records are authenticated and decrypted in place when read.
+struct Decrypt {
+    file: File,
+    key: [u8; 32],
+    file_id: u32,
+    record_size: u64,
+    // data size
+    aad: [u8; 8],
+}
+
+impl ReadAt for Decrypt {
+    fn read_at(&self, buffer: &mut Vec<u8>, offset: u64) -> Result<(), ReadError> {
+        read_bytes_at(buffer, &self.file, offset)?;
+        let stored_record_size = (self.record_size + TAG_SIZE) as usize;
+        let first = offset / stored_record_size as u64;
+        for (k, record) in buffer.chunks_mut(stored_record_size).enumerate() {
+            let data_offset = (first + k as u64) * self.record_size;
+            let (data, t) = record.split_at_mut(record.len() - TAG_SIZE as usize);
+            let mut tag = [0_u8; 16];
+            tag.copy_from_slice(t);
+            let nonce = record_nonce(self.file_id, data_offset);
+            if !decrypt(&self.key, &nonce, &self.aad, data, &tag) {
+                return Err(ReadError::Authentication {
+                    offset: data_offset,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Read file written by `write_encrypted`: records are authenticated and
+/// decrypted by producers, consumers receive the data without tags and data
+/// offsets.
+///
+/// Returns `ReadError::Authentication` with the data offset of the first
+/// record failing authentication found, e.g. because it was modified, read
+/// with the wrong key or the file was truncated.
+#[allow(clippy::too_many_arguments)]
+pub fn read_encrypted<T: 'static + Clone + Send, R: 'static + Clone + Sync + Send>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    consumer: Arc<Consumer<T, R>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    record_size: u64,
+    key: [u8; 32],
+    file_id: u32,
+) -> Result<Vec<(u64, R)>, ReadError> {
+    check_counts(
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        num_buffers_per_producer,
+    )
+    .map_err(ReadError::Other)?;
+    if record_size == 0 {
+        return Err(ReadError::Other("Record size must be positive".to_string()));
+    }
+    let stored_size = std::fs::metadata(filename).map_err(ReadError::IO)?.len();
+    if stored_size == 0 {
+        // even empty data are stored as one record
+        return Err(ReadError::Authentication { offset: 0 });
+    }
+    let stored_record_size = record_size + TAG_SIZE;
+    let last = stored_size % stored_record_size;
+    // only the record of empty data holds no data
+    if (last != 0 && last < TAG_SIZE) || (last == TAG_SIZE && stored_size != TAG_SIZE) {
+        return Err(ReadError::Other(format!(
+            "Invalid encrypted file size {}",
+            stored_size
+        )));
+    }
+    let num_records = (stored_size + stored_record_size - 1) / stored_record_size;
+    let data_size = stored_size - num_records * TAG_SIZE;
+    let source = Decrypt {
+        file: File::open(filename).map_err(ReadError::IO)?,
+        key,
+        file_id,
+        record_size,
+        aad: data_size.to_le_bytes(),
+    };
+    if data_size == 0 {
+        // authenticate the empty record, no chunk to pass to the callback
+        source.read_at(&mut vec![0_u8; TAG_SIZE as usize], 0)?;
+        return Ok(Vec::new());
+    }
+    let tasks = aligned_tasks(
+        stored_size,
+        num_producers,
+        chunks_per_producer,
+        stored_record_size,
+    );
+    let open: Arc<Consumer<T, R>> = Arc::new(
+        move |buffer: &[u8], data: &T, chunk_id, num_chunks, offset| {
+            let plain: Vec<u8> = buffer
+                .chunks(stored_record_size as usize)
+                .flat_map(|r| &r[..r.len() - TAG_SIZE as usize])
+                .copied()
+                .collect();
+            let data_offset = offset / stored_record_size * record_size;
+            consumer(&plain, data, chunk_id, num_chunks, data_offset)
+        },
+    );
+    read_tasks(
+        filename,
+        tasks,
+        num_producers * chunks_per_producer,
+        num_consumers,
+        open,
+        client_data,
+        num_buffers_per_producer,
+        &ReadOptions {
+            source: Some(Arc::new(source)),
+            ..Default::default()
+        },
+    )
+}
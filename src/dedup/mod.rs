@@ -0,0 +1,198 @@
+//! Deduplication of identical chunks when writing.
+//!
+//! Consumers hash each chunk and look the hash up in a map shared by all
+//! consumers: when a chunk with the same content was already written, the
+//! chunk is not written again and a reference to the previously written chunk
+//! is recorded instead. Skipped regions are left as holes in the file, which
+//! take no space on filesystems supporting sparse files.
+//!
+//! References are returned in a `DedupMap`, which is required to read the file
+//! back through `read_file_dedup`.
+use crate::read::{read_file_with_options, Consumer, ReadAt, ReadError, ReadOptions};
+use crate::write::{
+    open_data_file, whole_chunks, write_chunks, Producer, WriteError, WriteOptions,
+};
+use core::fmt::Debug;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+#[cfg(unix)]
+use crate::io::io_at_unix::*;
+
+#[cfg(windows)]
+use crate::io::io_at_windows::*;
+
+/// Chunk not written to file because identical to the chunk at `source`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DedupRef {
+    /// File offset of the chunk.
+    pub offset: u64,
+    /// Chunk size.
+    pub size: u64,
+    /// File offset of the chunk with the same content.
+    pub source: u64,
+}
+
+/// References to deduplicated chunks, sorted by offset.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DedupMap {
+    pub refs: Vec<DedupRef>,
+}
+
+// -----------------------------------------------------------------------------
+/// State shared by consumers.
+pub(crate) struct Dedup {
+    // content hash -> (offset, size) of written chunk
+    written: Mutex<HashMap<u64, (u64, u64)>>,
+    refs: Mutex<Vec<DedupRef>>,
+    // used to compare chunks with matching hashes
+    reader: File,
+}
+
+impl Dedup {
+    /// Write `buffer` to `file` at `offset` unless a chunk with the same
+    /// content was already written; return the number of bytes written.
+    pub(crate) fn write(&self, buffer: &[u8], file: &File, offset: u64) -> Result<u64, WriteError> {
+        let mut hasher = DefaultHasher::new();
+        buffer.hash(&mut hasher);
+        let hash = hasher.finish();
+        let found = match self.written.lock() {
+            Ok(w) => w.get(&hash).copied(),
+            Err(err) => err.into_inner().get(&hash).copied(),
+        };
+        if let Some((source, size)) = found {
+            if size == buffer.len() as u64 {
+                // hashes can collide, compare content
+                let mut prev = vec![0_u8; buffer.len()];
+                read_bytes_at(&mut prev, &self.reader, source)
+                    .map_err(|err| WriteError::Other(format!("{:?}", err)))?;
+                if prev == buffer {
+                    self.lock_refs().push(DedupRef {
+                        offset,
+                        size,
+                        source,
+                    });
+                    return Ok(0);
+                }
+            }
+        }
+        write_bytes_at(buffer, file, offset)?;
+        // only chunks already written can be referenced
+        match self.written.lock() {
+            Ok(mut w) => w.entry(hash).or_insert((offset, buffer.len() as u64)),
+            Err(err) => err
+                .into_inner()
+                .entry(hash)
+                .or_insert((offset, buffer.len() as u64)),
+        };
+        Ok(buffer.len() as u64)
+    }
+    fn lock_refs(&self) -> std::sync::MutexGuard<'_, Vec<DedupRef>> {
+        match self.refs.lock() {
+            Ok(r) => r,
+            Err(err) => err.into_inner(),
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `write_to_file_with_options` but chunks identical to a chunk
+/// already written are not written again.
+///
+/// Returns the number of bytes actually written and the references to
+/// deduplicated chunks; use `read_file_dedup` to read the file back.
+/// Only chunks with the same size and content are deduplicated, the chunk
+/// layout is the one used by `write_to_file`.
+#[allow(clippy::too_many_arguments)]
+pub fn write_to_file_dedup<T: 'static + Clone + Send, E: 'static + Send + Debug>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    producer: Arc<Producer<T, E>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    total_size: usize,
+    mut options: WriteOptions,
+) -> Result<(usize, DedupMap), WriteError> {
+    let file = open_data_file(filename, total_size as u64, &mut options)?;
+    let dedup = Arc::new(Dedup {
+        written: Mutex::new(HashMap::new()),
+        refs: Mutex::new(Vec::new()),
+        reader: File::open(filename).map_err(WriteError::IO)?,
+    });
+    let written = write_chunks(
+        &file,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        whole_chunks(producer),
+        client_data,
+        num_buffers_per_producer,
+        total_size,
+        None,
+        Some(dedup.clone()),
+        &options,
+    )?;
+    let mut refs = dedup.lock_refs().clone();
+    refs.sort_by_key(|r| r.offset);
+    Ok((written.bytes_written, DedupMap { refs }))
+}
+
+// -----------------------------------------------------------------------------
+/// File written by `write_to_file_dedup`, resolving references when reading.
+struct DedupSource {
+    file: File,
+    map: Arc<DedupMap>,
+}
+
+impl ReadAt for DedupSource {
+    fn read_at(&self, buffer: &mut Vec<u8>, offset: u64) -> Result<(), ReadError> {
+        read_bytes_at(buffer, &self.file, offset)?;
+        let end = offset + buffer.len() as u64;
+        let refs = &self.map.refs;
+        let first = refs.partition_point(|r| r.offset + r.size <= offset);
+        let mut tmp = Vec::new();
+        for r in refs[first..].iter().take_while(|r| r.offset < end) {
+            let b = r.offset.max(offset);
+            let e = (r.offset + r.size).min(end);
+            tmp.resize((e - b) as usize, 0);
+            read_bytes_at(&mut tmp, &self.file, r.source + b - r.offset)?;
+            buffer[(b - offset) as usize..(e - offset) as usize].copy_from_slice(&tmp);
+        }
+        Ok(())
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `read_file` for files written by `write_to_file_dedup`:
+/// deduplicated chunks are read from the referenced location.
+#[allow(clippy::too_many_arguments)]
+pub fn read_file_dedup<T: 'static + Clone + Send, R: 'static + Clone + Sync + Send>(
+    filename: &str,
+    map: Arc<DedupMap>,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    consumer: Arc<Consumer<T, R>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+) -> Result<Vec<(u64, R)>, ReadError> {
+    let file = File::open(filename).map_err(ReadError::IO)?;
+    read_file_with_options(
+        filename,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        consumer,
+        client_data,
+        num_buffers_per_producer,
+        ReadOptions {
+            source: Some(Arc::new(DedupSource { file, map })),
+            ..Default::default()
+        },
+    )
+}
@@ -0,0 +1,147 @@
+//! Parallel comparison of two files.
+//!
+//! The first file is read through the read pipeline, consumers read the
+//! corresponding chunk of the second file and compare the two. Buffers
+//! holding data of the second file are recycled, at most one per consumer
+//! thread is allocated.
+use crate::read::{producer_tasks, read_tasks, Consumer, ReadError, ReadOptions};
+use std::fs::File;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+#[cfg(unix)]
+use crate::io::io_at_unix::*;
+
+#[cfg(windows)]
+use crate::io::io_at_windows::*;
+
+/// Result of file comparison.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Diff {
+    /// Files have the same content.
+    Equal,
+    /// Files have different sizes, content is not compared.
+    SizeMismatch { a: u64, b: u64 },
+    /// Ranges of differing bytes, sorted and non-overlapping; adjacent
+    /// differing bytes are merged into a single range.
+    Different(Vec<Range<u64>>),
+}
+
+impl Diff {
+    /// Return the offset of the first differing byte, `None` if the files
+    /// are equal or have different sizes.
+    pub fn first_difference(&self) -> Option<u64> {
+        match self {
+            Diff::Different(ranges) => ranges.first().map(|r| r.start),
+            _ => None,
+        }
+    }
+}
+
+// differing ranges in chunk, or error reading the second file
+type ChunkDiff = Result<Vec<Range<u64>>, String>;
+
+/// Second file and buffers not in use by consumers.
+struct Other {
+    file: File,
+    buffers: Mutex<Vec<Vec<u8>>>,
+}
+
+impl Other {
+    /// Take a buffer from the pool, allocating one if none is available.
+    fn take(&self) -> Vec<u8> {
+        match self.buffers.lock() {
+            Ok(mut b) => b.pop(),
+            Err(err) => err.into_inner().pop(),
+        }
+        .unwrap_or_default()
+    }
+    /// Return buffer to the pool.
+    fn put(&self, buffer: Vec<u8>) {
+        match self.buffers.lock() {
+            Ok(mut b) => b.push(buffer),
+            Err(err) => err.into_inner().push(buffer),
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Compare files `a` and `b` in parallel.
+///
+/// Files of different sizes are reported as `Diff::SizeMismatch` without
+/// reading any data.
+pub fn diff_files(
+    a: &str,
+    b: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    num_buffers_per_producer: u64,
+) -> Result<Diff, ReadError> {
+    let size_a = std::fs::metadata(a).map_err(ReadError::IO)?.len();
+    let size_b = std::fs::metadata(b).map_err(ReadError::IO)?.len();
+    if size_a != size_b {
+        return Ok(Diff::SizeMismatch {
+            a: size_a,
+            b: size_b,
+        });
+    }
+    let file_b = Arc::new(Other {
+        file: File::open(b).map_err(ReadError::IO)?,
+        buffers: Mutex::new(Vec::new()),
+    });
+    let compare: Arc<Consumer<Arc<Other>, ChunkDiff>> = Arc::new(
+        |buffer: &[u8], file_b: &Arc<Other>, _chunk_id, _num_chunks, offset| {
+            let mut other = file_b.take();
+            // only the bytes added are zeroed, the read overwrites the chunk
+            other.resize(buffer.len(), 0);
+            let diff = read_bytes_at(&mut other, &file_b.file, offset)
+                .map(|_| differing_ranges(buffer, &other, offset))
+                .map_err(|err| format!("{:?}", err));
+            file_b.put(other);
+            diff
+        },
+    );
+    let tasks = producer_tasks(size_a, num_producers, chunks_per_producer);
+    let chunks = read_tasks(
+        a,
+        tasks,
+        num_producers * chunks_per_producer,
+        num_consumers,
+        compare,
+        file_b,
+        num_buffers_per_producer,
+        &ReadOptions::default(),
+    )?;
+    let mut ranges = Vec::new();
+    for (_, r) in chunks {
+        ranges.extend(r.map_err(ReadError::Other)?);
+    }
+    ranges.sort_by_key(|r| r.start);
+    // merge ranges across chunk boundaries
+    let mut merged: Vec<Range<u64>> = Vec::new();
+    for r in ranges {
+        match merged.last_mut() {
+            Some(last) if last.end == r.start => last.end = r.end,
+            _ => merged.push(r),
+        }
+    }
+    Ok(if merged.is_empty() {
+        Diff::Equal
+    } else {
+        Diff::Different(merged)
+    })
+}
+
+/// Return ranges of differing bytes, `offset` is the file offset of the data.
+fn differing_ranges(a: &[u8], b: &[u8], offset: u64) -> Vec<Range<u64>> {
+    let mut ranges: Vec<Range<u64>> = Vec::new();
+    for (i, _) in a.iter().zip(b).enumerate().filter(|(_, (x, y))| x != y) {
+        let pos = offset + i as u64;
+        match ranges.last_mut() {
+            Some(last) if last.end == pos => last.end = pos + 1,
+            _ => ranges.push(pos..pos + 1),
+        }
+    }
+    ranges
+}
@@ -0,0 +1,119 @@
+//! Overwrite the content of existing files in parallel, e.g. before deleting
+//! them.
+//!
+//! The file size is not changed. Each pass writes the whole file through the
+//! regular write machinery, producers filling buffers with the pass pattern,
+//! and is flushed to disk before the next pass starts.
+//!
+//! Overwriting is best-effort erasure only: journaling and copy-on-write
+//! filesystems, SSD wear levelling and backups can keep copies of the
+//! original data.
+use crate::write::{write_chunks, ChunkProducer, WriteError, WriteOptions};
+use std::collections::hash_map::RandomState;
+use std::fs::File;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+
+/// Data written by an erase pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pass {
+    /// All bits cleared.
+    Zeros,
+    /// All bits set.
+    Ones,
+    /// Repeated byte.
+    Pattern(u8),
+    /// Pseudo-random bytes, different for each pass and each call.
+    Random,
+}
+
+// -----------------------------------------------------------------------------
+/// Overwrite the whole file with zeros; returns the number of bytes written.
+pub fn zero_file(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    num_buffers_per_producer: u64,
+) -> Result<usize, WriteError> {
+    erase_file(
+        filename,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        num_buffers_per_producer,
+        &[Pass::Zeros],
+    )
+    .map(|written| written[0])
+}
+
+// -----------------------------------------------------------------------------
+/// Overwrite the whole file once per element of `passes`, in order; returns
+/// the number of bytes written by each pass.
+///
+/// A common multi-pass sequence is `[Pass::Zeros, Pass::Ones, Pass::Random]`.
+pub fn erase_file(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    num_buffers_per_producer: u64,
+    passes: &[Pass],
+) -> Result<Vec<usize>, WriteError> {
+    let file = File::options()
+        .write(true)
+        .open(filename)
+        .map_err(WriteError::IO)?;
+    let total_size = file.metadata().map_err(WriteError::IO)?.len() as usize;
+    let options = WriteOptions::default();
+    let mut written = Vec::with_capacity(passes.len());
+    for pass in passes {
+        let fill: Arc<ChunkProducer<u64, String>> = match *pass {
+            Pass::Zeros => pattern(0),
+            Pass::Ones => pattern(0xFF),
+            Pass::Pattern(b) => pattern(b),
+            Pass::Random => Arc::new(|buffer: &mut Vec<u8>, seed: &u64, offset: u64| {
+                random_fill(buffer, *seed ^ offset);
+                Ok(None)
+            }),
+        };
+        let seed = RandomState::new().build_hasher().finish();
+        let report = write_chunks(
+            &file,
+            num_producers,
+            num_consumers,
+            chunks_per_producer,
+            fill,
+            seed,
+            num_buffers_per_producer,
+            total_size,
+            None,
+            None,
+            &options,
+        )?;
+        file.sync_data().map_err(WriteError::IO)?;
+        written.push(report.bytes_written);
+    }
+    Ok(written)
+}
+
+/// Producer filling buffers with `byte`.
+fn pattern(byte: u8) -> Arc<ChunkProducer<u64, String>> {
+    Arc::new(move |buffer: &mut Vec<u8>, _seed: &u64, _offset: u64| {
+        buffer.iter_mut().for_each(|b| *b = byte);
+        Ok(None)
+    })
+}
+
+/// Fill buffer with xorshift64* output, not suitable for cryptography.
+fn random_fill(buffer: &mut [u8], seed: u64) {
+    // state must be non zero
+    let mut state = seed | 1;
+    for bytes in buffer.chunks_mut(8) {
+        state ^= state >> 12;
+        state ^= state << 25;
+        state ^= state >> 27;
+        let r = state.wrapping_mul(0x2545_F491_4F6C_DD1D).to_le_bytes();
+        bytes.copy_from_slice(&r[..bytes.len()]);
+    }
+}
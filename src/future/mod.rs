@@ -0,0 +1,152 @@
+//! Futures resolving to the result of read and write operations, for use
+//! from async code.
+//!
+//! `read_file_async` and `write_to_file_async` start the operation on
+//! background threads, like `read::spawn_read` and `write::spawn_write`, and
+//! return immediately a future resolving to the same result as the blocking
+//! functions. The I/O itself remains thread based: producers and consumers
+//! run on their own threads and callbacks are plain, non async, functions;
+//! awaiting the future never blocks the executor. The futures do not depend on
+//! a specific runtime and can be awaited from `tokio`, `async-std` or any
+//! other executor.
+//!
+//! Dropping a future does not stop the operation, which runs to completion
+//! in the background; use `ReadOptions::cancel` or `WriteOptions::cancel` to
+//! stop it.
+use core::fmt::Debug;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use crate::read::{spawn_read, Consumer, ReadError, ReadOptions};
+use crate::worker;
+use crate::write::{spawn_write, Producer, WriteError, WriteOptions};
+
+/// Future resolving to the result of `read_file_async`.
+pub type ReadFuture<R> = IoFuture<Result<Vec<(u64, R)>, ReadError>>;
+/// Future resolving to the result of `write_to_file_async`.
+pub type WriteFuture = IoFuture<Result<usize, WriteError>>;
+
+// result of the operation and waker of the task awaiting it
+struct Shared<T> {
+    result: Option<T>,
+    waker: Option<Waker>,
+}
+
+// -----------------------------------------------------------------------------
+/// Future resolving to the result of an operation running on a background
+/// thread.
+pub struct IoFuture<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+impl<T: Send + 'static> IoFuture<T> {
+    /// Run `f` on a new thread and return a future resolving to its result;
+    /// panics are converted to a result through `on_panic`.
+    fn spawn<F, P>(f: F, on_panic: P) -> std::io::Result<Self>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        P: FnOnce(String) -> T + Send + 'static,
+    {
+        let shared = Arc::new(Mutex::new(Shared {
+            result: None,
+            waker: None,
+        }));
+        let completed = shared.clone();
+        worker::spawn("par_io-future".to_string(), None, move || {
+            let r = match panic::catch_unwind(AssertUnwindSafe(f)) {
+                Ok(r) => r,
+                Err(payload) => on_panic(worker::panic_message(&*payload)),
+            };
+            let waker = {
+                let mut s = match completed.lock() {
+                    Ok(s) => s,
+                    Err(err) => err.into_inner(),
+                };
+                s.result = Some(r);
+                s.waker.take()
+            };
+            if let Some(w) = waker {
+                w.wake();
+            }
+        })?;
+        Ok(IoFuture { shared })
+    }
+}
+
+impl<T> Future for IoFuture<T> {
+    type Output = T;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut s = match self.shared.lock() {
+            Ok(s) => s,
+            Err(err) => err.into_inner(),
+        };
+        match s.result.take() {
+            Some(r) => Poll::Ready(r),
+            None => {
+                s.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `read::read_file_with_options` but returns immediately a future
+/// resolving to the result of the read, see the module documentation.
+#[allow(clippy::too_many_arguments)]
+pub fn read_file_async<T: 'static + Clone + Send, R: 'static + Clone + Sync + Send>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    consumer: Arc<Consumer<T, R>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    options: ReadOptions,
+) -> Result<ReadFuture<R>, ReadError> {
+    let handle = spawn_read(
+        filename,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        consumer,
+        client_data,
+        num_buffers_per_producer,
+        options,
+    )?;
+    IoFuture::spawn(move || handle.join(), |msg| Err(ReadError::Other(msg)))
+        .map_err(|err| ReadError::Other(format!("Cannot spawn thread - {}", err)))
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `write::write_to_file_with_options` but returns immediately a
+/// future resolving to the result of the write, see the module documentation.
+#[allow(clippy::too_many_arguments)]
+pub fn write_to_file_async<T: 'static + Clone + Send, E: 'static + Send + Debug>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    producer: Arc<Producer<T, E>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    total_size: usize,
+    options: WriteOptions,
+) -> Result<WriteFuture, WriteError> {
+    let handle = spawn_write(
+        filename,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        producer,
+        client_data,
+        num_buffers_per_producer,
+        total_size,
+        options,
+    )?;
+    IoFuture::spawn(move || handle.join(), |msg| Err(WriteError::Other(msg)))
+        .map_err(|err| WriteError::Other(format!("Cannot spawn thread - {}", err)))
+}
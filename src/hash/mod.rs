@@ -0,0 +1,365 @@
+//! Checksums computed while reading, without a second pass over the file.
+//!
+//! Chunks are consumed out of order by multiple threads, the way the digest
+//! of the whole file is computed depends on the algorithm:
+//!
+//! * CRC32C (Castagnoli) supports parallel combination: consumers compute the
+//!   checksum of each chunk and the checksums are combined in offset order
+//!   when the read completes, knowing only the chunk sizes; the extra memory
+//!   is a few bytes per chunk.
+//! * SHA-256 cannot be combined from the digests of the chunks: consumers
+//!   send a copy of each chunk to a hashing thread which hashes the data in
+//!   offset order, keeping the chunks received out of order in memory until
+//!   all the preceding data has been hashed; in the worst case, e.g. when the
+//!   first producer is slower than the others, most of the file is kept in
+//!   memory. Hashing runs concurrently with reading but is sequential.
+//!
+//! The digest covers the data read, i.e. the whole file unless
+//! `ReadOptions::skip_header` is set.
+use crate::read::{read_file_with_options, Consumer, ReadError, ReadOptions};
+use std::collections::BTreeMap;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// (offset, data) sent to the hashing thread
+type Chunk = (u64, Vec<u8>);
+
+/// Checksum algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    /// CRC-32C (Castagnoli), combined in parallel.
+    Crc32c,
+    /// SHA-256, hashed sequentially in offset order.
+    Sha256,
+}
+
+/// Checksum of the data read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Digest {
+    Crc32c(u32),
+    Sha256([u8; 32]),
+}
+
+// -----------------------------------------------------------------------------
+// CRC-32C
+
+// reversed Castagnoli polynomial
+const CRC32C_POLY: u32 = 0x82F6_3B78;
+
+const fn crc32c_table() -> [u32; 256] {
+    let mut table = [0_u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ CRC32C_POLY
+            } else {
+                crc >> 1
+            };
+            k += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static CRC32C_TABLE: [u32; 256] = crc32c_table();
+
+/// Return the CRC-32C of `data`.
+pub fn crc32c(data: &[u8]) -> u32 {
+    !data.iter().fold(!0_u32, |crc, b| {
+        CRC32C_TABLE[((crc ^ *b as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+fn gf2_matrix_times(mat: &[u32; 32], mut vec: u32) -> u32 {
+    let mut sum = 0;
+    let mut i = 0;
+    while vec != 0 {
+        if vec & 1 != 0 {
+            sum ^= mat[i];
+        }
+        vec >>= 1;
+        i += 1;
+    }
+    sum
+}
+
+fn gf2_matrix_square(square: &mut [u32; 32], mat: &[u32; 32]) {
+    for n in 0..32 {
+        square[n] = gf2_matrix_times(mat, mat[n]);
+    }
+}
+
+/// Return the CRC-32C of the concatenation of two blocks of data given the
+/// checksum of each block and the length of the second one.
+pub fn crc32c_combine(mut crc1: u32, crc2: u32, mut len2: u64) -> u32 {
+    if len2 == 0 {
+        return crc1;
+    }
+    // operators appending one, two, four... zero bits
+    let mut even = [0_u32; 32];
+    let mut odd = [0_u32; 32];
+    odd[0] = CRC32C_POLY;
+    for (n, o) in odd.iter_mut().enumerate().skip(1) {
+        *o = 1 << (n - 1);
+    }
+    gf2_matrix_square(&mut even, &odd);
+    gf2_matrix_square(&mut odd, &even);
+    // apply len2 zero bytes to crc1
+    loop {
+        gf2_matrix_square(&mut even, &odd);
+        if len2 & 1 != 0 {
+            crc1 = gf2_matrix_times(&even, crc1);
+        }
+        len2 >>= 1;
+        if len2 == 0 {
+            break;
+        }
+        gf2_matrix_square(&mut odd, &even);
+        if len2 & 1 != 0 {
+            crc1 = gf2_matrix_times(&odd, crc1);
+        }
+        len2 >>= 1;
+        if len2 == 0 {
+            break;
+        }
+    }
+    crc1 ^ crc2
+}
+
+// -----------------------------------------------------------------------------
+// SHA-256
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Incremental SHA-256 (FIPS 180-4).
+#[derive(Clone)]
+pub struct Sha256 {
+    state: [u32; 8],
+    block: [u8; 64],
+    block_len: usize,
+    total_len: u64,
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Sha256 {
+            state: [
+                0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+                0x5be0cd19,
+            ],
+            block: [0; 64],
+            block_len: 0,
+            total_len: 0,
+        }
+    }
+}
+
+impl Sha256 {
+    /// Hash `data`.
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.total_len += data.len() as u64;
+        while !data.is_empty() {
+            let n = (64 - self.block_len).min(data.len());
+            self.block[self.block_len..self.block_len + n].copy_from_slice(&data[..n]);
+            self.block_len += n;
+            data = &data[n..];
+            if self.block_len == 64 {
+                let block = self.block;
+                self.compress(&block);
+                self.block_len = 0;
+            }
+        }
+    }
+
+    /// Return the digest of the data hashed.
+    pub fn finalize(mut self) -> [u8; 32] {
+        let bits = self.total_len.wrapping_mul(8);
+        let mut padding = vec![0x80_u8];
+        padding.resize(1 + (119 - self.block_len) % 64, 0);
+        padding.extend_from_slice(&bits.to_be_bytes());
+        self.update(&padding);
+        let mut digest = [0_u8; 32];
+        for (d, s) in digest.chunks_exact_mut(4).zip(self.state) {
+            d.copy_from_slice(&s.to_be_bytes());
+        }
+        digest
+    }
+
+    fn compress(&mut self, block: &[u8; 64]) {
+        let mut w = [0_u32; 64];
+        for (i, b) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([b[0], b[1], b[2], b[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(SHA256_K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (s, v) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+}
+
+/// Return the digest of `data`.
+pub fn digest(algorithm: HashAlgorithm, data: &[u8]) -> Digest {
+    match algorithm {
+        HashAlgorithm::Crc32c => Digest::Crc32c(crc32c(data)),
+        HashAlgorithm::Sha256 => {
+            let mut h = Sha256::default();
+            h.update(data);
+            Digest::Sha256(h.finalize())
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `read::read_file_with_options`, also returning the digest of the
+/// data read computed with `algorithm`, see the module documentation.
+#[allow(clippy::too_many_arguments)]
+pub fn read_file_hashed<T: 'static + Clone + Send, R: 'static + Clone + Sync + Send>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    consumer: Arc<Consumer<T, R>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    options: ReadOptions,
+    algorithm: HashAlgorithm,
+) -> Result<(Vec<(u64, R)>, Digest), ReadError> {
+    let file_size = std::fs::metadata(filename).map_err(ReadError::IO)?.len();
+    let start = options.skip_header.min(file_size);
+    let read = |consumer: Arc<Consumer<T, R>>| {
+        read_file_with_options(
+            filename,
+            num_producers,
+            num_consumers,
+            chunks_per_producer,
+            consumer,
+            client_data,
+            num_buffers_per_producer,
+            options,
+        )
+    };
+    match algorithm {
+        HashAlgorithm::Crc32c => {
+            // (offset, size, checksum) of each chunk
+            let checksums = Arc::new(Mutex::new(Vec::new()));
+            let c = checksums.clone();
+            let results = read(Arc::new(
+                move |buffer: &[u8], data: &T, chunk_id, num_chunks, offset| {
+                    let crc = crc32c(buffer);
+                    match c.lock() {
+                        Ok(mut c) => c.push((offset, buffer.len() as u64, crc)),
+                        Err(err) => err.into_inner().push((offset, buffer.len() as u64, crc)),
+                    }
+                    consumer(buffer, data, chunk_id, num_chunks, offset)
+                },
+            ))?;
+            let mut checksums = match checksums.lock() {
+                Ok(mut c) => std::mem::take(&mut *c),
+                Err(err) => std::mem::take(&mut *err.into_inner()),
+            };
+            checksums.sort_unstable_by_key(|(offset, _, _)| *offset);
+            let mut next = start;
+            let mut crc = 0;
+            for (offset, size, chunk_crc) in checksums {
+                check_offset(next, offset)?;
+                crc = crc32c_combine(crc, chunk_crc, size);
+                next += size;
+            }
+            check_offset(next, file_size)?;
+            Ok((results, Digest::Crc32c(crc)))
+        }
+        HashAlgorithm::Sha256 => {
+            let (tx, rx) = channel::<Chunk>();
+            let hasher = thread::Builder::new()
+                .spawn(move || {
+                    let mut sha = Sha256::default();
+                    let mut pending = BTreeMap::new();
+                    let mut next = start;
+                    // receiving ends when the read completes and the sender
+                    // is dropped
+                    for (offset, data) in rx.iter() {
+                        pending.insert(offset, data);
+                        while let Some(data) = pending.remove(&next) {
+                            sha.update(&data);
+                            next += data.len() as u64;
+                        }
+                    }
+                    (sha.finalize(), next)
+                })
+                .map_err(|err| ReadError::Other(format!("Cannot spawn hasher - {}", err)))?;
+            // the sender is not Sync, consumers access it through a mutex
+            let tx: Arc<Mutex<Sender<Chunk>>> = Arc::new(Mutex::new(tx));
+            let results = read(Arc::new(
+                move |buffer: &[u8], data: &T, chunk_id, num_chunks, offset| {
+                    let chunk = (offset, buffer.to_vec());
+                    // the hasher only exits after all senders are dropped
+                    let _ = match tx.lock() {
+                        Ok(tx) => tx.send(chunk),
+                        Err(err) => err.into_inner().send(chunk),
+                    };
+                    consumer(buffer, data, chunk_id, num_chunks, offset)
+                },
+            ));
+            let (sha, next) = hasher
+                .join()
+                .map_err(|err| ReadError::Other(format!("{:?}", err)))?;
+            let results = results?;
+            check_offset(next, file_size)?;
+            Ok((results, Digest::Sha256(sha)))
+        }
+    }
+}
+
+/// Return an error if the data hashed does not end at `expected`.
+fn check_offset(next: u64, expected: u64) -> Result<(), ReadError> {
+    if next != expected {
+        return Err(ReadError::Other(format!(
+            "Missing data at offset {} when hashing",
+            next
+        )));
+    }
+    Ok(())
+}
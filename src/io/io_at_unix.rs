@@ -1,73 +1,378 @@
 //! Functions to read/write from/to files at specified offset wrapping pread/write.
 use crate::read::ReadError;
-use crate::write::WriteError;
+use crate::write::{ConsumerError, WriteError};
 use std::fs::File;
 use std::os::raw::c_void;
 use std::os::unix::io::{AsRawFd, RawFd};
 
 //----------------------------------------------------------------------------
 // Mapping C types to Rust.
+#[allow(non_camel_case_types)]
 pub type ssize_t = isize;
+#[allow(non_camel_case_types)]
 pub type size_t = usize;
-pub type off_t = isize;
+#[allow(non_camel_case_types)]
+pub type off_t = i64;
+#[repr(C)]
+struct iovec {
+    iov_base: *mut c_void,
+    iov_len: size_t,
+}
+// `off_t` is 64 bits on 64-bit targets, BSDs and macOS
+#[cfg(not(all(
+    any(target_os = "linux", target_os = "android"),
+    target_pointer_width = "32"
+)))]
+extern "C" {
+    fn pread(fd: RawFd, buf: *mut c_void, count: size_t, offset: off_t) -> ssize_t;
+    fn pwrite(fd: RawFd, buf: *mut c_void, count: size_t, offset: off_t) -> ssize_t;
+    fn preadv(fd: RawFd, iov: *const iovec, iovcnt: i32, offset: off_t) -> ssize_t;
+    fn pwritev(fd: RawFd, iov: *const iovec, iovcnt: i32, offset: off_t) -> ssize_t;
+}
+// `off_t` is 32 bits on 32-bit Linux targets, the `*64` variants take a
+// 64-bit `off64_t`
+#[cfg(all(
+    any(target_os = "linux", target_os = "android"),
+    target_pointer_width = "32"
+))]
 extern "C" {
+    #[link_name = "pread64"]
     fn pread(fd: RawFd, buf: *mut c_void, count: size_t, offset: off_t) -> ssize_t;
+    #[link_name = "pwrite64"]
     fn pwrite(fd: RawFd, buf: *mut c_void, count: size_t, offset: off_t) -> ssize_t;
+    #[link_name = "preadv64"]
+    fn preadv(fd: RawFd, iov: *const iovec, iovcnt: i32, offset: off_t) -> ssize_t;
+    #[link_name = "pwritev64"]
+    fn pwritev(fd: RawFd, iov: *const iovec, iovcnt: i32, offset: off_t) -> ssize_t;
+}
+
+/// Convert the offset of a transfer of `len` bytes to `off_t`, fail with
+/// `std::io::ErrorKind::InvalidInput` if the transfer ends past the largest
+/// offset supported by the system calls.
+fn file_offset(offset: u64, len: u64) -> std::io::Result<off_t> {
+    match offset.checked_add(len) {
+        Some(end) if end <= off_t::MAX as u64 => Ok(offset as off_t),
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("File offset {} + {} out of range", offset, len),
+        )),
+    }
 }
 
+/// Maximum number of buffers passed to each `preadv` and `pwritev` call, the minimum
+/// `IOV_MAX` required by POSIX is 16, Linux and macOS support 1024.
+const MAX_IOVECS: usize = 1024;
+
 //-----------------------------------------------------------------------------
-/// Read bytes from file at offset, inkoking `pread`.
-pub fn read_bytes_at(buffer: &mut Vec<u8>, file: &File, mut offset: u64) -> Result<(), ReadError> {
-    //td::fs::metadata(file).map_err(|err| ReadError::IO(err))?;
-    let mut data_read = 0;
-    let fd = file.as_raw_fd();
-    while data_read < buffer.len() {
-        let sz = buffer.len() - data_read as usize;
-        data_read += unsafe {
-            let ret = pread(
-                fd,
-                buffer.as_mut_ptr().offset(data_read as isize) as *mut c_void,
-                sz as size_t,
-                offset as off_t,
-            );
-            if ret < 0 {
-                return Err(ReadError::Other(format!(
-                    "{:?}",
-                    std::io::Error::last_os_error()
-                )));
-            } else {
-                ret as usize
+/// Invoke `io` until `len` bytes have been transferred, passing the position
+/// in the buffer, the number of bytes to transfer and the file offset;
+/// `io` returns the value returned by `pread` or `pwrite`.
+///
+/// Offsets not representable as `off_t` fail with
+/// `std::io::ErrorKind::InvalidInput` before invoking `io`.
+///
+/// Calls interrupted by a signal before transferring any data fail with
+/// `EINTR` and are invoked again with the same arguments. On failure the
+/// error and the file offset are returned, with `None` as the error when no
+/// bytes were transferred.
+fn transfer_at<F>(
+    len: usize,
+    mut offset: u64,
+    max_size: usize,
+    mut io: F,
+) -> Result<(), (Option<std::io::Error>, u64)>
+where
+    F: FnMut(usize, usize, off_t) -> ssize_t,
+{
+    let mut done = 0;
+    while done < len {
+        let sz = (len - done).min(max_size.max(1));
+        let pos = file_offset(offset, sz as u64).map_err(|err| (Some(err), offset))?;
+        let ret = io(done, sz, pos);
+        if ret < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                // nothing transferred, do not advance
+                continue;
             }
-        };
-        offset += data_read as u64;
+            return Err((Some(err), offset));
+        }
+        if ret == 0 {
+            return Err((None, offset));
+        }
+        // advance by the bytes transferred in this call only
+        done += ret as usize;
+        offset += ret as u64;
     }
     Ok(())
 }
 
+//-----------------------------------------------------------------------------
+/// Read bytes from file at offset, inkoking `pread`.
+pub fn read_bytes_at(buffer: &mut [u8], file: &File, offset: u64) -> Result<(), ReadError> {
+    read_bytes_at_max(buffer, file, offset, usize::MAX)
+}
+
+//-----------------------------------------------------------------------------
+/// Read bytes from file at offset transferring at most `max_size` bytes per
+/// `pread` call.
+pub fn read_bytes_at_max(
+    buffer: &mut [u8],
+    file: &File,
+    offset: u64,
+    max_size: usize,
+) -> Result<(), ReadError> {
+    let fd = file.as_raw_fd();
+    let len = buffer.len();
+    let ptr = buffer.as_mut_ptr();
+    let start = offset;
+    transfer_at(len, offset, max_size, |pos, sz, offset| unsafe {
+        pread(fd, ptr.add(pos) as *mut c_void, sz as size_t, offset)
+    })
+    .map_err(|(err, offset)| match err {
+        Some(err) => ReadError::Other(format!("{:?}", err)),
+        None => ReadError::UnexpectedEof {
+            offset: start,
+            expected: len as u64,
+            got: offset - start,
+        },
+    })
+}
+
 //-----------------------------------------------------------------------------
 /// Write bytes to file at offset, invoking `pwrite`.
-pub fn write_bytes_at(buffer: &Vec<u8>, file: &File, mut offset: u64) -> Result<(), WriteError> {
+pub fn write_bytes_at(buffer: &[u8], file: &File, offset: u64) -> Result<(), WriteError> {
+    write_bytes_at_max(buffer, file, offset, usize::MAX)
+}
+
+//-----------------------------------------------------------------------------
+/// Write bytes to file at offset transferring at most `max_size` bytes per
+/// `pwrite` call.
+pub fn write_bytes_at_max(
+    buffer: &[u8],
+    file: &File,
+    offset: u64,
+    max_size: usize,
+) -> Result<(), WriteError> {
     let fd = file.as_raw_fd();
-    let mut written = 0;
-    while written < buffer.len() {
-        let sz = buffer.len() - written as usize;
-        written += unsafe {
-            let ret = pwrite(
-                fd,
-                buffer.as_ptr().offset(written as isize) as *mut c_void,
-                sz as size_t,
-                offset as off_t,
-            );
-            if ret < 0 {
-                return Err(WriteError::Other(format!(
-                    "{:?}",
-                    std::io::Error::last_os_error()
-                )));
-            } else {
-                ret as usize
+    transfer_at(buffer.len(), offset, max_size, |pos, sz, offset| unsafe {
+        pwrite(
+            fd,
+            buffer.as_ptr().add(pos) as *mut c_void,
+            sz as size_t,
+            offset,
+        )
+    })
+    .map_err(|(err, offset)| {
+        WriteError::Consumer(ConsumerError {
+            msg: match err {
+                Some(err) => err.to_string(),
+                None => "No bytes written".to_string(),
+            },
+            offset,
+        })
+    })
+}
+
+//-----------------------------------------------------------------------------
+/// Invoke `io` until all the bytes of `buffers`, given as pointer and length
+/// and placed one after the other starting at `offset`, have been
+/// transferred, passing at most `MAX_IOVECS` buffers and `max_size` bytes per
+/// call; `io` returns the value returned by `preadv` or `pwritev`. Errors are
+/// reported as with `transfer_at`.
+fn transfer_buffers_at<F>(
+    buffers: &[(*mut u8, usize)],
+    offset: u64,
+    max_size: usize,
+    mut io: F,
+) -> Result<(), (Option<std::io::Error>, u64)>
+where
+    F: FnMut(&[iovec], off_t) -> ssize_t,
+{
+    let len = buffers.iter().map(|b| b.1).sum();
+    // buffer and position in the buffer of the next byte to transfer
+    let (mut index, mut pos) = (0, 0);
+    let mut skipped = 0;
+    let mut iovecs = Vec::with_capacity(buffers.len().min(MAX_IOVECS));
+    transfer_at(len, offset, max_size, |start, sz, offset| {
+        // skip the bytes transferred by the previous call
+        let mut skip = start - skipped;
+        while skip > 0 {
+            let n = skip.min(buffers[index].1 - pos);
+            skip -= n;
+            pos += n;
+            if pos == buffers[index].1 {
+                index += 1;
+                pos = 0;
+            }
+        }
+        skipped = start;
+        iovecs.clear();
+        let mut remaining = sz;
+        let (mut i, mut p) = (index, pos);
+        while remaining > 0 && i < buffers.len() && iovecs.len() < MAX_IOVECS {
+            let n = remaining.min(buffers[i].1 - p);
+            if n > 0 {
+                iovecs.push(iovec {
+                    iov_base: unsafe { buffers[i].0.add(p) } as *mut c_void,
+                    iov_len: n as size_t,
+                });
             }
-        };
-        offset += written as u64;
+            remaining -= n;
+            i += 1;
+            p = 0;
+        }
+        io(&iovecs, offset)
+    })
+}
+
+//-----------------------------------------------------------------------------
+/// Fill `buffers` one after the other with data read from file starting at
+/// offset, invoking `preadv` with at most `MAX_IOVECS` buffers and
+/// transferring at most `max_size` bytes per call.
+pub fn read_buffers_at_max(
+    buffers: &mut [&mut [u8]],
+    file: &File,
+    offset: u64,
+    max_size: usize,
+) -> Result<(), ReadError> {
+    let fd = file.as_raw_fd();
+    let buffers: Vec<(*mut u8, usize)> = buffers
+        .iter_mut()
+        .map(|b| (b.as_mut_ptr(), b.len()))
+        .collect();
+    let len: usize = buffers.iter().map(|b| b.1).sum();
+    let start = offset;
+    transfer_buffers_at(&buffers, offset, max_size, |iovecs, offset| unsafe {
+        preadv(fd, iovecs.as_ptr(), iovecs.len() as i32, offset)
+    })
+    .map_err(|(err, offset)| match err {
+        Some(err) => ReadError::Other(format!("{:?}", err)),
+        None => ReadError::UnexpectedEof {
+            offset: start,
+            expected: len as u64,
+            got: offset - start,
+        },
+    })
+}
+
+//-----------------------------------------------------------------------------
+/// Write `buffers` one after the other to file starting at offset, invoking
+/// `pwritev` with at most `MAX_IOVECS` buffers and transferring at most
+/// `max_size` bytes per call.
+pub fn write_buffers_at_max(
+    buffers: &[&[u8]],
+    file: &File,
+    offset: u64,
+    max_size: usize,
+) -> Result<(), WriteError> {
+    let fd = file.as_raw_fd();
+    // buffers are only read by `pwritev`
+    let buffers: Vec<(*mut u8, usize)> = buffers
+        .iter()
+        .map(|b| (b.as_ptr() as *mut u8, b.len()))
+        .collect();
+    transfer_buffers_at(&buffers, offset, max_size, |iovecs, offset| unsafe {
+        pwritev(fd, iovecs.as_ptr(), iovecs.len() as i32, offset)
+    })
+    .map_err(|(err, offset)| {
+        WriteError::Consumer(ConsumerError {
+            msg: match err {
+                Some(err) => err.to_string(),
+                None => "No bytes written".to_string(),
+            },
+            offset,
+        })
+    })
+}
+
+//-----------------------------------------------------------------------------
+// Hole punching.
+#[cfg(target_os = "linux")]
+const FALLOC_FL_KEEP_SIZE: i32 = 1;
+#[cfg(target_os = "linux")]
+const FALLOC_FL_PUNCH_HOLE: i32 = 2;
+#[cfg(target_os = "linux")]
+extern "C" {
+    #[cfg_attr(target_pointer_width = "32", link_name = "fallocate64")]
+    fn fallocate(fd: RawFd, mode: i32, offset: off_t, len: off_t) -> i32;
+}
+
+/// Deallocate `len` bytes at `offset`, which then read as zero, invoking
+/// `fallocate`; the file size is unchanged.
+#[cfg(target_os = "linux")]
+pub fn punch_hole(file: &File, offset: u64, len: u64) -> std::io::Result<()> {
+    let mode = FALLOC_FL_PUNCH_HOLE | FALLOC_FL_KEEP_SIZE;
+    // the range fits, so does its length
+    let offset = file_offset(offset, len)?;
+    if unsafe { fallocate(file.as_raw_fd(), mode, offset, len as off_t) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Not supported on this platform.
+#[cfg(not(target_os = "linux"))]
+pub fn punch_hole(_file: &File, _offset: u64, _len: u64) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        "Punching holes is only supported on Linux",
+    ))
+}
+
+//-----------------------------------------------------------------------------
+/// Return the preferred I/O block size of the filesystem (`st_blksize`).
+pub fn io_block_size(file: &File) -> std::io::Result<Option<u64>> {
+    use std::os::unix::fs::MetadataExt;
+    let size = file.metadata()?.blksize();
+    Ok(if size > 0 { Some(size) } else { None })
+}
+
+//-----------------------------------------------------------------------------
+// Advisory locks.
+const LOCK_SH: i32 = 1;
+const LOCK_EX: i32 = 2;
+const LOCK_NB: i32 = 4;
+extern "C" {
+    fn flock(fd: RawFd, operation: i32) -> i32;
+}
+
+/// Try to acquire an advisory lock on the whole file without blocking,
+/// invoking `flock`; the lock is released when the file is closed.
+/// Fails with `std::io::ErrorKind::WouldBlock` if the file is locked.
+pub fn try_lock_file(file: &File, exclusive: bool) -> std::io::Result<()> {
+    let operation = if exclusive { LOCK_EX } else { LOCK_SH } | LOCK_NB;
+    if unsafe { flock(file.as_raw_fd(), operation) } < 0 {
+        return Err(std::io::Error::last_os_error());
     }
     Ok(())
 }
+
+//-----------------------------------------------------------------------------
+// Direct I/O.
+#[cfg(target_os = "linux")]
+const O_DIRECT: i32 = if cfg!(any(target_arch = "arm", target_arch = "aarch64")) {
+    0o200000
+} else if cfg!(any(target_arch = "powerpc", target_arch = "powerpc64")) {
+    0o400000
+} else if cfg!(any(target_arch = "mips", target_arch = "mips64")) {
+    0o100000
+} else if cfg!(any(target_arch = "sparc", target_arch = "sparc64")) {
+    0x100000
+} else {
+    0o40000
+};
+
+/// Open a new write-only handle to `file` with `O_DIRECT` set, bypassing the
+/// page cache; offsets, lengths and buffer addresses of writes through the
+/// handle must be aligned to the logical block size of the device.
+#[cfg(target_os = "linux")]
+pub fn reopen_direct(file: &File) -> std::io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    // a new open file description is required: O_DIRECT set on a duplicated
+    // descriptor would also apply to `file`
+    std::fs::OpenOptions::new()
+        .write(true)
+        .custom_flags(O_DIRECT)
+        .open(format!("/proc/self/fd/{}", file.as_raw_fd()))
+}
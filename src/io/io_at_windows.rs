@@ -1,32 +1,193 @@
 //! Functions to read/write from/to files at offset.
 use crate::read::ReadError;
-use crate::write::WriteError;
+use crate::write::{ConsumerError, WriteError};
 use std::fs::File;
 
 //-----------------------------------------------------------------------------
 /// Read bytes from file at offset.
-pub fn read_bytes_at(buffer: &mut Vec<u8>, file: &File, mut offset: u64) -> Result<(), ReadError> {
+pub fn read_bytes_at(buffer: &mut [u8], file: &File, offset: u64) -> Result<(), ReadError> {
+    read_bytes_at_max(buffer, file, offset, usize::MAX)
+}
+
+//-----------------------------------------------------------------------------
+/// Read bytes from file at offset transferring at most `max_size` bytes per
+/// call.
+pub fn read_bytes_at_max(
+    buffer: &mut [u8],
+    file: &File,
+    mut offset: u64,
+    max_size: usize,
+) -> Result<(), ReadError> {
     use std::os::windows::fs::FileExt;
+    let start = offset;
     let mut data_read = 0;
     while data_read < buffer.len() {
-        data_read += file
-            .seek_read(&mut buffer[data_read..], offset)
-            .map_err(|err| ReadError::IO(err))?;
-        offset += data_read as u64;
+        let end = data_read + (buffer.len() - data_read).min(max_size.max(1));
+        let n = file
+            .seek_read(&mut buffer[data_read..end], offset)
+            .map_err(ReadError::IO)?;
+        if n == 0 {
+            return Err(ReadError::UnexpectedEof {
+                offset: start,
+                expected: buffer.len() as u64,
+                got: data_read as u64,
+            });
+        }
+        // advance by the bytes read in this call only
+        data_read += n;
+        offset += n as u64;
     }
     Ok(())
 }
 
 //-----------------------------------------------------------------------------
 /// Write bytes to file at offset.
-pub fn write_bytes_at(buffer: &Vec<u8>, file: &File, mut offset: u64) -> Result<(), WriteError> {
+pub fn write_bytes_at(buffer: &[u8], file: &File, offset: u64) -> Result<(), WriteError> {
+    write_bytes_at_max(buffer, file, offset, usize::MAX)
+}
+
+//-----------------------------------------------------------------------------
+/// Write bytes to file at offset transferring at most `max_size` bytes per
+/// call.
+pub fn write_bytes_at_max(
+    buffer: &[u8],
+    file: &File,
+    mut offset: u64,
+    max_size: usize,
+) -> Result<(), WriteError> {
     use std::os::windows::fs::FileExt;
     let mut written = 0;
     while written < buffer.len() {
-        written += file
-            .seek_write(&buffer[written..], offset)
-            .map_err(|err| WriteError::IO(err))?;
-        offset += written as u64;
+        let end = written + (buffer.len() - written).min(max_size.max(1));
+        let n = file
+            .seek_write(&buffer[written..end], offset)
+            .map_err(|err| {
+                WriteError::Consumer(ConsumerError {
+                    msg: err.to_string(),
+                    offset,
+                })
+            })?;
+        if n == 0 {
+            return Err(WriteError::Consumer(ConsumerError {
+                msg: "No bytes written".to_string(),
+                offset,
+            }));
+        }
+        // advance by the bytes written in this call only
+        written += n;
+        offset += n as u64;
+    }
+    Ok(())
+}
+
+//-----------------------------------------------------------------------------
+/// Fill `buffers` one after the other with data read from file starting at
+/// offset, with one `read_bytes_at_max` call per buffer.
+pub fn read_buffers_at_max(
+    buffers: &mut [&mut [u8]],
+    file: &File,
+    mut offset: u64,
+    max_size: usize,
+) -> Result<(), ReadError> {
+    for b in buffers {
+        read_bytes_at_max(b, file, offset, max_size)?;
+        offset += b.len() as u64;
+    }
+    Ok(())
+}
+
+//-----------------------------------------------------------------------------
+/// Write `buffers` one after the other to file starting at offset, with one
+/// `write_bytes_at_max` call per buffer.
+pub fn write_buffers_at_max(
+    buffers: &[&[u8]],
+    file: &File,
+    mut offset: u64,
+    max_size: usize,
+) -> Result<(), WriteError> {
+    for b in buffers {
+        write_bytes_at_max(b, file, offset, max_size)?;
+        offset += b.len() as u64;
+    }
+    Ok(())
+}
+
+//-----------------------------------------------------------------------------
+/// Deallocating file ranges is not supported on Windows.
+pub fn punch_hole(_file: &File, _offset: u64, _len: u64) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        "Punching holes is not supported on Windows",
+    ))
+}
+
+//-----------------------------------------------------------------------------
+/// Return the preferred I/O block size of the filesystem, not available on
+/// Windows.
+pub fn io_block_size(_file: &File) -> std::io::Result<Option<u64>> {
+    Ok(None)
+}
+
+//-----------------------------------------------------------------------------
+// File locks.
+/// `OVERLAPPED` structure, also used by overlapped reads.
+// fields are only read by the system
+#[allow(dead_code)]
+#[repr(C)]
+pub(crate) struct Overlapped {
+    pub internal: usize,
+    pub internal_high: usize,
+    pub offset: u32,
+    pub offset_high: u32,
+    pub event: *mut std::ffi::c_void,
+}
+const LOCKFILE_FAIL_IMMEDIATELY: u32 = 1;
+const LOCKFILE_EXCLUSIVE_LOCK: u32 = 2;
+const ERROR_LOCK_VIOLATION: i32 = 33;
+#[link(name = "kernel32")]
+extern "system" {
+    fn LockFileEx(
+        file: *mut std::ffi::c_void,
+        flags: u32,
+        reserved: u32,
+        bytes_low: u32,
+        bytes_high: u32,
+        overlapped: *mut Overlapped,
+    ) -> i32;
+}
+
+/// Try to lock the whole file without blocking, invoking `LockFileEx`;
+/// the lock is released when the file is closed.
+/// Fails with `std::io::ErrorKind::WouldBlock` if the file is locked.
+pub fn try_lock_file(file: &File, exclusive: bool) -> std::io::Result<()> {
+    use std::os::windows::io::AsRawHandle;
+    let mut flags = LOCKFILE_FAIL_IMMEDIATELY;
+    if exclusive {
+        flags |= LOCKFILE_EXCLUSIVE_LOCK;
+    }
+    let mut overlapped = Overlapped {
+        internal: 0,
+        internal_high: 0,
+        offset: 0,
+        offset_high: 0,
+        event: std::ptr::null_mut(),
+    };
+    let ret = unsafe {
+        LockFileEx(
+            file.as_raw_handle(),
+            flags,
+            0,
+            u32::MAX,
+            u32::MAX,
+            &mut overlapped,
+        )
+    };
+    if ret == 0 {
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() == Some(ERROR_LOCK_VIOLATION) {
+            return Err(std::io::ErrorKind::WouldBlock.into());
+        }
+        return Err(err);
     }
     Ok(())
 }
@@ -1,7 +1,13 @@
 //! Read/write data at offset from/to files, conditionally including UNIX/Windows
 //! implementations.
-#[cfg(any(unix))]
+#[cfg(unix)]
 pub mod io_at_unix;
 
-#[cfg(any(windows))]
+#[cfg(windows)]
 pub mod io_at_windows;
+
+#[cfg(all(feature = "io_uring", target_os = "linux"))]
+pub mod uring;
+
+#[cfg(all(feature = "overlapped", windows))]
+pub mod overlapped;
@@ -0,0 +1,192 @@
+//! Minimal overlapped (asynchronous) reader for Windows.
+//!
+//! The file is reopened with `FILE_FLAG_OVERLAPPED` and each chunk is split
+//! into segments read by concurrent `ReadFile` calls, each with its own
+//! `OVERLAPPED` structure holding the segment offset and an event signalled
+//! on completion, keeping more requests in flight than a sequence of
+//! positioned reads on a synchronous handle.
+use super::io_at_windows::Overlapped;
+use crate::read::ReadError;
+use std::ffi::c_void;
+use std::fs::File;
+use std::os::windows::io::AsRawHandle;
+
+//----------------------------------------------------------------------------
+// Win32 interface.
+type Handle = *mut c_void;
+const INVALID_HANDLE_VALUE: Handle = -1_isize as Handle;
+const GENERIC_READ: u32 = 0x80000000;
+const FILE_SHARE_READ_WRITE_DELETE: u32 = 0x1 | 0x2 | 0x4;
+const FILE_FLAG_OVERLAPPED: u32 = 0x40000000;
+const ERROR_HANDLE_EOF: i32 = 38;
+const ERROR_IO_PENDING: i32 = 997;
+
+#[link(name = "kernel32")]
+extern "system" {
+    fn ReOpenFile(original: Handle, access: u32, share: u32, flags: u32) -> Handle;
+    fn CreateEventW(
+        attributes: *mut c_void,
+        manual_reset: i32,
+        initial_state: i32,
+        name: *const u16,
+    ) -> Handle;
+    fn ReadFile(
+        file: Handle,
+        buffer: *mut c_void,
+        len: u32,
+        read: *mut u32,
+        overlapped: *mut Overlapped,
+    ) -> i32;
+    fn GetOverlappedResult(
+        file: Handle,
+        overlapped: *mut Overlapped,
+        transferred: *mut u32,
+        wait: i32,
+    ) -> i32;
+    fn CloseHandle(handle: Handle) -> i32;
+}
+
+//----------------------------------------------------------------------------
+/// File handle opened for overlapped reads and one completion event per
+/// request in flight.
+pub struct Reader {
+    handle: Handle,
+    events: Vec<Handle>,
+}
+
+// handles can be used from any thread
+unsafe impl Send for Reader {}
+
+impl Reader {
+    /// Reopen `file` for overlapped reads with up to `entries` requests in
+    /// flight.
+    pub fn new(file: &File, entries: u32) -> std::io::Result<Self> {
+        let handle = unsafe {
+            ReOpenFile(
+                file.as_raw_handle(),
+                GENERIC_READ,
+                FILE_SHARE_READ_WRITE_DELETE,
+                FILE_FLAG_OVERLAPPED,
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(std::io::Error::last_os_error());
+        }
+        // closes the handles created so far on error
+        let mut reader = Reader {
+            handle,
+            events: Vec::with_capacity(entries.max(1) as usize),
+        };
+        for _ in 0..entries.max(1) {
+            // manual reset, reset by `ReadFile` when the request starts
+            let event = unsafe { CreateEventW(std::ptr::null_mut(), 1, 0, std::ptr::null()) };
+            if event.is_null() {
+                return Err(std::io::Error::last_os_error());
+            }
+            reader.events.push(event);
+        }
+        Ok(reader)
+    }
+
+    /// Fill `buffer` with data read at `offset`, splitting the buffer into
+    /// segments of at most `segment_size` bytes read concurrently.
+    pub fn read_at(
+        &mut self,
+        buffer: &mut [u8],
+        offset: u64,
+        segment_size: usize,
+    ) -> Result<(), ReadError> {
+        let segment_size = segment_size.clamp(1, u32::MAX as usize);
+        // (position in buffer, remaining bytes) of pending segments
+        let mut pending: Vec<(usize, usize)> = (0..buffer.len())
+            .step_by(segment_size)
+            .map(|pos| (pos, segment_size.min(buffer.len() - pos)))
+            .rev()
+            .collect();
+        while !pending.is_empty() {
+            let batch: Vec<(usize, usize)> = (0..self.events.len().min(pending.len()))
+                .filter_map(|_| pending.pop())
+                .collect();
+            // requests refer to their `Overlapped` until completed: the
+            // vector is never reallocated while requests are in flight
+            let mut requests: Vec<Overlapped> = batch
+                .iter()
+                .zip(&self.events)
+                .map(|((pos, _), event)| {
+                    let o = offset + *pos as u64;
+                    Overlapped {
+                        internal: 0,
+                        internal_high: 0,
+                        offset: o as u32,
+                        offset_high: (o >> 32) as u32,
+                        event: *event,
+                    }
+                })
+                .collect();
+            let mut error = None;
+            let mut issued = 0;
+            for ((pos, len), request) in batch.iter().zip(requests.iter_mut()) {
+                let dst = unsafe { buffer.as_mut_ptr().add(*pos) } as *mut c_void;
+                let ok = unsafe {
+                    ReadFile(self.handle, dst, *len as u32, std::ptr::null_mut(), request)
+                };
+                if ok == 0 {
+                    let err = std::io::Error::last_os_error();
+                    if err.raw_os_error() != Some(ERROR_IO_PENDING) {
+                        error = Some(err);
+                        break;
+                    }
+                }
+                issued += 1;
+            }
+            // wait for all the requests issued, even after an error, before
+            // the buffer and the requests can be released
+            let mut eof = None;
+            for (k, request) in requests.iter_mut().enumerate().take(issued) {
+                let (pos, len) = batch[k];
+                let mut n = 0;
+                let ok = unsafe { GetOverlappedResult(self.handle, request, &mut n, 1) };
+                if ok == 0 {
+                    let err = std::io::Error::last_os_error();
+                    if err.raw_os_error() == Some(ERROR_HANDLE_EOF) {
+                        n = 0;
+                    } else {
+                        error.get_or_insert(err);
+                        continue;
+                    }
+                }
+                let n = n as usize;
+                if n == 0 {
+                    // segments are read concurrently, data is missing from
+                    // the position where the end of file was reached
+                    eof = Some(eof.map_or(pos, |e: usize| e.min(pos)));
+                } else if n < len {
+                    // short read, read the rest of the segment
+                    pending.push((pos + n, len - n));
+                }
+            }
+            if let Some(err) = error {
+                return Err(ReadError::IO(err));
+            }
+            if let Some(got) = eof {
+                return Err(ReadError::UnexpectedEof {
+                    offset,
+                    expected: buffer.len() as u64,
+                    got: got as u64,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Reader {
+    fn drop(&mut self) {
+        unsafe {
+            for event in &self.events {
+                CloseHandle(*event);
+            }
+            CloseHandle(self.handle);
+        }
+    }
+}
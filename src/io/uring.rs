@@ -0,0 +1,347 @@
+//! Minimal `io_uring` reader invoking the system calls directly.
+//!
+//! Each chunk is split into segments read by concurrent `IORING_OP_READ`
+//! requests submitted with a single `io_uring_enter` call, keeping more
+//! requests in flight than a sequence of `pread` calls.
+use crate::read::ReadError;
+use std::fs::File;
+use std::os::raw::{c_long, c_void};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+//----------------------------------------------------------------------------
+// Kernel interface, see `linux/io_uring.h`.
+const SYS_IO_URING_SETUP: c_long = 425;
+const SYS_IO_URING_ENTER: c_long = 426;
+const IORING_OFF_SQ_RING: i64 = 0;
+const IORING_OFF_CQ_RING: i64 = 0x8000000;
+const IORING_OFF_SQES: i64 = 0x10000000;
+const IORING_ENTER_GETEVENTS: u32 = 1;
+const IORING_OP_READ: u8 = 22;
+const PROT_READ_WRITE: i32 = 0x1 | 0x2;
+const MAP_SHARED_POPULATE: i32 = 0x01 | 0x8000;
+const EINTR: i32 = 4;
+const EAGAIN: i32 = 11;
+
+#[repr(C)]
+#[derive(Default)]
+struct SqringOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    flags: u32,
+    dropped: u32,
+    array: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct CqringOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    overflow: u32,
+    cqes: u32,
+    flags: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Params {
+    sq_entries: u32,
+    cq_entries: u32,
+    flags: u32,
+    sq_thread_cpu: u32,
+    sq_thread_idle: u32,
+    features: u32,
+    wq_fd: u32,
+    resv: [u32; 3],
+    sq_off: SqringOffsets,
+    cq_off: CqringOffsets,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Sqe {
+    opcode: u8,
+    flags: u8,
+    ioprio: u16,
+    fd: i32,
+    off: u64,
+    addr: u64,
+    len: u32,
+    rw_flags: u32,
+    user_data: u64,
+    buf_index: u16,
+    personality: u16,
+    splice_fd_in: i32,
+    addr3: u64,
+    pad: u64,
+}
+
+#[repr(C)]
+struct Cqe {
+    user_data: u64,
+    res: i32,
+    flags: u32,
+}
+
+extern "C" {
+    fn syscall(num: c_long, ...) -> c_long;
+    // 64-bit offset on 32-bit targets
+    #[cfg_attr(target_pointer_width = "32", link_name = "mmap64")]
+    fn mmap(
+        addr: *mut c_void,
+        len: usize,
+        prot: i32,
+        flags: i32,
+        fd: RawFd,
+        off: i64,
+    ) -> *mut c_void;
+    fn munmap(addr: *mut c_void, len: usize) -> i32;
+    fn close(fd: RawFd) -> i32;
+}
+
+/// Memory mapped region unmapped on drop.
+struct Mapping {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl Mapping {
+    fn new(fd: RawFd, len: usize, offset: i64) -> std::io::Result<Self> {
+        let ptr = unsafe {
+            mmap(
+                std::ptr::null_mut(),
+                len,
+                PROT_READ_WRITE,
+                MAP_SHARED_POPULATE,
+                fd,
+                offset,
+            )
+        };
+        if ptr as isize == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Mapping {
+            ptr: ptr as *mut u8,
+            len,
+        })
+    }
+    /// Return pointer to the `u32` at `offset`.
+    fn u32_at(&self, offset: u32) -> *mut u32 {
+        unsafe { self.ptr.add(offset as usize) as *mut u32 }
+    }
+    fn atomic_at(&self, offset: u32) -> &AtomicU32 {
+        unsafe { &*(self.u32_at(offset) as *const AtomicU32) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe {
+            munmap(self.ptr as *mut c_void, self.len);
+        }
+    }
+}
+
+//----------------------------------------------------------------------------
+/// Submission and completion queues of one `io_uring` instance, used by a
+/// single thread at a time.
+pub struct Ring {
+    fd: RawFd,
+    sq: Mapping,
+    cq: Mapping,
+    sqes: Mapping,
+    params: Params,
+}
+
+// the mapped memory is only accessed through `&mut Ring`
+unsafe impl Send for Ring {}
+
+impl Ring {
+    /// Create ring with `entries` submission queue entries; fails if the
+    /// kernel does not support `io_uring` or it is disabled.
+    pub fn new(entries: u32) -> std::io::Result<Self> {
+        let mut params = Params::default();
+        let fd = unsafe { syscall(SYS_IO_URING_SETUP, entries, &mut params as *mut Params) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let fd = fd as RawFd;
+        let map = || -> std::io::Result<(Mapping, Mapping, Mapping)> {
+            let sq_len = params.sq_off.array as usize + params.sq_entries as usize * 4;
+            let cq_len = params.cq_off.cqes as usize
+                + params.cq_entries as usize * std::mem::size_of::<Cqe>();
+            let sqes_len = params.sq_entries as usize * std::mem::size_of::<Sqe>();
+            Ok((
+                Mapping::new(fd, sq_len, IORING_OFF_SQ_RING)?,
+                Mapping::new(fd, cq_len, IORING_OFF_CQ_RING)?,
+                Mapping::new(fd, sqes_len, IORING_OFF_SQES)?,
+            ))
+        };
+        match map() {
+            Ok((sq, cq, sqes)) => Ok(Ring {
+                fd,
+                sq,
+                cq,
+                sqes,
+                params,
+            }),
+            Err(err) => {
+                unsafe { close(fd) };
+                Err(err)
+            }
+        }
+    }
+
+    /// Number of requests which can be submitted at once.
+    pub fn entries(&self) -> usize {
+        self.params.sq_entries as usize
+    }
+
+    /// Queue read of `len` bytes at file `offset` into `dst`.
+    fn push_read(&mut self, fd: RawFd, dst: *mut u8, len: u32, offset: u64, user_data: u64) {
+        let off = &self.params.sq_off;
+        let tail = unsafe { *self.sq.u32_at(off.tail) };
+        let mask = unsafe { *self.sq.u32_at(off.ring_mask) };
+        let index = tail & mask;
+        let sqe = Sqe {
+            opcode: IORING_OP_READ,
+            fd,
+            off: offset,
+            addr: dst as u64,
+            len,
+            user_data,
+            ..Default::default()
+        };
+        unsafe {
+            std::ptr::write((self.sqes.ptr as *mut Sqe).add(index as usize), sqe);
+            *self.sq.u32_at(off.array).add(index as usize) = index;
+        }
+        // make the entry visible to the kernel before the new tail
+        self.sq
+            .atomic_at(off.tail)
+            .store(tail.wrapping_add(1), Ordering::Release);
+    }
+
+    /// Submit `n` queued requests and wait for `n` completions, returning
+    /// `(user_data, result)` pairs.
+    fn submit_and_wait(&mut self, n: u32) -> std::io::Result<Vec<(u64, i32)>> {
+        let mut to_submit = n;
+        let mut completions = Vec::with_capacity(n as usize);
+        while completions.len() < n as usize {
+            let ret = unsafe {
+                syscall(
+                    SYS_IO_URING_ENTER,
+                    self.fd,
+                    to_submit,
+                    1_u32,
+                    IORING_ENTER_GETEVENTS,
+                    std::ptr::null::<c_void>(),
+                    0_usize,
+                )
+            };
+            if ret < 0 {
+                let err = std::io::Error::last_os_error();
+                if err.kind() == std::io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err);
+            }
+            to_submit -= (ret as u32).min(to_submit);
+            let off = &self.params.cq_off;
+            let head = self.cq.atomic_at(off.head).load(Ordering::Acquire);
+            let tail = self.cq.atomic_at(off.tail).load(Ordering::Acquire);
+            let mask = unsafe { *self.cq.u32_at(off.ring_mask) };
+            let mut h = head;
+            while h != tail {
+                let cqe = unsafe {
+                    &*(self.cq.ptr.add(off.cqes as usize) as *const Cqe).add((h & mask) as usize)
+                };
+                completions.push((cqe.user_data, cqe.res));
+                h = h.wrapping_add(1);
+            }
+            self.cq.atomic_at(off.head).store(h, Ordering::Release);
+        }
+        Ok(completions)
+    }
+
+    /// Fill `buffer` with data read from `file` at `offset`, splitting the
+    /// buffer into segments of at most `segment_size` bytes read
+    /// concurrently. Returns `Ok(false)` if the read operation is not
+    /// supported by the kernel and nothing was read.
+    pub fn read_at(
+        &mut self,
+        buffer: &mut [u8],
+        file: &File,
+        offset: u64,
+        segment_size: usize,
+    ) -> Result<bool, ReadError> {
+        let fd = file.as_raw_fd();
+        let segment_size = segment_size.clamp(1, u32::MAX as usize);
+        // (position in buffer, remaining bytes) of pending segments
+        let mut pending: Vec<(usize, usize)> = (0..buffer.len())
+            .step_by(segment_size)
+            .map(|pos| (pos, segment_size.min(buffer.len() - pos)))
+            .rev()
+            .collect();
+        let mut first = true;
+        while !pending.is_empty() {
+            let batch: Vec<(usize, usize)> = (0..self.entries().min(pending.len()))
+                .filter_map(|_| pending.pop())
+                .collect();
+            for (i, (pos, len)) in batch.iter().enumerate() {
+                let dst = unsafe { buffer.as_mut_ptr().add(*pos) };
+                self.push_read(fd, dst, *len as u32, offset + *pos as u64, i as u64);
+            }
+            let completions = self
+                .submit_and_wait(batch.len() as u32)
+                .map_err(ReadError::IO)?;
+            for (user_data, res) in completions {
+                let (pos, len) = batch[user_data as usize];
+                if res < 0 {
+                    if -res == EINTR || -res == EAGAIN {
+                        pending.push((pos, len));
+                        continue;
+                    }
+                    if first && -res == 22 {
+                        // EINVAL: opcode not supported by this kernel
+                        return Ok(false);
+                    }
+                    return Err(ReadError::IO(std::io::Error::from_raw_os_error(-res)));
+                }
+                if res == 0 {
+                    // segments are read concurrently, data is missing from
+                    // the position where the end of file was reached
+                    return Err(ReadError::UnexpectedEof {
+                        offset,
+                        expected: buffer.len() as u64,
+                        got: pos as u64,
+                    });
+                }
+                // short read, read the rest of the segment
+                let n = res as usize;
+                if n < len {
+                    pending.push((pos + n, len - n));
+                }
+            }
+            first = false;
+        }
+        Ok(true)
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        unsafe {
+            close(self.fd);
+        }
+    }
+}
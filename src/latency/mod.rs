@@ -0,0 +1,196 @@
+//! Per-chunk I/O latency distribution.
+//!
+//! Each worker thread records the latency of every chunk read or written into
+//! a thread local histogram, merged into the shared `LatencyReport` when the
+//! thread exits; no lock is taken while performing I/O.
+//!
+//! The histogram uses logarithmic buckets subdivided into 16 linear
+//! sub-buckets, reported values are accurate within about 6%.
+use std::sync::Mutex;
+use std::time::Duration;
+
+// values below `LINEAR` are stored exactly, above that each power of two range
+// is split into `HALF` buckets
+const SUB_BITS: u32 = 5;
+const LINEAR: u64 = 1 << SUB_BITS;
+const HALF: u64 = LINEAR / 2;
+
+// -----------------------------------------------------------------------------
+/// Histogram of durations in nanoseconds.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    count: u64,
+    min: u64,
+    max: u64,
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Add one sample.
+    pub fn record(&mut self, d: Duration) {
+        let v = d.as_nanos().min(u64::MAX as u128) as u64;
+        let i = bucket(v);
+        if i >= self.counts.len() {
+            self.counts.resize(i + 1, 0);
+        }
+        self.counts[i] += 1;
+        self.min = if self.count == 0 { v } else { self.min.min(v) };
+        self.max = self.max.max(v);
+        self.count += 1;
+    }
+    /// Add all the samples in `other`.
+    pub fn merge(&mut self, other: &Histogram) {
+        if other.count == 0 {
+            return;
+        }
+        if other.counts.len() > self.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (c, o) in self.counts.iter_mut().zip(&other.counts) {
+            *c += o;
+        }
+        self.min = if self.count == 0 {
+            other.min
+        } else {
+            self.min.min(other.min)
+        };
+        self.max = self.max.max(other.max);
+        self.count += other.count;
+    }
+    /// Number of samples.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+    pub fn min(&self) -> Duration {
+        Duration::from_nanos(self.min)
+    }
+    pub fn max(&self) -> Duration {
+        Duration::from_nanos(self.max)
+    }
+    /// Return the value below which `percentile` percent of the samples fall,
+    /// zero if the histogram is empty.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+        let rank = ((percentile.clamp(0.0, 100.0) / 100.0 * self.count as f64).ceil() as u64)
+            .clamp(1, self.count);
+        let mut seen = 0;
+        for (i, c) in self.counts.iter().enumerate() {
+            seen += c;
+            if seen >= rank {
+                let v = bucket_upper(i).clamp(self.min, self.max);
+                return Duration::from_nanos(v);
+            }
+        }
+        Duration::from_nanos(self.max)
+    }
+}
+
+/// Bucket index of value `v`.
+fn bucket(v: u64) -> usize {
+    if v < LINEAR {
+        return v as usize;
+    }
+    let shift = 64 - v.leading_zeros() - SUB_BITS;
+    (LINEAR + (shift as u64 - 1) * HALF + ((v >> shift) - HALF)) as usize
+}
+
+/// Highest value stored in bucket `i`.
+fn bucket_upper(i: usize) -> u64 {
+    let i = i as u64;
+    if i < LINEAR {
+        return i;
+    }
+    let shift = (i - LINEAR) / HALF + 1;
+    let m = (i - LINEAR) % HALF + HALF;
+    (((m as u128 + 1) << shift) - 1).min(u64::MAX as u128) as u64
+}
+
+/// Latency percentiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencySummary {
+    /// Number of chunks.
+    pub count: u64,
+    pub min: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+// -----------------------------------------------------------------------------
+/// Latencies of chunk I/O operations recorded by all worker threads.
+#[derive(Debug, Default)]
+pub struct LatencyReport {
+    histogram: Mutex<Histogram>,
+}
+
+impl LatencyReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Return a copy of the merged histogram.
+    pub fn histogram(&self) -> Histogram {
+        match self.histogram.lock() {
+            Ok(h) => h.clone(),
+            Err(err) => err.into_inner().clone(),
+        }
+    }
+    /// Return latency percentiles.
+    pub fn summary(&self) -> LatencySummary {
+        let h = self.histogram();
+        LatencySummary {
+            count: h.count(),
+            min: h.min(),
+            p50: h.percentile(50.0),
+            p95: h.percentile(95.0),
+            p99: h.percentile(99.0),
+            max: h.max(),
+        }
+    }
+    fn merge(&self, other: &Histogram) {
+        match self.histogram.lock() {
+            Ok(mut h) => h.merge(other),
+            Err(err) => err.into_inner().merge(other),
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Thread local histogram merged into the report when dropped, so that samples
+/// are not lost when the thread exits early because of an error.
+pub(crate) struct Recorder {
+    local: Histogram,
+    report: Option<std::sync::Arc<LatencyReport>>,
+}
+
+impl Recorder {
+    pub(crate) fn new(report: Option<std::sync::Arc<LatencyReport>>) -> Self {
+        Recorder {
+            local: Histogram::new(),
+            report,
+        }
+    }
+    /// Return the current time if recording is enabled.
+    pub(crate) fn start(&self) -> Option<std::time::Instant> {
+        self.report.as_ref().map(|_| std::time::Instant::now())
+    }
+    /// Record the time elapsed since `start`.
+    pub(crate) fn stop(&mut self, start: Option<std::time::Instant>) {
+        if let Some(s) = start {
+            self.local.record(s.elapsed());
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Some(r) = &self.report {
+            r.merge(&self.local);
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! Partitioning of the data among producers and chunks.
+//!
+//! The data is split into `num_producers` contiguous regions of
+//! `producer_chunk_size` bytes, the last region holding the remainder, and
+//! each region is split into `chunks_per_producer` chunks, the last chunk of
+//! each region holding the remainder of the region. `plan` returns the
+//! resulting sizes without reading or writing anything, e.g. to validate the
+//! parameters of a large job; reads and writes use the same layout, unless
+//! `ReadOptions::chunk_size` or `ReadOptions::align_to_block_size` is set.
+//!
+//! Sizes are rounded up: with small sizes some producers can receive fewer
+//! chunks than requested, or none, see `Layout::num_chunks`. Chunks are never
+//! empty: when `chunks_per_producer` exceeds the size of a region the region
+//! is split into one byte chunks.
+
+/// Chunk layout computed by `plan`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    pub total_size: u64,
+    pub num_producers: u64,
+    pub chunks_per_producer: u64,
+    /// Size of the region assigned to each producer but the last one.
+    pub producer_chunk_size: u64,
+    /// Size of the region assigned to the last producer.
+    pub last_producer_chunk_size: u64,
+    /// Size of each chunk but the last one of every producer but the last
+    /// one.
+    pub task_chunk_size: u64,
+    /// Size of the last chunk of every producer but the last one.
+    pub last_task_chunk_size: u64,
+    /// Size of each chunk but the last one of the last producer.
+    pub last_producer_task_chunk_size: u64,
+    /// Size of the last chunk of the last producer.
+    pub last_producer_last_task_chunk_size: u64,
+    /// Number of non empty chunks, at most
+    /// `num_producers * chunks_per_producer`.
+    pub num_chunks: u64,
+}
+
+impl Layout {
+    /// Same as `plan` with region and chunk sizes rounded up to a multiple of
+    /// `block_size`.
+    pub(crate) fn aligned(
+        total_size: u64,
+        num_producers: u64,
+        chunks_per_producer: u64,
+        block_size: u64,
+    ) -> Self {
+        let num_producers = num_producers.max(1);
+        let chunks_per_producer = chunks_per_producer.max(1);
+        let block_size = block_size.max(1);
+        let round_up = |size: u64| (size + block_size - 1) / block_size * block_size;
+        let task_size =
+            |region: u64| round_up((region + chunks_per_producer - 1) / chunks_per_producer);
+        let producer_chunk_size = round_up((total_size + num_producers - 1) / num_producers);
+        // saturating: producers can generate fewer chunks than the requested
+        // number when sizes are rounded up
+        let last_producer_chunk_size =
+            total_size.saturating_sub((num_producers - 1) * producer_chunk_size);
+        let task_chunk_size = task_size(producer_chunk_size);
+        let last_producer_task_chunk_size = task_size(last_producer_chunk_size);
+        let num_chunks = (0..num_producers)
+            .map(|i| {
+                let begin = (producer_chunk_size * i).min(total_size);
+                let region = (begin + producer_chunk_size).min(total_size) - begin;
+                let task = task_size(region);
+                (region + task)
+                    .saturating_sub(1)
+                    .checked_div(task)
+                    .unwrap_or(0)
+            })
+            .sum();
+        Layout {
+            total_size,
+            num_producers,
+            chunks_per_producer,
+            producer_chunk_size,
+            last_producer_chunk_size,
+            task_chunk_size,
+            last_task_chunk_size: producer_chunk_size
+                .saturating_sub((chunks_per_producer - 1) * task_chunk_size),
+            last_producer_task_chunk_size,
+            last_producer_last_task_chunk_size: last_producer_chunk_size
+                .saturating_sub((chunks_per_producer - 1) * last_producer_task_chunk_size),
+            num_chunks,
+        }
+    }
+    /// Offset of the region assigned to producer `i`.
+    pub fn region_offset(&self, i: u64) -> u64 {
+        (self.producer_chunk_size * i).min(self.total_size)
+    }
+    /// Size of the region assigned to producer `i`; regions are empty past
+    /// the end of the data when there are more producers than needed, e.g.
+    /// 5 producers writing 7 bytes get regions of 2, 2, 2, 1 and 0 bytes.
+    pub fn region_size(&self, i: u64) -> u64 {
+        let begin = self.region_offset(i);
+        (begin + self.producer_chunk_size).min(self.total_size) - begin
+    }
+    /// Size of each chunk but the last one generated by producer `i`.
+    pub fn chunk_size(&self, i: u64) -> u64 {
+        if i != self.num_producers - 1 {
+            self.task_chunk_size
+        } else {
+            self.last_producer_task_chunk_size
+        }
+    }
+    /// Size of the largest chunk, i.e. the size of each buffer.
+    pub fn buffer_size(&self) -> u64 {
+        self.task_chunk_size
+            .max(self.last_producer_task_chunk_size)
+            .min(self.total_size)
+    }
+    /// Number of buffers allocated with `num_buffers_per_producer` buffers
+    /// per producer, never more than the number of chunks of each producer.
+    pub fn num_buffers(&self, num_buffers_per_producer: u64) -> u64 {
+        self.num_producers * self.chunks_per_producer.min(num_buffers_per_producer)
+    }
+    /// Memory used by buffers, in bytes.
+    pub fn buffer_memory(&self, num_buffers_per_producer: u64) -> u64 {
+        self.num_buffers(num_buffers_per_producer) * self.buffer_size()
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Return the number of buffers per producer replacing
+/// `num_buffers_per_producer` when the memory used by buffers of
+/// `buffer_size` bytes is capped to `max_memory` bytes, and whether the cap is
+/// honored; at least one buffer per producer is allocated, exceeding the cap
+/// when it is lower than `num_producers * buffer_size`.
+pub(crate) fn buffers_per_producer(
+    num_buffers_per_producer: u64,
+    max_memory: Option<u64>,
+    num_producers: u64,
+    buffer_size: u64,
+) -> (u64, bool) {
+    match max_memory {
+        Some(max) => {
+            let n = max
+                .checked_div(num_producers.max(1) * buffer_size)
+                .unwrap_or(u64::MAX);
+            (n.max(1), n > 0)
+        }
+        None => (num_buffers_per_producer, true),
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Return the layout of `total_size` bytes split among `num_producers`
+/// producers generating or reading `chunks_per_producer` chunks each; zero
+/// counts are treated as one.
+pub fn plan(total_size: u64, num_producers: u64, chunks_per_producer: u64) -> Layout {
+    Layout::aligned(total_size, num_producers, chunks_per_producer, 1)
+}
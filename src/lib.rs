@@ -78,6 +78,9 @@
 //!                },
 //!                ReadError::Other(err) => {
 //!                    eprintln!("Error: {:?}", err);
+//!                },
+//!                err => {
+//!                    eprintln!("Error: {:?}", err);
 //!                }
 //!            }
 //!        }
@@ -131,15 +134,62 @@
 //!                WriteError::Producer(ProducerError{msg, offset}) => {
 //!                    eprintln!("Producer error: {} at {}", msg, offset);
 //!                },
+//!                WriteError::Consumer(ConsumerError{msg, offset}) => {
+//!                    eprintln!("Consumer error: {} at {}", msg, offset);
+//!                },
 //!                WriteError::IO(err) => {
 //!                    eprintln!("I/O error: {:?}", err);
 //!                },
+//!                WriteError::Cancelled{written} => {
+//!                    eprintln!("Cancelled after writing {} bytes", written);
+//!                },
+//!                WriteError::VerifyFailed{offset} => {
+//!                    eprintln!("Verification failed at {}", offset);
+//!                },
+//!                WriteError::Locked{filename} => {
+//!                    eprintln!("{} is locked", filename);
+//!                },
+//!                WriteError::RetryExhausted{offset, attempts, error} => {
+//!                    eprintln!("Write at {} failed {} times: {:?}", offset, attempts, error);
+//!                },
 //!                WriteError::Other(err) => {
 //!                    eprintln!("Error: {:?}", err);
 //!                },
 //!            }
 //!        }
 //!    }
+pub mod buffer;
+pub mod cancel;
+pub mod checkpoint;
+pub mod checksum;
+pub mod codec;
+#[cfg(feature = "zstd")]
+pub mod compress;
+mod config;
+pub mod container;
+pub mod copy;
+pub mod cpu;
+#[cfg(feature = "encryption")]
+pub mod crypt;
+pub mod dedup;
+pub mod diff;
+pub mod erase;
+pub mod future;
+pub mod hash;
 mod io;
+pub mod latency;
+pub mod layout;
+pub mod lock;
+pub mod ordered;
+pub mod pipe;
+pub mod pod;
+pub mod pool;
+pub mod progress;
 pub mod read;
+pub mod retry;
+pub mod select;
+pub mod stats;
+pub mod throttle;
+pub mod watchdog;
+mod worker;
 pub mod write;
@@ -0,0 +1,44 @@
+//! File locking policy applied when opening files for reading or writing.
+//!
+//! Locks are advisory on Unix (`flock`): they only prevent other processes
+//! from acquiring a conflicting lock, not from accessing the file. On Windows
+//! (`LockFileEx`) they are mandatory: an exclusive lock also prevents other
+//! handles from reading and writing the file, a shared lock from writing it.
+//!
+//! Locks are acquired without blocking and released when the file is closed,
+//! i.e. when the read or write operation completes.
+#[cfg(unix)]
+use crate::io::io_at_unix::try_lock_file;
+#[cfg(windows)]
+use crate::io::io_at_windows::try_lock_file;
+use std::fs::File;
+
+/// Lock acquired on the file before reading or writing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LockPolicy {
+    /// Do not lock the file.
+    #[default]
+    NoLock,
+    /// Fail if any other lock is held, prevent other locks.
+    Exclusive,
+    /// Fail if an exclusive lock is held, prevent exclusive locks.
+    Shared,
+}
+
+// -----------------------------------------------------------------------------
+/// Lock the whole file according to `policy` without blocking.
+///
+/// Returns `Ok(false)` if a conflicting lock is held, in which case the file
+/// is not locked; the lock is released when `file` is closed.
+pub fn try_lock(file: &File, policy: LockPolicy) -> std::io::Result<bool> {
+    let exclusive = match policy {
+        LockPolicy::NoLock => return Ok(true),
+        LockPolicy::Exclusive => true,
+        LockPolicy::Shared => false,
+    };
+    match try_lock_file(file, exclusive) {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => Ok(false),
+        Err(err) => Err(err),
+    }
+}
@@ -0,0 +1,79 @@
+//! Parallel read with ordered output to a sequential sink.
+//!
+//! Chunks are read and copied by consumer threads in parallel, a single stage
+//! running in the calling thread writes them to the sink in offset order,
+//! keeping chunks received out of order until all the preceding data has been
+//! written. The sink does not need to be seekable, e.g. standard output.
+use crate::read::{spawn_read, Consumer, ReadError, ReadOptions};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::sync::Arc;
+
+type Chunk = (u64, Vec<u8>);
+
+// -----------------------------------------------------------------------------
+/// Read file in parallel and write its content to `sink` in order.
+///
+/// Returns the number of bytes written, equal to the file size.
+/// Memory usage is not bounded when chunks are received out of order: all
+/// the chunks following a missing one are kept in memory until it arrives.
+pub fn read_ordered<W: Write>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    num_buffers_per_producer: u64,
+    sink: &mut W,
+) -> Result<usize, ReadError> {
+    let total_size = std::fs::metadata(filename).map_err(ReadError::IO)?.len();
+    let (tx, rx) = sync_channel::<Chunk>(num_consumers as usize);
+    let consume: Arc<Consumer<SyncSender<Chunk>, ()>> = Arc::new(
+        |buffer: &[u8], tx: &SyncSender<Chunk>, _chunk_id, _num_chunks, offset| {
+            // the output stage only stops receiving on sink errors, which
+            // are reported after the read completes
+            let _ = tx.send((offset, buffer.to_vec()));
+        },
+    );
+    let handle = spawn_read(
+        filename,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        consume,
+        tx,
+        num_buffers_per_producer,
+        ReadOptions::default(),
+    )?;
+    let mut pending = BTreeMap::new();
+    let mut next = 0;
+    let mut sink_err = None;
+    // receiving ends when the read completes and all senders are dropped
+    for (offset, data) in rx.iter() {
+        pending.insert(offset, data);
+        while let Some(data) = pending.remove(&next) {
+            if let Err(err) = sink.write_all(&data) {
+                sink_err = Some(err);
+                break;
+            }
+            next += data.len() as u64;
+        }
+        if sink_err.is_some() {
+            break;
+        }
+    }
+    // unblock consumers waiting to send
+    drop(rx);
+    handle.join()?;
+    if let Some(err) = sink_err {
+        return Err(ReadError::IO(err));
+    }
+    sink.flush().map_err(ReadError::IO)?;
+    if next != total_size {
+        return Err(ReadError::Other(format!(
+            "Missing data at offset {}, file size is {}",
+            next, total_size
+        )));
+    }
+    Ok(next as usize)
+}
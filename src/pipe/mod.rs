@@ -0,0 +1,284 @@
+//! Streaming transform from one file to another.
+//!
+//! Chunks read by the read pipeline are passed to a transform function on the
+//! consumer threads, the transformed data is then sent through a bounded
+//! channel to writer threads which write it to the destination file at the same
+//! offset it was read from. At most `channel_capacity` transformed chunks are
+//! waiting to be written at any time, consumers block when the channel is full.
+//!
+//! `transform_file` instead runs the write pipeline with producers reading
+//! the source chunk into the write buffer and transforming it in place:
+//! reading, transforming and writing share the same buffers and no data is
+//! copied between threads.
+use crate::config::check_counts;
+use crate::lock::LockPolicy;
+use crate::read::{producer_tasks, read_tasks, Consumer, ReadError, ReadOptions};
+use crate::write::{create_file, write_chunks, ChunkProducer, OpenMode, Region, WriteError};
+use std::fs::File;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+#[cfg(unix)]
+use crate::io::io_at_unix::*;
+
+#[cfg(windows)]
+use crate::io::io_at_windows::*;
+
+/// Transform applied to each chunk: receives the data read from the source
+/// file and the file offset and returns the data to write at the same offset
+/// in the destination file.
+pub type Transform = dyn Fn(&[u8], u64) -> Vec<u8> + Send + Sync;
+/// In-place transform used by `transform_file`: receives the data read from
+/// the source file and the file offset, modifies the data in place and
+/// returns the number of bytes to write at the same offset in the destination
+/// file, at most the size of the data.
+pub type TransformInPlace = dyn Fn(&mut [u8], u64) -> usize + Send + Sync;
+
+/// Pipe configuration.
+#[derive(Debug, Clone)]
+pub struct PipeConfig {
+    pub num_producers: u64,
+    pub num_consumers: u64,
+    pub chunks_per_producer: u64,
+    pub num_buffers_per_producer: u64,
+    /// Number of threads writing to the destination file.
+    pub num_writers: u64,
+    /// Maximum number of transformed chunks waiting to be written.
+    pub channel_capacity: usize,
+}
+
+impl Default for PipeConfig {
+    fn default() -> Self {
+        PipeConfig {
+            num_producers: 4,
+            num_consumers: 2,
+            chunks_per_producer: 2,
+            num_buffers_per_producer: 2,
+            num_writers: 2,
+            channel_capacity: 4,
+        }
+    }
+}
+
+/// Error type returned by `pipe`.
+#[derive(Debug)]
+pub enum PipeError {
+    /// Error reading source file.
+    Read(ReadError),
+    /// Error writing destination file.
+    Write(WriteError),
+    /// Transform returned a different number of bytes than it received, or
+    /// more bytes than it received with `transform_file`.
+    Size {
+        offset: u64,
+        expected: usize,
+        actual: usize,
+    },
+}
+
+type Message = (u64, Vec<u8>);
+
+// (offset, expected size, actual size) of transform output with wrong size
+type SizeMismatch = (u64, usize, usize);
+
+// -----------------------------------------------------------------------------
+/// Read `src` in parallel, transform each chunk and write the result to `dst`
+/// at the same offset, returning the number of bytes written.
+///
+/// The transform must return exactly as many bytes as it receives, the
+/// destination file has the same size as the source file.
+///
+/// The transform is invoked concurrently from all the consumer threads and
+/// chunks are processed in no particular order; transforms keeping state across
+/// chunks need interior mutability and must not rely on chunks being received
+/// in file order.
+///
+/// Memory usage is bounded by the read buffers plus `channel_capacity`
+/// transformed chunks, the source file is never loaded in memory.
+pub fn pipe(
+    src: &str,
+    dst: &str,
+    transform: Arc<Transform>,
+    config: PipeConfig,
+) -> Result<usize, PipeError> {
+    let total_size = std::fs::metadata(src)
+        .map_err(|err| PipeError::Read(ReadError::IO(err)))?
+        .len();
+    let file = create_file(dst, total_size, OpenMode::Truncate, LockPolicy::NoLock)
+        .map_err(PipeError::Write)?;
+    let (tx, rx) = sync_channel::<Message>(config.channel_capacity);
+    let writers = build_writers(config.num_writers, &file, rx).map_err(PipeError::Write)?;
+    let consume: Arc<Consumer<SyncSender<Message>, Result<(), SizeMismatch>>> = Arc::new(
+        move |buffer: &[u8], tx: &SyncSender<Message>, _chunk_id, _num_chunks, offset| {
+            let out = transform(buffer, offset);
+            if out.len() != buffer.len() {
+                return Err((offset, buffer.len(), out.len()));
+            }
+            // writers only stop receiving when they panic, which is reported
+            // when joining them
+            let _ = tx.send((offset, out));
+            Ok(())
+        },
+    );
+    let tasks = producer_tasks(total_size, config.num_producers, config.chunks_per_producer);
+    let read = read_tasks(
+        src,
+        tasks,
+        config.num_producers * config.chunks_per_producer,
+        config.num_consumers,
+        consume,
+        tx,
+        config.num_buffers_per_producer,
+        &ReadOptions::default(),
+    );
+    // all senders are dropped at this point, writers exit after draining the
+    // channel
+    let mut written = 0;
+    let mut write_err = None;
+    for w in writers {
+        match w.join() {
+            Ok(Ok(n)) => written += n,
+            Ok(Err(err)) => write_err = write_err.or(Some(err)),
+            Err(err) => write_err = write_err.or(Some(WriteError::Other(format!("{:?}", err)))),
+        }
+    }
+    if let Some(err) = write_err {
+        return Err(PipeError::Write(err));
+    }
+    for (_, r) in read.map_err(PipeError::Read)? {
+        if let Err((offset, expected, actual)) = r {
+            return Err(PipeError::Size {
+                offset,
+                expected,
+                actual,
+            });
+        }
+    }
+    Ok(written)
+}
+
+// -----------------------------------------------------------------------------
+/// Read `src` in parallel, transform each chunk in place and write the result
+/// to `dst` at the same offset, returning the number of bytes written.
+///
+/// Producer threads read each chunk into a write buffer and invoke the
+/// transform, consumer threads write the first bytes of the buffer, as many as
+/// returned by the transform, to the destination file; memory usage is
+/// bounded by `num_buffers_per_producer` buffers per producer and
+/// `num_writers` and `channel_capacity` are ignored. The destination file has
+/// the same size as the source file, the bytes of a chunk after the returned
+/// size read as zero.
+///
+/// As with `pipe` the transform is invoked concurrently and chunks are
+/// processed in no particular order.
+pub fn transform_file(
+    src: &str,
+    dst: &str,
+    transform: Arc<TransformInPlace>,
+    config: PipeConfig,
+) -> Result<usize, PipeError> {
+    check_counts(
+        config.num_producers,
+        config.num_consumers,
+        config.chunks_per_producer,
+        config.num_buffers_per_producer,
+    )
+    .map_err(|err| PipeError::Write(WriteError::Other(err)))?;
+    let source = File::open(src).map_err(|err| PipeError::Read(ReadError::IO(err)))?;
+    let total_size = source
+        .metadata()
+        .map_err(|err| PipeError::Read(ReadError::IO(err)))?
+        .len();
+    let file = create_file(dst, total_size, OpenMode::Truncate, LockPolicy::NoLock)
+        .map_err(PipeError::Write)?;
+    // first read error or size mismatch, reported instead of the producer
+    // error it causes
+    let failed: Arc<Mutex<Option<PipeError>>> = Arc::new(Mutex::new(None));
+    let fail = failed.clone();
+    let producer: Arc<ChunkProducer<(), String>> =
+        Arc::new(move |buffer: &mut Vec<u8>, _data: &(), offset: u64| {
+            let err = match read_bytes_at(buffer, &source, offset) {
+                Ok(()) => {
+                    let size = transform(buffer, offset);
+                    if size <= buffer.len() {
+                        let mut regions = vec![Region::Write(size as u64)];
+                        if size < buffer.len() {
+                            regions.push(Region::Keep((buffer.len() - size) as u64));
+                        }
+                        return Ok(Some(regions));
+                    }
+                    PipeError::Size {
+                        offset,
+                        expected: buffer.len(),
+                        actual: size,
+                    }
+                }
+                Err(err) => PipeError::Read(err),
+            };
+            let msg = format!("{:?}", err);
+            match fail.lock() {
+                Ok(mut f) => f.get_or_insert(err),
+                Err(f) => f.into_inner().get_or_insert(err),
+            };
+            Err(msg)
+        });
+    let written = write_chunks(
+        &file,
+        config.num_producers,
+        config.num_consumers,
+        config.chunks_per_producer,
+        producer,
+        (),
+        config.num_buffers_per_producer,
+        total_size as usize,
+        None,
+        None,
+        &Default::default(),
+    );
+    let failed = match failed.lock() {
+        Ok(mut f) => f.take(),
+        Err(f) => f.into_inner().take(),
+    };
+    if let Some(err) = failed {
+        return Err(err);
+    }
+    written.map(|r| r.bytes_written).map_err(PipeError::Write)
+}
+
+// -----------------------------------------------------------------------------
+/// Build writer threads writing the chunks received from `rx` to `file`.
+/// After an error writers keep draining the channel without writing so that
+/// consumers never block on a full channel.
+fn build_writers(
+    num_writers: u64,
+    file: &File,
+    rx: Receiver<Message>,
+) -> Result<Vec<thread::JoinHandle<Result<usize, WriteError>>>, WriteError> {
+    let rx = Arc::new(Mutex::new(rx));
+    let mut writers = Vec::new();
+    for _ in 0..num_writers.max(1) {
+        let file = file.try_clone().map_err(WriteError::IO)?;
+        let rx = rx.clone();
+        writers.push(thread::spawn(move || {
+            let mut written = 0;
+            let mut ret = Ok(());
+            loop {
+                let msg = match rx.lock() {
+                    Ok(rx) => rx.recv(),
+                    Err(err) => err.into_inner().recv(),
+                };
+                let (offset, data) = match msg {
+                    Ok(m) => m,
+                    Err(_) => break,
+                };
+                if ret.is_ok() {
+                    ret = write_bytes_at(&data, &file, offset);
+                    written += data.len();
+                }
+            }
+            ret.map(|_| written)
+        }));
+    }
+    Ok(writers)
+}
@@ -0,0 +1,56 @@
+//! Plain data types which can be read from any sequence of bytes, used to
+//! pass typed slices to callbacks, see `read::read_file_as`.
+//!
+//! Chunk buffers are byte vectors which are not necessarily aligned to the
+//! alignment of the element type: properly aligned buffers are reinterpreted
+//! in place, other buffers are copied to a vector of elements first.
+use std::mem::{align_of, size_of};
+
+/// Type for which any bit pattern is a valid value and which has no padding,
+/// like `bytemuck::Pod`.
+///
+/// # Safety
+///
+/// Implementors must be `Copy`, have no padding bytes and accept any bit
+/// pattern; implemented for the primitive integer and floating point types.
+pub unsafe trait Pod: Copy + Send + Sync + 'static {}
+
+macro_rules! impl_pod {
+    ($($t:ty),*) => {
+        $(unsafe impl Pod for $t {})*
+    };
+}
+
+impl_pod!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+// -----------------------------------------------------------------------------
+/// Reinterpret `bytes` as a slice of `E` elements; `None` if `bytes` is not
+/// aligned to the alignment of `E` or its length is not a multiple of the
+/// element size.
+pub(crate) fn cast<E: Pod>(bytes: &[u8]) -> Option<&[E]> {
+    if bytes.len() % size_of::<E>() != 0 || bytes.as_ptr() as usize % align_of::<E>() != 0 {
+        return None;
+    }
+    // SAFETY: the pointer is aligned, the length is a whole number of
+    // elements and any bit pattern is a valid `E`
+    Some(unsafe {
+        std::slice::from_raw_parts(bytes.as_ptr() as *const E, bytes.len() / size_of::<E>())
+    })
+}
+
+/// Copy the whole elements of `bytes` into a new vector.
+pub(crate) fn to_vec<E: Pod>(bytes: &[u8]) -> Vec<E> {
+    let len = bytes.len() / size_of::<E>();
+    let mut v = Vec::<E>::with_capacity(len);
+    // SAFETY: the destination has capacity for `len` elements, any bit
+    // pattern is a valid `E` and the source does not overlap the new vector
+    unsafe {
+        std::ptr::copy_nonoverlapping(
+            bytes.as_ptr(),
+            v.as_mut_ptr() as *mut u8,
+            len * size_of::<E>(),
+        );
+        v.set_len(len);
+    }
+    v
+}
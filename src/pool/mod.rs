@@ -0,0 +1,227 @@
+//! Reusable worker threads.
+//!
+//! Each read or write operation normally spawns its producer and consumer
+//! threads and joins them before returning; when processing many small files
+//! thread creation can dominate the run time. A `ParIoPool` keeps its threads
+//! alive across operations: `ParIoPool::read` and `ParIoPool::write` run the
+//! producers and consumers of each operation on the pool threads, and the
+//! pool can also be passed to other functions through `ReadOptions::pool` or
+//! `WriteOptions::pool`.
+//!
+//! Producers and consumers block waiting for each other, so every task must
+//! be running at the same time: when all the threads are busy, e.g. because
+//! an operation uses more threads than the pool was created with or multiple
+//! operations run concurrently, a new thread is added to the pool instead of
+//! queueing the task. `stack_size` options are ignored for tasks run on the
+//! pool.
+//!
+//! Threads exit when the last clone of the pool is dropped.
+use core::fmt::Debug;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::read::{read_file_with_options, Consumer, ReadError, ReadOptions};
+use crate::write::{write_to_file_with_options, Producer, WriteError, WriteOptions};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+struct Inner {
+    jobs: Mutex<Option<Sender<Job>>>,
+    rx: Arc<Mutex<Receiver<Job>>>,
+    // number of threads waiting for a job which was not already submitted
+    idle: Arc<AtomicUsize>,
+    threads: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl Inner {
+    /// Add a thread to the pool, not counted as idle.
+    fn add_thread(&self) -> std::io::Result<()> {
+        let rx = self.rx.clone();
+        let name = match self.threads.lock() {
+            Ok(t) => t.len(),
+            Err(err) => err.into_inner().len(),
+        };
+        let h = thread::Builder::new()
+            .name(format!("par_io-pool-{}", name))
+            .spawn(move || loop {
+                let job = match rx.lock() {
+                    Ok(rx) => rx.recv(),
+                    Err(err) => err.into_inner().recv(),
+                };
+                match job {
+                    Ok(job) => job(),
+                    // pool dropped
+                    Err(_) => break,
+                }
+            })?;
+        match self.threads.lock() {
+            Ok(mut t) => t.push(h),
+            Err(err) => err.into_inner().push(h),
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        // closing the channel makes idle threads exit
+        match self.jobs.lock() {
+            Ok(mut tx) => tx.take(),
+            Err(err) => err.into_inner().take(),
+        };
+        let threads = match self.threads.get_mut() {
+            Ok(t) => std::mem::take(t),
+            Err(err) => std::mem::take(err.into_inner()),
+        };
+        let current = thread::current().id();
+        for h in threads {
+            // the last reference might be dropped by a task running on the
+            // pool
+            if h.thread().id() != current {
+                let _ = h.join();
+            }
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Pool of threads running producers and consumers, see the module
+/// documentation.
+#[derive(Clone)]
+pub struct ParIoPool {
+    inner: Arc<Inner>,
+    num_producers: u64,
+    num_consumers: u64,
+}
+
+impl ParIoPool {
+    /// Create pool with one thread per producer and consumer; `read` and
+    /// `write` use `num_producers` producers and `num_consumers` consumers.
+    pub fn new(num_producers: u64, num_consumers: u64) -> std::io::Result<Self> {
+        let (tx, rx) = channel();
+        let inner = Arc::new(Inner {
+            jobs: Mutex::new(Some(tx)),
+            rx: Arc::new(Mutex::new(rx)),
+            idle: Arc::new(AtomicUsize::new(0)),
+            threads: Mutex::new(Vec::new()),
+        });
+        for _ in 0..num_producers + num_consumers {
+            inner.add_thread()?;
+            inner.idle.fetch_add(1, Ordering::SeqCst);
+        }
+        Ok(ParIoPool {
+            inner,
+            num_producers,
+            num_consumers,
+        })
+    }
+
+    /// Return the number of threads, including threads added when all the
+    /// threads were busy.
+    pub fn num_threads(&self) -> usize {
+        match self.inner.threads.lock() {
+            Ok(t) => t.len(),
+            Err(err) => err.into_inner().len(),
+        }
+    }
+
+    /// Run `f` on an idle thread, adding a thread if none is idle, and
+    /// return the receiver of its result; the thread is idle again before
+    /// the result is sent.
+    pub(crate) fn spawn<F, T>(&self, f: F) -> std::io::Result<Receiver<thread::Result<T>>>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (tx, rx) = channel();
+        let idle = self.inner.idle.clone();
+        self.execute(Box::new(move || {
+            let r = panic::catch_unwind(AssertUnwindSafe(f));
+            idle.fetch_add(1, Ordering::SeqCst);
+            // the receiver might have been dropped
+            let _ = tx.send(r);
+        }))?;
+        Ok(rx)
+    }
+
+    /// Run `job` on an idle thread, adding a thread if none is idle.
+    fn execute(&self, job: Job) -> std::io::Result<()> {
+        let reserved = self
+            .inner
+            .idle
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok();
+        if !reserved {
+            self.inner.add_thread()?;
+        }
+        let sent = match self.inner.jobs.lock() {
+            Ok(tx) => tx.as_ref().map(|tx| tx.send(job).is_ok()),
+            Err(err) => err.into_inner().as_ref().map(|tx| tx.send(job).is_ok()),
+        };
+        match sent {
+            Some(true) => Ok(()),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Thread pool shut down",
+            )),
+        }
+    }
+
+    /// Same as `read::read_file_with_options` with the number of producers
+    /// and consumers of the pool, running them on the pool threads.
+    pub fn read<T: 'static + Clone + Send, R: 'static + Clone + Sync + Send>(
+        &self,
+        filename: &str,
+        chunks_per_producer: u64,
+        consumer: Arc<Consumer<T, R>>,
+        client_data: T,
+        num_buffers_per_producer: u64,
+        options: ReadOptions,
+    ) -> Result<Vec<(u64, R)>, ReadError> {
+        read_file_with_options(
+            filename,
+            self.num_producers,
+            self.num_consumers,
+            chunks_per_producer,
+            consumer,
+            client_data,
+            num_buffers_per_producer,
+            ReadOptions {
+                pool: Some(self.clone()),
+                ..options
+            },
+        )
+    }
+
+    /// Same as `write::write_to_file_with_options` with the number of
+    /// producers and consumers of the pool, running them on the pool threads.
+    #[allow(clippy::too_many_arguments)]
+    pub fn write<T: 'static + Clone + Send, E: 'static + Send + Debug>(
+        &self,
+        filename: &str,
+        chunks_per_producer: u64,
+        producer: Arc<Producer<T, E>>,
+        client_data: T,
+        num_buffers_per_producer: u64,
+        total_size: usize,
+        options: WriteOptions,
+    ) -> Result<usize, WriteError> {
+        write_to_file_with_options(
+            filename,
+            self.num_producers,
+            self.num_consumers,
+            chunks_per_producer,
+            producer,
+            client_data,
+            num_buffers_per_producer,
+            total_size,
+            WriteOptions {
+                pool: Some(self.clone()),
+                ..options
+            },
+        )
+    }
+}
@@ -0,0 +1,42 @@
+//! Progress reporting.
+//!
+//! A `Progress` callback set in `ReadOptions::progress` or
+//! `WriteOptions::progress` is invoked after each chunk is consumed or
+//! written with the number of bytes processed so far by all consumers and the
+//! total number of bytes. The callback runs on consumer threads, possibly
+//! concurrently, and should return quickly; with multiple consumers the
+//! values passed to successive invocations are not necessarily increasing.
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Progress callback receiving the number of bytes processed and the total
+/// number of bytes.
+pub type Progress = dyn Fn(u64, u64) + Send + Sync;
+
+// -----------------------------------------------------------------------------
+/// Cumulative byte count shared by consumer threads.
+pub(crate) struct Tracker {
+    done: AtomicU64,
+    total: u64,
+    f: Arc<Progress>,
+}
+
+impl Tracker {
+    pub fn new(f: Arc<Progress>, total: u64) -> Self {
+        Tracker {
+            done: AtomicU64::new(0),
+            total,
+            f,
+        }
+    }
+    /// Add `bytes` to the number of bytes processed and invoke the callback;
+    /// the reported count never exceeds the total.
+    pub fn add(&self, bytes: u64) {
+        let done = self.done.fetch_add(bytes, Ordering::SeqCst) + bytes;
+        (self.f)(done.min(self.total), self.total);
+    }
+    /// Number of bytes processed so far.
+    pub fn done(&self) -> u64 {
+        self.done.load(Ordering::SeqCst)
+    }
+}
@@ -1,21 +1,47 @@
 //! Parallel async file read.
+use core::fmt::Debug;
 use std::fs::File;
 use std::ops::Fn;
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::mpsc::channel;
+use std::sync::mpsc::Receiver;
 use std::sync::mpsc::Sender;
-use std::sync::Arc;
-use std::thread;
+use std::sync::{Arc, Mutex};
 use std::thread::JoinHandle;
+use std::time::Duration;
 
-#[cfg(any(unix))]
+use crate::buffer::{allocate, check_capacity, BufferAlloc};
+use crate::cancel::CancelToken;
+use crate::config::{check_counts, ParConfig};
+use crate::cpu::{self, Affinity, CpuReport, Placement, Worker};
+use crate::latency::{LatencyReport, Recorder};
+use crate::layout::{buffers_per_producer, Layout};
+use crate::lock::{try_lock, LockPolicy};
+use crate::pod::{self, Pod};
+use crate::pool::ParIoPool;
+use crate::progress::{Progress, Tracker};
+use crate::retry::RetryPolicy;
+use crate::select::{select_consumer, ConsumerSelector};
+use crate::stats::{self, Stats, StatsReport};
+use crate::throttle::Throttle;
+use crate::watchdog::{Activity, ProgressMonitor};
+use crate::worker::{self, thread_name, Panicked};
+
+#[cfg(unix)]
 use crate::io::io_at_unix::*;
 
-#[cfg(any(windows))]
+#[cfg(windows)]
 use crate::io::io_at_windows::*;
 
 // -----------------------------------------------------------------------------
 type Senders = Vec<Sender<Message>>;
 type Buffer = Vec<u8>;
+type ProducerHandles = Vec<worker::Handle<Result<(), ReadError>>>;
+type ConsumerHandles<A> = Vec<worker::Handle<A>>;
+type ReadResult<R> = Result<Vec<(u64, R)>, ReadError>;
+type BufferId = u64;
+// buffers in the shared pool keep their id
+type Pool = Arc<Mutex<Receiver<(BufferId, Buffer)>>>;
 #[derive(Clone)]
 pub struct Config {
     chunk_id: u64,
@@ -23,16 +49,25 @@ pub struct Config {
     producer_tx: Sender<Message>,
     consumers: Senders,
     offset: u64,
+    // number of times the current chunk was extended
+    extensions: u32,
+    // id of the buffer travelling with the configuration
+    buffer_id: BufferId,
 }
 pub type ProducerConfig = Config;
 pub type ConsumerConfig = Config;
 type ProducerId = u64;
 type NumProducers = u64;
+type ConsumerId = usize;
 pub enum Message {
     Consume(ConsumerConfig, Buffer), // sent to consumers
     Produce(ProducerConfig, Buffer), // sent to producers
     End(ProducerId, NumProducers),   // sent from producers to all consumers
-                                     // to signal end of transmission
+    // to signal end of transmission
+    Extend(ConsumerConfig, Buffer, u64, ConsumerId), // sent from consumers to producers
+    // to request a chunk re-read with
+    // a new size
+    Abort, // sent to consumers to stop immediately when no progress is made
 }
 
 /// Error type containing errors generated by the producer and consumer threads and I/O operations.
@@ -42,40 +77,401 @@ pub enum ReadError {
     IO(std::io::Error),
     /// Error generated by channel send operations.
     Send(std::sync::mpsc::SendError<Message>),
+    /// Data read twice from the same offset did not match after all the
+    /// verification attempts.
+    Unstable { offset: u64, attempts: u32 },
+    /// Consumer still requesting more bytes after the maximum number of
+    /// chunk extensions.
+    ExtensionLimit { chunk_id: u64, max_extensions: u32 },
+    /// Encrypted chunk at data offset `offset` failed authentication, see
+    /// the `crypt` module (`encryption` feature).
+    Authentication { offset: u64 },
+    /// Checksum of the block at data offset `offset` does not match its
+    /// content, see the `checksum` module.
+    ChecksumMismatch { offset: u64 },
+    /// The lock required by `ReadOptions::lock` could not be acquired
+    /// because another lock is held on `filename`.
+    Locked { filename: String },
+    /// Reading the chunk at `offset` failed `attempts` times, see
+    /// `ReadOptions::retry`; `error` is the last error.
+    RetryExhausted {
+        offset: u64,
+        attempts: u32,
+        error: Box<ReadError>,
+    },
+    /// Read cancelled through `ReadOptions::cancel`; the results of the
+    /// chunks consumed before cancellation are discarded.
+    Cancelled,
+    /// No chunk was consumed for longer than `ReadOptions::progress_timeout`;
+    /// `offset` is the offset of the stalled read, if known.
+    Timeout { offset: Option<u64> },
+    /// The consumer callback panicked with message `msg` while processing
+    /// the chunk at `offset`; the results of the other chunks are discarded.
+    Panic { msg: String, offset: u64 },
+    /// End of file reached after reading `got` of the `expected` bytes at
+    /// `offset`, e.g. because the file was truncated during the read.
+    UnexpectedEof {
+        offset: u64,
+        expected: u64,
+        got: u64,
+    },
+    /// The consumer callback passed to `read_file_try` returned `error`,
+    /// formatted with `Debug`, for the chunk at `offset`; the results of the
+    /// other chunks are discarded.
+    Consumer { offset: u64, error: String },
     /// Other errors.
     Other(String),
 }
 
+impl From<std::io::Error> for ReadError {
+    fn from(err: std::io::Error) -> Self {
+        ReadError::IO(err)
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Source of data read by producer threads.
+///
+/// The default source is the file being read, opened once per producer;
+/// a custom source can be used to read from a different medium or inject
+/// faults.
+pub trait ReadAt: Send + Sync {
+    /// Fill `buffer` with data read at `offset`.
+    fn read_at(&self, buffer: &mut Vec<u8>, offset: u64) -> Result<(), ReadError>;
+    /// Fill `buffers` one after the other with data read starting at
+    /// `offset`; invokes `read_at` once per buffer by default.
+    fn read_buffers_at(&self, buffers: &mut [&mut Vec<u8>], offset: u64) -> Result<(), ReadError> {
+        let mut offset = offset;
+        for b in buffers.iter_mut() {
+            self.read_at(b, offset)?;
+            offset += b.len() as u64;
+        }
+        Ok(())
+    }
+}
+
+impl ReadAt for File {
+    fn read_at(&self, buffer: &mut Vec<u8>, offset: u64) -> Result<(), ReadError> {
+        read_bytes_at(buffer, self, offset)
+    }
+    fn read_buffers_at(&self, buffers: &mut [&mut Vec<u8>], offset: u64) -> Result<(), ReadError> {
+        let mut slices: Vec<&mut [u8]> = buffers.iter_mut().map(|b| b.as_mut_slice()).collect();
+        read_buffers_at_max(&mut slices, self, offset, usize::MAX)
+    }
+}
+
+/// File read with at most `max_io_size` bytes per system call.
+struct LimitedFile {
+    file: File,
+    max_io_size: usize,
+}
+
+impl ReadAt for LimitedFile {
+    fn read_at(&self, buffer: &mut Vec<u8>, offset: u64) -> Result<(), ReadError> {
+        read_bytes_at_max(buffer, &self.file, offset, self.max_io_size)
+    }
+    fn read_buffers_at(&self, buffers: &mut [&mut Vec<u8>], offset: u64) -> Result<(), ReadError> {
+        let mut slices: Vec<&mut [u8]> = buffers.iter_mut().map(|b| b.as_mut_slice()).collect();
+        read_buffers_at_max(&mut slices, &self.file, offset, self.max_io_size)
+    }
+}
+
+/// Return `file` as a data source honouring `max_io_size`.
+fn file_source(file: File, max_io_size: Option<usize>) -> Arc<dyn ReadAt> {
+    match max_io_size {
+        Some(max_io_size) => Arc::new(LimitedFile { file, max_io_size }),
+        None => Arc::new(file),
+    }
+}
+
+/// File read through `io_uring`, falling back to `pread` when the kernel
+/// does not support it.
+#[cfg(all(feature = "io_uring", target_os = "linux"))]
+struct UringFile {
+    file: File,
+    ring: std::sync::Mutex<Option<crate::io::uring::Ring>>,
+    segment_size: usize,
+}
+
+#[cfg(all(feature = "io_uring", target_os = "linux"))]
+impl ReadAt for UringFile {
+    fn read_at(&self, buffer: &mut Vec<u8>, offset: u64) -> Result<(), ReadError> {
+        let mut ring = match self.ring.lock() {
+            Ok(r) => r,
+            Err(err) => err.into_inner(),
+        };
+        if let Some(r) = ring.as_mut() {
+            if r.read_at(buffer, &self.file, offset, self.segment_size)? {
+                return Ok(());
+            }
+            *ring = None;
+        }
+        read_bytes_at_max(buffer, &self.file, offset, self.segment_size)
+    }
+}
+
+/// Size of the segments a chunk is split into when reading through
+/// `io_uring` and `max_io_size` is not set.
+pub const IO_URING_SEGMENT_SIZE: usize = 1 << 20;
+
+/// Number of reads submitted at once by each producer through `io_uring`.
+#[cfg(all(feature = "io_uring", target_os = "linux"))]
+const IO_URING_ENTRIES: u32 = 32;
+
+/// File read through overlapped I/O on Windows.
+#[cfg(all(feature = "overlapped", windows))]
+struct OverlappedFile {
+    reader: std::sync::Mutex<crate::io::overlapped::Reader>,
+    segment_size: usize,
+}
+
+#[cfg(all(feature = "overlapped", windows))]
+impl ReadAt for OverlappedFile {
+    fn read_at(&self, buffer: &mut Vec<u8>, offset: u64) -> Result<(), ReadError> {
+        match self.reader.lock() {
+            Ok(mut r) => r.read_at(buffer, offset, self.segment_size),
+            Err(err) => err.into_inner().read_at(buffer, offset, self.segment_size),
+        }
+    }
+}
+
+/// Number of reads in flight for each producer with overlapped I/O.
+#[cfg(all(feature = "overlapped", windows))]
+const OVERLAPPED_ENTRIES: u32 = 32;
+
+/// Return `file` as a data source read through `io_uring` or overlapped I/O
+/// if enabled and supported, through positioned reads otherwise.
+fn open_source(file: File, options: &ReadOptions) -> Arc<dyn ReadAt> {
+    #[cfg(all(feature = "io_uring", target_os = "linux"))]
+    if options.io_uring {
+        if let Ok(ring) = crate::io::uring::Ring::new(IO_URING_ENTRIES) {
+            return Arc::new(UringFile {
+                file,
+                ring: std::sync::Mutex::new(Some(ring)),
+                segment_size: options.max_io_size.unwrap_or(IO_URING_SEGMENT_SIZE),
+            });
+        }
+    }
+    #[cfg(all(feature = "overlapped", windows))]
+    if options.overlapped {
+        if let Ok(reader) = crate::io::overlapped::Reader::new(&file, OVERLAPPED_ENTRIES) {
+            return Arc::new(OverlappedFile {
+                reader: std::sync::Mutex::new(reader),
+                segment_size: options.max_io_size.unwrap_or(IO_URING_SEGMENT_SIZE),
+            });
+        }
+    }
+    file_source(file, options.max_io_size)
+}
+
+/// Source retrying failed reads according to a `RetryPolicy`.
+struct RetrySource {
+    source: Arc<dyn ReadAt>,
+    policy: RetryPolicy,
+}
+
+impl ReadAt for RetrySource {
+    fn read_at(&self, buffer: &mut Vec<u8>, offset: u64) -> Result<(), ReadError> {
+        self.policy
+            .run(|| self.source.read_at(buffer, offset))
+            .map_err(|(err, attempts)| ReadError::RetryExhausted {
+                offset,
+                attempts,
+                error: Box::new(err),
+            })
+    }
+}
+
+/// Read options.
+#[derive(Clone)]
+pub struct ReadOptions {
+    /// Read each chunk twice and compare the data, re-reading on mismatch;
+    /// doubles the amount of I/O and requires one extra buffer per producer.
+    pub double_read_verify: bool,
+    /// Number of times a chunk is re-read when the two reads do not match,
+    /// before returning `ReadError::Unstable`.
+    pub max_verify_retries: u32,
+    /// Data source used instead of the file, which is still used to
+    /// compute the size of the data.
+    pub source: Option<Arc<dyn ReadAt>>,
+    /// Record the CPU each producer and consumer thread runs on.
+    pub cpu_report: Option<Arc<CpuReport>>,
+    /// Record the time taken to read each chunk.
+    pub latency_report: Option<Arc<LatencyReport>>,
+    /// Record throughput, per-thread counters and wait times, see the
+    /// `stats` module.
+    pub stats: Option<Arc<StatsReport>>,
+    /// Stack size of producer and consumer threads, the default stack size
+    /// is used if `None`; increase for callbacks with deep recursion or large
+    /// stack allocations.
+    pub stack_size: Option<usize>,
+    /// Round chunk sizes up to a multiple of the filesystem's preferred I/O
+    /// block size (see `block_size`); fewer than `chunks_per_producer` chunks
+    /// are read when rounding makes chunks larger.
+    pub align_to_block_size: bool,
+    /// Allocate this many buffers shared by all producers instead of
+    /// `num_buffers_per_producer` buffers per producer, bounding memory
+    /// usage independently of the number of producers; a producer waits
+    /// for a buffer to be returned when none is available.
+    /// Ignored by `read_file_adaptive`.
+    pub shared_pool: Option<usize>,
+    /// Lock acquired on the file before reading, see the `lock` module;
+    /// ignored when `source` is set. When locking, all producers read through
+    /// the locked file instead of opening the file once per producer.
+    pub lock: LockPolicy,
+    /// Number of bytes at the start of the file not read, e.g. a header
+    /// parsed separately; chunks cover the rest of the file and offsets
+    /// passed to the callback are still file offsets. Nothing is read if the
+    /// file is not larger than the header.
+    pub skip_header: u64,
+    /// Function invoked on each buffer lifecycle transition, see
+    /// `BufferEvent`; buffers are allocated once in `launch` and identified
+    /// by a sequential id starting at zero.
+    pub on_buffer: Option<Arc<BufferHook>>,
+    /// Maximum number of bytes transferred by each read system call, chunks
+    /// are read with multiple calls; mostly useful to test short reads.
+    pub max_io_size: Option<usize>,
+    /// Consumer selection strategy, round-robin if `None`.
+    pub selector: Option<Arc<dyn ConsumerSelector>>,
+    /// Stop reading chunks when cancelled.
+    pub cancel: Option<Arc<CancelToken>>,
+    /// Read chunks again when reading fails, see the `retry` module.
+    pub retry: Option<RetryPolicy>,
+    /// Function invoked from consumer threads after each chunk is consumed,
+    /// see the `progress` module.
+    pub progress: Option<Arc<Progress>>,
+    /// Abort with `ReadError::Timeout` when no chunk is consumed for longer
+    /// than the specified duration, e.g. because reading from a custom
+    /// `source` blocks; consumers are joined, stalled producer threads are
+    /// left running and exit when the read returns. Stalled consumer
+    /// callbacks still block the operation.
+    pub progress_timeout: Option<Duration>,
+    /// Run producers and consumers on the threads of a pool instead of
+    /// spawning new threads, see the `pool` module.
+    pub pool: Option<ParIoPool>,
+    /// Read chunks through `io_uring` on Linux, submitting the segments of a
+    /// chunk (see `IO_URING_SEGMENT_SIZE`) at once; requires the `io_uring`
+    /// feature, `pread` is used if the feature is disabled or the kernel
+    /// does not support `io_uring`. Ignored when `source` or `lock` is set.
+    pub io_uring: bool,
+    /// Read chunks of exactly `chunk_size` bytes, the last chunk holding the
+    /// remainder, instead of deriving the chunk size from the file size and
+    /// `chunks_per_producer`; the file is split into
+    /// `ceil(file size / chunk_size)` chunks distributed among producers and
+    /// the number of chunks passed to the callback is the total number of
+    /// chunks. Overrides `align_to_block_size`.
+    pub chunk_size: Option<usize>,
+    /// Limit the aggregate throughput of consumers to the specified number
+    /// of bytes per second, best effort, see the `throttle` module.
+    pub max_bytes_per_sec: Option<u64>,
+    /// Pin producer and consumer threads to CPUs, see the `cpu` module; not
+    /// applied when `pool` is set.
+    pub affinity: Option<Affinity>,
+    /// Allocator of the chunk buffers, `buffer::DefaultAlloc` if `None`, see
+    /// the `buffer` module.
+    pub allocator: Option<Arc<dyn BufferAlloc>>,
+    /// Cap the memory used by chunk buffers to the specified number of
+    /// bytes, deriving the number of buffers per producer from the chunk size
+    /// instead of using `num_buffers_per_producer`; at least one buffer per
+    /// producer is allocated, `Stats::memory_cap_exceeded` reports when this
+    /// exceeds the cap.
+    pub max_memory_bytes: Option<u64>,
+    /// Read chunks through overlapped I/O on Windows, reading the segments
+    /// of a chunk (see `IO_URING_SEGMENT_SIZE`) concurrently from a handle
+    /// reopened with `FILE_FLAG_OVERLAPPED`; requires the `overlapped`
+    /// feature, positioned synchronous reads are used if the feature is
+    /// disabled or the file cannot be reopened. Ignored when `source` or
+    /// `lock` is set.
+    pub overlapped: bool,
+    /// Read the chunks of a producer at contiguous file offsets into the
+    /// buffers already returned to the producer with a single `preadv` call
+    /// (see `ReadAt::read_buffers_at`), reducing the number of system calls
+    /// with small chunks. Not applied with `double_read_verify` or
+    /// `shared_pool`; buffers are read one at a time on Windows and through
+    /// `io_uring` and overlapped I/O.
+    pub vectored: bool,
+    /// Read a file written by `compress::write_compressed`: the frames listed
+    /// in its index are read as chunks and decompressed before being passed
+    /// to the callback with their data offset, see
+    /// `compress::read_compressed`; `chunks_per_producer`, `chunk_size`,
+    /// `align_to_block_size` and `skip_header` are ignored. Requires the
+    /// `zstd` feature, reading fails with `ReadError::Other` otherwise.
+    pub compressed: bool,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        ReadOptions {
+            double_read_verify: false,
+            max_verify_retries: 3,
+            source: None,
+            cpu_report: None,
+            latency_report: None,
+            stats: None,
+            stack_size: None,
+            align_to_block_size: false,
+            shared_pool: None,
+            lock: LockPolicy::NoLock,
+            skip_header: 0,
+            on_buffer: None,
+            max_io_size: None,
+            selector: None,
+            cancel: None,
+            retry: None,
+            progress: None,
+            progress_timeout: None,
+            pool: None,
+            io_uring: false,
+            chunk_size: None,
+            max_bytes_per_sec: None,
+            affinity: None,
+            allocator: None,
+            max_memory_bytes: None,
+            overlapped: false,
+            vectored: false,
+            compressed: false,
+        }
+    }
+}
+
+impl ReadOptions {
+    /// CPUs worker threads are pinned to, `None` when running on a pool.
+    pub(crate) fn placement(&self) -> Option<Placement> {
+        Placement::new(self.affinity.as_ref()).filter(|_| self.pool.is_none())
+    }
+}
+
+/// Buffer lifecycle transition reported to `ReadOptions::on_buffer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BufferEvent {
+    /// Buffer filled by a producer and sent to a consumer, also sent after
+    /// a chunk extension.
+    Dispatched,
+    /// Consumer callback returned.
+    Consumed,
+    /// Buffer sent back to its producer or to the shared pool for reuse.
+    Recycled,
+}
+
+/// Function invoked with the event, buffer id and chunk id from producer and
+/// consumer threads.
+pub type BufferHook = dyn Fn(BufferEvent, u64, u64) + Send + Sync;
+
 // Moving a generic Fn instance requires customization
-type Consumer<T, R> = dyn Fn(
+pub(crate) type Consumer<T, R> = dyn Fn(
     &[u8], // data read from file
     &T,    // client data
     u64,   // chunk id
     u64,   // number of chunks
     u64,   // file offset (where data is read from)
 ) -> R;
+// Consumer borrowing non 'static data, used with scoped threads.
+type BorrowedConsumer<'a, T, R> = dyn Fn(&[u8], &T, u64, u64, u64) -> R + 'a;
 struct FnMove<T, R> {
     f: Arc<Consumer<T, R>>,
 }
-impl<T, R> FnMove<T, R> {
-    fn call(&self, buf: &[u8], d: &T, a: u64, b: u64, c: u64) -> R {
-        (self.f)(buf, d, a, b, c)
-    }
-}
 unsafe impl<T, R> Send for FnMove<T, R> {}
 
-// -----------------------------------------------------------------------------
-/// Select target consumer given current producer ID.
-/// Current implementation is round robin: each producer sends to the next
-fn select_tx(
-    _i: usize,
-    previous_consumer_id: usize,
-    num_consumers: usize,
-    _num_producers: usize,
-) -> usize {
-    (previous_consumer_id + 1) % num_consumers
-}
-
 // -----------------------------------------------------------------------------
 /// Separate file reading from data consumption using the producer-consumer pattern
 /// and a fixed number of pre-allocated buffers to achieve constant memory usage.
@@ -86,6 +482,12 @@ fn select_tx(
 /// The number of buffers equals the number of producers times the number of buffers per producer,
 /// regardless of the number of chunks read.
 ///
+/// Producers only read into buffers sent back by consumers, so the channels between producers
+/// and consumers never hold more chunks than there are buffers: producers wait when consumers
+/// fall behind. Callback results are collected for every chunk and returned, use
+/// `read_file_reduce` or `ReadBuilder::chunks` to process large files without keeping one
+/// result per chunk.
+///
 /// ## Arguments
 /// * `filename` - file to read
 /// * `num_producers` - number of producers = number of producer threads
@@ -95,6 +497,9 @@ fn select_tx(
 /// * `client_data` - data to be passed to consumer function
 /// * `num_buffers_per_producer` - number of buffers per producer; these buffers are sent to consumers and reused
 ///
+/// All counts must be greater than zero, `ReadError::Other` is returned otherwise.
+/// Reading an empty file returns an empty vector without spawning any thread.
+///
 /// ## Return
 /// * `Result<Vec<(u64, R)>, ReadError>`:
 ///     * vector of `(bytes written per chunk, callback return value)` tuples or error
@@ -110,7 +515,6 @@ fn select_tx(
 ///                             u64    // file offset (where data is read from)
 ///                            ) -> R;
 /// ```
-
 // -----------------------------------------------------------------------------
 // Internal layout.
 // ```ignore
@@ -135,214 +539,2110 @@ pub fn read_file<T: 'static + Clone + Send, R: 'static + Clone + Sync + Send>(
     client_data: T,
     num_buffers_per_producer: u64,
 ) -> Result<Vec<(u64, R)>, ReadError> {
-    let total_size = match std::fs::metadata(&filename) {
-        Ok(m) => m.len(),
-        Err(err) => {
-            return Err(ReadError::IO(err));
-        }
+    read_file_with_options(
+        filename,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        consumer,
+        client_data,
+        num_buffers_per_producer,
+        ReadOptions::default(),
+    )
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `read_file` with additional options.
+#[allow(clippy::too_many_arguments)]
+pub fn read_file_with_options<T: 'static + Clone + Send, R: 'static + Clone + Sync + Send>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    consumer: Arc<Consumer<T, R>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    options: ReadOptions,
+) -> Result<Vec<(u64, R)>, ReadError> {
+    check_counts(
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        num_buffers_per_producer,
+    )
+    .map_err(ReadError::Other)?;
+    if options.compressed {
+        #[cfg(feature = "zstd")]
+        return crate::compress::read_compressed_with_options(
+            filename,
+            num_producers,
+            num_consumers,
+            consumer,
+            client_data,
+            num_buffers_per_producer,
+            &options,
+        );
+        #[cfg(not(feature = "zstd"))]
+        return Err(ReadError::Other(
+            "Reading compressed files requires the zstd feature".to_string(),
+        ));
+    }
+    let (tasks, num_chunks) = file_tasks(filename, num_producers, chunks_per_producer, &options)?;
+    read_tasks(
+        filename,
+        tasks,
+        num_chunks,
+        num_consumers,
+        consumer,
+        client_data,
+        num_buffers_per_producer,
+        &options,
+    )
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `read_file_with_options` but reads from an already open file, e.g.
+/// a file opened with specific flags or inherited from a parent process,
+/// instead of opening it by name.
+///
+/// The file is duplicated with `File::try_clone` and the duplicate shared by
+/// producers, which read it with positional reads without moving the file
+/// cursor. `ReadOptions::source` takes precedence over the file as with
+/// `read_file_with_options`; `lock` and `io_uring` are not applied.
+#[allow(clippy::too_many_arguments)]
+pub fn read_from_file<T: 'static + Clone + Send, R: 'static + Clone + Sync + Send>(
+    file: &File,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    consumer: Arc<Consumer<T, R>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    options: ReadOptions,
+) -> Result<Vec<(u64, R)>, ReadError> {
+    check_counts(
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        num_buffers_per_producer,
+    )
+    .map_err(ReadError::Other)?;
+    let (tasks, num_chunks) = open_file_tasks(file, num_producers, chunks_per_producer, &options)?;
+    let source = match options.source {
+        Some(source) => source,
+        None => file_source(file.try_clone()?, options.max_io_size),
     };
-    let producer_chunk_size = (total_size + num_producers - 1) / num_producers;
-    let last_producer_chunk_size = total_size - (num_producers - 1) * producer_chunk_size;
-    let task_chunk_size = (producer_chunk_size + chunks_per_producer - 1) / chunks_per_producer;
-    let last_task_chunk_size = producer_chunk_size - (chunks_per_producer - 1) * task_chunk_size;
-    let last_prod_task_chunk_size =
-        (last_producer_chunk_size + chunks_per_producer - 1) / chunks_per_producer;
-    let last_last_prod_task_chunk_size =
-        last_producer_chunk_size - (chunks_per_producer - 1) * last_prod_task_chunk_size;
-
-    let (tx_producers, prods) = build_producers(num_producers, chunks_per_producer, &filename)?;
-    let (tx_consumers, consumers_handles) = build_consumers(num_consumers, consumer, client_data);
-    let reserved_size = last_task_chunk_size
-        .max(last_last_prod_task_chunk_size)
-        .max(task_chunk_size);
-    launch(
-        tx_producers,
-        tx_consumers,
-        task_chunk_size,
-        last_prod_task_chunk_size,
+    read_tasks(
+        "",
+        tasks,
+        num_chunks,
+        num_consumers,
+        consumer,
+        client_data,
+        num_buffers_per_producer,
+        &ReadOptions {
+            source: Some(source),
+            lock: LockPolicy::NoLock,
+            ..options
+        },
+    )
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `read_file_with_options` but also returns the statistics of the
+/// read, recorded through a new `StatsReport` replacing `options.stats`.
+#[allow(clippy::too_many_arguments)]
+pub fn read_file_with_stats<T: 'static + Clone + Send, R: 'static + Clone + Sync + Send>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    consumer: Arc<Consumer<T, R>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    options: ReadOptions,
+) -> (Result<Vec<(u64, R)>, ReadError>, Stats) {
+    let report = Arc::new(StatsReport::new());
+    let result = read_file_with_options(
+        filename,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        consumer,
+        client_data,
+        num_buffers_per_producer,
+        ReadOptions {
+            stats: Some(report.clone()),
+            ..options
+        },
+    );
+    (result, report.stats())
+}
+
+// -----------------------------------------------------------------------------
+/// Compute the chunks read by each producer and the number of chunks passed
+/// to the callback, honouring the `skip_header`, `align_to_block_size` and
+/// `chunk_size` options.
+fn file_tasks(
+    filename: &str,
+    num_producers: u64,
+    chunks_per_producer: u64,
+    options: &ReadOptions,
+) -> Result<(Tasks, u64), ReadError> {
+    let file = File::open(filename).map_err(ReadError::IO)?;
+    open_file_tasks(&file, num_producers, chunks_per_producer, options)
+}
+
+/// Same as `file_tasks` for an open file.
+fn open_file_tasks(
+    file: &File,
+    num_producers: u64,
+    chunks_per_producer: u64,
+    options: &ReadOptions,
+) -> Result<(Tasks, u64), ReadError> {
+    let total_size = file.metadata().map_err(ReadError::IO)?.len();
+    let body_size = total_size.saturating_sub(options.skip_header);
+    let (mut tasks, num_chunks) = if let Some(chunk_size) = options.chunk_size {
+        let chunk_size = (chunk_size as u64).max(1);
+        (
+            fixed_size_tasks(body_size, num_producers, chunk_size),
+            (body_size + chunk_size - 1) / chunk_size,
+        )
+    } else if options.align_to_block_size {
+        let block_size = io_block_size(file).map_err(ReadError::IO)?.unwrap_or(1);
+        (
+            aligned_tasks(body_size, num_producers, chunks_per_producer, block_size),
+            chunks_per_producer * num_producers,
+        )
+    } else {
+        (
+            producer_tasks(body_size, num_producers, chunks_per_producer),
+            chunks_per_producer * num_producers,
+        )
+    };
+    shift_tasks(&mut tasks, options.skip_header);
+    Ok((tasks, num_chunks))
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `read_file_with_options` but returns the results indexed by chunk:
+/// the result of chunk `chunk_id` is at index `chunk_id - 1`, chunk ids
+/// starting at one, and is `None` for chunks not read, e.g. when the file is
+/// too small to be split into the requested number of chunks.
+///
+/// Results are moved to their slot after all the consumers complete, with no
+/// sorting. The returned vector holds one `Option<R>` per chunk, like the
+/// vector returned by `read_file`; use `read_file_reduce` to keep memory usage
+/// independent of the number of chunks.
+#[allow(clippy::too_many_arguments)]
+pub fn read_file_indexed<T: 'static + Clone + Send, R: 'static + Clone + Sync + Send>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    consumer: Arc<Consumer<T, R>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    options: ReadOptions,
+) -> Result<Vec<Option<R>>, ReadError> {
+    check_counts(
+        num_producers,
+        num_consumers,
         chunks_per_producer,
-        reserved_size as usize,
         num_buffers_per_producer,
+    )
+    .map_err(ReadError::Other)?;
+    let (tasks, num_chunks) = file_tasks(filename, num_producers, chunks_per_producer, &options)?;
+    let results = read_tasks(
+        filename,
+        tasks,
+        num_chunks,
+        num_consumers,
+        consumer,
+        client_data,
+        num_buffers_per_producer,
+        &options,
     )?;
+    let mut indexed: Vec<Option<R>> = Vec::new();
+    indexed.resize_with(num_chunks as usize, || None);
+    for (chunk_id, r) in results {
+        indexed[chunk_id as usize - 1] = Some(r);
+    }
+    Ok(indexed)
+}
 
-    let mut ret = Vec::new();
-    for h in consumers_handles {
-        match h.join() {
-            Ok(chunks) => {
-                ret.extend(chunks);
-            }
-            Err(err) => {
-                return Err(ReadError::Other(format!("{:?}", err)));
+// -----------------------------------------------------------------------------
+/// Destination of `read_to_vec`, shared by consumers writing the ranges of
+/// their chunks.
+#[derive(Clone, Copy)]
+struct SharedBuffer {
+    ptr: *mut u8,
+    len: usize,
+}
+
+// Consumers only write the range of the chunk they received and chunk
+// ranges never overlap; the buffer outlives the consumers, which are all
+// joined before `read_file_with_options` returns.
+unsafe impl Send for SharedBuffer {}
+unsafe impl Sync for SharedBuffer {}
+
+// -----------------------------------------------------------------------------
+/// Read the whole file in parallel and return its content, the parallel
+/// equivalent of `std::fs::read`.
+///
+/// The returned vector is allocated once with the size of the file and each
+/// consumer copies the chunks it receives at their offset; fails with
+/// `ReadError::Other` if the file grows while being read.
+pub fn read_to_vec(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    num_buffers_per_producer: u64,
+) -> Result<Vec<u8>, ReadError> {
+    let size = std::fs::metadata(filename)?.len() as usize;
+    let mut data = vec![0_u8; size];
+    let dst = SharedBuffer {
+        ptr: data.as_mut_ptr(),
+        len: data.len(),
+    };
+    let copy =
+        |buffer: &[u8], dst: &SharedBuffer, _chunk_id: u64, _num_chunks: u64, offset: u64| {
+            let offset = offset as usize;
+            if offset + buffer.len() > dst.len {
+                return false;
             }
-        }
-    }
-    for p in prods {
-        match p.join() {
-            Ok(_) => {}
-            Err(err) => {
-                return Err(ReadError::Other(format!("{:?}", err)));
+            // SAFETY: the range is inside the destination buffer and only
+            // written by this consumer, see `SharedBuffer`
+            unsafe {
+                std::ptr::copy_nonoverlapping(buffer.as_ptr(), dst.ptr.add(offset), buffer.len());
             }
-        }
+            true
+        };
+    let copied = read_file_with_options(
+        filename,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        Arc::new(copy),
+        dst,
+        num_buffers_per_producer,
+        ReadOptions::default(),
+    )?;
+    if copied.iter().any(|(_, in_range)| !in_range) {
+        return Err(ReadError::Other(format!(
+            "{} size changed while reading",
+            filename
+        )));
     }
-    Ok(ret)
+    Ok(data)
 }
 
 // -----------------------------------------------------------------------------
-/// Build producers and return array of Sender objects.
-fn build_producers(
+/// Read the whole file in parallel discarding the data and return the number
+/// of bytes read.
+///
+/// No user code is invoked and no result is collected per chunk, which makes
+/// it suitable to measure the throughput of the read pipeline or to load a
+/// file into the page cache before reading it.
+pub fn read_file_discard(
+    filename: &str,
     num_producers: u64,
+    num_consumers: u64,
     chunks_per_producer: u64,
-    filename: &str,
-) -> Result<(Senders, Vec<JoinHandle<Result<(), ReadError>>>), ReadError> {
-    let total_size = std::fs::metadata(filename)
-        .map_err(|err| ReadError::IO(err))?
-        .len();
-    let mut tx_producers: Senders = Senders::new();
-    let producer_chunk_size = (total_size + num_producers - 1) / num_producers;
-    let last_producer_chunk_size = total_size - (num_producers - 1) * producer_chunk_size;
-    let task_chunk_size = (producer_chunk_size + chunks_per_producer - 1) / chunks_per_producer;
-    let last_prod_task_chunk_size =
-        (last_producer_chunk_size + chunks_per_producer - 1) / chunks_per_producer;
-    let mut producer_handles = Vec::new();
-    // currently producers exit after sending data, and consumers try
-    // to send data back to disconnected producers, ignoring the returned
-    // send() error
-    // another option is to have consumers return an 'Exit' signal when done
-    // consuming data and producers exiting after al the consumers have
-    // returned the signal
-    for i in 0..num_producers {
-        let (tx, rx) = channel();
-        tx_producers.push(tx);
-        let mut chunk_id = chunks_per_producer * i;
-        let mut offset = producer_chunk_size * i;
-        let end_offset = if i != num_producers - 1 {
-            offset + producer_chunk_size
-        } else {
-            offset + last_producer_chunk_size
-        };
-        let file = File::open(&filename).map_err(|err| ReadError::IO(err))?;
-        use Message::*;
-        let h = thread::spawn(move || -> Result<(), ReadError> {
-            let mut prev_consumer = i as usize;
-            while let Ok(Produce(mut cfg, mut buffer)) = rx.recv() {
-                let chunk_size = if i != num_producers - 1 {
-                    task_chunk_size.min(end_offset - offset)
-                } else {
-                    last_prod_task_chunk_size.min(end_offset - offset)
-                };
-                assert!(buffer.capacity() >= chunk_size as usize);
-                unsafe {
-                    buffer.set_len(chunk_size as usize);
-                }
-                let num_consumers = cfg.consumers.len();
-                // to support multiple consumers per producer we need to keep track of
-                // the destination; by adding the element into a Set and notify all
-                // of them when the producer exits
-                let c = select_tx(
-                    i as usize,
-                    prev_consumer,
-                    num_consumers,
-                    num_producers as usize,
-                );
-                prev_consumer = c;
-
-                match read_bytes_at(&mut buffer, &file, offset as u64) {
-                    Err(err) => {
-                        // signal the end of stream to consumers
-                        (0..cfg.consumers.len()).for_each(|x| {
-                            let _ = cfg.consumers[x].send(End(i, num_producers));
-                        });
-                        return Err(err);
-                    }
-                    Ok(()) => {
-                        chunk_id += 1;
-                        cfg.chunk_id = chunk_id;
-                        cfg.offset = offset;
-                        offset += buffer.len() as u64;
-                        if let Err(err) = cfg.consumers[c].send(Consume(cfg.clone(), buffer)) {
-                            return Err(ReadError::Send(err));
-                        }
-                        if offset as u64 >= end_offset {
-                            // signal the end of stream to consumers
-                            (0..cfg.consumers.len()).for_each(|x| {
-                                let _ = cfg.consumers[x].send(End(i, num_producers));
-                            });
-                            break;
-                        }
-                    }
-                }
-            }
-            return Ok(());
-        });
-        producer_handles.push(h);
-    }
-    Ok((tx_producers, producer_handles))
+    num_buffers_per_producer: u64,
+) -> Result<usize, ReadError> {
+    let count =
+        |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| buffer.len();
+    read_file_reduce(
+        filename,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        Arc::new(count),
+        (),
+        num_buffers_per_producer,
+        ReadOptions::default(),
+        0,
+        Arc::new(|bytes, n| bytes + n),
+        |a, b| a + b,
+    )
 }
 
 // -----------------------------------------------------------------------------
-/// Build consumers and return tuple of (Sender objects, JoinHandles)
-fn build_consumers<T: 'static + Clone + Send, R: 'static + Clone + Sync + Send>(
+/// Same as `read_file_with_options` with a callback returning a `Result`: the
+/// first error stops the read, no more chunks are read and chunks already
+/// read are returned to producers without invoking the callback, and
+/// `ReadError::Consumer` is returned with the offset of the chunk.
+///
+/// Cancelling the read through `ReadOptions::cancel` still returns
+/// `ReadError::Cancelled` unless a consumer failed first.
+#[allow(clippy::too_many_arguments)]
+pub fn read_file_try<
+    T: 'static + Clone + Send,
+    R: 'static + Clone + Sync + Send,
+    E: 'static + Debug + Send,
+>(
+    filename: &str,
+    num_producers: u64,
     num_consumers: u64,
-    f: Arc<Consumer<T, R>>,
-    data: T,
-) -> (Senders, Vec<JoinHandle<Vec<(u64, R)>>>) {
-    let mut consumers_handles = Vec::new();
-    let mut tx_consumers = Vec::new();
-    for _i in 0..num_consumers {
-        let (tx, rx) = channel();
-        tx_consumers.push(tx);
-        use Message::*;
-        let cc = FnMove { f: f.clone() };
-        let data = data.clone();
-        let h = thread::spawn(move || {
-            let mut ret = Vec::new();
-            let mut producers_end_signal_count = 0;
-            let mut _bytes = 0;
-            loop {
-                // consumers tx endpoints live inside the ReadData instance
-                // sent along messages, when producers finish sending data
-                // all transmission endpoints die resulting in recv()
-                // failing and consumers exiting
-                if let Ok(msg) = rx.recv() {
-                    match msg {
-                        Consume(cfg, buffer) => {
-                            _bytes += buffer.len();
-                            ret.push((
-                                cfg.chunk_id,
-                                cc.call(&buffer, &data, cfg.chunk_id, cfg.num_chunks, cfg.offset),
-                            ));
-                            if let Err(_err) = cfg.producer_tx.send(Produce(cfg.clone(), buffer)) {
-                                // senders might have already exited at this point after having added
-                                // data to the queue
-                                // from Rust docs
-                                // "A send operation can only fail if the receiving end of a channel is disconnected, implying that the data could never be received"
-                                // TBD
-                                //break;
-                            }
-                        }
-                        End(_prod_id, num_producers) => {
-                            producers_end_signal_count += 1;
-                            if producers_end_signal_count >= num_producers {
-                                break;
-                            }
-                        }
-                        _ => {
-                            // this should be unreachable!
-                            panic!("Wrong message type received");
-                        }
-                    }
-                } else {
-                    // we do not care if the communication channel was closed
-                    // since it only happen when the producer is finished
-                    // of an error elsewhere occurred
-                    //break;
+    chunks_per_producer: u64,
+    consumer: Arc<Consumer<T, Result<R, E>>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    options: ReadOptions,
+) -> Result<Vec<(u64, R)>, ReadError> {
+    // first error, the read is cancelled when recorded
+    let failed: Arc<Mutex<Option<(u64, String)>>> = Arc::new(Mutex::new(None));
+    let stop = Arc::new(CancelToken::linked(options.cancel.clone()));
+    let try_consume: Arc<Consumer<T, Option<R>>> = {
+        let failed = failed.clone();
+        let stop = stop.clone();
+        Arc::new(move |buffer, data, chunk_id, num_chunks, offset| {
+            match consumer(buffer, data, chunk_id, num_chunks, offset) {
+                Ok(r) => Some(r),
+                Err(err) => {
+                    let mut f = match failed.lock() {
+                        Ok(f) => f,
+                        Err(e) => e.into_inner(),
+                    };
+                    f.get_or_insert_with(|| (offset, format!("{:?}", err)));
+                    stop.cancel();
+                    None
                 }
             }
-            return ret;
-        });
-        consumers_handles.push(h);
+        })
+    };
+    let read = read_file_with_options(
+        filename,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        try_consume,
+        client_data,
+        num_buffers_per_producer,
+        ReadOptions {
+            cancel: Some(stop),
+            ..options
+        },
+    );
+    let failed = match failed.lock() {
+        Ok(mut f) => f.take(),
+        Err(e) => e.into_inner().take(),
+    };
+    if let Some((offset, error)) = failed {
+        return Err(ReadError::Consumer { offset, error });
     }
-    (tx_consumers, consumers_handles)
+    // no error recorded: all the results are set
+    Ok(read?
+        .into_iter()
+        .filter_map(|(chunk_id, r)| r.map(|r| (chunk_id, r)))
+        .collect())
+}
+
+/// Callback receiving chunks as slices of `E` elements, see `read_file_as`;
+/// the last parameter is the index of the first element of the chunk.
+pub type TypedConsumer<E, T, R> = dyn Fn(
+    &[E], // elements read from file
+    &T,   // client data
+    u64,  // chunk id
+    u64,  // number of chunks
+    u64,  // index of the first element
+) -> R;
+
+// -----------------------------------------------------------------------------
+/// Same as `read_file_with_options` but passes each chunk to `consumer` as a
+/// slice of `E` elements, e.g. `f32` samples, instead of bytes.
+///
+/// Chunk boundaries are aligned to the element size, also when
+/// `ReadOptions::chunk_size` is set, in which case it is rounded up to a
+/// multiple of the element size, and `align_to_block_size` is ignored. The
+/// offset passed to the callback is the index of the first element of the
+/// chunk, counted from `ReadOptions::skip_header`. Fails with
+/// `ReadError::Other` if the size of the data is not a multiple of the element
+/// size. Chunks whose buffer is not aligned to the alignment of `E` are copied
+/// before invoking the callback, see the `pod` module.
+#[allow(clippy::too_many_arguments)]
+pub fn read_file_as<E: Pod, T: 'static + Clone + Send, R: 'static + Clone + Sync + Send>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    consumer: Arc<TypedConsumer<E, T, R>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    options: ReadOptions,
+) -> Result<Vec<(u64, R)>, ReadError> {
+    check_counts(
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        num_buffers_per_producer,
+    )
+    .map_err(ReadError::Other)?;
+    let element_size = std::mem::size_of::<E>().max(1) as u64;
+    let total_size = std::fs::metadata(filename)?.len();
+    let body_size = total_size.saturating_sub(options.skip_header);
+    if body_size % element_size != 0 {
+        return Err(ReadError::Other(format!(
+            "{} data size {} is not a multiple of the element size {}",
+            filename, body_size, element_size
+        )));
+    }
+    let (mut tasks, num_chunks) = match options.chunk_size {
+        Some(n) => {
+            let chunk_size = ((n as u64).max(1) + element_size - 1) / element_size * element_size;
+            (
+                fixed_size_tasks(body_size, num_producers, chunk_size),
+                (body_size + chunk_size - 1) / chunk_size,
+            )
+        }
+        None => (
+            aligned_tasks(body_size, num_producers, chunks_per_producer, element_size),
+            chunks_per_producer * num_producers,
+        ),
+    };
+    shift_tasks(&mut tasks, options.skip_header);
+    let skip_header = options.skip_header;
+    let typed: Arc<Consumer<T, R>> = Arc::new(move |buffer, data, chunk_id, num_chunks, offset| {
+        let first = (offset - skip_header) / element_size;
+        match pod::cast::<E>(buffer) {
+            Some(elements) => consumer(elements, data, chunk_id, num_chunks, first),
+            None => consumer(&pod::to_vec::<E>(buffer), data, chunk_id, num_chunks, first),
+        }
+    });
+    read_tasks(
+        filename,
+        tasks,
+        num_chunks,
+        num_consumers,
+        typed,
+        client_data,
+        num_buffers_per_producer,
+        &options,
+    )
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `read_file_with_options` but, instead of collecting one result
+/// per chunk, each consumer thread folds the results of the callback into
+/// an accumulator starting from a clone of `init`; the accumulators of all
+/// consumers are merged through `combine` in consumer order.
+///
+/// Chunks are assigned to consumers independently of the callback results
+/// but the order in which each consumer receives its chunks is not
+/// deterministic: `fold` should not depend on the order of the results.
+#[allow(clippy::too_many_arguments)]
+pub fn read_file_reduce<T, R, A, C>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    consumer: Arc<Consumer<T, R>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    options: ReadOptions,
+    init: A,
+    fold: Arc<dyn Fn(A, R) -> A + Send + Sync>,
+    combine: C,
+) -> Result<A, ReadError>
+where
+    T: 'static + Clone + Send,
+    R: 'static + Clone + Sync + Send,
+    A: 'static + Clone + Send + Sync,
+    C: Fn(A, A) -> A,
+{
+    check_counts(
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        num_buffers_per_producer,
+    )
+    .map_err(ReadError::Other)?;
+    let (tasks, num_chunks) = file_tasks(filename, num_producers, chunks_per_producer, &options)?;
+    let first = init.clone();
+    let partials = read_tasks_fold(
+        filename,
+        tasks,
+        num_chunks,
+        num_consumers,
+        consumer,
+        client_data,
+        num_buffers_per_producer,
+        &options,
+        None,
+        Arc::new(move || init.clone()),
+        Arc::new(move |acc, _chunk_id, r| fold(acc, r)),
+    )?;
+    Ok(partials.into_iter().reduce(combine).unwrap_or(first))
+}
+
+// -----------------------------------------------------------------------------
+/// Builder for `read_file_with_options` calls.
+///
+/// Unset fields default to one producer per available CPU, one consumer per
+/// producer, one chunk and two buffers per producer, unit client data and
+/// default options.
+///
+/// ```ignore
+/// let chunks = ReadBuilder::new(&filename)
+///     .producers(4)
+///     .chunks_per_producer(8)
+///     .run(std::sync::Arc::new(consume))?;
+/// ```
+#[derive(Clone)]
+pub struct ReadBuilder<T = ()> {
+    filename: String,
+    config: ParConfig,
+    client_data: T,
+    options: ReadOptions,
+}
+
+impl ReadBuilder<()> {
+    /// Create builder reading from `filename`.
+    pub fn new(filename: &str) -> Self {
+        ReadBuilder {
+            filename: filename.to_string(),
+            config: ParConfig::default(),
+            client_data: (),
+            options: ReadOptions::default(),
+        }
+    }
+}
+
+impl<T: 'static + Clone + Send> ReadBuilder<T> {
+    /// Set number of producer threads.
+    pub fn producers(mut self, n: u64) -> Self {
+        self.config.num_producers = n;
+        self
+    }
+    /// Set number of consumer threads.
+    pub fn consumers(mut self, n: u64) -> Self {
+        self.config.num_consumers = Some(n);
+        self
+    }
+    /// Set number of chunks read by each producer.
+    pub fn chunks_per_producer(mut self, n: u64) -> Self {
+        self.config.chunks_per_producer = n;
+        self
+    }
+    /// Set number of buffers allocated by each producer.
+    pub fn buffers_per_producer(mut self, n: u64) -> Self {
+        self.config.num_buffers_per_producer = n;
+        self
+    }
+    /// Set data passed to the consumer callback.
+    pub fn client_data<U: 'static + Clone + Send>(self, data: U) -> ReadBuilder<U> {
+        ReadBuilder {
+            filename: self.filename,
+            config: self.config,
+            client_data: data,
+            options: self.options,
+        }
+    }
+    /// Set read options.
+    pub fn options(mut self, options: ReadOptions) -> Self {
+        self.options = options;
+        self
+    }
+    /// Read chunks of `n` bytes, see `ReadOptions::chunk_size`.
+    pub fn chunk_size(mut self, n: usize) -> Self {
+        self.options.chunk_size = Some(n);
+        self
+    }
+    /// Read through `io_uring` when available, see `ReadOptions::io_uring`.
+    pub fn io_uring(mut self, enable: bool) -> Self {
+        self.options.io_uring = enable;
+        self
+    }
+    /// Read through overlapped I/O when available, see
+    /// `ReadOptions::overlapped`.
+    pub fn overlapped(mut self, enable: bool) -> Self {
+        self.options.overlapped = enable;
+        self
+    }
+    /// Limit throughput, see `ReadOptions::max_bytes_per_sec`.
+    pub fn max_bytes_per_sec(mut self, n: u64) -> Self {
+        self.options.max_bytes_per_sec = Some(n);
+        self
+    }
+    /// Cap buffer memory, see `ReadOptions::max_memory_bytes`.
+    pub fn max_memory_bytes(mut self, n: u64) -> Self {
+        self.options.max_memory_bytes = Some(n);
+        self
+    }
+    /// Read file passing each chunk to `consumer`, return the
+    /// `(chunk id, callback return value)` tuples.
+    pub fn run<R: 'static + Clone + Sync + Send>(
+        self,
+        consumer: Arc<Consumer<T, R>>,
+    ) -> Result<Vec<(u64, R)>, ReadError> {
+        read_file_with_options(
+            &self.filename,
+            self.config.num_producers,
+            self.config.num_consumers(),
+            self.config.chunks_per_producer,
+            consumer,
+            self.client_data,
+            self.config.num_buffers_per_producer,
+            self.options,
+        )
+    }
+    /// Start reading the file and return an iterator over its chunks, see
+    /// `ChunkReader`; the number of consumers and client data are ignored.
+    pub fn chunks(self) -> Result<ChunkReader, ReadError> {
+        ChunkReader::new(
+            &self.filename,
+            self.config.num_producers,
+            self.config.chunks_per_producer,
+            self.config.num_buffers_per_producer,
+            self.options,
+        )
+    }
+    /// Same as `run` but returns the results indexed by chunk, see
+    /// `read_file_indexed`.
+    pub fn run_indexed<R: 'static + Clone + Sync + Send>(
+        self,
+        consumer: Arc<Consumer<T, R>>,
+    ) -> Result<Vec<Option<R>>, ReadError> {
+        read_file_indexed(
+            &self.filename,
+            self.config.num_producers,
+            self.config.num_consumers(),
+            self.config.chunks_per_producer,
+            consumer,
+            self.client_data,
+            self.config.num_buffers_per_producer,
+            self.options,
+        )
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `read_file` but only reads the bytes in the range `[start, end)`;
+/// chunks are computed relative to `start` and offsets passed to the callback
+/// are file offsets.
+///
+/// `end` is clamped to the file size and the number of producers to the
+/// number of bytes in the range; nothing is read if the range is empty.
+#[allow(clippy::too_many_arguments)]
+pub fn read_file_range<T: 'static + Clone + Send, R: 'static + Clone + Sync + Send>(
+    filename: &str,
+    start: u64,
+    end: u64,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    consumer: Arc<Consumer<T, R>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+) -> Result<Vec<(u64, R)>, ReadError> {
+    check_counts(
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        num_buffers_per_producer,
+    )
+    .map_err(ReadError::Other)?;
+    let file_size = std::fs::metadata(filename).map_err(ReadError::IO)?.len();
+    let end = end.min(file_size);
+    let start = start.min(end);
+    let num_producers = num_producers.min(end - start).max(1);
+    let mut tasks = producer_tasks(end - start, num_producers, chunks_per_producer);
+    shift_tasks(&mut tasks, start);
+    read_tasks(
+        filename,
+        tasks,
+        chunks_per_producer * num_producers,
+        num_consumers,
+        consumer,
+        client_data,
+        num_buffers_per_producer,
+        &ReadOptions::default(),
+    )
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `read_file` but chunks are the `(offset, size)` ranges in `chunks`
+/// instead of being computed from the file size, e.g. to read one record or
+/// one compressed frame per chunk; ranges are not required to be sorted or
+/// contiguous.
+///
+/// Chunk `k` in `chunks` has id `k + 1` and the number of chunks passed to
+/// the callback is `chunks.len()`; consecutive chunks are assigned to each
+/// producer, producers receiving at most one chunk more than each other.
+/// Offsets passed to the callback are file offsets. Options defining the
+/// chunk layout, `chunk_size`, `align_to_block_size` and `skip_header`, are
+/// ignored. Fails with `ReadError::Other` if a range extends past the end of
+/// the file.
+#[allow(clippy::too_many_arguments)]
+pub fn read_file_chunks<T: 'static + Clone + Send, R: 'static + Clone + Sync + Send>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks: &[(u64, u64)],
+    consumer: Arc<Consumer<T, R>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    options: &ReadOptions,
+) -> Result<Vec<(u64, R)>, ReadError> {
+    check_counts(num_producers, num_consumers, 1, num_buffers_per_producer)
+        .map_err(ReadError::Other)?;
+    let file_size = std::fs::metadata(filename).map_err(ReadError::IO)?.len();
+    if let Some((offset, size)) = chunks
+        .iter()
+        .find(|(offset, size)| offset.checked_add(*size).map_or(true, |e| e > file_size))
+    {
+        return Err(ReadError::Other(format!(
+            "Chunk at offset {} of {} bytes past the end of the file ({} bytes)",
+            offset, size, file_size
+        )));
+    }
+    let chunks: Vec<Chunk> = chunks
+        .iter()
+        .enumerate()
+        .map(|(k, &(offset, size))| Chunk {
+            id: k as u64 + 1,
+            offset,
+            size,
+        })
+        .collect();
+    let num_chunks = chunks.len() as u64;
+    read_tasks(
+        filename,
+        split_tasks(chunks, num_producers),
+        num_chunks,
+        num_consumers,
+        consumer,
+        client_data,
+        num_buffers_per_producer,
+        options,
+    )
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `read_file` but consumer threads are scoped to the function call:
+/// the callback and the client data can borrow from the caller's stack frame
+/// and are not required to be `'static`.
+pub fn read_file_scoped<T, R, F>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    consumer: &F,
+    client_data: T,
+    num_buffers_per_producer: u64,
+) -> Result<Vec<(u64, R)>, ReadError>
+where
+    T: Clone + Send,
+    R: Send,
+    F: Fn(&[u8], &T, u64, u64, u64) -> R + Sync,
+{
+    check_counts(
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        num_buffers_per_producer,
+    )
+    .map_err(ReadError::Other)?;
+    let total_size = std::fs::metadata(filename).map_err(ReadError::IO)?.len();
+    let tasks = producer_tasks(total_size, num_producers, chunks_per_producer);
+    let reserved_size = tasks.iter().flatten().map(|c| c.size).max().unwrap_or(0) as usize;
+    let num_buffers: Vec<u64> = tasks
+        .iter()
+        .map(|t| (t.len() as u64).min(num_buffers_per_producer).max(1))
+        .collect();
+    let chunk_count = tasks_chunk_count(&tasks);
+    // cancelled by consumers when the callback panics
+    let stop = Arc::new(CancelToken::new());
+    let panicked = Panicked::default();
+    let options = ReadOptions {
+        cancel: Some(stop.clone()),
+        ..Default::default()
+    };
+    let (tx_producers, prods) =
+        build_producers(tasks, filename, reserved_size, &options, None, None, None)?;
+    let capacity = (chunk_count + num_consumers as usize - 1) / num_consumers as usize;
+    let ret = std::thread::scope(|s| -> Result<Vec<(u64, R)>, ReadError> {
+        let mut tx_consumers = Senders::new();
+        let mut consumers_handles = Vec::new();
+        for i in 0..num_consumers {
+            let (tx, rx) = channel();
+            tx_consumers.push(tx);
+            let data = client_data.clone();
+            let stop = &stop;
+            let panicked = &panicked;
+            let h = worker::spawn_scoped(s, thread_name(Worker::Consumer(i)), None, move || {
+                consume(
+                    i,
+                    rx,
+                    consumer,
+                    &data,
+                    Vec::with_capacity(capacity),
+                    &push_result,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    Some(stop),
+                    None,
+                    Some(panicked),
+                )
+            })
+            .map_err(|err| ReadError::Other(format!("Cannot spawn consumer - {}", err)))?;
+            consumers_handles.push(h);
+        }
+        launch(
+            tx_producers,
+            tx_consumers,
+            chunks_per_producer * num_producers,
+            reserved_size,
+            &num_buffers,
+            None,
+            None,
+        );
+        let mut ret = Vec::with_capacity(chunk_count);
+        for h in consumers_handles {
+            ret.extend(
+                h.join()
+                    .map_err(|err| ReadError::Other(format!("{:?}", err)))?,
+            );
+        }
+        Ok(ret)
+    })?;
+    for p in prods {
+        p.join()
+            .map_err(|err| ReadError::Other(format!("{:?}", err)))??;
+    }
+    if let Some((msg, offset)) = panicked.take() {
+        return Err(ReadError::Panic { msg, offset });
+    }
+    Ok(ret)
+}
+
+// -----------------------------------------------------------------------------
+/// Handle to a read operation started by `spawn_read`.
+///
+/// Dropping the handle waits for the operation to complete, discarding the
+/// result, so that no thread is left running.
+pub struct ReadHandle<R> {
+    handle: Option<JoinHandle<ReadResult<R>>>,
+}
+
+impl<R> ReadHandle<R> {
+    /// Return `true` if the read operation has completed.
+    pub fn is_finished(&self) -> bool {
+        self.handle.as_ref().map_or(true, |h| h.is_finished())
+    }
+    /// Wait for the read operation to complete and return the same result
+    /// as `read_file_with_options`.
+    pub fn join(mut self) -> ReadResult<R> {
+        match self.handle.take() {
+            Some(h) => h
+                .join()
+                .map_err(|err| ReadError::Other(format!("{:?}", err)))?,
+            None => Err(ReadError::Other("Read already joined".to_string())),
+        }
+    }
+}
+
+impl<R> Drop for ReadHandle<R> {
+    fn drop(&mut self) {
+        if let Some(h) = self.handle.take() {
+            let _ = h.join();
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `read_file_with_options` but returns immediately; the read is
+/// performed in a separate thread and the returned handle is used to wait for
+/// the result.
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_read<T: 'static + Clone + Send, R: 'static + Clone + Sync + Send>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    consumer: Arc<Consumer<T, R>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    options: ReadOptions,
+) -> Result<ReadHandle<R>, ReadError> {
+    let filename = filename.to_owned();
+    let cc = FnMove { f: consumer };
+    let h = worker::spawn("par_io-read".to_string(), None, move || {
+        // move the whole wrapper, not just the non-Send field
+        let cc = cc;
+        read_file_with_options(
+            &filename,
+            num_producers,
+            num_consumers,
+            chunks_per_producer,
+            cc.f,
+            client_data,
+            num_buffers_per_producer,
+            options,
+        )
+    })
+    .map_err(|err| ReadError::Other(format!("Cannot spawn read thread - {}", err)))?;
+    Ok(ReadHandle { handle: Some(h) })
+}
+
+/// Result of adaptive consumer callback.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Consumed<R> {
+    /// Chunk consumed.
+    Done(R),
+    /// Chunk must be read again with the specified size, starting at the
+    /// same offset; the size is clamped to the end of the file.
+    NeedBytes(u64),
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `read_file` but the consumer callback can request a chunk to be
+/// read again with a different size, e.g. when a record spans past the end of
+/// the chunk, by returning `Consumed::NeedBytes`.
+///
+/// The producer which read the chunk re-reads it with the requested size and
+/// sends it again to the same consumer; chunks can overlap after extension.
+/// A chunk can be extended at most `max_extensions` times, after which
+/// `ReadError::ExtensionLimit` is returned. Buffers grow to the largest
+/// requested size.
+///
+/// Callback signature:
+///
+/// ```ignore
+/// type Consumer<T, R> = dyn Fn(&[u8], // data read from file
+///                              &T,    // client data
+///                              u64,   // chunk id
+///                              u64,   // number of chunks
+///                              u64    // file offset (where data is read from)
+///                             ) -> Consumed<R>;
+/// ```
+#[allow(clippy::too_many_arguments)]
+pub fn read_file_adaptive<T: 'static + Clone + Send, R: 'static + Clone + Sync + Send>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    consumer: Arc<Consumer<T, Consumed<R>>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    max_extensions: u32,
+) -> Result<Vec<(u64, R)>, ReadError> {
+    check_counts(
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        num_buffers_per_producer,
+    )
+    .map_err(ReadError::Other)?;
+    let total_size = std::fs::metadata(filename).map_err(ReadError::IO)?.len();
+    let tasks = producer_tasks(total_size, num_producers, chunks_per_producer);
+    let extension: Arc<Extension<Consumed<R>>> = Arc::new(|r: &Consumed<R>| match r {
+        Consumed::NeedBytes(size) => Some(*size),
+        Consumed::Done(_) => None,
+    });
+    read_tasks_extensible(
+        filename,
+        tasks,
+        chunks_per_producer * num_producers,
+        num_consumers,
+        consumer,
+        client_data,
+        num_buffers_per_producer,
+        &ReadOptions::default(),
+        Some((extension, max_extensions)),
+    )?
+    .into_iter()
+    .map(|(chunk_id, r)| match r {
+        Consumed::Done(r) => Ok((chunk_id, r)),
+        Consumed::NeedBytes(_) => Err(ReadError::ExtensionLimit {
+            chunk_id,
+            max_extensions,
+        }),
+    })
+    .collect()
+}
+
+// -----------------------------------------------------------------------------
+/// Region of the file read by a single producer task.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Chunk {
+    pub id: u64,
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// Chunks assigned to each producer, in file order.
+pub(crate) type Tasks = Vec<Vec<Chunk>>;
+
+// -----------------------------------------------------------------------------
+/// Subdivide `total_size` bytes among producers and split each producer's
+/// region into `chunks_per_producer` chunks; the last chunk of each region
+/// is shorter when sizes do not divide evenly.
+pub(crate) fn producer_tasks(
+    total_size: u64,
+    num_producers: u64,
+    chunks_per_producer: u64,
+) -> Tasks {
+    aligned_tasks(total_size, num_producers, chunks_per_producer, 1)
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `producer_tasks` with producer regions and chunk sizes rounded up
+/// to a multiple of `block_size`.
+pub(crate) fn aligned_tasks(
+    total_size: u64,
+    num_producers: u64,
+    chunks_per_producer: u64,
+    block_size: u64,
+) -> Tasks {
+    let round_up = |size: u64| (size + block_size - 1) / block_size * block_size;
+    let producer_chunk_size =
+        Layout::aligned(total_size, num_producers, chunks_per_producer, block_size)
+            .producer_chunk_size;
+    (0..num_producers)
+        .map(|i| {
+            let begin = (producer_chunk_size * i).min(total_size);
+            let end_offset = (begin + producer_chunk_size).min(total_size);
+            let task_chunk_size =
+                round_up((end_offset - begin + chunks_per_producer - 1) / chunks_per_producer);
+            let mut chunks = Vec::new();
+            let mut offset = begin;
+            while offset < end_offset {
+                let size = task_chunk_size.min(end_offset - offset);
+                chunks.push(Chunk {
+                    id: chunks_per_producer * i + chunks.len() as u64 + 1,
+                    offset,
+                    size,
+                });
+                offset += size;
+            }
+            chunks
+        })
+        .collect()
+}
+
+// -----------------------------------------------------------------------------
+/// Subdivide `total_size` bytes into chunks of `chunk_size` bytes, the last
+/// chunk holding the remainder, and assign consecutive chunks to each
+/// producer; producers get at most one chunk more than each other.
+pub(crate) fn fixed_size_tasks(total_size: u64, num_producers: u64, chunk_size: u64) -> Tasks {
+    let num_chunks = (total_size + chunk_size - 1) / chunk_size;
+    let chunks_per_producer = num_chunks / num_producers;
+    let remainder = num_chunks % num_producers;
+    let mut first = 0;
+    (0..num_producers)
+        .map(|i| {
+            let count = chunks_per_producer + u64::from(i < remainder);
+            let chunks = (first..first + count)
+                .map(|c| {
+                    let offset = c * chunk_size;
+                    Chunk {
+                        id: c + 1,
+                        offset,
+                        size: chunk_size.min(total_size - offset),
+                    }
+                })
+                .collect();
+            first += count;
+            chunks
+        })
+        .collect()
+}
+
+// -----------------------------------------------------------------------------
+/// Assign consecutive `chunks` to each producer; producers get at most one
+/// chunk more than each other and producers without chunks are dropped.
+pub(crate) fn split_tasks(chunks: Vec<Chunk>, num_producers: u64) -> Tasks {
+    let per_producer = chunks.len() / num_producers as usize;
+    let remainder = chunks.len() % num_producers as usize;
+    let mut chunks = chunks.into_iter();
+    (0..num_producers as usize)
+        .map(|i| {
+            chunks
+                .by_ref()
+                .take(per_producer + usize::from(i < remainder))
+                .collect::<Vec<_>>()
+        })
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+// -----------------------------------------------------------------------------
+/// Apply `ReadOptions::max_memory_bytes` to the number of buffers per
+/// producer.
+fn capped_buffers(
+    num_buffers_per_producer: u64,
+    tasks: &Tasks,
+    buffer_size: u64,
+    options: &ReadOptions,
+) -> u64 {
+    let (n, honored) = buffers_per_producer(
+        num_buffers_per_producer,
+        options.max_memory_bytes,
+        tasks.len() as u64,
+        buffer_size,
+    );
+    if !honored {
+        if let Some(s) = &options.stats {
+            s.memory_cap_exceeded();
+        }
+    }
+    n
+}
+
+// -----------------------------------------------------------------------------
+/// Move all chunks `offset` bytes forward.
+pub(crate) fn shift_tasks(tasks: &mut Tasks, offset: u64) {
+    tasks.iter_mut().flatten().for_each(|c| c.offset += offset);
+}
+
+// -----------------------------------------------------------------------------
+/// Return the filesystem's preferred I/O block size for `filename`, `None` if
+/// not available on the current platform.
+pub fn block_size(filename: &str) -> Result<Option<u64>, ReadError> {
+    let file = File::open(filename).map_err(ReadError::IO)?;
+    io_block_size(&file).map_err(ReadError::IO)
+}
+
+// -----------------------------------------------------------------------------
+/// Number of chunks, and therefore of elements in the vector returned by
+/// `read_file`, when reading `file_size` bytes.
+pub fn chunk_count(file_size: u64, num_producers: u64, chunks_per_producer: u64) -> usize {
+    tasks_chunk_count(&producer_tasks(
+        file_size,
+        num_producers,
+        chunks_per_producer,
+    ))
+}
+
+// -----------------------------------------------------------------------------
+/// Number of chunks actually read: can be lower than the number of producers
+/// times the number of chunks per producer when the file is small.
+pub(crate) fn tasks_chunk_count(tasks: &Tasks) -> usize {
+    tasks.iter().map(|t| t.len()).sum()
+}
+
+// -----------------------------------------------------------------------------
+/// Read the chunks in `tasks`, one producer thread per element, and pass
+/// them to consumer threads.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn read_tasks<T: 'static + Clone + Send, R: 'static + Clone + Sync + Send>(
+    filename: &str,
+    tasks: Tasks,
+    num_chunks: u64,
+    num_consumers: u64,
+    consumer: Arc<Consumer<T, R>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    options: &ReadOptions,
+) -> Result<Vec<(u64, R)>, ReadError> {
+    read_tasks_extensible(
+        filename,
+        tasks,
+        num_chunks,
+        num_consumers,
+        consumer,
+        client_data,
+        num_buffers_per_producer,
+        options,
+        None,
+    )
+}
+
+// Return the new chunk size if the consumer result is a request to re-read
+// the chunk with a different size.
+pub(crate) type Extension<R> = dyn Fn(&R) -> Option<u64> + Send + Sync;
+
+// -----------------------------------------------------------------------------
+/// Same as `read_tasks`, re-reading chunks for which `extension` returns a
+/// new size, at most `max_extensions` times per chunk.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn read_tasks_extensible<T: 'static + Clone + Send, R: 'static + Clone + Sync + Send>(
+    filename: &str,
+    tasks: Tasks,
+    num_chunks: u64,
+    num_consumers: u64,
+    consumer: Arc<Consumer<T, R>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    options: &ReadOptions,
+    extension: Option<(Arc<Extension<R>>, u32)>,
+) -> Result<Vec<(u64, R)>, ReadError> {
+    let capacity =
+        (tasks_chunk_count(&tasks) + num_consumers as usize - 1) / num_consumers as usize;
+    let partials = read_tasks_fold(
+        filename,
+        tasks,
+        num_chunks,
+        num_consumers,
+        consumer,
+        client_data,
+        num_buffers_per_producer,
+        options,
+        extension,
+        Arc::new(move || Vec::with_capacity(capacity)),
+        Arc::new(push_result),
+    )?;
+    Ok(partials.into_iter().flatten().collect())
+}
+
+// Create the initial accumulator of each consumer.
+type Init<A> = dyn Fn() -> A + Send + Sync;
+// Accumulate the result returned by the consumer callback for a chunk.
+type Fold<A, R> = dyn Fn(A, u64, R) -> A + Send + Sync;
+
+/// Append chunk id and result to vector, default accumulator of consumers.
+fn push_result<R>(mut v: Vec<(u64, R)>, chunk_id: u64, r: R) -> Vec<(u64, R)> {
+    v.push((chunk_id, r));
+    v
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `read_tasks_extensible`, each consumer accumulates the results of
+/// the callback through `fold` starting from the value returned by `init`;
+/// return the accumulators in consumer order.
+#[allow(clippy::too_many_arguments)]
+fn read_tasks_fold<
+    T: 'static + Clone + Send,
+    R: 'static + Clone + Sync + Send,
+    A: 'static + Send,
+>(
+    filename: &str,
+    tasks: Tasks,
+    num_chunks: u64,
+    num_consumers: u64,
+    consumer: Arc<Consumer<T, R>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    options: &ReadOptions,
+    extension: Option<(Arc<Extension<R>>, u32)>,
+    init: Arc<Init<A>>,
+    fold: Arc<Fold<A, R>>,
+) -> Result<Vec<A>, ReadError> {
+    // empty file or range: do not spawn threads
+    if tasks_chunk_count(&tasks) == 0 {
+        return Ok((0..num_consumers).map(|_| init()).collect());
+    }
+    // cancelled by consumers when the callback panics, or by the client
+    let panicked = Arc::new(Panicked::default());
+    let user_cancel = options.cancel.clone();
+    let options = &ReadOptions {
+        cancel: Some(Arc::new(CancelToken::linked(user_cancel.clone()))),
+        ..options.clone()
+    };
+    // consumers share the token bucket
+    let consumer: Arc<Consumer<T, R>> = match options.max_bytes_per_sec {
+        Some(rate) => {
+            let throttle = Throttle::new(rate);
+            Arc::new(move |buffer, data, chunk_id, num_chunks, offset| {
+                throttle.acquire(buffer.len());
+                consumer(buffer, data, chunk_id, num_chunks, offset)
+            })
+        }
+        None => consumer,
+    };
+    let reserved_size = tasks.iter().flatten().map(|c| c.size).max().unwrap_or(0);
+    let num_buffers_per_producer =
+        capped_buffers(num_buffers_per_producer, &tasks, reserved_size, options);
+    let num_buffers: Vec<u64> = tasks
+        .iter()
+        .map(|t| (t.len() as u64).min(num_buffers_per_producer).max(1))
+        .collect();
+    // the number of bytes consumed is also used to detect stalls
+    let progress = match (&options.progress, options.progress_timeout) {
+        (Some(f), _) => Some(f.clone()),
+        (None, Some(_)) => Some(Arc::new(|_, _| {}) as Arc<Progress>),
+        (None, None) => None,
+    }
+    .map(|f| {
+        let total = tasks.iter().flatten().map(|c| c.size).sum();
+        Arc::new(Tracker::new(f, total))
+    });
+    let activity = options
+        .progress_timeout
+        .map(|_| Arc::new(Activity::new(tasks.len() as u64)));
+    let (pool_tx, pool_rx) = match options.shared_pool {
+        Some(n) if extension.is_none() => {
+            let (tx, rx) = channel();
+            (Some((n.max(1), tx)), Some(Arc::new(Mutex::new(rx))))
+        }
+        _ => (None, None),
+    };
+    let (tx_producers, prods) = build_producers(
+        tasks,
+        filename,
+        reserved_size as usize,
+        options,
+        // with extensions enabled producers must service requests until
+        // all their buffers are returned
+        extension.as_ref().map(|_| num_buffers.as_slice()),
+        pool_rx,
+        activity.clone(),
+    )?;
+    let (tx_consumers, consumers_handles) = build_consumers(
+        num_consumers,
+        consumer,
+        client_data,
+        init,
+        fold,
+        options.cpu_report.clone(),
+        options.stats.clone(),
+        options.stack_size,
+        options.pool.clone(),
+        extension,
+        pool_tx.as_ref().map(|(_, tx)| tx.clone()),
+        options.on_buffer.clone(),
+        options.cancel.clone(),
+        progress.clone(),
+        Some(panicked.clone()),
+        options.placement(),
+    )?;
+    // stopped when going out of scope
+    let monitor = progress.zip(activity).zip(options.progress_timeout).map(
+        |((progress, activity), timeout)| {
+            let consumers = tx_consumers.clone();
+            ProgressMonitor::spawn(
+                move || progress.done(),
+                move || activity.stalled_offset(),
+                timeout,
+                move || {
+                    // make consumers exit, producers exit when they fail to
+                    // send the next chunk
+                    consumers.iter().for_each(|c| {
+                        let _ = c.send(Message::Abort);
+                    });
+                },
+            )
+        },
+    );
+    launch(
+        tx_producers,
+        tx_consumers,
+        num_chunks,
+        reserved_size as usize,
+        &num_buffers,
+        pool_tx,
+        options.allocator.as_ref(),
+    );
+
+    let mut ret = Vec::with_capacity(num_consumers as usize);
+    for h in consumers_handles {
+        match h.join() {
+            Ok(acc) => {
+                ret.push(acc);
+            }
+            Err(err) => {
+                return Err(ReadError::Other(format!("{:?}", err)));
+            }
+        }
+    }
+    if let Some(offset) = monitor.as_ref().and_then(|m| m.expired()) {
+        // stalled producers cannot be joined
+        return Err(ReadError::Timeout { offset });
+    }
+    for p in prods {
+        match p.join() {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => {
+                return Err(err);
+            }
+            Err(err) => {
+                return Err(ReadError::Other(format!("{:?}", err)));
+            }
+        }
+    }
+    if let Some((msg, offset)) = panicked.take() {
+        return Err(ReadError::Panic { msg, offset });
+    }
+    if user_cancel.as_ref().map_or(false, |c| c.is_cancelled()) {
+        return Err(ReadError::Cancelled);
+    }
+    Ok(ret)
+}
+
+// -----------------------------------------------------------------------------
+/// Iterator over the chunks of a file read in parallel by producer threads,
+/// yielding `(file offset, data)` pairs.
+///
+/// The iterator replaces the consumer threads: each call to `next` receives a
+/// chunk, copies its data into the returned vector and sends the buffer back
+/// to its producer, so that producers never read ahead by more than their
+/// buffers. Chunks are yielded in arrival order, not in file order.
+///
+/// After the last chunk, an error returned by a producer is yielded once
+/// before the iteration ends, as is `ReadError::Cancelled` if the read is
+/// cancelled through `ReadOptions::cancel`; chunks received after
+/// cancellation are discarded. Dropping the iterator stops the producers and
+/// waits for them to exit.
+pub struct ChunkReader {
+    rx: Option<Receiver<Message>>,
+    producers: ProducerHandles,
+    // end of stream signals received from producers
+    ends: worker::Ends,
+    done: bool,
+    pool: Option<Sender<(BufferId, Buffer)>>,
+    on_buffer: Option<Arc<BufferHook>>,
+    cancel: Option<Arc<CancelToken>>,
+    progress: Option<Tracker>,
+}
+
+impl ChunkReader {
+    /// Start reading `filename` with `num_producers` producers, each reading
+    /// `chunks_per_producer` chunks with `num_buffers_per_producer` buffers;
+    /// consumer options are ignored.
+    pub fn new(
+        filename: &str,
+        num_producers: u64,
+        chunks_per_producer: u64,
+        num_buffers_per_producer: u64,
+        options: ReadOptions,
+    ) -> Result<Self, ReadError> {
+        check_counts(
+            num_producers,
+            1,
+            chunks_per_producer,
+            num_buffers_per_producer,
+        )
+        .map_err(ReadError::Other)?;
+        let (tasks, num_chunks) =
+            file_tasks(filename, num_producers, chunks_per_producer, &options)?;
+        let reserved_size = tasks.iter().flatten().map(|c| c.size).max().unwrap_or(0);
+        let num_buffers_per_producer =
+            capped_buffers(num_buffers_per_producer, &tasks, reserved_size, &options);
+        let num_buffers: Vec<u64> = tasks
+            .iter()
+            .map(|t| (t.len() as u64).min(num_buffers_per_producer).max(1))
+            .collect();
+        let progress = options.progress.clone().map(|f| {
+            let total = tasks.iter().flatten().map(|c| c.size).sum();
+            Tracker::new(f, total)
+        });
+        let (pool_tx, pool_rx) = match options.shared_pool {
+            Some(n) => {
+                let (tx, rx) = channel();
+                (Some((n.max(1), tx)), Some(Arc::new(Mutex::new(rx))))
+            }
+            None => (None, None),
+        };
+        let (tx_producers, producers) = build_producers(
+            tasks,
+            filename,
+            reserved_size as usize,
+            &options,
+            None,
+            pool_rx,
+            None,
+        )?;
+        let (tx, rx) = channel();
+        let pool = pool_tx.as_ref().map(|(_, tx)| tx.clone());
+        launch(
+            tx_producers,
+            vec![tx],
+            num_chunks,
+            reserved_size as usize,
+            &num_buffers,
+            pool_tx,
+            options.allocator.as_ref(),
+        );
+        Ok(ChunkReader {
+            rx: Some(rx),
+            producers,
+            ends: worker::Ends::default(),
+            done: false,
+            pool,
+            on_buffer: options.on_buffer,
+            cancel: options.cancel,
+            progress,
+        })
+    }
+
+    /// Wait for producers to exit and return the first error.
+    fn finish(&mut self) -> Option<Result<(u64, Vec<u8>), ReadError>> {
+        self.done = true;
+        let mut error = None;
+        for p in self.producers.drain(..) {
+            let r = match p.join() {
+                Ok(r) => r,
+                Err(err) => Err(ReadError::Other(format!("{:?}", err))),
+            };
+            if let (Err(err), None) = (r, &error) {
+                error = Some(err);
+            }
+        }
+        if let Some(err) = error {
+            return Some(Err(err));
+        }
+        if self.cancel.as_ref().map_or(false, |c| c.is_cancelled()) {
+            return Some(Err(ReadError::Cancelled));
+        }
+        None
+    }
+}
+
+impl Iterator for ChunkReader {
+    type Item = Result<(u64, Vec<u8>), ReadError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        use Message::*;
+        if self.done {
+            return None;
+        }
+        loop {
+            let msg = match self.rx.as_ref().map(|rx| rx.recv()) {
+                Some(Ok(msg)) => msg,
+                _ => return self.finish(),
+            };
+            match msg {
+                Consume(cfg, buffer) => {
+                    let cancelled = self.cancel.as_ref().map_or(false, |c| c.is_cancelled());
+                    let data = if cancelled {
+                        None
+                    } else {
+                        Some(buffer.to_vec())
+                    };
+                    if let Some(hook) = &self.on_buffer {
+                        if !cancelled {
+                            hook(BufferEvent::Consumed, cfg.buffer_id, cfg.chunk_id);
+                        }
+                        hook(BufferEvent::Recycled, cfg.buffer_id, cfg.chunk_id);
+                    }
+                    let offset = cfg.offset;
+                    // the producer might have exited already
+                    if let Some(pool) = &self.pool {
+                        let _ = pool.send((cfg.buffer_id, buffer));
+                    } else {
+                        let _ = cfg.producer_tx.send(Produce(cfg.clone(), buffer));
+                    }
+                    if let Some(data) = data {
+                        if let Some(p) = &self.progress {
+                            p.add(data.len() as u64);
+                        }
+                        return Some(Ok((offset, data)));
+                    }
+                }
+                End(prod_id, num_producers) => {
+                    if self.ends.end(prod_id, num_producers) {
+                        return self.finish();
+                    }
+                }
+                _ => {
+                    panic!("Wrong message type received");
+                }
+            }
+        }
+    }
+}
+
+impl Drop for ChunkReader {
+    fn drop(&mut self) {
+        // dropping the receiver and the pool makes producers fail to send or
+        // receive buffers and exit
+        self.rx.take();
+        self.pool.take();
+        for p in self.producers.drain(..) {
+            let _ = p.join();
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Build producers and return array of Sender objects.
+fn build_producers(
+    tasks: Tasks,
+    filename: &str,
+    reserved_size: usize,
+    options: &ReadOptions,
+    wait_for_buffers: Option<&[u64]>,
+    pool: Option<Pool>,
+    activity: Option<Arc<Activity>>,
+) -> Result<(Senders, ProducerHandles), ReadError> {
+    let num_producers = tasks.len() as u64;
+    let end_of_data = tasks
+        .iter()
+        .flatten()
+        .map(|c| c.offset + c.size)
+        .max()
+        .unwrap_or(0);
+    let locked: Option<Arc<dyn ReadAt>> = match (&options.source, options.lock) {
+        (None, LockPolicy::Shared | LockPolicy::Exclusive) => {
+            let file = File::open(filename).map_err(ReadError::IO)?;
+            if !try_lock(&file, options.lock).map_err(ReadError::IO)? {
+                return Err(ReadError::Locked {
+                    filename: filename.to_string(),
+                });
+            }
+            Some(file_source(file, options.max_io_size))
+        }
+        _ => None,
+    };
+    let placement = options.placement();
+    let mut tx_producers: Senders = Senders::new();
+    let mut producer_handles = Vec::new();
+    // currently producers exit after sending data, and consumers try
+    // to send data back to disconnected producers, ignoring the returned
+    // send() error
+    // another option is to have consumers return an 'Exit' signal when done
+    // consuming data and producers exiting after al the consumers have
+    // returned the signal
+    for (i, chunks) in (0..num_producers).zip(tasks) {
+        let (tx, rx) = channel();
+        tx_producers.push(tx);
+        let source: Arc<dyn ReadAt> = match options.source.as_ref().or(locked.as_ref()) {
+            Some(source) => source.clone(),
+            None => open_source(File::open(filename).map_err(ReadError::IO)?, options),
+        };
+        let source: Arc<dyn ReadAt> = match options.retry {
+            Some(policy) => Arc::new(RetrySource { source, policy }),
+            None => source,
+        };
+        let double_read_verify = options.double_read_verify;
+        let max_verify_retries = options.max_verify_retries;
+        let cpu_report = options.cpu_report.clone();
+        let latency_report = options.latency_report.clone();
+        let stats_report = options.stats.clone();
+        let activity = activity.clone();
+        let num_buffers = wait_for_buffers.map(|n| n[i as usize]);
+        let pool = pool.clone();
+        // a failed vectored read would fail all the chunks batched
+        let vectored = options.vectored && !double_read_verify && pool.is_none();
+        let on_buffer = options.on_buffer.clone();
+        let selector = options.selector.clone();
+        let cancel = options.cancel.clone();
+        let placement = placement.clone();
+        let dispatched = move |cfg: &Config| {
+            if let Some(f) = &on_buffer {
+                f(BufferEvent::Dispatched, cfg.buffer_id, cfg.chunk_id);
+            }
+        };
+        use Message::*;
+        let h = worker::spawn_on(
+            options.pool.as_ref(),
+            thread_name(Worker::Producer(i)),
+            options.stack_size,
+            move || -> Result<(), ReadError> {
+                cpu::pin(placement.as_ref(), Worker::Producer(i));
+                let mut latency = Recorder::new(latency_report);
+                let mut counters = stats::Recorder::new(Worker::Producer(i), stats_report);
+                if let Some(r) = &cpu_report {
+                    r.record(Worker::Producer(i));
+                }
+                let mut prev_consumer = i as usize;
+                let mut check_buffer: Vec<u8> = if double_read_verify {
+                    Vec::with_capacity(reserved_size)
+                } else {
+                    Vec::new()
+                };
+                let mut chunks = chunks.into_iter().peekable();
+                // buffers returned after all chunks were sent
+                let mut idle_buffers = 0;
+                // message received while batching buffers
+                let mut pending = None;
+                loop {
+                    let wait = counters.wait_start();
+                    let msg = match pending.take() {
+                        Some(msg) => Ok(msg),
+                        None => rx.recv(),
+                    };
+                    counters.wait_stop(wait);
+                    let (mut cfg, mut buffer) = match msg {
+                        Ok(Produce(cfg, buffer)) => (cfg, buffer),
+                        Ok(Extend(mut cfg, mut buffer, size, consumer)) => {
+                            let size = size.min(end_of_data - cfg.offset);
+                            buffer.resize(size as usize, 0);
+                            if let Err(err) = source.read_at(&mut buffer, cfg.offset) {
+                                (0..cfg.consumers.len()).for_each(|x| {
+                                    let _ = cfg.consumers[x].send(End(i, num_producers));
+                                });
+                                return Err(err);
+                            }
+                            cfg.extensions += 1;
+                            dispatched(&cfg);
+                            let _ = cfg.consumers[consumer].send(Consume(cfg.clone(), buffer));
+                            continue;
+                        }
+                        _ => break,
+                    };
+                    if cancel.as_ref().map_or(false, |c| c.is_cancelled()) {
+                        // chunks already sent are drained by consumers
+                        (0..cfg.consumers.len()).for_each(|x| {
+                            let _ = cfg.consumers[x].send(End(i, num_producers));
+                        });
+                        break;
+                    }
+                    let chunk = match chunks.next() {
+                        Some(chunk) => chunk,
+                        None => {
+                            if let Some(n) = num_buffers {
+                                idle_buffers += 1;
+                                if idle_buffers < n {
+                                    continue;
+                                }
+                            }
+                            // nothing to read, signal the end of stream to consumers
+                            (0..cfg.consumers.len()).for_each(|x| {
+                                let _ = cfg.consumers[x].send(End(i, num_producers));
+                            });
+                            break;
+                        }
+                    };
+                    if let Some(pool) = &pool {
+                        // the message only carries the configuration, take a
+                        // buffer from the shared pool
+                        let wait = counters.wait_start();
+                        (cfg.buffer_id, buffer) = match pool.lock() {
+                            Ok(rx) => rx.recv(),
+                            Err(err) => err.into_inner().recv(),
+                        }
+                        .map_err(|err| ReadError::Other(format!("Buffer pool closed - {}", err)))?;
+                        counters.wait_stop(wait);
+                    }
+                    if let Err(msg) = check_capacity(&buffer, chunk.size as usize) {
+                        (0..cfg.consumers.len()).for_each(|x| {
+                            let _ = cfg.consumers[x].send(End(i, num_producers));
+                        });
+                        return Err(ReadError::Other(msg));
+                    }
+                    // only the bytes added to the buffer are zeroed, the
+                    // whole chunk is then overwritten by the read
+                    buffer.resize(chunk.size as usize, 0);
+                    let mut batch = vec![(cfg, buffer, chunk)];
+                    // read the next contiguous chunks into the buffers already
+                    // returned by consumers
+                    if vectored {
+                        loop {
+                            let end = batch
+                                .last()
+                                .map_or(0, |(_, _, chunk)| chunk.offset + chunk.size);
+                            if chunks.peek().map_or(true, |chunk| chunk.offset != end) {
+                                break;
+                            }
+                            let (cfg, mut buffer) = match rx.try_recv() {
+                                Ok(Produce(cfg, buffer)) => (cfg, buffer),
+                                Ok(msg) => {
+                                    pending = Some(msg);
+                                    break;
+                                }
+                                Err(_) => break,
+                            };
+                            let chunk = match chunks.next() {
+                                Some(chunk) => chunk,
+                                None => break,
+                            };
+                            if let Err(msg) = check_capacity(&buffer, chunk.size as usize) {
+                                (0..cfg.consumers.len()).for_each(|x| {
+                                    let _ = cfg.consumers[x].send(End(i, num_producers));
+                                });
+                                return Err(ReadError::Other(msg));
+                            }
+                            buffer.resize(chunk.size as usize, 0);
+                            batch.push((cfg, buffer, chunk));
+                        }
+                    }
+
+                    let offset = batch[0].2.offset;
+                    let start = latency.start();
+                    if let Some(a) = &activity {
+                        a.begin(i, offset);
+                    }
+                    let read = if batch.len() > 1 {
+                        let mut buffers: Vec<&mut Vec<u8>> =
+                            batch.iter_mut().map(|(_, buffer, _)| buffer).collect();
+                        source.read_buffers_at(&mut buffers, offset)
+                    } else if double_read_verify {
+                        read_verified(
+                            source.as_ref(),
+                            &mut batch[0].1,
+                            &mut check_buffer,
+                            offset,
+                            max_verify_retries,
+                        )
+                    } else {
+                        source.read_at(&mut batch[0].1, offset)
+                    };
+                    if let Some(a) = &activity {
+                        a.end(i);
+                    }
+                    latency.stop(start);
+                    if let Err(err) = read {
+                        // signal the end of stream to consumers
+                        let consumers = &batch[0].0.consumers;
+                        (0..consumers.len()).for_each(|x| {
+                            let _ = consumers[x].send(End(i, num_producers));
+                        });
+                        return Err(err);
+                    }
+                    let mut last = None;
+                    for (mut cfg, buffer, chunk) in batch {
+                        let num_consumers = cfg.consumers.len();
+                        // chunks go to any consumer, hence the end of stream is
+                        // signalled to all of them, see `worker::Ends`
+                        let c = select_consumer(
+                            selector.as_deref(),
+                            i,
+                            prev_consumer,
+                            num_consumers,
+                            num_producers as usize,
+                            chunk.offset,
+                        );
+                        prev_consumer = c;
+                        cfg.chunk_id = chunk.id;
+                        cfg.offset = chunk.offset;
+                        cfg.extensions = 0;
+                        dispatched(&cfg);
+                        counters.chunk(chunk.size);
+                        if let Err(err) = cfg.consumers[c].send(Consume(cfg.clone(), buffer)) {
+                            return Err(ReadError::Send(err));
+                        }
+                        last = Some(cfg);
+                    }
+                    if let Some(cfg) = last {
+                        if chunks.peek().is_none() && num_buffers.is_none() {
+                            // signal the end of stream to consumers
+                            (0..cfg.consumers.len()).for_each(|x| {
+                                let _ = cfg.consumers[x].send(End(i, num_producers));
+                            });
+                            break;
+                        }
+                        if pool.is_some() {
+                            // buffers are not returned to the producer,
+                            // schedule the next read
+                            let _ = cfg.producer_tx.send(Produce(cfg.clone(), Buffer::new()));
+                        }
+                    }
+                }
+                if let Some(r) = &cpu_report {
+                    r.record(Worker::Producer(i));
+                }
+                Ok(())
+            },
+        )
+        .map_err(|err| ReadError::Other(format!("Cannot spawn producer - {}", err)))?;
+        producer_handles.push(h);
+    }
+    Ok((tx_producers, producer_handles))
+}
+
+// -----------------------------------------------------------------------------
+/// Read data twice and compare, retrying up to `max_retries` times on mismatch.
+fn read_verified(
+    source: &dyn ReadAt,
+    buffer: &mut Vec<u8>,
+    check_buffer: &mut Vec<u8>,
+    offset: u64,
+    max_retries: u32,
+) -> Result<(), ReadError> {
+    check_buffer.resize(buffer.len(), 0);
+    for _ in 0..=max_retries {
+        source.read_at(buffer, offset)?;
+        source.read_at(check_buffer, offset)?;
+        if buffer == check_buffer {
+            return Ok(());
+        }
+    }
+    Err(ReadError::Unstable {
+        offset,
+        attempts: max_retries + 1,
+    })
+}
+
+// -----------------------------------------------------------------------------
+/// Build consumers and return tuple of (Sender objects, JoinHandles)
+#[allow(clippy::too_many_arguments)]
+fn build_consumers<
+    T: 'static + Clone + Send,
+    R: 'static + Clone + Sync + Send,
+    A: 'static + Send,
+>(
+    num_consumers: u64,
+    f: Arc<Consumer<T, R>>,
+    data: T,
+    init: Arc<Init<A>>,
+    fold: Arc<Fold<A, R>>,
+    cpu_report: Option<Arc<CpuReport>>,
+    stats_report: Option<Arc<StatsReport>>,
+    stack_size: Option<usize>,
+    thread_pool: Option<ParIoPool>,
+    extension: Option<(Arc<Extension<R>>, u32)>,
+    pool: Option<Sender<(BufferId, Buffer)>>,
+    on_buffer: Option<Arc<BufferHook>>,
+    cancel: Option<Arc<CancelToken>>,
+    progress: Option<Arc<Tracker>>,
+    panicked: Option<Arc<Panicked>>,
+    placement: Option<Placement>,
+) -> Result<(Senders, ConsumerHandles<A>), ReadError> {
+    let mut consumers_handles = Vec::new();
+    let mut tx_consumers = Vec::new();
+    for i in 0..num_consumers {
+        let (tx, rx) = channel();
+        tx_consumers.push(tx);
+        let cc = FnMove { f: f.clone() };
+        let data = data.clone();
+        let cpu_report = cpu_report.clone();
+        let stats_report = stats_report.clone();
+        let extension = extension.clone();
+        // with a shared pool buffers are returned to the pool instead of
+        // the producer
+        let pool = pool.clone();
+        let on_buffer = on_buffer.clone();
+        let init = init.clone();
+        let fold = fold.clone();
+        let cancel = cancel.clone();
+        let progress = progress.clone();
+        let panicked = panicked.clone();
+        let placement = placement.clone();
+        let h = worker::spawn_on(
+            thread_pool.as_ref(),
+            thread_name(Worker::Consumer(i)),
+            stack_size,
+            move || {
+                cpu::pin(placement.as_ref(), Worker::Consumer(i));
+                // move the Send wrapper, not only its field
+                let cc = cc;
+                consume(
+                    i,
+                    rx,
+                    &*cc.f,
+                    &data,
+                    init(),
+                    &*fold,
+                    cpu_report.as_deref(),
+                    stats_report,
+                    extension.as_ref(),
+                    pool.as_ref(),
+                    on_buffer.as_deref(),
+                    cancel.as_deref(),
+                    progress.as_deref(),
+                    panicked.as_deref(),
+                )
+            },
+        )
+        .map_err(|err| ReadError::Other(format!("Cannot spawn consumer - {}", err)))?;
+        consumers_handles.push(h);
+    }
+    Ok((tx_consumers, consumers_handles))
+}
+
+// -----------------------------------------------------------------------------
+/// Consume the chunks received from `rx` until all producers have signalled
+/// the end of stream, return the results of `f` accumulated into `acc`.
+#[allow(clippy::too_many_arguments)]
+fn consume<T, R, A>(
+    i: u64,
+    rx: Receiver<Message>,
+    f: &BorrowedConsumer<'_, T, R>,
+    data: &T,
+    mut acc: A,
+    fold: &dyn Fn(A, u64, R) -> A,
+    cpu_report: Option<&CpuReport>,
+    stats_report: Option<Arc<StatsReport>>,
+    extension: Option<&(Arc<Extension<R>>, u32)>,
+    pool: Option<&Sender<(BufferId, Buffer)>>,
+    on_buffer: Option<&BufferHook>,
+    cancel: Option<&CancelToken>,
+    progress: Option<&Tracker>,
+    panicked: Option<&Panicked>,
+) -> A {
+    use Message::*;
+    if let Some(r) = cpu_report {
+        r.record(Worker::Consumer(i));
+    }
+    let mut counters = stats::Recorder::new(Worker::Consumer(i), stats_report);
+    let mut ends = worker::Ends::default();
+    loop {
+        // consumers tx endpoints live inside the ReadData instance
+        // sent along messages, when producers finish sending data
+        // all transmission endpoints die resulting in recv()
+        // failing and consumers exiting
+        let wait = counters.wait_start();
+        let msg = rx.recv();
+        counters.wait_stop(wait);
+        if let Ok(msg) = msg {
+            match msg {
+                Consume(cfg, buffer) => {
+                    // after cancellation buffers are returned without
+                    // invoking the callback
+                    if !cancel.map_or(false, |c| c.is_cancelled()) {
+                        counters.chunk(buffer.len() as u64);
+                        let r = panic::catch_unwind(AssertUnwindSafe(|| {
+                            f(&buffer, data, cfg.chunk_id, cfg.num_chunks, cfg.offset)
+                        }));
+                        if let Some(hook) = on_buffer {
+                            hook(BufferEvent::Consumed, cfg.buffer_id, cfg.chunk_id);
+                        }
+                        let r = match r {
+                            Ok(r) => Some(r),
+                            Err(payload) => {
+                                // stop reading, the remaining buffers are
+                                // returned without invoking the callback
+                                if let Some(p) = panicked {
+                                    p.record(&*payload, cfg.offset);
+                                }
+                                if let Some(c) = cancel {
+                                    c.cancel();
+                                }
+                                None
+                            }
+                        };
+                        if let Some(r) = r {
+                            if let Some((extend, max_extensions)) = extension {
+                                if let Some(size) = extend(&r) {
+                                    if cfg.extensions < *max_extensions {
+                                        let chunk_id = cfg.chunk_id;
+                                        let tx = cfg.producer_tx.clone();
+                                        let len = buffer.len() as u64;
+                                        // on failure the producer has exited and the
+                                        // result is kept
+                                        if tx.send(Extend(cfg, buffer, size, i as usize)).is_err() {
+                                            acc = fold(acc, chunk_id, r);
+                                            if let Some(p) = progress {
+                                                p.add(len);
+                                            }
+                                        }
+                                        continue;
+                                    }
+                                }
+                            }
+                            acc = fold(acc, cfg.chunk_id, r);
+                            if let Some(p) = progress {
+                                p.add(buffer.len() as u64);
+                            }
+                        }
+                    }
+                    if let Some(hook) = on_buffer {
+                        hook(BufferEvent::Recycled, cfg.buffer_id, cfg.chunk_id);
+                    }
+                    if let Some(pool) = pool {
+                        let _ = pool.send((cfg.buffer_id, buffer));
+                    } else {
+                        // the producer might have already exited after having
+                        // queued its last chunk, the buffer is then dropped
+                        let _ = cfg.producer_tx.send(Produce(cfg.clone(), buffer));
+                    }
+                }
+                End(prod_id, num_producers) => {
+                    if ends.end(prod_id, num_producers) {
+                        break;
+                    }
+                }
+                Abort => break,
+                _ => {
+                    // this should be unreachable!
+                    panic!("Wrong message type received");
+                }
+            }
+        } else {
+            // all producers have disconnected, no more chunks can arrive
+            break;
+        }
+    }
+    if let Some(r) = cpu_report {
+        r.record(Worker::Consumer(i));
+    }
+    acc
 }
 
 // -----------------------------------------------------------------------------
@@ -355,44 +2655,54 @@ fn build_consumers<T: 'static + Clone + Send, R: 'static + Clone + Sync + Send>(
 /// to consume the data in a bffer while the producer is writing data to a different
 /// buffer and therefore more than one buffer per producer is required for
 /// the operation to perform asynchronously.
+/// With a shared pool each producer receives a single message without buffer
+/// and all the buffers are added to the pool.
 fn launch(
     tx_producers: Senders,
     tx_consumers: Senders,
-    task_chunk_size: u64,
-    last_producer_task_chunk_size: u64,
-    chunks_per_producer: u64,
+    num_chunks: u64,
     reserved_size: usize,
-    num_buffers_per_producer: u64,
-) -> Result<(), ReadError> {
-    let num_producers = tx_producers.len() as u64;
-    let num_buffers_per_producer = num_buffers_per_producer;
-    for i in 0..num_producers {
-        let tx = tx_producers[i as usize].clone();
+    num_buffers: &[u64],
+    pool: Option<(usize, Sender<(BufferId, Buffer)>)>,
+    alloc: Option<&Arc<dyn BufferAlloc>>,
+) {
+    if let Some((pool_size, pool_tx)) = pool {
+        for tx in &tx_producers {
+            let cfg = ProducerConfig {
+                chunk_id: 0, //overwritten
+                num_chunks,
+                producer_tx: tx.clone(),
+                consumers: tx_consumers.clone(),
+                offset: 0, // overwritten
+                extensions: 0,
+                buffer_id: 0, // overwritten
+            };
+            let _ = tx.send(Message::Produce(cfg, Buffer::new()));
+        }
+        for id in 0..pool_size {
+            let _ = pool_tx.send((id as BufferId, allocate(alloc, reserved_size)));
+        }
+        return;
+    }
+    let mut buffer_id = 0;
+    for (tx, num_buffers) in tx_producers.iter().zip(num_buffers) {
         //number of messages/buffers to be sent to each producer's queue before
         //the computation starts
-        let num_buffers = chunks_per_producer.min(num_buffers_per_producer);
-        for _ in 0..num_buffers {
-            let mut buffer: Vec<u8> = Vec::new();
-            let chunk_size = if i != num_producers - 1 {
-                task_chunk_size
-            } else {
-                last_producer_task_chunk_size
-            };
-            buffer.reserve(reserved_size);
-            unsafe {
-                buffer.set_len(chunk_size as usize);
-            }
+        for _ in 0..*num_buffers {
+            let buffer = allocate(alloc, reserved_size);
             let cfg = ProducerConfig {
                 chunk_id: 0, //overwritten
-                num_chunks: chunks_per_producer * num_producers,
+                num_chunks,
                 producer_tx: tx.clone(),
                 consumers: tx_consumers.clone(),
                 offset: 0, // overwritten
+                extensions: 0,
+                buffer_id,
             };
-            if let Err(err) = tx.send(Message::Produce(cfg, buffer)) {
-                return Err(ReadError::Send(err));
-            }
+            buffer_id += 1;
+            // the producer might have already read all its chunks using
+            // the buffers sent back by consumers and exited
+            let _ = tx.send(Message::Produce(cfg, buffer));
         }
     }
-    Ok(())
 }
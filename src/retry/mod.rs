@@ -0,0 +1,53 @@
+//! Retry of failed chunk reads and writes.
+//!
+//! With a `RetryPolicy` set in `ReadOptions::retry` or `WriteOptions::retry`
+//! a chunk whose read or write fails, e.g. because of a transient network
+//! filesystem error, is read or written again from the start of the chunk
+//! after a delay; the delay doubles after each failed attempt. After
+//! `max_attempts` failures `ReadError::RetryExhausted` or
+//! `WriteError::RetryExhausted` is returned with the last error.
+use std::time::Duration;
+
+// -----------------------------------------------------------------------------
+/// Number of attempts and delay between attempts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first; values lower than
+    /// one are treated as one.
+    pub max_attempts: u32,
+    /// Delay before the second attempt, doubled before each further attempt.
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            backoff: Duration::from_millis(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Invoke `f` until it succeeds or fails `max_attempts` times, return
+    /// the last error and the number of attempts on failure.
+    pub(crate) fn run<E, F>(&self, mut f: F) -> Result<(), (E, u32)>
+    where
+        F: FnMut() -> Result<(), E>,
+    {
+        let max_attempts = self.max_attempts.max(1);
+        let mut delay = self.backoff;
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            match f() {
+                Ok(()) => return Ok(()),
+                Err(err) if attempts >= max_attempts => return Err((err, attempts)),
+                Err(_) => {
+                    std::thread::sleep(delay);
+                    delay = delay.saturating_mul(2);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! Selection of the consumer thread receiving each chunk.
+//!
+//! Producers send chunks to consumers in round-robin order by default; a
+//! custom `ConsumerSelector` passed through `ReadOptions::selector` or
+//! `WriteOptions::selector` can route chunks differently, e.g. by offset to
+//! keep related chunks on the same consumer or away from slow consumers.
+
+/// Consumer selection strategy, invoked by producer threads before sending
+/// each chunk.
+pub trait ConsumerSelector: Send + Sync {
+    /// Return the index of the consumer receiving the chunk at `offset`
+    /// produced by `producer_id`, given the index of the consumer which
+    /// received the previous chunk from the same producer; the returned
+    /// index is taken modulo `num_consumers`.
+    ///
+    /// `prev_consumer` is initially the producer id.
+    fn select(
+        &self,
+        producer_id: u64,
+        prev_consumer: usize,
+        num_consumers: usize,
+        num_producers: usize,
+        offset: u64,
+    ) -> usize;
+}
+
+/// Default strategy: each producer sends chunks to consumers in turn.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RoundRobin;
+
+impl ConsumerSelector for RoundRobin {
+    fn select(
+        &self,
+        _producer_id: u64,
+        prev_consumer: usize,
+        num_consumers: usize,
+        _num_producers: usize,
+        _offset: u64,
+    ) -> usize {
+        (prev_consumer + 1) % num_consumers
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Select consumer through `selector`, round-robin if `None`.
+pub(crate) fn select_consumer(
+    selector: Option<&dyn ConsumerSelector>,
+    producer_id: u64,
+    prev_consumer: usize,
+    num_consumers: usize,
+    num_producers: usize,
+    offset: u64,
+) -> usize {
+    let s = selector.unwrap_or(&RoundRobin);
+    s.select(
+        producer_id,
+        prev_consumer,
+        num_consumers,
+        num_producers,
+        offset,
+    ) % num_consumers
+}
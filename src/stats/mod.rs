@@ -0,0 +1,150 @@
+//! Throughput and per-thread statistics, to tune the number of producers and
+//! consumers.
+//!
+//! Worker threads update the counters of the shared `StatsReport` after
+//! each chunk and each wait, taking a lock; the cost is negligible compared
+//! to the I/O of a chunk. Wait times are the time spent blocked receiving
+//! from channels: producers waiting for a free buffer, consumers waiting for
+//! a chunk. Producers waiting for buffers most of the time indicate too few
+//! consumers, and vice versa.
+use crate::cpu::Worker;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Statistics of one read or write operation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Total number of bytes read or written.
+    pub bytes: u64,
+    /// Time elapsed between the start of the first worker thread and the
+    /// last chunk or wait recorded.
+    pub duration: Duration,
+    /// Bytes processed by each consumer, indexed by consumer id.
+    pub consumer_bytes: Vec<u64>,
+    /// Chunks generated by each producer, indexed by producer id.
+    pub producer_chunks: Vec<u64>,
+    /// Total time producers spent waiting for buffers.
+    pub producer_wait: Duration,
+    /// Total time consumers spent waiting for chunks.
+    pub consumer_wait: Duration,
+    /// `true` when `max_memory_bytes` is lower than the memory needed by one
+    /// buffer per producer, which is allocated anyway.
+    pub memory_cap_exceeded: bool,
+}
+
+impl Stats {
+    /// Bytes per second, zero if no time elapsed.
+    pub fn throughput(&self) -> f64 {
+        let secs = self.duration.as_secs_f64();
+        if secs > 0.0 {
+            self.bytes as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Statistics recorded by all worker threads; pass a new report to each
+/// operation, counters are accumulated across operations otherwise.
+#[derive(Debug, Default)]
+pub struct StatsReport {
+    // statistics, time of first thread start and of last update
+    state: Mutex<(Stats, Option<Instant>, Option<Instant>)>,
+}
+
+impl StatsReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Return the statistics recorded so far.
+    pub fn stats(&self) -> Stats {
+        let state = match self.state.lock() {
+            Ok(s) => s,
+            Err(err) => err.into_inner(),
+        };
+        let mut stats = state.0.clone();
+        if let (Some(start), Some(end)) = (state.1, state.2) {
+            stats.duration = end.saturating_duration_since(start);
+        }
+        stats
+    }
+    /// Add `chunks` chunks of `bytes` bytes and `wait` time to the counters
+    /// of `worker`.
+    fn add(&self, worker: Worker, chunks: u64, bytes: u64, wait: Duration) {
+        let mut state = match self.state.lock() {
+            Ok(s) => s,
+            Err(err) => err.into_inner(),
+        };
+        let (stats, _, end) = &mut *state;
+        let (counts, id, n) = match worker {
+            Worker::Producer(id) => {
+                stats.producer_wait += wait;
+                (&mut stats.producer_chunks, id, chunks)
+            }
+            Worker::Consumer(id) => {
+                stats.consumer_wait += wait;
+                stats.bytes += bytes;
+                (&mut stats.consumer_bytes, id, bytes)
+            }
+        };
+        let id = id as usize;
+        if counts.len() <= id {
+            counts.resize(id + 1, 0);
+        }
+        counts[id] += n;
+        *end = Some(Instant::now());
+    }
+    /// Record that the memory cap could not be honored.
+    pub(crate) fn memory_cap_exceeded(&self) {
+        let mut state = match self.state.lock() {
+            Ok(s) => s,
+            Err(err) => err.into_inner(),
+        };
+        state.0.memory_cap_exceeded = true;
+    }
+    /// Record the start time of a worker thread.
+    fn started(&self, time: Instant) {
+        let mut state = match self.state.lock() {
+            Ok(s) => s,
+            Err(err) => err.into_inner(),
+        };
+        state.1 = Some(state.1.map_or(time, |t| t.min(time)));
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Records the counters of one worker thread. Counters are added to the
+/// report after each chunk rather than when the thread exits, because
+/// operations return without waiting for producer threads to exit.
+pub(crate) struct Recorder {
+    worker: Worker,
+    report: Option<Arc<StatsReport>>,
+}
+
+impl Recorder {
+    pub(crate) fn new(worker: Worker, report: Option<Arc<StatsReport>>) -> Self {
+        if let Some(r) = &report {
+            r.started(Instant::now());
+            // make the thread appear in the per-thread counters
+            r.add(worker, 0, 0, Duration::ZERO);
+        }
+        Recorder { worker, report }
+    }
+    /// Return the current time if recording is enabled.
+    pub(crate) fn wait_start(&self) -> Option<Instant> {
+        self.report.as_ref().map(|_| Instant::now())
+    }
+    /// Add the time elapsed since `start` to the wait time.
+    pub(crate) fn wait_stop(&mut self, start: Option<Instant>) {
+        if let (Some(r), Some(s)) = (&self.report, start) {
+            r.add(self.worker, 0, 0, s.elapsed());
+        }
+    }
+    /// Record one chunk of `bytes` bytes.
+    pub(crate) fn chunk(&mut self, bytes: u64) {
+        if let Some(r) = &self.report {
+            r.add(self.worker, 1, bytes, Duration::ZERO);
+        }
+    }
+}
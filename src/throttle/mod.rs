@@ -0,0 +1,65 @@
+//! Throughput limiting.
+//!
+//! When `ReadOptions::max_bytes_per_sec` or `WriteOptions::max_bytes_per_sec`
+//! is set, all the consumer threads of an operation share a token bucket
+//! filled at the specified rate: before consuming or writing a chunk a
+//! consumer takes as many tokens as there are bytes in the chunk and sleeps
+//! when not enough tokens are available. Producers are slowed down in turn
+//! since buffers are returned to them at the same pace.
+//!
+//! The limit applies to the aggregate throughput of the operation and is best
+//! effort: the granularity is the chunk, the bucket holds at most
+//! `BURST` worth of data, so that short bursts above the limit are possible,
+//! and sleeping time is subject to the accuracy of the operating system
+//! scheduler. Use chunks much smaller than the amount of data transferred per
+//! second for a smooth rate.
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Maximum time worth of data that can be transferred in a burst after the
+/// bucket has filled up.
+pub const BURST: Duration = Duration::from_millis(100);
+
+// -----------------------------------------------------------------------------
+/// Token bucket shared by consumer threads.
+pub(crate) struct Throttle {
+    bytes_per_sec: f64,
+    capacity: f64,
+    // available tokens, negative when chunks larger than the capacity were
+    // taken, and time of the last refill
+    state: Mutex<(f64, Instant)>,
+}
+
+impl Throttle {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        let bytes_per_sec = bytes_per_sec.max(1) as f64;
+        let capacity = bytes_per_sec * BURST.as_secs_f64();
+        Throttle {
+            bytes_per_sec,
+            capacity,
+            state: Mutex::new((capacity, Instant::now())),
+        }
+    }
+    /// Take `bytes` tokens, sleeping until the bucket is no longer in debt.
+    pub fn acquire(&self, bytes: usize) {
+        let wait = {
+            let mut state = match self.state.lock() {
+                Ok(s) => s,
+                Err(err) => err.into_inner(),
+            };
+            let (available, last) = &mut *state;
+            let now = Instant::now();
+            let refill = now.duration_since(*last).as_secs_f64() * self.bytes_per_sec;
+            *available = (*available + refill).min(self.capacity) - bytes as f64;
+            *last = now;
+            if *available < 0. {
+                Duration::from_secs_f64(-*available / self.bytes_per_sec)
+            } else {
+                Duration::ZERO
+            }
+        };
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Detection of stalled callbacks.
+//!
+//! Each producer records when a callback invocation starts and ends; a monitor
+//! thread periodically checks the recorded timestamps and reports the
+//! invocations running for longer than the configured duration.
+//! Threads cannot be safely killed, so stalled callbacks are only reported.
+//!
+//! A second monitor aborts the operation when no chunk completes within a
+//! timeout, see `ReadOptions::progress_timeout` and
+//! `WriteOptions::progress_timeout`; stalled threads are left running and
+//! exit when they resume.
+use crate::worker;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Callback invocation running for longer than the configured duration.
+#[derive(Debug, Clone)]
+pub struct StallWarning {
+    /// Id of the producer thread invoking the callback.
+    pub producer_id: u64,
+    /// File offset passed to the callback.
+    pub offset: u64,
+    /// Time elapsed since the callback was invoked.
+    pub elapsed: Duration,
+}
+
+/// Function called from the monitor thread for each stalled invocation.
+pub type StallHandler = dyn Fn(&StallWarning) + Send + Sync;
+
+// (start time, offset, already reported)
+type Slot = Mutex<Option<(Instant, u64, bool)>>;
+
+// -----------------------------------------------------------------------------
+/// Per-producer record of the callback invocation in progress.
+pub(crate) struct Activity {
+    slots: Vec<Slot>,
+}
+
+impl Activity {
+    pub(crate) fn new(num_producers: u64) -> Self {
+        Activity {
+            slots: (0..num_producers).map(|_| Mutex::new(None)).collect(),
+        }
+    }
+    /// Record the start of a callback invocation.
+    pub(crate) fn begin(&self, producer_id: u64, offset: u64) {
+        if let Ok(mut s) = self.slots[producer_id as usize].lock() {
+            *s = Some((Instant::now(), offset, false));
+        }
+    }
+    /// Record the end of a callback invocation.
+    pub(crate) fn end(&self, producer_id: u64) {
+        if let Ok(mut s) = self.slots[producer_id as usize].lock() {
+            *s = None;
+        }
+    }
+    /// Return the offset of the longest running invocation, if any.
+    pub(crate) fn stalled_offset(&self) -> Option<u64> {
+        self.slots
+            .iter()
+            .filter_map(|slot| slot.lock().ok().and_then(|s| s.map(|(t, o, _)| (t, o))))
+            .min_by_key(|(start, _)| *start)
+            .map(|(_, offset)| offset)
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Monitor thread, stopped and joined when dropped.
+pub(crate) struct Watchdog {
+    done: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    /// Start monitoring `activity`, reporting each invocation running for
+    /// longer than `timeout` once through `handler`.
+    pub(crate) fn spawn(
+        activity: Arc<Activity>,
+        timeout: Duration,
+        handler: Arc<StallHandler>,
+    ) -> Self {
+        let done = Arc::new(AtomicBool::new(false));
+        let stop = done.clone();
+        let period = (timeout / 4).clamp(Duration::from_millis(1), Duration::from_millis(100));
+        let handle = worker::spawn("par_io-watchdog".to_string(), None, move || {
+            while !stop.load(Ordering::SeqCst) {
+                thread::sleep(period);
+                for (i, slot) in activity.slots.iter().enumerate() {
+                    let warning = match slot.lock() {
+                        Ok(mut s) => match s.as_mut() {
+                            Some((start, offset, reported))
+                                if !*reported && start.elapsed() > timeout =>
+                            {
+                                *reported = true;
+                                Some(StallWarning {
+                                    producer_id: i as u64,
+                                    offset: *offset,
+                                    elapsed: start.elapsed(),
+                                })
+                            }
+                            _ => None,
+                        },
+                        Err(_) => None,
+                    };
+                    if let Some(w) = warning {
+                        handler(&w);
+                    }
+                }
+            }
+        })
+        .expect("failed to spawn thread");
+        Watchdog {
+            done,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.done.store(true, Ordering::SeqCst);
+        if let Some(h) = self.handle.take() {
+            let _ = h.join();
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Monitor thread aborting an operation when no progress is made, stopped and
+/// joined when dropped.
+pub(crate) struct ProgressMonitor {
+    done: Arc<AtomicBool>,
+    // set when aborted, with the offset of the stalled chunk if known
+    expired: Arc<Mutex<Option<Option<u64>>>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl ProgressMonitor {
+    /// Invoke `abort` once if the value returned by `progress` does not
+    /// change for longer than `timeout`; `stalled` returns the offset of the
+    /// stalled chunk, if known.
+    pub(crate) fn spawn<P, S, A>(progress: P, stalled: S, timeout: Duration, abort: A) -> Self
+    where
+        P: Fn() -> u64 + Send + 'static,
+        S: Fn() -> Option<u64> + Send + 'static,
+        A: FnOnce() + Send + 'static,
+    {
+        let done = Arc::new(AtomicBool::new(false));
+        let expired = Arc::new(Mutex::new(None));
+        let stop = done.clone();
+        let exp = expired.clone();
+        let period = (timeout / 4).clamp(Duration::from_millis(1), Duration::from_millis(100));
+        let handle = worker::spawn("par_io-monitor".to_string(), None, move || {
+            let mut last = progress();
+            let mut last_change = Instant::now();
+            while !stop.load(Ordering::SeqCst) {
+                // woken up when dropped
+                thread::park_timeout(period);
+                let current = progress();
+                if current != last {
+                    last = current;
+                    last_change = Instant::now();
+                } else if last_change.elapsed() > timeout && !stop.load(Ordering::SeqCst) {
+                    // recorded before aborting, the operation checks it as
+                    // soon as its threads exit
+                    let offset = stalled();
+                    match exp.lock() {
+                        Ok(mut e) => *e = Some(offset),
+                        Err(err) => *err.into_inner() = Some(offset),
+                    }
+                    abort();
+                    break;
+                }
+            }
+        })
+        .expect("failed to spawn thread");
+        ProgressMonitor {
+            done,
+            expired,
+            handle: Some(handle),
+        }
+    }
+    /// Return `Some` with the offset of the stalled chunk, if known, if the
+    /// operation was aborted.
+    pub(crate) fn expired(&self) -> Option<Option<u64>> {
+        match self.expired.lock() {
+            Ok(e) => *e,
+            Err(err) => *err.into_inner(),
+        }
+    }
+}
+
+impl Drop for ProgressMonitor {
+    fn drop(&mut self) {
+        self.done.store(true, Ordering::SeqCst);
+        if let Some(h) = self.handle.take() {
+            h.thread().unpark();
+            let _ = h.join();
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! Worker thread creation.
+//!
+//! Threads are named after their role, e.g. `par_io-producer-3` or
+//! `par_io-consumer-1`, so that they can be told apart in debuggers,
+//! profilers and panic messages; operating systems may truncate the name,
+//! Linux keeps only the first 15 bytes. Tasks run on a `ParIoPool` keep the
+//! name of the pool thread, `par_io-pool-<index>`.
+use std::any::Any;
+use std::sync::mpsc::Receiver;
+use std::sync::Mutex;
+use std::thread;
+use std::thread::JoinHandle;
+
+use crate::cpu::Worker;
+use crate::pool::ParIoPool;
+
+// -----------------------------------------------------------------------------
+/// Name of a producer or consumer thread.
+pub(crate) fn thread_name(worker: Worker) -> String {
+    match worker {
+        Worker::Producer(i) => format!("par_io-producer-{}", i),
+        Worker::Consumer(i) => format!("par_io-consumer-{}", i),
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Spawn thread named `name` with the requested stack size, or the default
+/// stack size (currently 2 MiB, see `std::thread`) if `None`.
+pub(crate) fn spawn<F, T>(
+    name: String,
+    stack_size: Option<usize>,
+    f: F,
+) -> std::io::Result<JoinHandle<T>>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let mut builder = thread::Builder::new().name(name);
+    if let Some(size) = stack_size {
+        builder = builder.stack_size(size);
+    }
+    builder.spawn(f)
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `spawn` for a thread scoped to `scope`, which can borrow non
+/// `'static` data.
+pub(crate) fn spawn_scoped<'scope, 'env, F, T>(
+    scope: &'scope thread::Scope<'scope, 'env>,
+    name: String,
+    stack_size: Option<usize>,
+    f: F,
+) -> std::io::Result<thread::ScopedJoinHandle<'scope, T>>
+where
+    F: FnOnce() -> T + Send + 'scope,
+    T: Send + 'scope,
+{
+    let mut builder = thread::Builder::new().name(name);
+    if let Some(size) = stack_size {
+        builder = builder.stack_size(size);
+    }
+    builder.spawn_scoped(scope, f)
+}
+
+// -----------------------------------------------------------------------------
+/// Handle to a task running on a dedicated thread or on a pool thread.
+pub(crate) enum Handle<T> {
+    Thread(JoinHandle<T>),
+    Pool(Receiver<thread::Result<T>>),
+}
+
+impl<T> Handle<T> {
+    /// Wait for the task to complete, return `Err` if the task panicked or
+    /// was discarded by a pool shutting down.
+    pub fn join(self) -> thread::Result<T> {
+        match self {
+            Handle::Thread(h) => h.join(),
+            Handle::Pool(rx) => match rx.recv() {
+                Ok(r) => r,
+                Err(_) => Err(Box::new("Task discarded by thread pool".to_string())),
+            },
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Run `f` on a thread taken from `pool`, or on a new thread named `name`
+/// with the requested stack size if `None`.
+pub(crate) fn spawn_on<F, T>(
+    pool: Option<&ParIoPool>,
+    name: String,
+    stack_size: Option<usize>,
+    f: F,
+) -> std::io::Result<Handle<T>>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    match pool {
+        Some(pool) => pool.spawn(f).map(Handle::Pool),
+        None => spawn(name, stack_size, f).map(Handle::Thread),
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Return the message of a panic payload, set by `panic!` with either a
+/// literal or a formatted string.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "Unknown panic payload".to_string()
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Message and offset of the first panic caught in a client callback.
+#[derive(Default)]
+pub(crate) struct Panicked(Mutex<Option<(String, u64)>>);
+
+impl Panicked {
+    /// Record the panic unless one was already recorded.
+    pub fn record(&self, payload: &(dyn Any + Send), offset: u64) {
+        let mut p = match self.0.lock() {
+            Ok(p) => p,
+            Err(err) => err.into_inner(),
+        };
+        p.get_or_insert_with(|| (panic_message(payload), offset));
+    }
+    /// Return the recorded panic.
+    pub fn take(&self) -> Option<(String, u64)> {
+        match self.0.lock() {
+            Ok(mut p) => p.take(),
+            Err(err) => err.into_inner().take(),
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// End of stream accounting on the consumer side.
+///
+/// Chunks are routed to consumers dynamically, by round robin or by a
+/// `ConsumerSelector`, so a consumer cannot know in advance which producers
+/// will feed it. Each producer therefore sends `End` to every consumer after
+/// its last chunk: messages from the same producer are received in order,
+/// so a consumer receiving `End` from a producer has already received all the
+/// chunks that producer sent to it, whether it was fed by it or not. A
+/// consumer stops once it has received `End` from every producer; repeated
+/// signals from the same producer are counted once.
+#[derive(Default)]
+pub(crate) struct Ends {
+    seen: Vec<bool>,
+    remaining: u64,
+}
+
+impl Ends {
+    /// Record the end signal sent by `producer` out of `num_producers`,
+    /// return `true` when all producers have ended.
+    pub fn end(&mut self, producer: u64, num_producers: u64) -> bool {
+        if self.seen.is_empty() {
+            self.seen = vec![false; num_producers as usize];
+            self.remaining = num_producers;
+        }
+        match self.seen.get_mut(producer as usize) {
+            Some(seen) if !*seen => {
+                *seen = true;
+                self.remaining -= 1;
+            }
+            _ => {}
+        }
+        self.remaining == 0
+    }
+}
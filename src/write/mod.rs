@@ -1,17 +1,42 @@
 //! Parallel async file write.
 use core::fmt::Debug;
+use std::collections::{BTreeMap, VecDeque};
 use std::fs::File;
 use std::ops::Fn;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::mpsc::channel;
+use std::sync::mpsc::Receiver;
 use std::sync::mpsc::Sender;
 use std::sync::Arc;
+use std::sync::{Condvar, Mutex};
 use std::thread;
 use std::thread::JoinHandle;
+use std::time::Duration;
 
-#[cfg(any(unix))]
+use crate::buffer::{allocate, check_capacity, BufferAlloc};
+use crate::cancel::CancelToken;
+use crate::checkpoint::{Checkpoint, CheckpointEntry};
+use crate::config::{check_counts, ParConfig};
+use crate::cpu::{self, Affinity, CpuReport, Placement, Worker};
+use crate::dedup::Dedup;
+use crate::latency::{LatencyReport, Recorder};
+use crate::layout::{buffers_per_producer, plan, Layout};
+use crate::lock::{try_lock, LockPolicy};
+use crate::pool::ParIoPool;
+use crate::progress::{Progress, Tracker};
+use crate::read::{aligned_tasks, ReadAt};
+use crate::retry::RetryPolicy;
+use crate::select::{select_consumer, ConsumerSelector};
+use crate::stats::{self, Stats, StatsReport};
+use crate::throttle::Throttle;
+use crate::watchdog::{Activity, ProgressMonitor, StallHandler, Watchdog};
+use crate::worker::{self, thread_name};
+
+#[cfg(unix)]
 use crate::io::io_at_unix::*;
 
-#[cfg(any(windows))]
+#[cfg(windows)]
 use crate::io::io_at_windows::*;
 // -----------------------------------------------------------------------------
 // TYPES
@@ -19,9 +44,15 @@ use crate::io::io_at_windows::*;
 type Senders = Vec<Sender<Message>>;
 type Buffer = Vec<u8>;
 type Offset = u64;
+// bytes written, offsets of chunks which failed verification, write errors
+type ConsumerHandles = Vec<worker::Handle<Result<(usize, Vec<u64>, Vec<ChunkError>), WriteError>>>;
+// producer errors are also sent to consumers, which return them
+type ProducerHandles = Vec<worker::Handle<Result<(), String>>>;
 #[derive(Clone)]
 struct Config {
+    chunk_id: u64,
     offset: Offset,
+    regions: Option<Vec<Region>>,
     consumers: Senders,
     producer_tx: Sender<Message>,
 }
@@ -35,18 +66,109 @@ enum Message {
     Produce(ProducerConfig, Buffer), // sent to producers
     End(ProducerId, NumProducers),   // sent from producers to all consumers
     // to signal end of transmission
-    Error(ProducerError), // sent from producer to consumers to signal
-                          // error
+    Error(ProducerError), // sent from producer to consumers to signal error
+    // sent to consumers to stop when another consumer failed
+    Abort,
 }
 
 // Moving a generic Fn instance requires customization
-type Producer<T, E> = dyn Fn(
+pub(crate) type Producer<T, E> = dyn Fn(
+    &mut Vec<u8>, // <- buffer to write to
+    &T,           // <- client data
+    u64,          // <- file offset (where data is written)
+) -> Result<(), E>;
+/// Region of a chunk returned by a gap-aware producer callback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    /// Number of bytes written to file.
+    Write(u64),
+    /// Number of bytes left untouched in the file; the corresponding
+    /// buffer content is ignored.
+    Keep(u64),
+    /// Number of bytes reading as zero without allocating disk space; the
+    /// corresponding buffer content is ignored. Nothing is written unless
+    /// the file is opened with `OpenMode::CreateOrKeep`, in which case the
+    /// existing content is deallocated through `fallocate` with
+    /// `FALLOC_FL_PUNCH_HOLE` on Linux, or overwritten with zeros elsewhere
+    /// or if the file system does not support punching holes.
+    Hole(u64),
+}
+type RegionProducer<T, E> = dyn Fn(
+    &mut Vec<u8>, // <- buffer to write to
+    &T,           // <- client data
+    u64,          // <- file offset (where data is written)
+) -> Result<Vec<Region>, E>;
+// Producer used internally: `None` means the whole chunk is written.
+pub(crate) type ChunkProducer<T, E> =
+    dyn Fn(&mut Vec<u8>, &T, u64) -> Result<Option<Vec<Region>>, E>;
+/// Value returned by producer callbacks which can end the data before the
+/// maximum size, see `write_to_file_until`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Produced {
+    /// The whole chunk was generated.
+    More,
+    /// Last chunk of the data, holding the specified number of bytes.
+    Done(usize),
+}
+/// Producer callback ending the data early by returning `Produced::Done`,
+/// see `write_to_file_until`.
+pub type EndingProducer<T, E> = dyn Fn(
+    &mut Vec<u8>, // <- buffer to write to
+    &T,           // <- client data
+    u64,          // <- file offset (where data is written)
+) -> Result<Produced, E>;
+/// Producer callback also receiving the id of the chunk and the total number
+/// of chunks, see `write_to_file_with_chunk_ids`.
+pub type ChunkIdProducer<T, E> = dyn Fn(
     &mut Vec<u8>, // <- buffer to write to
     &T,           // <- client data
     u64,          // <- file offset (where data is written)
+    u64,          // <- chunk id
+    u64,          // <- number of chunks
 ) -> Result<(), E>;
+/// Function returning the client data of the chunk with the given id, see
+/// `write_to_file_per_chunk`.
+pub type ChunkData<T> = dyn Fn(u64) -> T + Send + Sync;
+/// Producer callback generating variable-sized chunks, see
+/// `write_to_file_variable`.
+pub type VariableProducer<T, E> = dyn Fn(
+    &mut Vec<u8>, // <- buffer to write to
+    &T,           // <- client data
+    u64,          // <- chunk index
+) -> Result<usize, E>;
+// Chunk producer borrowing non 'static data, used with scoped threads.
+type BorrowedChunkProducer<'a, T, E> =
+    dyn Fn(&mut Vec<u8>, &T, u64) -> Result<Option<Vec<Region>>, E> + 'a;
 struct FnMove<T, E> {
-    f: Arc<Producer<T, E>>,
+    f: Arc<ChunkProducer<T, E>>,
+}
+struct VariableFnMove<T, E> {
+    f: Arc<VariableProducer<T, E>>,
+}
+
+/// Wrap producer writing whole chunks.
+pub(crate) fn whole_chunks<T: 'static, E: 'static>(
+    producer: Arc<Producer<T, E>>,
+) -> Arc<ChunkProducer<T, E>> {
+    Arc::new(move |buffer: &mut Vec<u8>, data: &T, offset: u64| {
+        let len = buffer.len();
+        producer(buffer, data, offset).map(|_| generated(buffer, len))
+    })
+}
+
+/// Return the regions of a chunk of `len` bytes after the producer callback
+/// returned: a producer generating less data shortens the buffer, only the
+/// bytes generated are then written and counted and the rest of the chunk is
+/// left untouched. The buffer is restored to `len` bytes.
+pub(crate) fn generated(buffer: &mut Vec<u8>, len: usize) -> Option<Vec<Region>> {
+    let n = buffer.len();
+    if n >= len {
+        return None;
+    }
+    buffer.resize(len, 0);
+    let mut regions = vec![Region::Write(n as u64), Region::Keep((len - n) as u64)];
+    regions.retain(|r| *r != Region::Write(0));
+    Some(regions)
 }
 
 /// Error generated by producers.
@@ -56,42 +178,350 @@ pub struct ProducerError {
     pub offset: u64,
 }
 
+/// Error generated by consumers when writing data to file.
+#[derive(Debug)]
+pub struct ConsumerError {
+    pub msg: String,
+    /// File offset where the write failed.
+    pub offset: u64,
+}
+
 /// Error type containing errors generated by the producer and consumer threads and I/O operations.
 #[derive(Debug)]
 pub enum WriteError {
     /// Error generated by producer including producer callback.
     Producer(ProducerError),
+    /// Error generated by consumer when writing data to file.
+    Consumer(ConsumerError),
     /// `std::io::Error` generated by consumer.
     IO(std::io::Error),
+    /// Write cancelled through `WriteOptions::cancel` before all chunks were
+    /// produced; chunks produced before cancellation were written to file.
+    Cancelled {
+        /// Number of bytes written.
+        written: usize,
+    },
+    /// Data read back after writing the chunk at `offset` does not match the
+    /// data written.
+    VerifyFailed { offset: u64 },
+    /// The lock required by `WriteOptions::lock` could not be acquired
+    /// because another lock is held on `filename`.
+    Locked { filename: String },
+    /// Writing the chunk at `offset` failed `attempts` times, see
+    /// `WriteOptions::retry`; `error` is the last error.
+    RetryExhausted {
+        offset: u64,
+        attempts: u32,
+        error: Box<WriteError>,
+    },
+    /// No chunk was written for longer than `WriteOptions::progress_timeout`;
+    /// `offset` is the offset of the stalled producer callback, if known.
+    Timeout { offset: Option<u64> },
     /// Other errors
     Other(String),
 }
 
-/// Simple conversion from string to write error.
-fn to_write_err(err: String) -> WriteError {
-    WriteError::Other(err)
+impl From<std::io::Error> for WriteError {
+    fn from(err: std::io::Error) -> Self {
+        WriteError::IO(err)
+    }
 }
 
-/// Fn is wrapped inside an FnMove struct so that it can be moved
-impl<T, E> FnMove<T, E> {
-    fn call(&self, buf: &mut Vec<u8>, t: &T, a: u64) -> Result<(), E> {
-        (self.f)(buf, t, a)
+/// Event emitted by consumers after each chunk is written to file.
+#[derive(Debug, Clone)]
+pub struct WriteEvent {
+    /// Chunk id.
+    pub chunk_id: u64,
+    /// File offset where the chunk was written.
+    pub offset: u64,
+    /// Number of bytes in chunk.
+    pub bytes: u64,
+    /// Total number of bytes written so far by all consumers.
+    pub cumulative: u64,
+}
+
+/// How the file is opened before writing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OpenMode {
+    /// Create file or truncate existing file.
+    #[default]
+    Truncate,
+    /// Create file if it does not exist, keep existing content otherwise;
+    /// the file is extended if smaller than the data written.
+    CreateOrKeep,
+    /// Create file if it does not exist and write the data after the
+    /// existing content: `WriteOptions::data_offset` is relative to the end
+    /// of file, measured after the file is locked. Chunks are still written
+    /// at absolute offsets, the file is not opened with `O_APPEND`.
+    Append,
+}
+
+/// Flush to disk performed once after all chunks are written, see
+/// `WriteOptions::sync`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SyncMode {
+    /// Do not flush, data can remain in the page cache after returning.
+    #[default]
+    None,
+    /// Flush data and the metadata required to read it back, such as the
+    /// file size, with `fdatasync`.
+    Data,
+    /// Flush data and all metadata with `fsync`.
+    All,
+}
+
+/// Alignment of the file offset and size of the chunks written with direct
+/// I/O, see `WriteOptions::direct_io`.
+pub const DIRECT_IO_ALIGNMENT: u64 = 4096;
+
+/// Map the offset of a chunk relative to the start of the data to the
+/// offset where it is written.
+pub type OffsetMap = dyn Fn(u64) -> u64 + Send + Sync;
+
+/// Hook invoked on each buffer after its chunk is written, before the buffer
+/// is sent back to its producer.
+pub type RecycleHook = dyn Fn(&mut Vec<u8>) + Send + Sync;
+
+/// Write options.
+#[derive(Clone)]
+pub struct WriteOptions {
+    /// How the file is opened.
+    pub open_mode: OpenMode,
+    /// Report producer callback invocations running for longer than the
+    /// specified duration by invoking the function from the monitor thread
+    /// for each stalled callback; stalled callbacks are reported, not
+    /// interrupted.
+    pub on_stall: Option<(Duration, Arc<StallHandler>)>,
+    /// Abort with `WriteError::Timeout` when no chunk is written for longer
+    /// than the specified duration; consumers are joined, stalled producer
+    /// threads are left running and exit when the callback returns.
+    pub progress_timeout: Option<Duration>,
+    /// Record the CPU each producer and consumer thread runs on.
+    pub cpu_report: Option<Arc<CpuReport>>,
+    /// Record the time taken to write each chunk.
+    pub latency_report: Option<Arc<LatencyReport>>,
+    /// Record throughput, per-thread counters and wait times, see the
+    /// `stats` module.
+    pub stats: Option<Arc<StatsReport>>,
+    /// Stack size of producer and consumer threads, the default stack size
+    /// is used if `None`; increase for callbacks with deep recursion or large
+    /// stack allocations.
+    pub stack_size: Option<usize>,
+    /// Stop producing chunks when cancelled; the write succeeds if all the
+    /// chunks were already produced.
+    pub cancel: Option<Arc<CancelToken>>,
+    /// Flush file data to disk before returning `WriteError::Cancelled`.
+    pub sync_on_cancel: bool,
+    /// Read back each chunk after writing it and compare with the data
+    /// written; chunks deduplicated or fully kept are not verified.
+    pub verify: bool,
+    /// Data source used to read back chunks instead of the file.
+    pub verify_source: Option<Arc<dyn ReadAt>>,
+    /// Do not stop on verification failures, collect the offsets of the
+    /// chunks which failed verification instead, see `write_to_file_with_report`.
+    pub best_effort: bool,
+    /// Path of the checkpoint file where consumers record the chunks durably
+    /// written, see `checkpoint::resume_write`.
+    pub checkpoint: Option<String>,
+    /// Testing only: simulate a crash by not writing any chunk after the
+    /// first `crash_after` chunks; an error is returned after all consumers
+    /// have recorded their checkpoint.
+    pub crash_after: Option<u64>,
+    /// File offset where data starts, the bytes before it are reserved,
+    /// e.g. for a header; offsets passed to the producer callback and
+    /// reported in events, checkpoints and reports are relative to the start
+    /// of the data.
+    pub data_offset: u64,
+    /// Write each chunk at the offset returned by the function instead of
+    /// the chunk offset; `data_offset` is added to the returned offset.
+    pub offset_map: Option<Arc<OffsetMap>>,
+    /// Return `WriteError::Other` naming the ranges involved when a chunk is
+    /// written over data already written; the range is checked before
+    /// writing. Disabled by default, written ranges are recorded only when
+    /// enabled.
+    pub detect_overlaps: bool,
+    /// Lock acquired on the file before writing, see the `lock` module.
+    pub lock: LockPolicy,
+    /// Maximum number of bytes transferred by each write system call, chunks
+    /// are written with multiple calls; mostly useful to test short writes.
+    /// Not applied to deduplicated writes.
+    pub max_io_size: Option<usize>,
+    /// Consumer selection strategy, round-robin if `None`.
+    pub selector: Option<Arc<dyn ConsumerSelector>>,
+    /// Write chunks again when writing fails, see the `retry` module. Not
+    /// applied to deduplicated writes.
+    pub retry: Option<RetryPolicy>,
+    /// Function invoked from consumer threads after each chunk is written,
+    /// see the `progress` module.
+    pub progress: Option<Arc<Progress>>,
+    /// Run producers and consumers on the threads of a pool instead of
+    /// spawning new threads, see the `pool` module.
+    pub pool: Option<ParIoPool>,
+    /// Linux only: write chunks through a file handle opened with `O_DIRECT`,
+    /// bypassing the page cache. Chunks are copied to a staging buffer aligned
+    /// to `DIRECT_IO_ALIGNMENT` owned by each consumer; chunks whose file
+    /// offset or size is not a multiple of the alignment, usually the last
+    /// chunk, fall back to buffered I/O, as do deduplicated and gap-aware
+    /// writes. Returns `WriteError::Other` on other platforms.
+    pub direct_io: bool,
+    /// Pin producer and consumer threads to CPUs, see the `cpu` module; not
+    /// applied when `pool` is set.
+    pub affinity: Option<Affinity>,
+    /// Limit the aggregate throughput of consumers to the specified number
+    /// of bytes per second, best effort, see the `throttle` module.
+    pub max_bytes_per_sec: Option<u64>,
+    /// Allocator of the chunk buffers, `buffer::DefaultAlloc` if `None`, see
+    /// the `buffer` module.
+    pub allocator: Option<Arc<dyn BufferAlloc>>,
+    /// Coalesce the chunks already queued for a consumer at contiguous file
+    /// offsets and write them with a single `pwritev` call, reducing the
+    /// number of system calls with small chunks; most effective with few
+    /// consumers, since consecutive chunks are otherwise sent to different
+    /// consumers. Not applied to deduplicated, gap-aware and direct I/O
+    /// writes, nor when `crash_after` is set; buffers are written one at a
+    /// time on Windows.
+    pub vectored: bool,
+    /// Cap the memory used by chunk buffers to the specified number of
+    /// bytes, deriving the number of buffers per producer from the chunk size
+    /// instead of using `num_buffers_per_producer`; at least one buffer per
+    /// producer is allocated, `Stats::memory_cap_exceeded` reports when this
+    /// exceeds the cap.
+    pub max_memory_bytes: Option<u64>,
+    /// Flush the file to disk after all consumers completed, before
+    /// returning; this can considerably increase the time taken by the
+    /// write, see `SyncMode`.
+    pub sync: SyncMode,
+    /// Invoked by consumers on each buffer after writing its chunk, e.g. to
+    /// scrub sensitive data; buffers are zeroed by producers before being
+    /// passed to the producer callback in any case.
+    pub on_recycle: Option<Arc<RecycleHook>>,
+    /// Do not stop on write errors: consumers record the chunks which could
+    /// not be written and keep writing the other chunks, see
+    /// `write_to_file_with_report`; functions not returning a `WriteReport`
+    /// do not report the failed chunks. The bytes of the failed chunks are not
+    /// counted as written; chunks are written one at a time, `vectored` is
+    /// ignored.
+    pub continue_on_error: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions {
+            open_mode: OpenMode::default(),
+            on_stall: None,
+            progress_timeout: None,
+            cpu_report: None,
+            latency_report: None,
+            stats: None,
+            stack_size: None,
+            cancel: None,
+            sync_on_cancel: false,
+            verify: false,
+            verify_source: None,
+            best_effort: false,
+            checkpoint: None,
+            crash_after: None,
+            data_offset: 0,
+            offset_map: None,
+            detect_overlaps: false,
+            lock: LockPolicy::NoLock,
+            max_io_size: None,
+            selector: None,
+            retry: None,
+            progress: None,
+            pool: None,
+            direct_io: false,
+            max_bytes_per_sec: None,
+            affinity: None,
+            allocator: None,
+            vectored: false,
+            max_memory_bytes: None,
+            sync: SyncMode::None,
+            on_recycle: None,
+            continue_on_error: false,
+        }
     }
 }
 
-unsafe impl<T, E> Send for FnMove<T, E> {}
+impl WriteOptions {
+    /// CPUs worker threads are pinned to, `None` when running on a pool.
+    pub(crate) fn placement(&self) -> Option<Placement> {
+        Placement::new(self.affinity.as_ref()).filter(|_| self.pool.is_none())
+    }
+}
 
 // -----------------------------------------------------------------------------
-/// Select target consumer given current producer ID. Round-robin scheme.
-fn select_tx(
-    _i: usize,
-    previous_consumer_id: usize,
-    num_consumers: usize,
-    _num_producers: usize,
-) -> usize {
-    (previous_consumer_id + 1) % num_consumers
+/// Ranges written to file, shared by consumers to detect overlapping writes.
+#[derive(Default)]
+struct WrittenRanges {
+    // start -> end
+    ranges: Mutex<BTreeMap<u64, u64>>,
+}
+
+impl WrittenRanges {
+    /// Record range `start..end`, fail if it overlaps a range already
+    /// recorded.
+    fn insert(&self, start: u64, end: u64) -> Result<(), WriteError> {
+        if start == end {
+            return Ok(());
+        }
+        let mut ranges = match self.ranges.lock() {
+            Ok(r) => r,
+            Err(err) => err.into_inner(),
+        };
+        if let Some((&s, &e)) = ranges.range(..end).next_back() {
+            if e > start {
+                return Err(WriteError::Other(format!(
+                    "Write to range {}..{} overlaps range {}..{} already written",
+                    start, end, s, e
+                )));
+            }
+        }
+        ranges.insert(start, end);
+        Ok(())
+    }
+}
+
+/// Result of a write operation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WriteReport {
+    /// Number of bytes written.
+    pub bytes_written: usize,
+    /// Offsets of the chunks which failed verification, in increasing order;
+    /// only populated with both `verify` and `best_effort` enabled.
+    pub failed_offsets: Vec<u64>,
+    /// Chunks which could not be written, in increasing offset order; only
+    /// populated with `continue_on_error` enabled.
+    pub errors: Vec<ChunkError>,
+}
+
+/// Chunk which could not be written, see `WriteOptions::continue_on_error`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkError {
+    /// Offset of the chunk relative to the start of the data.
+    pub offset: u64,
+    /// Size of the chunk.
+    pub size: u64,
+    /// Error returned when writing the chunk.
+    pub msg: String,
+}
+
+impl WriteReport {
+    /// Return `true` if all chunks were written and passed verification.
+    pub fn is_success(&self) -> bool {
+        self.failed_offsets.is_empty() && self.errors.is_empty()
+    }
+}
+
+/// Simple conversion from string to write error.
+fn to_write_err(err: String) -> WriteError {
+    WriteError::Other(err)
 }
 
+/// Fn is wrapped inside an FnMove struct so that it can be moved
+unsafe impl<T, E> Send for FnMove<T, E> {}
+unsafe impl<T, E> Send for VariableFnMove<T, E> {}
+
 /// -----------------------------------------------------------------------------
 /// Separate file writing from data production using the producer-consumer model
 /// and a fixed number of pre-allocated buffers to keep memory usage constant.
@@ -112,11 +542,25 @@ fn select_tx(
 /// * `client_data` - data to be passed to producer function
 /// * `num_buffers_per_producer` - number of buffers per producer
 ///
+/// All counts must be greater than zero, `WriteError::Other` is returned otherwise.
+/// When `total_size` is zero the file is created empty and zero is returned
+/// without spawning any thread.
+///
+/// The file is sized to `total_size` bytes before writing. The buffer passed
+/// to the callback is zeroed and sized to the chunk: bytes the callback does
+/// not set are written as zeros. A producer running out of data shortens the
+/// buffer to the bytes it generated, e.g. with `Vec::truncate`; only those
+/// bytes are written and the rest of the chunk is left as is, zero in a new
+/// file. The returned size is the number of bytes actually written and is
+/// less than `total_size` when producers shortened chunks. To also truncate
+/// the file at the end of the data use `write_to_file_until` and return
+/// `Produced::Done`.
+///
 /// ## Return
 /// * `Result<(), WriteError>`: number of bytes written to file or error;
-///                             error returned form callback must implement Debug
-///                             trait and is converted to `String` before
-///                             being returned
+///   error returned form callback must implement Debug
+///   trait and is converted to `String` before
+///   being returned
 ///
 /// Callback signature:
 ///
@@ -142,6 +586,7 @@ fn select_tx(
 // 5. producer_chunk_size
 // 6. last_producer_chunk_size
 // 7. total_size
+#[allow(clippy::too_many_arguments)]
 pub fn write_to_file<T: 'static + Clone + Send, E: 'static + Send + Debug>(
     filename: &str,
     num_producers: u64,
@@ -152,271 +597,2779 @@ pub fn write_to_file<T: 'static + Clone + Send, E: 'static + Send + Debug>(
     num_buffers_per_producer: u64,
     total_size: usize,
 ) -> Result<usize, WriteError> {
-    let total_size = total_size as u64;
-    let producer_chunk_size = (total_size + num_producers - 1) / num_producers;
-    let last_producer_chunk_size = total_size - (num_producers - 1) * producer_chunk_size;
-    let task_chunk_size = (producer_chunk_size + chunks_per_producer - 1) / chunks_per_producer;
-    let last_task_chunk_size = producer_chunk_size - (chunks_per_producer - 1) * task_chunk_size;
-    let last_prod_task_chunk_size =
-        (last_producer_chunk_size + chunks_per_producer - 1) / chunks_per_producer;
-    let last_last_prod_task_chunk_size =
-        last_producer_chunk_size - (chunks_per_producer - 1) * last_prod_task_chunk_size;
-    let file = File::create(filename).map_err(|err| to_write_err(err.to_string()))?;
-    file.set_len(total_size)
-        .map_err(|err| to_write_err(err.to_string()))?;
-    drop(file);
-    let tx_producers = build_producers(
+    write_to_file_with_options(
+        filename,
         num_producers,
+        num_consumers,
+        chunks_per_producer,
+        producer,
+        client_data,
+        num_buffers_per_producer,
         total_size,
+        WriteOptions::default(),
+    )
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `write_to_file` with additional options.
+#[allow(clippy::too_many_arguments)]
+pub fn write_to_file_with_options<T: 'static + Clone + Send, E: 'static + Send + Debug>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    producer: Arc<Producer<T, E>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    total_size: usize,
+    options: WriteOptions,
+) -> Result<usize, WriteError> {
+    write_to_file_with_report(
+        filename,
+        num_producers,
+        num_consumers,
         chunks_per_producer,
         producer,
         client_data,
-    );
-    let (tx_consumers, consumers_handles) = match build_consumers(num_consumers, filename) {
-        Ok(r) => r,
-        Err(err) => {
-            return Err(err);
-        }
-    };
-    let reserved_size = last_task_chunk_size
-        .max(last_last_prod_task_chunk_size)
-        .max(task_chunk_size);
-    launch(
-        tx_producers,
-        tx_consumers,
-        producer_chunk_size,
-        last_producer_chunk_size,
-        task_chunk_size,
+        num_buffers_per_producer,
+        total_size,
+        options,
+    )
+    .map(|r| r.bytes_written)
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `write_to_file_with_options` but also returns the statistics of
+/// the write, recorded through a new `StatsReport` replacing `options.stats`.
+#[allow(clippy::too_many_arguments)]
+pub fn write_to_file_with_stats<T: 'static + Clone + Send, E: 'static + Send + Debug>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    producer: Arc<Producer<T, E>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    total_size: usize,
+    options: WriteOptions,
+) -> (Result<usize, WriteError>, Stats) {
+    let report = Arc::new(StatsReport::new());
+    let result = write_to_file_with_options(
+        filename,
+        num_producers,
+        num_consumers,
         chunks_per_producer,
-        reserved_size as usize,
+        producer,
+        client_data,
         num_buffers_per_producer,
-    )?;
+        total_size,
+        WriteOptions {
+            stats: Some(report.clone()),
+            ..options
+        },
+    );
+    (result, report.stats())
+}
 
-    let mut bytes_consumed = 0;
-    for h in consumers_handles {
-        match h.join() {
-            Ok(n) => match n {
-                Ok(bytes) => {
-                    bytes_consumed += bytes;
-                }
-                Err(err) => {
-                    return Err(err);
-                }
-            },
-            Err(err) => {
-                return Err(WriteError::Other(format!("{:?}", err)));
-            }
+// -----------------------------------------------------------------------------
+/// Builder for `write_to_file_with_options` calls.
+///
+/// Unset fields default to one producer per available CPU, one consumer per
+/// producer, one chunk and two buffers per producer, unit client data and
+/// default options; the total size must be set.
+///
+/// ```ignore
+/// let written = WriteBuilder::new(&filename)
+///     .producers(4)
+///     .chunks_per_producer(8)
+///     .total_size(1 << 20)
+///     .run(std::sync::Arc::new(producer))?;
+/// ```
+#[derive(Clone)]
+pub struct WriteBuilder<T = ()> {
+    filename: String,
+    config: ParConfig,
+    total_size: Option<usize>,
+    client_data: T,
+    options: WriteOptions,
+}
+
+impl WriteBuilder<()> {
+    /// Create builder writing to `filename`.
+    pub fn new(filename: &str) -> Self {
+        WriteBuilder {
+            filename: filename.to_string(),
+            config: ParConfig::default(),
+            total_size: None,
+            client_data: (),
+            options: WriteOptions::default(),
         }
     }
-    Ok(bytes_consumed)
+}
+
+impl<T: 'static + Clone + Send> WriteBuilder<T> {
+    /// Set number of producer threads.
+    pub fn producers(mut self, n: u64) -> Self {
+        self.config.num_producers = n;
+        self
+    }
+    /// Set number of consumer threads.
+    pub fn consumers(mut self, n: u64) -> Self {
+        self.config.num_consumers = Some(n);
+        self
+    }
+    /// Set number of chunks generated by each producer.
+    pub fn chunks_per_producer(mut self, n: u64) -> Self {
+        self.config.chunks_per_producer = n;
+        self
+    }
+    /// Set number of buffers allocated by each producer.
+    pub fn buffers_per_producer(mut self, n: u64) -> Self {
+        self.config.num_buffers_per_producer = n;
+        self
+    }
+    /// Set number of bytes written to file.
+    pub fn total_size(mut self, n: usize) -> Self {
+        self.total_size = Some(n);
+        self
+    }
+    /// Set data passed to the producer callback.
+    pub fn client_data<U: 'static + Clone + Send>(self, data: U) -> WriteBuilder<U> {
+        WriteBuilder {
+            filename: self.filename,
+            config: self.config,
+            total_size: self.total_size,
+            client_data: data,
+            options: self.options,
+        }
+    }
+    /// Set write options.
+    pub fn options(mut self, options: WriteOptions) -> Self {
+        self.options = options;
+        self
+    }
+    /// Bypass the page cache, see `WriteOptions::direct_io`.
+    pub fn direct_io(mut self, enable: bool) -> Self {
+        self.options.direct_io = enable;
+        self
+    }
+    /// Limit throughput, see `WriteOptions::max_bytes_per_sec`.
+    pub fn max_bytes_per_sec(mut self, n: u64) -> Self {
+        self.options.max_bytes_per_sec = Some(n);
+        self
+    }
+    /// Flush to disk before returning, see `WriteOptions::sync`.
+    pub fn sync(mut self, mode: SyncMode) -> Self {
+        self.options.sync = mode;
+        self
+    }
+    /// Cap buffer memory, see `WriteOptions::max_memory_bytes`.
+    pub fn max_memory_bytes(mut self, n: u64) -> Self {
+        self.options.max_memory_bytes = Some(n);
+        self
+    }
+    /// Read back and compare each chunk after writing it, see
+    /// `WriteOptions::verify`.
+    pub fn verify(mut self, enable: bool) -> Self {
+        self.options.verify = enable;
+        self
+    }
+    /// Keep writing after write errors, see
+    /// `WriteOptions::continue_on_error` and `run_with_report`.
+    pub fn continue_on_error(mut self, enable: bool) -> Self {
+        self.options.continue_on_error = enable;
+        self
+    }
+    /// Write file invoking `producer` to generate data, return the number of
+    /// bytes written; fails with `WriteError::Other` if the total size was
+    /// not set.
+    pub fn run<E: 'static + Send + Debug>(
+        self,
+        producer: Arc<Producer<T, E>>,
+    ) -> Result<usize, WriteError> {
+        let total_size = self
+            .total_size
+            .ok_or_else(|| WriteError::Other("Total size not set".to_string()))?;
+        write_to_file_with_options(
+            &self.filename,
+            self.config.num_producers,
+            self.config.num_consumers(),
+            self.config.chunks_per_producer,
+            producer,
+            self.client_data,
+            self.config.num_buffers_per_producer,
+            total_size,
+            self.options,
+        )
+    }
+    /// Same as `run` but returns a `WriteReport`, see
+    /// `write_to_file_with_report`.
+    pub fn run_with_report<E: 'static + Send + Debug>(
+        self,
+        producer: Arc<Producer<T, E>>,
+    ) -> Result<WriteReport, WriteError> {
+        let total_size = self
+            .total_size
+            .ok_or_else(|| WriteError::Other("Total size not set".to_string()))?;
+        write_to_file_with_report(
+            &self.filename,
+            self.config.num_producers,
+            self.config.num_consumers(),
+            self.config.chunks_per_producer,
+            producer,
+            self.client_data,
+            self.config.num_buffers_per_producer,
+            total_size,
+            self.options,
+        )
+    }
+    /// Write file invoking `producer`, which can end the data before the
+    /// total size, see `write_to_file_until`; fails with `WriteError::Other`
+    /// if the total size was not set.
+    pub fn run_until<E: 'static + Send + Debug>(
+        self,
+        producer: Arc<EndingProducer<T, E>>,
+    ) -> Result<usize, WriteError> {
+        let max_size = self
+            .total_size
+            .ok_or_else(|| WriteError::Other("Total size not set".to_string()))?;
+        write_to_file_until(
+            &self.filename,
+            self.config.num_producers,
+            self.config.num_consumers(),
+            self.config.chunks_per_producer,
+            producer,
+            self.client_data,
+            self.config.num_buffers_per_producer,
+            max_size,
+            self.options,
+        )
+    }
+    /// Write file invoking `producer`, which also receives the chunk id and
+    /// the number of chunks, see `write_to_file_with_chunk_ids`; fails with
+    /// `WriteError::Other` if the total size was not set.
+    pub fn run_with_chunk_ids<E: 'static + Send + Debug>(
+        self,
+        producer: Arc<ChunkIdProducer<T, E>>,
+    ) -> Result<usize, WriteError> {
+        let total_size = self
+            .total_size
+            .ok_or_else(|| WriteError::Other("Total size not set".to_string()))?;
+        write_to_file_with_chunk_ids(
+            &self.filename,
+            self.config.num_producers,
+            self.config.num_consumers(),
+            self.config.chunks_per_producer,
+            producer,
+            self.client_data,
+            self.config.num_buffers_per_producer,
+            total_size,
+            self.options,
+        )
+    }
+    /// Write file invoking `producer` to generate chunks of at most
+    /// `max_chunk_size` bytes, see `write_to_file_variable`; the total size
+    /// is ignored.
+    pub fn run_variable<E: 'static + Send + Debug>(
+        self,
+        producer: Arc<VariableProducer<T, E>>,
+        max_chunk_size: usize,
+    ) -> Result<usize, WriteError> {
+        write_to_file_variable(
+            &self.filename,
+            self.config.num_producers,
+            self.config.num_consumers(),
+            self.config.chunks_per_producer,
+            producer,
+            self.client_data,
+            self.config.num_buffers_per_producer,
+            max_chunk_size,
+            self.options,
+        )
+    }
 }
 
 // -----------------------------------------------------------------------------
-/// Build producers and return array of Sender objects.
-fn build_producers<T: 'static + Clone + Send, E: 'static + Send + Debug>(
+/// Same as `write_to_file` but producer threads are scoped to the function
+/// call: the callback and the client data can borrow from the caller's stack
+/// frame and are not required to be `'static`.
+#[allow(clippy::too_many_arguments)]
+pub fn write_to_file_scoped<T, E, F>(
+    filename: &str,
     num_producers: u64,
-    total_size: u64,
+    num_consumers: u64,
     chunks_per_producer: u64,
-    f: Arc<Producer<T, E>>,
-    data: T,
-) -> Senders {
-    let mut tx_producers: Senders = Senders::new();
-    let producer_chunk_size = (total_size + num_producers - 1) / num_producers;
-    let last_producer_chunk_size = total_size - (num_producers - 1) * producer_chunk_size;
-    let task_chunk_size = (producer_chunk_size + chunks_per_producer - 1) / chunks_per_producer;
-    let last_prod_task_chunk_size =
-        (last_producer_chunk_size + chunks_per_producer - 1) / chunks_per_producer;
-    // currently producers exit after sending all data, and consumers might try
-    // to send data back to disconnected producers, ignoring the returned
-    // send() error;
-    // another option is to have consumers return and 'End' signal when done
-    // consuming data and producers exiting after al the consumers have
-    // returned the signal
-    for i in 0..num_producers {
-        let (tx, rx) = channel();
-        tx_producers.push(tx);
-        let mut offset = producer_chunk_size * i;
-        let end_offset = if i != num_producers - 1 {
-            offset + producer_chunk_size
-        } else {
-            offset + last_producer_chunk_size
+    producer: &F,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    total_size: usize,
+) -> Result<usize, WriteError>
+where
+    T: Clone + Send,
+    E: Debug,
+    F: Fn(&mut Vec<u8>, &T, u64) -> Result<(), E> + Sync,
+{
+    check_counts(
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        num_buffers_per_producer,
+    )
+    .map_err(WriteError::Other)?;
+    let options = WriteOptions::default();
+    let file = create_file(
+        filename,
+        total_size as u64 + options.data_offset,
+        options.open_mode,
+        options.lock,
+    )?;
+    let chunk_producer =
+        |buffer: &mut Vec<u8>, data: &T, offset: u64| -> Result<Option<Vec<Region>>, E> {
+            let len = buffer.len();
+            producer(buffer, data, offset).map(|_| generated(buffer, len))
         };
-        use Message::*;
-        let cc = FnMove { f: f.clone() };
-        let data = data.clone();
-        thread::spawn(move || -> Result<(), String> {
-            let mut prev_consumer = i as usize;
-            while let Ok(Produce(mut cfg, mut buffer)) = rx.recv() {
-                let chunk_size = if i != num_producers - 1 {
-                    task_chunk_size.min(end_offset - offset)
-                } else {
-                    last_prod_task_chunk_size.min(end_offset - offset)
-                };
-                assert!(buffer.capacity() >= chunk_size as usize);
-                unsafe {
-                    buffer.set_len(chunk_size as usize);
-                }
-                let num_consumers = cfg.consumers.len();
-                // to support multiple consumers per producer we need to keep track of
-                // the destination, by adding the element into a Set and notify all
-                // of them when the producer exits
-                let c = select_tx(
-                    i as usize,
-                    prev_consumer,
-                    num_consumers,
-                    num_producers as usize,
-                );
-                prev_consumer = c;
-
-                match cc.call(&mut buffer, &data, offset as u64) {
-                    Err(err) => {
-                        (0..cfg.consumers.len()).for_each(|c| {
-                            let _ = cfg.consumers[c].send(Error(ProducerError {
-                                msg: format!("{:?}", err),
-                                offset: offset,
-                            }));
-                        });
-                        return Err(format!("{:?}", err));
-                    }
-                    Ok(()) => {
-                        cfg.offset = offset;
-                        offset += buffer.len() as u64;
-                        if let Err(err) = cfg.consumers[c].send(Consume(cfg.clone(), buffer)) {
-                            return Err(format!(
-                                "Cannot send buffer to consumer - {}",
-                                err.to_string()
-                            ));
-                        }
-                        if offset >= end_offset {
-                            // signal the end of stream to consumers
-                            (0..cfg.consumers.len()).for_each(|x| {
-                                // consumer might have exited already
-                                let _ = cfg.consumers[x].send(End(i, num_producers));
-                            });
-                            break;
-                        }
-                    }
+    thread::scope(|s| {
+        write_chunks_with(
+            &file,
+            num_producers,
+            num_consumers,
+            chunks_per_producer,
+            num_buffers_per_producer,
+            total_size,
+            None,
+            None,
+            &options,
+            None,
+            |_activity, interrupted| {
+                let mut tx_producers = Senders::new();
+                for i in 0..num_producers {
+                    let (tx, rx) = channel();
+                    tx_producers.push(tx);
+                    let range =
+                        producer_range(i, num_producers, total_size as u64, chunks_per_producer);
+                    let data = client_data.clone();
+                    let chunk_producer = &chunk_producer;
+                    let interrupted = interrupted.clone();
+                    worker::spawn_scoped(s, thread_name(Worker::Producer(i)), None, move || {
+                        produce(
+                            i,
+                            num_producers,
+                            rx,
+                            chunk_producer,
+                            &data,
+                            range,
+                            None,
+                            None,
+                            None,
+                            None,
+                            &interrupted,
+                            None,
+                        )
+                    })
+                    .map_err(|err| WriteError::Other(format!("Cannot spawn producer - {}", err)))?;
                 }
-            }
-            return Ok(());
-        });
-    }
-    tx_producers
+                // scoped threads are joined at the end of the scope
+                Ok((tx_producers, ProducerHandles::new()))
+            },
+        )
+    })
+    .map(|r| r.bytes_written)
 }
 
 // -----------------------------------------------------------------------------
-/// Build consumers and return tuple of (Sender objects, JoinHandles)
-fn build_consumers(
+/// Write `data` to file in parallel, each producer copying its part of the
+/// slice; return the number of bytes written. Producer threads are scoped:
+/// `data` can borrow from the caller's stack frame.
+pub fn write_slice_to_file(
+    filename: &str,
+    num_producers: u64,
     num_consumers: u64,
-    file_name: &str,
-) -> Result<(Senders, Vec<JoinHandle<Result<usize, WriteError>>>), WriteError> {
-    let mut consumers_handles = Vec::new();
-    let mut tx_consumers = Vec::new();
-    for _i in 0..num_consumers {
-        let (tx, rx) = channel();
-        tx_consumers.push(tx);
-        use Message::*;
-        let file_name = file_name.to_owned();
-        let h = thread::spawn(move || {
-            let file = File::options()
-                .write(true)
-                .open(&file_name)
-                .map_err(|err| WriteError::IO(err))?;
-            let mut producers_end_signal_count = 0;
-            let mut bytes = 0;
-            loop {
-                // consumers tx endpoints live inside the ReadData instance
-                // sent along messages, when producers finish sending data
-                // all transmission endpoints die resulting in recv()
-                // failing and consumers exiting
-                if let Ok(msg) = rx.recv() {
-                    match msg {
-                        Error(err) => {
-                            return Err(WriteError::Producer(err));
-                        }
-                        Consume(cfg, buffer) => {
-                            bytes += buffer.len();
-                            write_bytes_at(&buffer, &file, cfg.offset)?;
-                            if let Err(_err) = cfg.producer_tx.send(Produce(cfg.clone(), buffer)) {
-                                // senders might have already exited at this point after having added
-                                // data to the queue
-                                // from Rust docs
-                                //A send operation can only fail if the receiving end of a channel is disconnected, implying that the data could never be received
-                                // TBD
-                                //break;
-                            }
-                        }
-                        End(_prod_id, num_producers) => {
-                            producers_end_signal_count += 1;
-                            if producers_end_signal_count >= num_producers {
-                                break;
-                            }
-                        }
-                        _ => {
-                            panic!("Wrong message type");
-                        }
-                    }
-                } else {
-                    // we do not care if the communication channel was closed
-                    // since it only happen when the producer is finished
-                    // of an error elsewhere occurred
-                    //break;
-                }
-            }
-            return Ok(bytes);
-        });
-        consumers_handles.push(h);
-    }
-    Ok((tx_consumers, consumers_handles))
+    chunks_per_producer: u64,
+    data: &[u8],
+    num_buffers_per_producer: u64,
+) -> Result<usize, WriteError> {
+    let producer = |buffer: &mut Vec<u8>, data: &&[u8], offset: u64| -> Result<(), String> {
+        let offset = offset as usize;
+        let len = buffer.len();
+        buffer.copy_from_slice(&data[offset..offset + len]);
+        Ok(())
+    };
+    write_to_file_scoped(
+        filename,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        &producer,
+        data,
+        num_buffers_per_producer,
+        data.len(),
+    )
 }
 
 // -----------------------------------------------------------------------------
-/// Launch computation by sending messages to transmission endpoints of producer
-/// channels.
-/// In order to keep memory usage constant, buffers are sent to consumers and
-/// returned to the producer who sent them.
-/// One producer can send messages to multiple consumers.
-/// To allow for asynchronous data consumption, a consumers needs to be able
-/// to consume the data in a buffer while the producer is writing data to a different
+/// Same as `write_to_file_with_options` but each chunk is read from a reader
+/// returned by `reader_factory`, invoked with the offset and size of the
+/// chunk and returning a reader positioned at the chunk start; exactly the
+/// size of the chunk is read.
+///
+/// Errors returned by the factory or the reader, including readers ending
+/// before the end of the chunk, are reported as `WriteError::Producer`.
+#[allow(clippy::too_many_arguments)]
+pub fn write_reader_to_file<F, Rd>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    reader_factory: F,
+    num_buffers_per_producer: u64,
+    total_size: usize,
+    options: WriteOptions,
+) -> Result<usize, WriteError>
+where
+    F: Fn(u64, usize) -> std::io::Result<Rd> + Send + Sync + 'static,
+    Rd: std::io::Read,
+{
+    let producer = move |buffer: &mut Vec<u8>, _data: &(), offset: u64| {
+        reader_factory(offset, buffer.len())?.read_exact(buffer)
+    };
+    write_to_file_with_options(
+        filename,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        Arc::new(producer),
+        (),
+        num_buffers_per_producer,
+        total_size,
+        options,
+    )
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `write_to_file_with_options` but returns a `WriteReport`; with both
+/// `verify` and `best_effort` enabled, the report contains the offsets of all
+/// the chunks which failed verification instead of stopping at the first, and
+/// with `continue_on_error` enabled the chunks which could not be written, e.g.
+/// to retry writing them.
+#[allow(clippy::too_many_arguments)]
+pub fn write_to_file_with_report<T: 'static + Clone + Send, E: 'static + Send + Debug>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    producer: Arc<Producer<T, E>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    total_size: usize,
+    mut options: WriteOptions,
+) -> Result<WriteReport, WriteError> {
+    check_counts(
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        num_buffers_per_producer,
+    )
+    .map_err(WriteError::Other)?;
+    let file = open_data_file(filename, total_size as u64, &mut options)?;
+    write_chunks(
+        &file,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        whole_chunks(producer),
+        client_data,
+        num_buffers_per_producer,
+        total_size,
+        None,
+        None,
+        &options,
+    )
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `write_to_file_with_options` but writes to an already open file,
+/// e.g. a file opened with specific flags or an anonymous file, instead of
+/// opening it by name; the file must be open for writing, and for reading
+/// with `verify`.
+///
+/// Consumers write through duplicates of the file obtained with
+/// `File::try_clone`, at absolute offsets starting at
+/// `WriteOptions::data_offset`, without moving the file cursor. The file is
+/// extended if smaller than the data written and never truncated, as with
+/// `OpenMode::CreateOrKeep`; `open_mode` and `lock` are not applied.
+#[allow(clippy::too_many_arguments)]
+pub fn write_to_open_file<T: 'static + Clone + Send, E: 'static + Send + Debug>(
+    file: &File,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    producer: Arc<Producer<T, E>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    total_size: usize,
+    options: WriteOptions,
+) -> Result<usize, WriteError> {
+    check_counts(
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        num_buffers_per_producer,
+    )
+    .map_err(WriteError::Other)?;
+    let end = options.data_offset + total_size as u64;
+    if file.metadata()?.len() < end {
+        file.set_len(end)?;
+    }
+    write_chunks(
+        file,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        whole_chunks(producer),
+        client_data,
+        num_buffers_per_producer,
+        total_size,
+        None,
+        None,
+        &options,
+    )
+    .map(|r| r.bytes_written)
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `write_to_file_with_options` but the producer callback returns the
+/// list of regions of the chunk to write to file or to leave untouched.
+///
+/// Regions are consecutive and must cover the whole chunk, i.e. the sum of
+/// their sizes must equal the buffer length, otherwise a `WriteError::Producer`
+/// error is returned. Bytes in `Region::Keep` regions are never written, use
+/// `OpenMode::CreateOrKeep` to preserve the existing file content. Bytes in
+/// `Region::Hole` regions are left unallocated, preserving the sparseness of
+/// files with large runs of zeros such as disk images.
+///
+/// The returned value is the number of bytes actually written, holes
+/// excluded.
+///
+/// Callback signature:
+///
+/// ```ignore
+/// type RegionProducer<T, E> = dyn Fn(&mut Vec<u8>, // <- buffer to write to
+///                                    &T,           // <- client data
+///                                    u64           // <- file offset (where data is written)
+///                                   ) -> Result<Vec<Region>, E>;
+/// ```
+#[allow(clippy::too_many_arguments)]
+pub fn write_to_file_with_gaps<T: 'static + Clone + Send, E: 'static + Send + Debug>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    producer: Arc<RegionProducer<T, E>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    total_size: usize,
+    options: WriteOptions,
+) -> Result<usize, WriteError> {
+    write_to_file_with_chunks(
+        filename,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        Arc::new(move |buffer: &mut Vec<u8>, data: &T, offset: u64| {
+            producer(buffer, data, offset).map(Some)
+        }),
+        client_data,
+        num_buffers_per_producer,
+        total_size,
+        options,
+    )
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `write_to_file_with_options` but the producer callback can end
+/// the data before `max_size` bytes by returning `Produced::Done(n)`: the
+/// data ends `n` bytes after the offset of the chunk and the file is
+/// truncated to that size after all consumers have completed.
+///
+/// `max_size` is a reservation: the file is first sized for `max_size` bytes
+/// and chunks are assigned to producers as with `write_to_file`, producer
+/// `i` generating the `i`-th of `num_producers` equal parts of `max_size`.
+/// Chunks starting after the end of the data are skipped once a producer
+/// returned `Produced::Done`, but chunks of other producers already
+/// generated or being generated are still written and then discarded by the
+/// truncation; with multiple producers the chunk ending the data must
+/// therefore not depend on data generated by producers of later parts.
+/// When several chunks return `Produced::Done` the smallest end offset is
+/// used. With `OpenMode::CreateOrKeep` existing content after the end of the
+/// data is also discarded.
+///
+/// The returned value is the size of the data, `max_size` if no chunk
+/// returned `Produced::Done`.
+///
+/// Callback signature:
+///
+/// ```ignore
+/// type EndingProducer<T, E> = dyn Fn(&mut Vec<u8>, // <- buffer to write to
+///                                    &T,           // <- client data
+///                                    u64           // <- file offset (where data is written)
+///                                   ) -> Result<Produced, E>;
+/// ```
+#[allow(clippy::too_many_arguments)]
+pub fn write_to_file_until<T: 'static + Clone + Send, E: 'static + Send + Debug>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    producer: Arc<EndingProducer<T, E>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    max_size: usize,
+    mut options: WriteOptions,
+) -> Result<usize, WriteError> {
+    check_counts(
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        num_buffers_per_producer,
+    )
+    .map_err(WriteError::Other)?;
+    // offset of the end of the data
+    let end = Arc::new(AtomicU64::new(u64::MAX));
+    let end_offset = end.clone();
+    let chunk_producer: Arc<ChunkProducer<T, E>> =
+        Arc::new(move |buffer: &mut Vec<u8>, data: &T, offset: u64| {
+            let len = buffer.len() as u64;
+            if offset >= end_offset.load(Ordering::SeqCst) {
+                return Ok(Some(vec![Region::Keep(len)]));
+            }
+            match producer(buffer, data, offset)? {
+                Produced::More => Ok(None),
+                Produced::Done(n) => {
+                    let n = (n as u64).min(len);
+                    end_offset.fetch_min(offset + n, Ordering::SeqCst);
+                    let mut regions = vec![Region::Write(n), Region::Keep(len - n)];
+                    regions.retain(|r| *r != Region::Write(0) && *r != Region::Keep(0));
+                    Ok(Some(regions))
+                }
+            }
+        });
+    let file = open_data_file(filename, max_size as u64, &mut options)?;
+    write_chunks(
+        &file,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        chunk_producer,
+        client_data,
+        num_buffers_per_producer,
+        max_size,
+        None,
+        None,
+        &options,
+    )?;
+    let size = end.load(Ordering::SeqCst).min(max_size as u64);
+    file.set_len(options.data_offset + size)
+        .map_err(WriteError::IO)?;
+    // data already flushed, flush the new size
+    sync_file(&file, options.sync)?;
+    Ok(size as usize)
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `write_to_file_with_options` but the producer callback also
+/// receives the id of the chunk and the total number of chunks, to e.g.
+/// generate a header in the first chunk or a footer in the last one.
+///
+/// As with read callbacks, chunk ids start at 1 and follow the file offset:
+/// the first chunk of the file has id 1 and the last one id `num_chunks`.
+/// The number of chunks can be smaller than
+/// `num_producers * chunks_per_producer` when chunk sizes are rounded up.
+///
+/// Callback signature:
+///
+/// ```ignore
+/// type ChunkIdProducer<T, E> = dyn Fn(&mut Vec<u8>, // <- buffer to write to
+///                                     &T,           // <- client data
+///                                     u64,          // <- file offset (where data is written)
+///                                     u64,          // <- chunk id
+///                                     u64           // <- number of chunks
+///                                    ) -> Result<(), E>;
+/// ```
+#[allow(clippy::too_many_arguments)]
+pub fn write_to_file_with_chunk_ids<T: 'static + Clone + Send, E: 'static + Send + Debug>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    producer: Arc<ChunkIdProducer<T, E>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    total_size: usize,
+    options: WriteOptions,
+) -> Result<usize, WriteError> {
+    check_counts(
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        num_buffers_per_producer,
+    )
+    .map_err(WriteError::Other)?;
+    // offset range, chunk size and id of the chunk preceding the first chunk
+    // of each producer
+    let mut ranges = Vec::with_capacity(num_producers as usize);
+    let mut num_chunks = 0;
+    for i in 0..num_producers {
+        let r = producer_range(i, num_producers, total_size as u64, chunks_per_producer);
+        if r.end_offset > r.offset {
+            ranges.push((r.offset, r.end_offset, r.chunk_size, num_chunks));
+            num_chunks += (r.end_offset - r.offset + r.chunk_size - 1) / r.chunk_size;
+        }
+    }
+    let chunk_producer: Arc<ChunkProducer<T, E>> =
+        Arc::new(move |buffer: &mut Vec<u8>, data: &T, offset: u64| {
+            let (start, _, chunk_size, first) = ranges
+                .iter()
+                .find(|(start, end, _, _)| *start <= offset && offset < *end)
+                .copied()
+                .unwrap_or((offset, offset, 1, 0));
+            let chunk_id = first + (offset - start) / chunk_size + 1;
+            let len = buffer.len();
+            producer(buffer, data, offset, chunk_id, num_chunks).map(|_| generated(buffer, len))
+        });
+    write_to_file_with_chunks(
+        filename,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        chunk_producer,
+        client_data,
+        num_buffers_per_producer,
+        total_size,
+        options,
+    )
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `write_to_file_with_options` but each chunk has its own client
+/// data, returned by `chunk_data` for the id of the chunk, e.g. a nonce or
+/// metadata specific to the records of the chunk.
+///
+/// Chunk ids are the ids passed to `write_to_file_with_chunk_ids` callbacks:
+/// they start at 1 and follow the file offset. To index a `Vec` of per-chunk
+/// values, move it into `chunk_data` and return a clone of element
+/// `chunk_id - 1`.
+///
+/// `chunk_data` is called on the producer thread right before the chunk is
+/// generated, once for each chunk; the returned value is owned by the
+/// producer, borrowed by `producer` and dropped once the chunk is generated,
+/// hence `T` needs neither `Clone` nor `Send`.
+#[allow(clippy::too_many_arguments)]
+pub fn write_to_file_per_chunk<T: 'static, E: 'static + Send + Debug>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    producer: Arc<Producer<T, E>>,
+    chunk_data: Arc<ChunkData<T>>,
+    num_buffers_per_producer: u64,
+    total_size: usize,
+    options: WriteOptions,
+) -> Result<usize, WriteError> {
+    let chunk_producer =
+        move |buffer: &mut Vec<u8>,
+              chunk_data: &Arc<ChunkData<T>>,
+              offset: u64,
+              chunk_id: u64,
+              _num_chunks: u64| { producer(buffer, &chunk_data(chunk_id), offset) };
+    write_to_file_with_chunk_ids(
+        filename,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        Arc::new(chunk_producer),
+        chunk_data,
+        num_buffers_per_producer,
+        total_size,
+        options,
+    )
+}
+
+// -----------------------------------------------------------------------------
+/// Create file and write data generated by internal chunk producer.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn write_to_file_with_chunks<T: 'static + Clone + Send, E: 'static + Send + Debug>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    producer: Arc<ChunkProducer<T, E>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    total_size: usize,
+    mut options: WriteOptions,
+) -> Result<usize, WriteError> {
+    check_counts(
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        num_buffers_per_producer,
+    )
+    .map_err(WriteError::Other)?;
+    let file = open_data_file(filename, total_size as u64, &mut options)?;
+    write_chunks(
+        &file,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        producer,
+        client_data,
+        num_buffers_per_producer,
+        total_size,
+        None,
+        None,
+        &options,
+    )
+    .map(|r| r.bytes_written)
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `write_to_file_with_options` but the producer callback generates
+/// blocks of `chunks_per_block` consecutive chunks at once.
+///
+/// Each producer thread owns a staging buffer holding one block: the callback
+/// is invoked with the staging buffer and the file offset of the block, the
+/// block is then sliced into chunks sent to consumers. Blocks never span
+/// producer regions, the last block of a region can be smaller.
+///
+/// Memory usage increases by the size of one block per producer.
+#[allow(clippy::too_many_arguments)]
+pub fn write_to_file_blocks<T: 'static + Clone + Send, E: 'static + Send + Debug>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    chunks_per_block: u64,
+    producer: Arc<Producer<T, E>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    total_size: usize,
+    mut options: WriteOptions,
+) -> Result<usize, WriteError> {
+    check_counts(
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        num_buffers_per_producer,
+    )
+    .map_err(WriteError::Other)?;
+    let file = open_data_file(filename, total_size as u64, &mut options)?;
+    write_chunks(
+        &file,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        staged_blocks(producer, num_producers, total_size as u64, chunks_per_block),
+        client_data,
+        num_buffers_per_producer,
+        total_size,
+        None,
+        None,
+        &options,
+    )
+    .map(|r| r.bytes_written)
+}
+
+// Block generated by a block producer: (file offset, data)
+type Staging = Mutex<(u64, Vec<u8>)>;
+
+/// Wrap producer generating blocks of `chunks_per_block` chunks, copying
+/// each chunk from the block containing it and generating a new block when
+/// the chunk is not in the current one.
+fn staged_blocks<T: 'static, E: 'static>(
+    producer: Arc<Producer<T, E>>,
+    num_producers: u64,
+    total_size: u64,
+    chunks_per_block: u64,
+) -> Arc<ChunkProducer<T, E>> {
+    // chunks_per_producer does not affect producer regions
+    let producer_chunk_size = plan(total_size, num_producers, 1)
+        .producer_chunk_size
+        .max(1);
+    // one staging buffer per producer thread, each only accessed by its
+    // own producer
+    let staging: Vec<Staging> = (0..num_producers)
+        .map(|_| Mutex::new((0, Vec::new())))
+        .collect();
+    Arc::new(move |buffer: &mut Vec<u8>, data: &T, offset: u64| {
+        let p = offset / producer_chunk_size;
+        let mut guard = match staging[p as usize].lock() {
+            Ok(g) => g,
+            Err(err) => err.into_inner(),
+        };
+        let (block_offset, block) = &mut *guard;
+        let len = buffer.len() as u64;
+        if offset < *block_offset || offset + len > *block_offset + block.len() as u64 {
+            let region_end = ((p + 1) * producer_chunk_size).min(total_size);
+            let size = (len * chunks_per_block.max(1)).min(region_end - offset);
+            // zero the block: bytes not generated by the producer must not
+            // leak data from the previous block
+            block.clear();
+            block.resize(size as usize, 0);
+            producer(block, data, offset)?;
+            *block_offset = offset;
+        }
+        let begin = (offset - *block_offset) as usize;
+        buffer.copy_from_slice(&block[begin..begin + len as usize]);
+        Ok(None)
+    })
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `write_to_file_with_options` but the producer callback decides the
+/// size of each chunk, e.g. when serializing records whose encoded length is
+/// only known after encoding.
+///
+/// `num_producers * chunks_per_producer` chunks are generated, chunk `k` by
+/// producer `k % num_producers`. The callback receives a buffer of
+/// `max_chunk_size` bytes and the chunk index instead of the file offset,
+/// which depends on the size of the preceding chunks, and returns the number
+/// of bytes generated at the start of the buffer; returning more than
+/// `max_chunk_size` bytes is a `WriteError::Producer` error. The `offset`
+/// of producer errors is the chunk index.
+///
+/// Chunks are written one after the other in chunk index order: chunks are
+/// generated in parallel, but a producer waits for the preceding chunk to be
+/// generated before its chunk is assigned a file offset and sent to
+/// consumers. The file is not preallocated and is not truncated to the data
+/// written with `OpenMode::CreateOrKeep`. Progress is reported against the
+/// maximum size `num_producers * chunks_per_producer * max_chunk_size`,
+/// `on_stall` is ignored.
+///
+/// The returned value is the number of bytes written.
+///
+/// Callback signature:
+///
+/// ```ignore
+/// type VariableProducer<T, E> = dyn Fn(&mut Vec<u8>, // <- buffer to write to
+///                                      &T,           // <- client data
+///                                      u64           // <- chunk index
+///                                     ) -> Result<usize, E>;
+/// ```
+#[allow(clippy::too_many_arguments)]
+pub fn write_to_file_variable<T: 'static + Clone + Send, E: 'static + Send + Debug>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    producer: Arc<VariableProducer<T, E>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    max_chunk_size: usize,
+    mut options: WriteOptions,
+) -> Result<usize, WriteError> {
+    check_counts(
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        num_buffers_per_producer,
+    )
+    .map_err(WriteError::Other)?;
+    let num_chunks = num_producers * chunks_per_producer;
+    let max_total_size = (num_chunks as usize)
+        .checked_mul(max_chunk_size)
+        .ok_or_else(|| WriteError::Other("Maximum data size overflows".to_string()))?;
+    let file = open_data_file(filename, 0, &mut options)?;
+    let sequencer = Arc::new(Sequencer::default());
+    let placement = options.placement();
+    // the chunk sizes computed from the maximum size are all equal to
+    // `max_chunk_size`, used to size the buffers
+    write_chunks_with(
+        &file,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        num_buffers_per_producer,
+        max_total_size,
+        None,
+        None,
+        &options,
+        Some(max_chunk_size as u64),
+        |_activity, interrupted| {
+            let mut tx_producers = Senders::new();
+            let mut prods = ProducerHandles::new();
+            for i in 0..num_producers {
+                let (tx, rx) = channel();
+                tx_producers.push(tx);
+                let cc = VariableFnMove {
+                    f: producer.clone(),
+                };
+                let data = client_data.clone();
+                let sequencer = sequencer.clone();
+                let cpu_report = options.cpu_report.clone();
+                let stats_report = options.stats.clone();
+                let cancel = options.cancel.clone();
+                let interrupted = interrupted.clone();
+                let selector = options.selector.clone();
+                let placement = placement.clone();
+                let h = worker::spawn_on(
+                    options.pool.as_ref(),
+                    thread_name(Worker::Producer(i)),
+                    options.stack_size,
+                    move || {
+                        cpu::pin(placement.as_ref(), Worker::Producer(i));
+                        // move the Send wrapper, not only its field
+                        let cc = cc;
+                        produce_variable(
+                            i,
+                            num_producers,
+                            num_chunks,
+                            max_chunk_size,
+                            rx,
+                            &*cc.f,
+                            &data,
+                            &sequencer,
+                            cpu_report.as_deref(),
+                            stats_report,
+                            cancel.as_deref(),
+                            &interrupted,
+                            selector.as_deref(),
+                        )
+                    },
+                );
+                match h {
+                    Ok(h) => prods.push(h),
+                    Err(err) => {
+                        join_producers(tx_producers, prods)?;
+                        return Err(WriteError::Other(format!(
+                            "Cannot spawn producer - {}",
+                            err
+                        )));
+                    }
+                }
+            }
+            Ok((tx_producers, prods))
+        },
+    )
+    .map(|r| r.bytes_written)
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `write_to_file` but the write is performed in a separate thread and
+/// a `WriteEvent` is sent to the returned `Receiver` each time a chunk is
+/// written to file, in completion order.
+///
+/// The returned `JoinHandle` yields the same result as `write_to_file`.
+/// Dropping the `Receiver` does not affect the write operation: consumers stop
+/// emitting events once they detect the receiving end is gone.
+#[allow(clippy::too_many_arguments)]
+pub fn write_to_file_with_events<T: 'static + Clone + Send, E: 'static + Send + Debug>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    producer: Arc<Producer<T, E>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    total_size: usize,
+) -> (Receiver<WriteEvent>, JoinHandle<Result<usize, WriteError>>) {
+    let (tx, rx) = channel();
+    let filename = filename.to_owned();
+    let producer = FnMove {
+        f: whole_chunks(producer),
+    };
+    let h = worker::spawn("par_io-write".to_string(), None, move || {
+        // move the whole wrapper, not just the non-Send field
+        let producer = producer;
+        check_counts(
+            num_producers,
+            num_consumers,
+            chunks_per_producer,
+            num_buffers_per_producer,
+        )
+        .map_err(WriteError::Other)?;
+        let file = create_file(
+            &filename,
+            total_size as u64,
+            OpenMode::Truncate,
+            LockPolicy::NoLock,
+        )?;
+        write_chunks(
+            &file,
+            num_producers,
+            num_consumers,
+            chunks_per_producer,
+            producer.f,
+            client_data,
+            num_buffers_per_producer,
+            total_size,
+            Some(tx),
+            None,
+            &WriteOptions::default(),
+        )
+        .map(|r| r.bytes_written)
+    })
+    // same as thread::spawn
+    .expect("failed to spawn thread");
+    (rx, h)
+}
+
+// -----------------------------------------------------------------------------
+/// Handle to a write operation started by `spawn_write`.
+///
+/// Dropping the handle waits for the operation to complete, discarding the
+/// result, so that no thread is left running.
+pub struct WriteHandle {
+    handle: Option<JoinHandle<Result<usize, WriteError>>>,
+    bytes_done: Arc<AtomicU64>,
+}
+
+impl WriteHandle {
+    /// Number of bytes written so far, increasing as chunks are written;
+    /// bytes of regions kept by gap-aware writes are included.
+    pub fn bytes_done(&self) -> u64 {
+        self.bytes_done.load(Ordering::SeqCst)
+    }
+    /// Return `true` if the write operation has completed.
+    pub fn is_finished(&self) -> bool {
+        self.handle.as_ref().map_or(true, |h| h.is_finished())
+    }
+    /// Wait for the write operation to complete and return the same result
+    /// as `write_to_file_with_options`.
+    pub fn join(mut self) -> Result<usize, WriteError> {
+        match self.handle.take() {
+            Some(h) => h
+                .join()
+                .map_err(|err| WriteError::Other(format!("{:?}", err)))?,
+            None => Err(WriteError::Other("Write already joined".to_string())),
+        }
+    }
+}
+
+impl Drop for WriteHandle {
+    fn drop(&mut self) {
+        if let Some(h) = self.handle.take() {
+            let _ = h.join();
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `write_to_file_with_options` but returns immediately; the write is
+/// performed in a separate thread and the returned handle is used to wait for
+/// the result.
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_write<T: 'static + Clone + Send, E: 'static + Send + Debug>(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    producer: Arc<Producer<T, E>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    total_size: usize,
+    options: WriteOptions,
+) -> Result<WriteHandle, WriteError> {
+    let filename = filename.to_owned();
+    let producer = FnMove {
+        f: whole_chunks(producer),
+    };
+    // updated through the progress callback, chaining the client callback
+    let bytes_done = Arc::new(AtomicU64::new(0));
+    let done = bytes_done.clone();
+    let progress = options.progress.clone();
+    let options = WriteOptions {
+        progress: Some(Arc::new(move |bytes, total| {
+            // invocations from different consumers can be reordered
+            done.fetch_max(bytes, Ordering::SeqCst);
+            if let Some(f) = &progress {
+                f(bytes, total);
+            }
+        })),
+        ..options
+    };
+    let h = worker::spawn("par_io-write".to_string(), None, move || {
+        // move the whole wrapper, not just the non-Send field
+        let producer = producer;
+        write_to_file_with_chunks(
+            &filename,
+            num_producers,
+            num_consumers,
+            chunks_per_producer,
+            producer.f,
+            client_data,
+            num_buffers_per_producer,
+            total_size,
+            options,
+        )
+    })
+    .map_err(|err| WriteError::Other(format!("Cannot spawn write thread - {}", err)))?;
+    Ok(WriteHandle {
+        handle: Some(h),
+        bytes_done,
+    })
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `write_to_file` but data is written to an anonymous temporary file
+/// created in directory `dir`; the file is returned positioned at the start
+/// of the data and is removed when closed.
+///
+/// On Linux the file is created with `O_TMPFILE` and never has a name; on
+/// other platforms, on architectures other than x86, ARM, PowerPC, RISC-V,
+/// s390x and LoongArch, where the value of `O_TMPFILE` differs, or when the
+/// filesystem does not support `O_TMPFILE`, a file is created and
+/// immediately unlinked.
+#[allow(clippy::too_many_arguments)]
+pub fn write_to_tempfile<T: 'static + Clone + Send, E: 'static + Send + Debug>(
+    dir: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    producer: Arc<Producer<T, E>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    total_size: usize,
+) -> Result<File, WriteError> {
+    check_counts(
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        num_buffers_per_producer,
+    )
+    .map_err(WriteError::Other)?;
+    use std::io::{Seek, SeekFrom};
+    let mut file = open_tempfile(dir).map_err(WriteError::IO)?;
+    file.set_len(total_size as u64).map_err(WriteError::IO)?;
+    write_chunks(
+        &file,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        whole_chunks(producer),
+        client_data,
+        num_buffers_per_producer,
+        total_size,
+        None,
+        None,
+        &WriteOptions::default(),
+    )?;
+    file.seek(SeekFrom::Start(0)).map_err(WriteError::IO)?;
+    Ok(file)
+}
+
+// -----------------------------------------------------------------------------
+/// Open anonymous file for reading and writing in directory `dir`.
+fn open_tempfile(dir: &str) -> std::io::Result<File> {
+    #[cfg(target_os = "linux")]
+    if let Some(flags) = o_tmpfile() {
+        use std::os::unix::fs::OpenOptionsExt;
+        if let Ok(file) = File::options()
+            .read(true)
+            .write(true)
+            .custom_flags(flags)
+            .open(dir)
+        {
+            return Ok(file);
+        }
+    }
+    use std::sync::atomic::AtomicUsize;
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let path = std::path::Path::new(dir).join(format!(
+        ".par_io-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::SeqCst)
+    ));
+    let file = File::options()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    std::fs::remove_file(&path)?;
+    Ok(file)
+}
+
+/// Return `O_TMPFILE`, i.e. `__O_TMPFILE | O_DIRECTORY`, whose value depends
+/// on the architecture; `None` where it is not known, e.g. on sparc, alpha
+/// and mips.
+#[cfg(target_os = "linux")]
+fn o_tmpfile() -> Option<i32> {
+    if cfg!(any(
+        target_arch = "x86",
+        target_arch = "x86_64",
+        target_arch = "riscv32",
+        target_arch = "riscv64",
+        target_arch = "s390x",
+        target_arch = "loongarch64"
+    )) {
+        Some(0o20200000)
+    } else if cfg!(any(
+        target_arch = "arm",
+        target_arch = "aarch64",
+        target_arch = "powerpc",
+        target_arch = "powerpc64"
+    )) {
+        // `O_DIRECTORY` is 0o40000
+        Some(0o20040000)
+    } else {
+        None
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Flush `file` to disk according to `mode`.
+fn sync_file(file: &File, mode: SyncMode) -> Result<(), WriteError> {
+    match mode {
+        SyncMode::None => Ok(()),
+        SyncMode::Data => file.sync_data().map_err(WriteError::IO),
+        SyncMode::All => file.sync_all().map_err(WriteError::IO),
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Truncate or extend existing file to `new_size` bytes; extending creates a
+/// hole read back as zeros.
+pub fn resize_file(filename: &str, new_size: u64) -> Result<(), WriteError> {
+    let file = File::options()
+        .write(true)
+        .open(filename)
+        .map_err(WriteError::IO)?;
+    file.set_len(new_size).map_err(WriteError::IO)
+}
+
+// -----------------------------------------------------------------------------
+/// Open file according to `mode`, lock it according to `lock` and make sure it
+/// can hold `total_size` bytes; the file is truncated after being locked.
+pub(crate) fn create_file(
+    filename: &str,
+    total_size: u64,
+    mode: OpenMode,
+    lock: LockPolicy,
+) -> Result<File, WriteError> {
+    create_file_at(filename, total_size, mode, lock).map(|(file, _)| file)
+}
+
+// -----------------------------------------------------------------------------
+/// Open file for writing `total_size` bytes after `options.data_offset`; with
+/// `OpenMode::Append` the data offset is moved past the end of file.
+pub(crate) fn open_data_file(
+    filename: &str,
+    total_size: u64,
+    options: &mut WriteOptions,
+) -> Result<File, WriteError> {
+    let (file, base) = create_file_at(
+        filename,
+        total_size + options.data_offset,
+        options.open_mode,
+        options.lock,
+    )?;
+    options.data_offset += base;
+    Ok(file)
+}
+
+/// Same as `create_file`, also returns the offset `total_size` is relative
+/// to: the previous file size with `OpenMode::Append`, zero otherwise.
+fn create_file_at(
+    filename: &str,
+    total_size: u64,
+    mode: OpenMode,
+    lock: LockPolicy,
+) -> Result<(File, u64), WriteError> {
+    // readable to allow verification
+    let file = File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(filename)
+        .map_err(|err| match mode {
+            OpenMode::Truncate => to_write_err(err.to_string()),
+            OpenMode::CreateOrKeep | OpenMode::Append => WriteError::IO(err),
+        })?;
+    if !try_lock(&file, lock).map_err(WriteError::IO)? {
+        return Err(WriteError::Locked {
+            filename: filename.to_string(),
+        });
+    }
+    match mode {
+        OpenMode::Truncate => {
+            file.set_len(0)
+                .and_then(|_| file.set_len(total_size))
+                .map_err(|err| to_write_err(err.to_string()))?;
+        }
+        OpenMode::CreateOrKeep => {
+            if file.metadata().map_err(WriteError::IO)?.len() < total_size {
+                file.set_len(total_size).map_err(WriteError::IO)?;
+            }
+        }
+        OpenMode::Append => {
+            let base = file.metadata().map_err(WriteError::IO)?.len();
+            file.set_len(base + total_size).map_err(WriteError::IO)?;
+            return Ok((file, base));
+        }
+    }
+    Ok((file, 0))
+}
+
+// -----------------------------------------------------------------------------
+/// Write data to file, optionally sending events to `events` after each chunk
+/// is written.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn write_chunks<T: 'static + Clone + Send, E: 'static + Send + Debug>(
+    file: &File,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    producer: Arc<ChunkProducer<T, E>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    total_size: usize,
+    events: Option<Sender<WriteEvent>>,
+    dedup: Option<Arc<Dedup>>,
+    options: &WriteOptions,
+) -> Result<WriteReport, WriteError> {
+    write_chunks_with(
+        file,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        num_buffers_per_producer,
+        total_size,
+        events,
+        dedup,
+        options,
+        None,
+        |activity, interrupted| {
+            build_producers(
+                (0..num_producers)
+                    .map(|i| {
+                        producer_range(i, num_producers, total_size as u64, chunks_per_producer)
+                    })
+                    .collect(),
+                producer,
+                client_data,
+                activity,
+                options.cpu_report.clone(),
+                options.stats.clone(),
+                options.cancel.clone(),
+                interrupted,
+                options.stack_size,
+                options.pool.clone(),
+                options.selector.clone(),
+                options.placement(),
+            )
+        },
+    )
+}
+
+// -----------------------------------------------------------------------------
+/// Producer ranges of `total_size` bytes split as `read::aligned_tasks`, all
+/// chunks but the last one of each producer holding whole blocks of
+/// `block_size` bytes.
+pub(crate) fn aligned_ranges(
+    total_size: u64,
+    num_producers: u64,
+    chunks_per_producer: u64,
+    block_size: u64,
+) -> Vec<ProducerRange> {
+    let tasks = aligned_tasks(total_size, num_producers, chunks_per_producer, block_size);
+    let mut ranges: Vec<ProducerRange> = tasks
+        .iter()
+        .zip(0..)
+        .filter_map(|(chunks, i)| {
+            let first = chunks.first()?;
+            let last = chunks.last()?;
+            Some(ProducerRange {
+                chunk_id: chunks_per_producer * i,
+                offset: first.offset,
+                end_offset: last.offset + last.size,
+                chunk_size: first.size,
+            })
+        })
+        .collect();
+    if ranges.is_empty() {
+        ranges.push(ProducerRange {
+            chunk_id: 0,
+            offset: 0,
+            end_offset: 0,
+            chunk_size: 0,
+        });
+    }
+    ranges
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `write_chunks` with the chunks generated by each producer
+/// specified by `ranges`, which must not overlap; `total_size` is the size of
+/// the data. Fails with `WriteError::Other` before writing anything if a range
+/// extends past `total_size` or has empty chunks.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn write_ranges<T: 'static + Clone + Send, E: 'static + Send + Debug>(
+    file: &File,
+    ranges: Vec<ProducerRange>,
+    num_consumers: u64,
+    producer: Arc<ChunkProducer<T, E>>,
+    client_data: T,
+    num_buffers_per_producer: u64,
+    total_size: usize,
+    options: &WriteOptions,
+) -> Result<WriteReport, WriteError> {
+    if let Some(r) = ranges.iter().find(|r| {
+        r.offset > r.end_offset
+            || r.end_offset > total_size as u64
+            || (r.offset < r.end_offset && r.chunk_size == 0)
+    }) {
+        return Err(WriteError::Other(format!(
+            "Invalid producer range [{}, {}) with chunks of {} bytes, data size is {}",
+            r.offset, r.end_offset, r.chunk_size, total_size
+        )));
+    }
+    let num_producers = ranges.len() as u64;
+    let chunks_per_producer = ranges
+        .iter()
+        .map(|r| (r.end_offset - r.offset + r.chunk_size.max(1) - 1) / r.chunk_size.max(1))
+        .max()
+        .unwrap_or(1)
+        .max(1);
+    let reserved_size = ranges.iter().map(|r| r.chunk_size).max().unwrap_or(0);
+    write_chunks_with(
+        file,
+        num_producers,
+        num_consumers,
+        chunks_per_producer,
+        num_buffers_per_producer,
+        total_size,
+        None,
+        None,
+        options,
+        Some(reserved_size),
+        |activity, interrupted| {
+            build_producers(
+                ranges,
+                producer,
+                client_data,
+                activity,
+                options.cpu_report.clone(),
+                options.stats.clone(),
+                options.cancel.clone(),
+                interrupted,
+                options.stack_size,
+                options.pool.clone(),
+                options.selector.clone(),
+                options.placement(),
+            )
+        },
+    )
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `write_chunks` with producer threads started by `build`, which
+/// receives the activity tracker used to detect stalls; buffers can hold at
+/// least `reserved_size` bytes if specified, and the largest chunk computed
+/// from `total_size` in any case.
+#[allow(clippy::too_many_arguments)]
+fn write_chunks_with<P>(
+    file: &File,
+    num_producers: u64,
+    num_consumers: u64,
+    chunks_per_producer: u64,
+    num_buffers_per_producer: u64,
+    total_size: usize,
+    events: Option<Sender<WriteEvent>>,
+    dedup: Option<Arc<Dedup>>,
+    options: &WriteOptions,
+    reserved_size: Option<u64>,
+    build: P,
+) -> Result<WriteReport, WriteError>
+where
+    P: FnOnce(
+        Option<Arc<Activity>>,
+        Arc<AtomicBool>,
+    ) -> Result<(Senders, ProducerHandles), WriteError>,
+{
+    // nothing to write, the file is already created: do not spawn threads
+    if total_size == 0 {
+        return Ok(WriteReport::default());
+    }
+    let total_size = total_size as u64;
+    let layout = plan(total_size, num_producers, chunks_per_producer);
+    let activity = options
+        .on_stall
+        .as_ref()
+        .map(|(timeout, _)| *timeout)
+        .or(options.progress_timeout)
+        .map(|_| Arc::new(Activity::new(num_producers)));
+    // stopped when going out of scope
+    let _watchdog = activity
+        .as_ref()
+        .zip(options.on_stall.clone())
+        .map(|(activity, (timeout, handler))| Watchdog::spawn(activity.clone(), timeout, handler));
+    // set by producers stopping before their last chunk after cancellation
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let checkpoint = match &options.checkpoint {
+        Some(path) => Some(Arc::new(Checkpoint::open(path)?)),
+        None => None,
+    };
+    let (tx_producers, prods) = build(activity.clone(), interrupted.clone())?;
+    let chunks_started = Arc::new(AtomicU64::new(0));
+    // the number of bytes written is also used to detect stalls
+    let progress = match (&options.progress, options.progress_timeout) {
+        (Some(f), _) => Some(f.clone()),
+        (None, Some(_)) => Some(Arc::new(|_, _| {}) as Arc<Progress>),
+        (None, None) => None,
+    }
+    .map(|f| Arc::new(Tracker::new(f, total_size)));
+    let (tx_consumers, consumers_handles) = match build_consumers(
+        num_consumers,
+        file,
+        events,
+        dedup,
+        checkpoint,
+        chunks_started.clone(),
+        progress.clone(),
+        options,
+    ) {
+        Ok(r) => r,
+        Err(err) => {
+            // producers exit when their channels are disconnected
+            join_producers(tx_producers, prods)?;
+            return Err(err);
+        }
+    };
+    // stopped when going out of scope
+    let monitor = progress.zip(activity).zip(options.progress_timeout).map(
+        |((progress, activity), timeout)| {
+            let consumers = tx_consumers.clone();
+            let stalled = activity.clone();
+            ProgressMonitor::spawn(
+                move || progress.done(),
+                move || stalled.stalled_offset(),
+                timeout,
+                move || {
+                    // make consumers exit, producers exit when they fail to
+                    // send the next chunk
+                    let offset = activity.stalled_offset().unwrap_or(0);
+                    consumers.iter().for_each(|c| {
+                        let _ = c.send(Message::Error(ProducerError {
+                            msg: "No progress".to_string(),
+                            offset,
+                        }));
+                    });
+                },
+            )
+        },
+    );
+    let reserved_size = layout.buffer_size().max(reserved_size.unwrap_or(0));
+    let (num_buffers_per_producer, honored) = buffers_per_producer(
+        num_buffers_per_producer,
+        options.max_memory_bytes,
+        num_producers,
+        reserved_size,
+    );
+    if !honored {
+        if let Some(s) = &options.stats {
+            s.memory_cap_exceeded();
+        }
+    }
+    launch(
+        tx_producers,
+        tx_consumers,
+        &layout,
+        reserved_size as usize,
+        num_buffers_per_producer,
+        options.allocator.as_ref(),
+    );
+
+    let mut bytes_consumed = 0;
+    let mut failed_offsets = Vec::new();
+    let mut errors = Vec::new();
+    // all threads are joined before returning the first error
+    let mut first_error = None;
+    for h in consumers_handles {
+        match h.join() {
+            Ok(n) => match n {
+                Ok((bytes, failed, e)) => {
+                    bytes_consumed += bytes;
+                    failed_offsets.extend(failed);
+                    errors.extend(e);
+                }
+                Err(err) => {
+                    first_error.get_or_insert(err);
+                }
+            },
+            Err(err) => {
+                first_error.get_or_insert(WriteError::Other(format!("{:?}", err)));
+            }
+        }
+    }
+    if first_error.is_some() {
+        if let Some(offset) = monitor.as_ref().and_then(|m| m.expired()) {
+            // stalled producers cannot be joined
+            return Err(WriteError::Timeout { offset });
+        }
+    }
+    if let Err(err) = join_producers(Senders::new(), prods) {
+        first_error.get_or_insert(err);
+    }
+    if let Some(err) = first_error {
+        return Err(err);
+    }
+    if let Some(k) = options.crash_after {
+        if chunks_started.load(Ordering::SeqCst) > k {
+            return Err(WriteError::Other(format!(
+                "Simulated crash after {} chunks",
+                k
+            )));
+        }
+    }
+    // a cancellation requested after all chunks were generated does not
+    // affect the write
+    if interrupted.load(Ordering::SeqCst) {
+        if options.sync_on_cancel {
+            file.sync_all().map_err(WriteError::IO)?;
+        }
+        return Err(WriteError::Cancelled {
+            written: bytes_consumed,
+        });
+    }
+    sync_file(file, options.sync)?;
+    failed_offsets.sort_unstable();
+    errors.sort_unstable_by_key(|e: &ChunkError| e.offset);
+    Ok(WriteReport {
+        bytes_written: bytes_consumed,
+        failed_offsets,
+        errors,
+    })
+}
+
+// -----------------------------------------------------------------------------
+/// Build producers and return array of Sender objects.
+#[allow(clippy::too_many_arguments)]
+fn build_producers<T: 'static + Clone + Send, E: 'static + Send + Debug>(
+    ranges: Vec<ProducerRange>,
+    f: Arc<ChunkProducer<T, E>>,
+    data: T,
+    activity: Option<Arc<Activity>>,
+    cpu_report: Option<Arc<CpuReport>>,
+    stats_report: Option<Arc<StatsReport>>,
+    cancel: Option<Arc<CancelToken>>,
+    interrupted: Arc<AtomicBool>,
+    stack_size: Option<usize>,
+    pool: Option<ParIoPool>,
+    selector: Option<Arc<dyn ConsumerSelector>>,
+    placement: Option<Placement>,
+) -> Result<(Senders, ProducerHandles), WriteError> {
+    let num_producers = ranges.len() as u64;
+    let mut tx_producers: Senders = Senders::new();
+    let mut prods = ProducerHandles::new();
+    // currently producers exit after sending all data, and consumers might try
+    // to send data back to disconnected producers, ignoring the returned
+    // send() error;
+    // another option is to have consumers return and 'End' signal when done
+    // consuming data and producers exiting after al the consumers have
+    // returned the signal
+    for (i, range) in (0..num_producers).zip(ranges) {
+        let (tx, rx) = channel();
+        tx_producers.push(tx);
+        let cc = FnMove { f: f.clone() };
+        let data = data.clone();
+        let activity = activity.clone();
+        let cpu_report = cpu_report.clone();
+        let stats_report = stats_report.clone();
+        let cancel = cancel.clone();
+        let interrupted = interrupted.clone();
+        let selector = selector.clone();
+        let placement = placement.clone();
+        let h = worker::spawn_on(
+            pool.as_ref(),
+            thread_name(Worker::Producer(i)),
+            stack_size,
+            move || -> Result<(), String> {
+                cpu::pin(placement.as_ref(), Worker::Producer(i));
+                // move the Send wrapper, not only its field
+                let cc = cc;
+                produce(
+                    i,
+                    num_producers,
+                    rx,
+                    &*cc.f,
+                    &data,
+                    range,
+                    activity.as_deref(),
+                    cpu_report.as_deref(),
+                    stats_report,
+                    cancel.as_deref(),
+                    &interrupted,
+                    selector.as_deref(),
+                )
+            },
+        );
+        match h {
+            Ok(h) => prods.push(h),
+            Err(err) => {
+                join_producers(tx_producers, prods)?;
+                return Err(WriteError::Other(format!(
+                    "Cannot spawn producer - {}",
+                    err
+                )));
+            }
+        }
+    }
+    Ok((tx_producers, prods))
+}
+
+// -----------------------------------------------------------------------------
+/// Disconnect producers from `tx_producers` and wait for them to exit; errors
+/// returned by producers are ignored since consumers report them.
+fn join_producers(tx_producers: Senders, prods: ProducerHandles) -> Result<(), WriteError> {
+    drop(tx_producers);
+    let mut result = Ok(());
+    for p in prods {
+        if let Err(err) = p.join() {
+            if result.is_ok() {
+                result = Err(WriteError::Other(format!("{:?}", err)));
+            }
+        }
+    }
+    result
+}
+
+// -----------------------------------------------------------------------------
+/// Chunks generated by one producer.
+#[derive(Clone, Copy)]
+pub(crate) struct ProducerRange {
+    // id of the chunk preceding the first chunk
+    pub chunk_id: u64,
+    pub offset: u64,
+    pub end_offset: u64,
+    // size of all chunks but the last one
+    pub chunk_size: u64,
+}
+
+/// Return the chunks generated by producer `i`.
+fn producer_range(
+    i: u64,
+    num_producers: u64,
+    total_size: u64,
+    chunks_per_producer: u64,
+) -> ProducerRange {
+    let layout = plan(total_size, num_producers, chunks_per_producer);
+    let offset = layout.region_offset(i);
+    ProducerRange {
+        chunk_id: chunks_per_producer * i,
+        offset,
+        end_offset: offset + layout.region_size(i),
+        chunk_size: layout.chunk_size(i),
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Generate the chunks in `range`, one for each buffer received from `rx`,
+/// and send them to consumers.
+#[allow(clippy::too_many_arguments)]
+fn produce<T, E: Debug>(
+    i: u64,
+    num_producers: u64,
+    rx: Receiver<Message>,
+    f: &BorrowedChunkProducer<'_, T, E>,
+    data: &T,
+    range: ProducerRange,
+    activity: Option<&Activity>,
+    cpu_report: Option<&CpuReport>,
+    stats_report: Option<Arc<StatsReport>>,
+    cancel: Option<&CancelToken>,
+    interrupted: &AtomicBool,
+    selector: Option<&dyn ConsumerSelector>,
+) -> Result<(), String> {
+    use Message::*;
+    let ProducerRange {
+        mut chunk_id,
+        mut offset,
+        end_offset,
+        chunk_size,
+    } = range;
+    if let Some(r) = cpu_report {
+        r.record(Worker::Producer(i));
+    }
+    let mut counters = stats::Recorder::new(Worker::Producer(i), stats_report);
+    let mut prev_consumer = i as usize;
+    loop {
+        let wait = counters.wait_start();
+        let msg = rx.recv();
+        counters.wait_stop(wait);
+        let (mut cfg, mut buffer) = match msg {
+            Ok(Produce(cfg, buffer)) => (cfg, buffer),
+            _ => break,
+        };
+        if cancel.map_or(false, |c| c.is_cancelled()) {
+            if offset < end_offset {
+                interrupted.store(true, Ordering::SeqCst);
+            }
+            // chunks already sent are still written by consumers
+            (0..cfg.consumers.len()).for_each(|x| {
+                let _ = cfg.consumers[x].send(End(i, num_producers));
+            });
+            break;
+        }
+        if offset >= end_offset {
+            // empty region past the end of the data, nothing to generate
+            (0..cfg.consumers.len()).for_each(|x| {
+                let _ = cfg.consumers[x].send(End(i, num_producers));
+            });
+            break;
+        }
+        let chunk_size = chunk_size.min(end_offset - offset);
+        if let Err(msg) = check_capacity(&buffer, chunk_size as usize) {
+            (0..cfg.consumers.len()).for_each(|c| {
+                let _ = cfg.consumers[c].send(Error(ProducerError {
+                    msg: msg.clone(),
+                    offset,
+                }));
+            });
+            return Err(msg);
+        }
+        // zero the buffer: bytes not generated by the producer must not
+        // leak data from the previous chunk
+        buffer.clear();
+        buffer.resize(chunk_size as usize, 0);
+        let num_consumers = cfg.consumers.len();
+        // chunks go to any consumer, hence the end of stream is signalled to
+        // all of them, see `worker::Ends`
+        let c = select_consumer(
+            selector,
+            i,
+            prev_consumer,
+            num_consumers,
+            num_producers as usize,
+            offset,
+        );
+        prev_consumer = c;
+
+        if let Some(a) = activity {
+            a.begin(i, offset);
+        }
+        // a panic in the callback is reported as a producer error
+        let produced = match panic::catch_unwind(AssertUnwindSafe(|| f(&mut buffer, data, offset)))
+        {
+            Ok(r) => r.map_err(|err| format!("{:?}", err)),
+            Err(payload) => Err(format!(
+                "Producer panicked - {}",
+                worker::panic_message(&*payload)
+            )),
+        };
+        if let Some(a) = activity {
+            a.end(i);
+        }
+        match produced {
+            Err(msg) => {
+                (0..cfg.consumers.len()).for_each(|c| {
+                    let _ = cfg.consumers[c].send(Error(ProducerError {
+                        msg: msg.clone(),
+                        offset,
+                    }));
+                });
+                return Err(msg);
+            }
+            Ok(regions) => {
+                if let Some(regions) = &regions {
+                    let covered: u64 = regions
+                        .iter()
+                        .map(|r| match r {
+                            Region::Write(n) | Region::Keep(n) | Region::Hole(n) => n,
+                        })
+                        .sum();
+                    if covered != buffer.len() as u64 {
+                        let msg = format!(
+                            "Regions cover {} bytes, chunk size is {}",
+                            covered,
+                            buffer.len()
+                        );
+                        (0..cfg.consumers.len()).for_each(|c| {
+                            let _ = cfg.consumers[c].send(Error(ProducerError {
+                                msg: msg.clone(),
+                                offset,
+                            }));
+                        });
+                        return Err(msg);
+                    }
+                }
+                chunk_id += 1;
+                cfg.chunk_id = chunk_id;
+                cfg.offset = offset;
+                cfg.regions = regions;
+                offset += buffer.len() as u64;
+                counters.chunk(buffer.len() as u64);
+                if let Err(err) = cfg.consumers[c].send(Consume(cfg.clone(), buffer)) {
+                    return Err(format!("Cannot send buffer to consumer - {}", err));
+                }
+                if offset >= end_offset {
+                    // signal the end of stream to consumers
+                    (0..cfg.consumers.len()).for_each(|x| {
+                        // consumer might have exited already
+                        let _ = cfg.consumers[x].send(End(i, num_producers));
+                    });
+                    break;
+                }
+            }
+        }
+    }
+    if let Some(r) = cpu_report {
+        r.record(Worker::Producer(i));
+    }
+    Ok(())
+}
+
+// -----------------------------------------------------------------------------
+/// Assigns file offsets to variable-sized chunks in chunk index order.
+#[derive(Default)]
+struct Sequencer {
+    // (index of the next chunk placed, offset of the next chunk, aborted)
+    state: Mutex<(u64, u64, bool)>,
+    placed: Condvar,
+}
+
+impl Sequencer {
+    /// Wait for all the chunks preceding chunk `index` to be placed and return
+    /// the offset of the chunk, `None` if aborted.
+    fn place(&self, index: u64, size: u64) -> Option<u64> {
+        let mut state = match self.state.lock() {
+            Ok(s) => s,
+            Err(err) => err.into_inner(),
+        };
+        while state.0 != index && !state.2 {
+            state = match self.placed.wait(state) {
+                Ok(s) => s,
+                Err(err) => err.into_inner(),
+            };
+        }
+        if state.2 {
+            return None;
+        }
+        let offset = state.1;
+        state.0 += 1;
+        state.1 += size;
+        self.placed.notify_all();
+        Some(offset)
+    }
+    /// Wake up and abort all the producers waiting to place a chunk.
+    fn abort(&self) {
+        match self.state.lock() {
+            Ok(mut s) => s.2 = true,
+            Err(err) => err.into_inner().2 = true,
+        }
+        self.placed.notify_all();
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Generate chunks `i, i + num_producers, ...` with a variable-sized chunk
+/// producer and send them to consumers at the offsets assigned by `sequencer`.
+#[allow(clippy::too_many_arguments)]
+fn produce_variable<T, E: Debug>(
+    i: u64,
+    num_producers: u64,
+    num_chunks: u64,
+    max_chunk_size: usize,
+    rx: Receiver<Message>,
+    f: &VariableProducer<T, E>,
+    data: &T,
+    sequencer: &Sequencer,
+    cpu_report: Option<&CpuReport>,
+    stats_report: Option<Arc<StatsReport>>,
+    cancel: Option<&CancelToken>,
+    interrupted: &AtomicBool,
+    selector: Option<&dyn ConsumerSelector>,
+) -> Result<(), String> {
+    use Message::*;
+    if let Some(r) = cpu_report {
+        r.record(Worker::Producer(i));
+    }
+    let mut counters = stats::Recorder::new(Worker::Producer(i), stats_report);
+    let mut consumers = Senders::new();
+    let mut prev_consumer = i as usize;
+    let mut result = Ok(());
+    for index in (i..num_chunks).step_by(num_producers as usize) {
+        let wait = counters.wait_start();
+        let msg = rx.recv();
+        counters.wait_stop(wait);
+        let (mut cfg, mut buffer) = match msg {
+            Ok(Produce(cfg, buffer)) => (cfg, buffer),
+            _ => {
+                // buffers dropped by consumers which exited after an error,
+                // producers waiting for this producer's chunks must stop
+                sequencer.abort();
+                break;
+            }
+        };
+        if consumers.is_empty() {
+            consumers = cfg.consumers.clone();
+        }
+        if cancel.map_or(false, |c| c.is_cancelled()) {
+            interrupted.store(true, Ordering::SeqCst);
+            // chunks already sent are still written by consumers
+            sequencer.abort();
+            break;
+        }
+        let size = check_capacity(&buffer, max_chunk_size)
+            .and_then(|_| {
+                // zero the buffer: bytes not generated by the producer must
+                // not leak data from the previous chunk
+                buffer.clear();
+                buffer.resize(max_chunk_size, 0);
+                match panic::catch_unwind(AssertUnwindSafe(|| f(&mut buffer, data, index))) {
+                    Ok(r) => r.map_err(|err| format!("{:?}", err)),
+                    Err(payload) => Err(format!(
+                        "Producer panicked - {}",
+                        worker::panic_message(&*payload)
+                    )),
+                }
+            })
+            .and_then(|n| {
+                if n > max_chunk_size {
+                    Err(format!(
+                        "Producer generated {} bytes, maximum chunk size is {}",
+                        n, max_chunk_size
+                    ))
+                } else {
+                    Ok(n)
+                }
+            });
+        let size = match size {
+            Ok(n) => n,
+            Err(msg) => {
+                sequencer.abort();
+                consumers.iter().for_each(|c| {
+                    let _ = c.send(Error(ProducerError {
+                        msg: msg.clone(),
+                        offset: index,
+                    }));
+                });
+                result = Err(msg);
+                break;
+            }
+        };
+        buffer.truncate(size);
+        let offset = match sequencer.place(index, size as u64) {
+            Some(offset) => offset,
+            // another producer failed or was cancelled
+            None => break,
+        };
+        let c = select_consumer(
+            selector,
+            i,
+            prev_consumer,
+            consumers.len(),
+            num_producers as usize,
+            offset,
+        );
+        prev_consumer = c;
+        cfg.chunk_id = index + 1;
+        cfg.offset = offset;
+        cfg.regions = None;
+        counters.chunk(size as u64);
+        if let Err(err) = consumers[c].send(Consume(cfg, buffer)) {
+            sequencer.abort();
+            return Err(format!("Cannot send buffer to consumer - {}", err));
+        }
+    }
+    // signal the end of stream to consumers, which might have exited already
+    consumers.iter().for_each(|c| {
+        let _ = c.send(End(i, num_producers));
+    });
+    if let Some(r) = cpu_report {
+        r.record(Worker::Producer(i));
+    }
+    result
+}
+
+// -----------------------------------------------------------------------------
+/// Build consumers and return tuple of (Sender objects, JoinHandles)
+#[allow(clippy::too_many_arguments)]
+fn build_consumers(
+    num_consumers: u64,
+    file: &File,
+    events: Option<Sender<WriteEvent>>,
+    dedup: Option<Arc<Dedup>>,
+    checkpoint: Option<Arc<Checkpoint>>,
+    chunks_started: Arc<AtomicU64>,
+    progress: Option<Arc<Tracker>>,
+    options: &WriteOptions,
+) -> Result<(Senders, ConsumerHandles), WriteError> {
+    let mut consumers_handles = Vec::new();
+    let mut tx_consumers = Vec::new();
+    let bytes_done = Arc::new(AtomicU64::new(0));
+    let direct = if options.direct_io {
+        Some(open_direct(file)?)
+    } else {
+        None
+    };
+    let written_ranges = if options.detect_overlaps {
+        Some(Arc::new(WrittenRanges::default()))
+    } else {
+        None
+    };
+    let throttle = options
+        .max_bytes_per_sec
+        .map(|r| Arc::new(Throttle::new(r)));
+    let placement = options.placement();
+    for i in 0..num_consumers {
+        let (tx, rx) = channel();
+        tx_consumers.push(tx);
+        use Message::*;
+        let file = file.try_clone().map_err(WriteError::IO)?;
+        let mut events = events.clone();
+        let bytes_done = bytes_done.clone();
+        let cpu_report = options.cpu_report.clone();
+        let dedup = dedup.clone();
+        let latency_report = options.latency_report.clone();
+        let stats_report = options.stats.clone();
+        let verify = options.verify;
+        let verify_source = options.verify_source.clone();
+        let best_effort = options.best_effort;
+        let checkpoint = checkpoint.clone();
+        let chunks_started = chunks_started.clone();
+        let crash_after = options.crash_after;
+        let data_offset = options.data_offset;
+        let offset_map = options.offset_map.clone();
+        let written_ranges = written_ranges.clone();
+        let max_io_size = options.max_io_size.unwrap_or(usize::MAX);
+        let continue_on_error = options.continue_on_error;
+        // a failed vectored write would fail all the chunks coalesced
+        let vectored =
+            options.vectored && !options.direct_io && crash_after.is_none() && !continue_on_error;
+        // holes read as zero in files created or truncated by the write
+        let zero_holes = options.open_mode == OpenMode::CreateOrKeep;
+        let retry = options.retry;
+        let progress = progress.clone();
+        let throttle = throttle.clone();
+        let on_recycle = options.on_recycle.clone();
+        let placement = placement.clone();
+        let direct = match &direct {
+            Some(f) => Some(f.try_clone().map_err(WriteError::IO)?),
+            None => None,
+        };
+        let h = worker::spawn_on(
+            options.pool.as_ref(),
+            thread_name(Worker::Consumer(i)),
+            options.stack_size,
+            move || {
+                cpu::pin(placement.as_ref(), Worker::Consumer(i));
+                let mut latency = Recorder::new(latency_report);
+                let mut counters = stats::Recorder::new(Worker::Consumer(i), stats_report);
+                let mut staging = AlignedBuffer::default();
+                if let Some(r) = &cpu_report {
+                    r.record(Worker::Consumer(i));
+                }
+                let mut ends = worker::Ends::default();
+                let mut bytes = 0;
+                let mut check_buffer = Vec::new();
+                let mut failed = Vec::new();
+                let mut errors = Vec::new();
+                let mut written = Vec::new();
+                // messages received while coalescing chunks, flagged when
+                // the chunk was already written
+                let mut pending: VecDeque<(Message, bool)> = VecDeque::new();
+                let to_file_offset = |offset| {
+                    let offset = match &offset_map {
+                        Some(map) => map(offset),
+                        None => offset,
+                    };
+                    offset + data_offset
+                };
+                // consumers tx endpoints live inside the ReadData instance
+                // sent along messages, when producers finish sending data
+                // all transmission endpoints die resulting in recv()
+                // failing and consumers exiting; this also happens when all
+                // producers exited without sending 'End' because a producer
+                // could not send data to a consumer which returned an error.
+                // A consumer failing sends 'Abort' to the other consumers,
+                // which exit dropping the buffers they hold: producers then
+                // exit when sending the next chunk fails or when no buffer
+                // is left
+                loop {
+                    let (msg, coalesced) = match pending.pop_front() {
+                        Some(m) => m,
+                        None => {
+                            let wait = counters.wait_start();
+                            let msg = rx.recv();
+                            counters.wait_stop(wait);
+                            match msg {
+                                Ok(msg) => (msg, false),
+                                Err(_) => break,
+                            }
+                        }
+                    };
+                    match msg {
+                        Error(err) => {
+                            return Err(WriteError::Producer(err));
+                        }
+                        Consume(cfg, mut buffer) => {
+                            if let Some(k) = crash_after {
+                                if chunks_started.fetch_add(1, Ordering::SeqCst) >= k {
+                                    // simulated crash: chunk not written
+                                    let _ = cfg.producer_tx.send(Produce(cfg.clone(), buffer));
+                                    continue;
+                                }
+                            }
+                            let file_offset = to_file_offset(cfg.offset);
+                            // coalesced chunks are recorded before writing
+                            // the first chunk
+                            if let (Some(w), false) = (&written_ranges, coalesced) {
+                                record_ranges(w, &buffer, cfg.regions.as_deref(), file_offset)
+                                    .map_err(|err| shutdown(&cfg, err))?;
+                            }
+                            if let Some(t) = &throttle {
+                                t.acquire(buffer.len());
+                            }
+                            let start = latency.start();
+                            // write errors are collected instead of stopping
+                            // with continue_on_error
+                            let result = (|| -> Result<u64, WriteError> {
+                                Ok(match &cfg.regions {
+                                    None => match &dedup {
+                                        Some(d) => d.write(&buffer, &file, file_offset)?,
+                                        None => {
+                                            match &direct {
+                                                Some(d)
+                                                    if is_aligned(file_offset, buffer.len()) =>
+                                                {
+                                                    write_retry(
+                                                        staging.copy_from(&buffer),
+                                                        d,
+                                                        file_offset,
+                                                        aligned_io_size(max_io_size),
+                                                        retry.as_ref(),
+                                                    )?
+                                                }
+                                                _ if coalesced => {}
+                                                _ if vectored && pending.is_empty() => {
+                                                    let mut end = file_offset + buffer.len() as u64;
+                                                    while let Ok(next) = rx.try_recv() {
+                                                        let contiguous = match &next {
+                                                            Consume(c, _)
+                                                                if c.regions.is_none() =>
+                                                            {
+                                                                to_file_offset(c.offset) == end
+                                                            }
+                                                            _ => false,
+                                                        };
+                                                        if !contiguous {
+                                                            pending.push_back((next, false));
+                                                            break;
+                                                        }
+                                                        if let Consume(_, b) = &next {
+                                                            if let Some(w) = &written_ranges {
+                                                                record_ranges(w, b, None, end)?;
+                                                            }
+                                                            end += b.len() as u64;
+                                                        }
+                                                        pending.push_back((next, true));
+                                                    }
+                                                    let buffers: Vec<&[u8]> =
+                                                        std::iter::once(buffer.as_slice())
+                                                            .chain(pending.iter().filter_map(
+                                                                |(m, c)| match m {
+                                                                    Consume(_, b) if *c => {
+                                                                        Some(b.as_slice())
+                                                                    }
+                                                                    _ => None,
+                                                                },
+                                                            ))
+                                                            .collect();
+                                                    write_buffers_retry(
+                                                        &buffers,
+                                                        &file,
+                                                        file_offset,
+                                                        max_io_size,
+                                                        retry.as_ref(),
+                                                    )?
+                                                }
+                                                _ => write_retry(
+                                                    &buffer,
+                                                    &file,
+                                                    file_offset,
+                                                    max_io_size,
+                                                    retry.as_ref(),
+                                                )?,
+                                            }
+                                            buffer.len() as u64
+                                        }
+                                    },
+                                    Some(regions) => write_regions(
+                                        &buffer,
+                                        regions,
+                                        &file,
+                                        file_offset,
+                                        max_io_size,
+                                        retry.as_ref(),
+                                        zero_holes,
+                                    )?,
+                                })
+                            })();
+                            latency.stop(start);
+                            let len = match result {
+                                Ok(len) => len,
+                                Err(err) if continue_on_error => {
+                                    errors.push(ChunkError {
+                                        offset: cfg.offset,
+                                        size: buffer.len() as u64,
+                                        msg: format!("{:?}", err),
+                                    });
+                                    let _ = cfg.producer_tx.send(Produce(cfg.clone(), buffer));
+                                    continue;
+                                }
+                                Err(err) => return Err(shutdown(&cfg, err)),
+                            };
+                            if verify && len > 0 {
+                                let source: &dyn ReadAt = match &verify_source {
+                                    Some(s) => s.as_ref(),
+                                    None => &file,
+                                };
+                                let regions = cfg.regions.as_deref();
+                                if !verify_chunk(
+                                    &buffer,
+                                    regions,
+                                    source,
+                                    file_offset,
+                                    &mut check_buffer,
+                                )
+                                .map_err(|err| shutdown(&cfg, err))?
+                                {
+                                    if !best_effort {
+                                        let err = WriteError::VerifyFailed { offset: cfg.offset };
+                                        return Err(shutdown(&cfg, err));
+                                    }
+                                    failed.push(cfg.offset);
+                                }
+                            }
+                            bytes += len as usize;
+                            counters.chunk(len);
+                            if let Some(p) = &progress {
+                                p.add(buffer.len() as u64);
+                            }
+                            if checkpoint.is_some() {
+                                written.push(CheckpointEntry {
+                                    offset: cfg.offset,
+                                    size: buffer.len() as u64,
+                                });
+                            }
+                            if let Some(tx) = &events {
+                                let event = WriteEvent {
+                                    chunk_id: cfg.chunk_id,
+                                    offset: cfg.offset,
+                                    bytes: len,
+                                    cumulative: bytes_done.fetch_add(len, Ordering::SeqCst) + len,
+                                };
+                                if tx.send(event).is_err() {
+                                    // receiver dropped, stop emitting events
+                                    events = None;
+                                }
+                            }
+                            if let Some(f) = &on_recycle {
+                                f(&mut buffer);
+                            }
+                            // the producer might have already exited after
+                            // having queued its last chunk, the buffer is then
+                            // dropped
+                            let _ = cfg.producer_tx.send(Produce(cfg.clone(), buffer));
+                        }
+                        End(prod_id, num_producers) => {
+                            if ends.end(prod_id, num_producers) {
+                                break;
+                            }
+                        }
+                        // chunks written so far are still counted, the error
+                        // is returned by the consumer which failed
+                        Abort => break,
+                        _ => {
+                            panic!("Wrong message type");
+                        }
+                    }
+                }
+                if let Some(c) = &checkpoint {
+                    c.record(&file, &written)?;
+                }
+                if let Some(r) = &cpu_report {
+                    r.record(Worker::Consumer(i));
+                }
+                Ok((bytes, failed, errors))
+            },
+        )
+        .map_err(|err| WriteError::Other(format!("Cannot spawn consumer - {}", err)))?;
+        consumers_handles.push(h);
+    }
+    Ok((tx_consumers, consumers_handles))
+}
+
+// -----------------------------------------------------------------------------
+/// Make all the consumers receiving chunks along with `cfg` stop, return `err`.
+fn shutdown(cfg: &Config, err: WriteError) -> WriteError {
+    // consumers might have exited already
+    cfg.consumers.iter().for_each(|c| {
+        let _ = c.send(Message::Abort);
+    });
+    err
+}
+
+// -----------------------------------------------------------------------------
+/// Record the file ranges of the regions of `buffer` written at `offset`.
+fn record_ranges(
+    written: &WrittenRanges,
+    buffer: &[u8],
+    regions: Option<&[Region]>,
+    offset: u64,
+) -> Result<(), WriteError> {
+    let whole = [Region::Write(buffer.len() as u64)];
+    let mut pos = offset;
+    for r in regions.unwrap_or(&whole) {
+        match *r {
+            Region::Write(n) | Region::Hole(n) => {
+                written.insert(pos, pos + n)?;
+                pos += n;
+            }
+            Region::Keep(n) => pos += n,
+        }
+    }
+    Ok(())
+}
+
+// -----------------------------------------------------------------------------
+/// Open a handle to `file` bypassing the page cache.
+#[cfg(target_os = "linux")]
+fn open_direct(file: &File) -> Result<File, WriteError> {
+    reopen_direct(file).map_err(WriteError::IO)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_direct(_file: &File) -> Result<File, WriteError> {
+    Err(WriteError::Other(
+        "Direct I/O is only supported on Linux".to_string(),
+    ))
+}
+
+/// Return `true` if a chunk of `len` bytes at `offset` can be written with
+/// direct I/O.
+fn is_aligned(offset: u64, len: usize) -> bool {
+    offset % DIRECT_IO_ALIGNMENT == 0 && len as u64 % DIRECT_IO_ALIGNMENT == 0
+}
+
+/// Round the maximum size of direct I/O system calls down to a multiple of
+/// the alignment.
+fn aligned_io_size(max_io_size: usize) -> usize {
+    let align = DIRECT_IO_ALIGNMENT as usize;
+    (max_io_size / align).max(1) * align
+}
+
+/// Staging buffer aligned to `DIRECT_IO_ALIGNMENT`, grown on demand.
+#[derive(Default)]
+struct AlignedBuffer {
+    storage: Vec<u8>,
+}
+
+impl AlignedBuffer {
+    /// Copy `data` into the buffer and return the aligned copy.
+    fn copy_from(&mut self, data: &[u8]) -> &[u8] {
+        let align = DIRECT_IO_ALIGNMENT as usize;
+        if self.storage.len() < data.len() + align {
+            self.storage.resize(data.len() + align, 0);
+        }
+        let start = self.storage.as_ptr().align_offset(align);
+        let dst = &mut self.storage[start..start + data.len()];
+        dst.copy_from_slice(data);
+        dst
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Write `buffer` at `offset`, writing it again on failure according to
+/// `retry`.
+fn write_retry(
+    buffer: &[u8],
+    file: &File,
+    offset: u64,
+    max_io_size: usize,
+    retry: Option<&RetryPolicy>,
+) -> Result<(), WriteError> {
+    match retry {
+        None => write_bytes_at_max(buffer, file, offset, max_io_size),
+        Some(policy) => policy
+            .run(|| write_bytes_at_max(buffer, file, offset, max_io_size))
+            .map_err(|(err, attempts)| WriteError::RetryExhausted {
+                offset,
+                attempts,
+                error: Box::new(err),
+            }),
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Same as `write_retry` writing `buffers` one after the other with vectored
+/// I/O.
+fn write_buffers_retry(
+    buffers: &[&[u8]],
+    file: &File,
+    offset: u64,
+    max_io_size: usize,
+    retry: Option<&RetryPolicy>,
+) -> Result<(), WriteError> {
+    match retry {
+        None => write_buffers_at_max(buffers, file, offset, max_io_size),
+        Some(policy) => policy
+            .run(|| write_buffers_at_max(buffers, file, offset, max_io_size))
+            .map_err(|(err, attempts)| WriteError::RetryExhausted {
+                offset,
+                attempts,
+                error: Box::new(err),
+            }),
+    }
+}
+
+// -----------------------------------------------------------------------------
+/// Write the `Region::Write` regions of `buffer` and return the number of
+/// bytes written; `Region::Hole` regions are zeroed if `zero_holes` is
+/// `true`, i.e. when the file was not created or truncated by the write.
+fn write_regions(
+    buffer: &[u8],
+    regions: &[Region],
+    file: &File,
+    offset: u64,
+    max_io_size: usize,
+    retry: Option<&RetryPolicy>,
+    zero_holes: bool,
+) -> Result<u64, WriteError> {
+    let mut pos = 0;
+    let mut written = 0;
+    for r in regions {
+        match *r {
+            Region::Write(n) => {
+                let end = pos + n as usize;
+                write_retry(
+                    &buffer[pos..end],
+                    file,
+                    offset + pos as u64,
+                    max_io_size,
+                    retry,
+                )?;
+                written += n;
+                pos = end;
+            }
+            Region::Keep(n) => {
+                pos += n as usize;
+            }
+            Region::Hole(n) => {
+                if zero_holes {
+                    zero_range(file, offset + pos as u64, n, max_io_size, retry)?;
+                }
+                pos += n as usize;
+            }
+        }
+    }
+    Ok(written)
+}
+
+// -----------------------------------------------------------------------------
+/// Make `len` bytes at `offset` read as zero, deallocating them if supported.
+fn zero_range(
+    file: &File,
+    offset: u64,
+    len: u64,
+    max_io_size: usize,
+    retry: Option<&RetryPolicy>,
+) -> Result<(), WriteError> {
+    if len == 0 || punch_hole(file, offset, len).is_ok() {
+        return Ok(());
+    }
+    const ZEROS_SIZE: u64 = 1 << 16;
+    let zeros = vec![0_u8; len.min(ZEROS_SIZE) as usize];
+    let mut pos = 0;
+    while pos < len {
+        let n = (len - pos).min(ZEROS_SIZE) as usize;
+        write_retry(&zeros[..n], file, offset + pos, max_io_size, retry)?;
+        pos += n as u64;
+    }
+    Ok(())
+}
+
+// -----------------------------------------------------------------------------
+/// Read back the regions of `buffer` written at `offset` and return `false` if
+/// the data read does not match.
+fn verify_chunk(
+    buffer: &[u8],
+    regions: Option<&[Region]>,
+    source: &dyn ReadAt,
+    offset: u64,
+    check_buffer: &mut Vec<u8>,
+) -> Result<bool, WriteError> {
+    let whole = [Region::Write(buffer.len() as u64)];
+    let mut pos = 0;
+    for r in regions.unwrap_or(&whole) {
+        match *r {
+            Region::Write(n) => {
+                let end = pos + n as usize;
+                check_buffer.resize(n as usize, 0);
+                source
+                    .read_at(check_buffer, offset + pos as u64)
+                    .map_err(|err| WriteError::Other(format!("{:?}", err)))?;
+                if check_buffer[..] != buffer[pos..end] {
+                    return Ok(false);
+                }
+                pos = end;
+            }
+            Region::Keep(n) => {
+                pos += n as usize;
+            }
+            Region::Hole(n) => {
+                check_buffer.resize(n as usize, 0);
+                source
+                    .read_at(check_buffer, offset + pos as u64)
+                    .map_err(|err| WriteError::Other(format!("{:?}", err)))?;
+                if check_buffer.iter().any(|b| *b != 0) {
+                    return Ok(false);
+                }
+                pos += n as usize;
+            }
+        }
+    }
+    Ok(true)
+}
+
+// -----------------------------------------------------------------------------
+/// Launch computation by sending messages to transmission endpoints of producer
+/// channels.
+/// In order to keep memory usage constant, buffers are sent to consumers and
+/// returned to the producer who sent them.
+/// One producer can send messages to multiple consumers.
+/// To allow for asynchronous data consumption, a consumers needs to be able
+/// to consume the data in a buffer while the producer is writing data to a different
 /// buffer and therefore more than one buffer per producer is required for
 /// the operation to perform asynchronously.
+#[allow(clippy::too_many_arguments)]
 fn launch(
     tx_producers: Senders,
     tx_consumers: Senders,
-    producer_chunk_size: u64,
-    task_chunk_size: u64,
-    last_producer_task_chunk_size: u64,
-    chunks_per_producer: u64,
+    layout: &Layout,
     reserved_size: usize,
     num_buffers_per_producer: u64,
-) -> Result<(), WriteError> {
-    let num_buffers_per_producer = num_buffers_per_producer;
-    let num_producers = tx_producers.len() as u64;
-    for i in 0..num_producers {
+    alloc: Option<&Arc<dyn BufferAlloc>>,
+) {
+    for i in 0..layout.num_producers {
         let tx = tx_producers[i as usize].clone();
-        let offset = (i as u64) * producer_chunk_size;
+        let offset = i * layout.producer_chunk_size;
         //number of messages/buffers to be sent to each producer's queue before
         //the computation starts
-        let num_buffers = chunks_per_producer.min(num_buffers_per_producer);
+        let num_buffers = layout.num_buffers(num_buffers_per_producer) / layout.num_producers;
         for _ in 0..num_buffers {
-            let mut buffer: Vec<u8> = Vec::new();
-            let chunk_size = if i != num_producers - 1 {
-                task_chunk_size
-            } else {
-                last_producer_task_chunk_size
-            };
-            buffer.reserve(2 * reserved_size);
-            unsafe {
-                buffer.set_len(chunk_size as usize);
-            }
+            // sized by the producer, which checks the capacity
+            let buffer = allocate(alloc, 2 * reserved_size);
             let cfg = ProducerConfig {
-                offset: offset,
+                chunk_id: 0, //overwritten
+                offset,
+                regions: None, // overwritten
                 producer_tx: tx.clone(),
                 consumers: tx_consumers.clone(),
             };
-            tx.send(Message::Produce(cfg, buffer))
-                .map_err(|err| WriteError::Other(err.to_string()))?
+            // the producer might have already generated all its chunks using
+            // the buffers sent back by consumers and exited
+            let _ = tx.send(Message::Produce(cfg, buffer));
         }
     }
-    Ok(())
 }
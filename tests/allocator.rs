@@ -0,0 +1,150 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::buffer::BufferAlloc;
+use par_io::read::{read_file_with_options, ReadError, ReadOptions};
+use par_io::write::{write_to_file_with_options, WriteError, WriteOptions};
+use std::sync::{Arc, Mutex};
+
+/// Allocator recording the requested capacities.
+#[derive(Default)]
+struct Recording {
+    requests: Mutex<Vec<usize>>,
+}
+
+impl BufferAlloc for Recording {
+    fn alloc(&self, capacity: usize) -> Vec<u8> {
+        self.requests.lock().unwrap().push(capacity);
+        // non empty buffers are cleared before use
+        let mut buffer = Vec::with_capacity(capacity + 1);
+        buffer.push(0xff);
+        buffer
+    }
+}
+
+#[test]
+fn read_buffers_from_allocator() {
+    let filename = "tmp-allocator_read";
+    let data: Vec<u8> = (0..9000).map(|i| (i % 251) as u8).collect();
+    let _delete_file_at_exit = create_file(filename, &data);
+    let alloc = Arc::new(Recording::default());
+    let consumer = |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, offset: u64| {
+        (offset, buffer.to_vec())
+    };
+    let mut chunks = read_file_with_options(
+        filename,
+        3,
+        2,
+        3,
+        Arc::new(consumer),
+        (),
+        2,
+        ReadOptions {
+            allocator: Some(alloc.clone()),
+            ..Default::default()
+        },
+    )
+    .expect("Read failed");
+    // two buffers per producer, sized for the largest chunk
+    assert_eq!(*alloc.requests.lock().unwrap(), vec![1000; 6]);
+    chunks.sort_by_key(|(_, (offset, _))| *offset);
+    let read: Vec<u8> = chunks.into_iter().flat_map(|(_, (_, b))| b).collect();
+    assert_eq!(read, data);
+}
+
+#[test]
+fn write_buffers_from_allocator() {
+    let filename = "tmp-allocator_write";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let alloc = Arc::new(Recording::default());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<(), String> {
+        for (i, b) in buffer.iter_mut().enumerate() {
+            *b = ((offset + i as u64) % 251) as u8;
+        }
+        Ok(())
+    };
+    write_to_file_with_options(
+        filename,
+        3,
+        2,
+        3,
+        Arc::new(producer),
+        (),
+        2,
+        9000,
+        WriteOptions {
+            allocator: Some(alloc.clone()),
+            ..Default::default()
+        },
+    )
+    .expect("Write failed");
+    assert_eq!(alloc.requests.lock().unwrap().len(), 6);
+    let data = std::fs::read(filename).expect("Cannot read file");
+    assert!(data.iter().enumerate().all(|(i, b)| *b == (i % 251) as u8));
+    assert_eq!(data.len(), 9000);
+}
+
+/// Allocator returning less capacity than requested.
+struct Short;
+
+impl BufferAlloc for Short {
+    fn alloc(&self, capacity: usize) -> Vec<u8> {
+        Vec::with_capacity(capacity / 4)
+    }
+}
+
+/// Buffers too small for the chunks are reported as producer errors instead
+/// of being silently reallocated.
+#[test]
+fn write_buffers_too_small() {
+    let filename = "tmp-allocator_short";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<(), String> {
+        buffer.fill(1);
+        Ok(())
+    };
+    match write_to_file_with_options(
+        filename,
+        2,
+        2,
+        2,
+        Arc::new(producer),
+        (),
+        2,
+        8000,
+        WriteOptions {
+            allocator: Some(Arc::new(Short)),
+            ..Default::default()
+        },
+    ) {
+        Err(WriteError::Producer(err)) => {
+            assert!(err.msg.contains("capacity"), "{}", err.msg)
+        }
+        r => panic!("Unexpected result: {:?}", r),
+    }
+}
+
+/// Same as `write_buffers_too_small` when reading: the producer returns an
+/// error instead of panicking.
+#[test]
+fn read_buffers_too_small() {
+    let filename = "tmp-allocator_read_short";
+    let _delete_file_at_exit = create_file(filename, &[1; 8000]);
+    let consumer =
+        |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| buffer.len();
+    match read_file_with_options(
+        filename,
+        2,
+        2,
+        2,
+        Arc::new(consumer),
+        (),
+        2,
+        ReadOptions {
+            allocator: Some(Arc::new(Short)),
+            ..Default::default()
+        },
+    ) {
+        Err(ReadError::Other(msg)) => assert!(msg.contains("capacity"), "{}", msg),
+        r => panic!("Unexpected result: {:?}", r),
+    }
+}
@@ -0,0 +1,53 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::write::{write_to_file_with_options, OpenMode, WriteOptions};
+use std::sync::Arc;
+
+fn append(filename: &str, value: u8, size: usize) -> Result<usize, String> {
+    let producer = move |buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<(), String> {
+        buffer.fill(value);
+        Ok(())
+    };
+    write_to_file_with_options(
+        filename,
+        3,
+        2,
+        2,
+        Arc::new(producer),
+        (),
+        2,
+        size,
+        WriteOptions {
+            open_mode: OpenMode::Append,
+            ..Default::default()
+        },
+    )
+    .map_err(|err| format!("{:?}", err))
+}
+
+/// Each write is appended after the content of the previous one.
+#[test]
+fn append_after_existing_content() -> Result<(), String> {
+    let filename = "tmp-append_test";
+    let _delete_file_at_exit = create_file(filename, &[0xAA; 1000]);
+    assert_eq!(append(filename, 1, 6001)?, 6001);
+    assert_eq!(append(filename, 2, 500)?, 500);
+    let data = std::fs::read(filename).map_err(|err| err.to_string())?;
+    assert_eq!(data.len(), 7501);
+    assert!(data[..1000].iter().all(|b| *b == 0xAA));
+    assert!(data[1000..7001].iter().all(|b| *b == 1));
+    assert!(data[7001..].iter().all(|b| *b == 2));
+    Ok(())
+}
+
+/// The file is created if it does not exist.
+#[test]
+fn append_creates_file() -> Result<(), String> {
+    let filename = "tmp-append_create_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let _ = std::fs::remove_file(filename);
+    assert_eq!(append(filename, 3, 1200)?, 1200);
+    let data = std::fs::read(filename).map_err(|err| err.to_string())?;
+    assert_eq!(data, vec![3; 1200]);
+    Ok(())
+}
@@ -0,0 +1,104 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::read::{read_file_with_options, ReadAt, ReadError, ReadOptions};
+use par_io::write::{write_to_file_with_options, WriteOptions};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const NUM_PRODUCERS: u64 = 3;
+const NUM_BUFFERS_PER_PRODUCER: u64 = 2;
+
+/// Number of chunks produced and not yet consumed, and its maximum.
+#[derive(Default)]
+struct InFlight {
+    current: AtomicU64,
+    max: AtomicU64,
+}
+
+impl InFlight {
+    fn produced(&self) {
+        let n = self.current.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max.fetch_max(n, Ordering::SeqCst);
+    }
+    fn consumed(&self) {
+        self.current.fetch_sub(1, Ordering::SeqCst);
+    }
+    fn max(&self) -> u64 {
+        self.max.load(Ordering::SeqCst)
+    }
+}
+
+/// In-memory data counting the chunks read.
+struct Counted(Arc<InFlight>);
+
+impl ReadAt for Counted {
+    fn read_at(&self, buffer: &mut Vec<u8>, _offset: u64) -> Result<(), ReadError> {
+        buffer.fill(3);
+        self.0.produced();
+        Ok(())
+    }
+}
+
+/// Producers wait for slow consumers instead of queuing chunks.
+#[test]
+fn slow_read_consumers() {
+    let filename = "tmp-backpressure_read";
+    let _delete_file_at_exit = create_file(filename, &[0; 30_000]);
+    let in_flight = Arc::new(InFlight::default());
+    let consume = |buffer: &[u8], in_flight: &Arc<InFlight>, _: u64, _: u64, _: u64| {
+        std::thread::sleep(Duration::from_millis(2));
+        in_flight.consumed();
+        buffer.len()
+    };
+    let chunks = read_file_with_options(
+        filename,
+        NUM_PRODUCERS,
+        2,
+        10,
+        Arc::new(consume),
+        in_flight.clone(),
+        NUM_BUFFERS_PER_PRODUCER,
+        ReadOptions {
+            source: Some(Arc::new(Counted(in_flight.clone()))),
+            ..Default::default()
+        },
+    )
+    .expect("Read failed");
+    assert_eq!(chunks.len(), 30);
+    assert!(in_flight.max() <= NUM_PRODUCERS * NUM_BUFFERS_PER_PRODUCER);
+}
+
+#[test]
+fn slow_write_consumers() {
+    let filename = "tmp-backpressure_write";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let in_flight = Arc::new(InFlight::default());
+    let produced = in_flight.clone();
+    let producer = move |buffer: &mut Vec<u8>, _: &(), _: u64| -> Result<(), String> {
+        buffer.fill(5);
+        produced.produced();
+        Ok(())
+    };
+    let consumed = in_flight.clone();
+    let size = write_to_file_with_options(
+        filename,
+        NUM_PRODUCERS,
+        2,
+        10,
+        Arc::new(producer),
+        (),
+        NUM_BUFFERS_PER_PRODUCER,
+        30_000,
+        WriteOptions {
+            on_recycle: Some(Arc::new(move |_: &mut Vec<u8>| {
+                std::thread::sleep(Duration::from_millis(2));
+                consumed.consumed();
+            })),
+            ..Default::default()
+        },
+    )
+    .expect("Write failed");
+    assert_eq!(size, 30_000);
+    assert!(in_flight.max() <= NUM_PRODUCERS * NUM_BUFFERS_PER_PRODUCER);
+}
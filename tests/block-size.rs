@@ -0,0 +1,47 @@
+mod common;
+use common::create_file;
+use par_io::read::{block_size, read_file_with_options, ReadOptions};
+use std::sync::Arc;
+
+/// Read with block size alignment and verify that all chunks start at a
+/// multiple of the detected block size and that only chunks ending at the
+/// end of the file have a size which is not a multiple of it.
+#[test]
+fn chunks_rounded_to_block_size() -> Result<(), String> {
+    let filename = "tmp-block_size_test";
+    let _delete_file_at_exit = create_file(filename, &[]);
+    let bs = match block_size(filename).map_err(|err| format!("{:?}", err))? {
+        Some(bs) => bs,
+        // not available on this platform
+        None => return Ok(()),
+    };
+    let len = (10 * bs + 123) as usize;
+    let data: Vec<u8> = (0..len).map(|i| (i % 249) as u8).collect();
+    let _delete_file_at_exit = create_file(filename, &data);
+    let consume = |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, offset: u64| {
+        (offset, buffer.to_vec())
+    };
+    let chunks = read_file_with_options(
+        filename,
+        3,
+        2,
+        4,
+        Arc::new(consume),
+        (),
+        2,
+        ReadOptions {
+            align_to_block_size: true,
+            ..Default::default()
+        },
+    )
+    .map_err(|err| format!("{:?}", err))?;
+    let mut read = vec![0_u8; len];
+    for (_, (offset, bytes)) in &chunks {
+        assert_eq!(offset % bs, 0);
+        let end = *offset as usize + bytes.len();
+        assert!(bytes.len() as u64 % bs == 0 || end == len);
+        read[*offset as usize..end].copy_from_slice(bytes);
+    }
+    assert_eq!(read, data);
+    Ok(())
+}
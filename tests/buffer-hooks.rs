@@ -0,0 +1,101 @@
+mod common;
+use common::create_file;
+use par_io::read::{read_file_with_options, BufferEvent, ReadOptions};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+type Events = Arc<Mutex<Vec<(BufferEvent, u64, u64)>>>;
+
+/// Read file recording all buffer events.
+fn read_events(filename: &str, num_producers: u64, shared_pool: Option<usize>) -> Events {
+    let events: Events = Arc::new(Mutex::new(Vec::new()));
+    let record = events.clone();
+    let consume =
+        |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| buffer.len();
+    read_file_with_options(
+        filename,
+        num_producers,
+        3,
+        4,
+        Arc::new(consume),
+        (),
+        2,
+        ReadOptions {
+            shared_pool,
+            on_buffer: Some(Arc::new(move |event, buffer_id, chunk_id| {
+                record.lock().unwrap().push((event, buffer_id, chunk_id))
+            })),
+            ..Default::default()
+        },
+    )
+    .expect("Error reading file");
+    events
+}
+
+/// Count events per type and return the distinct buffer ids.
+fn summary(events: &Events) -> (HashMap<BufferEvent, usize>, HashSet<u64>) {
+    let events = events.lock().unwrap();
+    let mut counts = HashMap::new();
+    for (event, _, _) in events.iter() {
+        *counts.entry(*event).or_insert(0) += 1;
+    }
+    let ids = events.iter().map(|(_, id, _)| *id).collect();
+    (counts, ids)
+}
+
+#[test]
+fn buffers_cycle() {
+    let filename = "tmp-buffer_hooks_test";
+    let _delete_file_at_exit = create_file(filename, &[1_u8; 4096]);
+    let num_producers = 4;
+    let (counts, ids) = summary(&read_events(filename, num_producers, None));
+    // 2 buffers per producer, 4 chunks per producer: a producer may get its
+    // buffers back before using all of them
+    let buffers = (0..2 * num_producers).collect::<HashSet<_>>();
+    assert!(ids.is_subset(&buffers));
+    assert!(ids.len() <= buffers.len());
+    let num_chunks = 4 * num_producers as usize;
+    for event in [
+        BufferEvent::Dispatched,
+        BufferEvent::Consumed,
+        BufferEvent::Recycled,
+    ] {
+        assert_eq!(counts[&event], num_chunks);
+    }
+}
+
+#[test]
+fn shared_pool_buffers_cycle() {
+    let filename = "tmp-buffer_hooks_pool_test";
+    let _delete_file_at_exit = create_file(filename, &[1_u8; 4096]);
+    let (counts, ids) = summary(&read_events(filename, 8, Some(3)));
+    let buffers = (0..3).collect::<HashSet<_>>();
+    assert!(ids.is_subset(&buffers));
+    assert!(ids.len() <= buffers.len());
+    assert_eq!(counts[&BufferEvent::Dispatched], 32);
+    assert_eq!(counts[&BufferEvent::Recycled], 32);
+}
+
+#[test]
+fn events_in_order() {
+    let filename = "tmp-buffer_hooks_order_test";
+    let _delete_file_at_exit = create_file(filename, &[1_u8; 4096]);
+    let events = read_events(filename, 2, None);
+    let events = events.lock().unwrap();
+    // each chunk is dispatched, consumed then recycled
+    for chunk_id in 1..=8 {
+        let chunk: Vec<BufferEvent> = events
+            .iter()
+            .filter(|(_, _, c)| *c == chunk_id)
+            .map(|(e, _, _)| *e)
+            .collect();
+        assert_eq!(
+            chunk,
+            [
+                BufferEvent::Dispatched,
+                BufferEvent::Consumed,
+                BufferEvent::Recycled
+            ]
+        );
+    }
+}
@@ -0,0 +1,57 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::read::{read_file, read_file_scoped, ReadError};
+use par_io::write::{write_to_file, ProducerError, WriteError};
+use std::sync::Arc;
+
+const PANIC_OFFSET: u64 = 3000;
+
+fn consume(buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, offset: u64) -> usize {
+    if offset == PANIC_OFFSET {
+        panic!("Cannot consume chunk at {}", offset);
+    }
+    buffer.len()
+}
+
+#[test]
+fn consumer_panic() {
+    let filename = "tmp-callback_panic_read";
+    let _delete_file_at_exit = create_file(filename, &[1_u8; 10_000]);
+    match read_file(filename, 2, 3, 5, Arc::new(consume), (), 2) {
+        Err(ReadError::Panic { msg, offset }) => {
+            assert_eq!(msg, "Cannot consume chunk at 3000");
+            assert_eq!(offset, PANIC_OFFSET);
+        }
+        r => panic!("Expected panic error, got {:?}", r),
+    }
+}
+
+#[test]
+fn scoped_consumer_panic() {
+    let filename = "tmp-callback_panic_read_scoped";
+    let _delete_file_at_exit = create_file(filename, &[1_u8; 10_000]);
+    match read_file_scoped(filename, 2, 3, 5, &consume, (), 2) {
+        Err(ReadError::Panic { offset, .. }) => assert_eq!(offset, PANIC_OFFSET),
+        r => panic!("Expected panic error, got {:?}", r),
+    }
+}
+
+#[test]
+fn producer_panic() {
+    let filename = "tmp-callback_panic_write";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<(), String> {
+        if offset == PANIC_OFFSET {
+            panic!("Cannot produce chunk");
+        }
+        buffer.fill(1);
+        Ok(())
+    };
+    match write_to_file(filename, 2, 3, 5, Arc::new(producer), (), 2, 10_000) {
+        Err(WriteError::Producer(ProducerError { msg, offset })) => {
+            assert_eq!(msg, "Producer panicked - Cannot produce chunk");
+            assert_eq!(offset, PANIC_OFFSET);
+        }
+        r => panic!("Expected producer error, got {:?}", r),
+    }
+}
@@ -0,0 +1,171 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::cancel::CancelToken;
+use par_io::read::{read_file_with_options, ReadError, ReadOptions};
+use par_io::write::{write_to_file_with_options, WriteError, WriteOptions};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Cancel from the producer callback while generating the third chunk and
+/// verify that the chunks produced before cancellation are written to file
+/// and reported in the returned error.
+#[test]
+fn cancelled_write_reports_partial_progress() {
+    let filename = "tmp-cancel_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let cancel = Arc::new(CancelToken::new());
+    let producer =
+        |buffer: &mut Vec<u8>, cancel: &Arc<CancelToken>, offset: u64| -> Result<(), String> {
+            buffer.fill(1);
+            if offset == 200 {
+                cancel.cancel();
+            }
+            Ok(())
+        };
+    // single producer with a single buffer: chunks are generated in order
+    // and no chunk is generated before the previous one has been written
+    match write_to_file_with_options(
+        filename,
+        1,
+        2,
+        10,
+        Arc::new(producer),
+        cancel.clone(),
+        1,
+        1000,
+        WriteOptions {
+            cancel: Some(cancel.clone()),
+            sync_on_cancel: true,
+            ..Default::default()
+        },
+    ) {
+        Err(WriteError::Cancelled { written }) => {
+            assert_eq!(written, 300);
+            let data = std::fs::read(filename).expect("Cannot read file");
+            assert!(data[..300].iter().all(|b| *b == 1));
+            assert!(data[300..].iter().all(|b| *b == 0));
+        }
+        r => panic!("Expected cancellation, got {:?}", r),
+    }
+}
+
+/// Cancel while generating the last chunk: all the data is written and the
+/// write succeeds.
+#[test]
+fn cancelled_after_last_chunk() {
+    let filename = "tmp-cancel_last_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let cancel = Arc::new(CancelToken::new());
+    let producer =
+        |buffer: &mut Vec<u8>, cancel: &Arc<CancelToken>, offset: u64| -> Result<(), String> {
+            buffer.fill(1);
+            if offset == 900 {
+                cancel.cancel();
+            }
+            Ok(())
+        };
+    let written = write_to_file_with_options(
+        filename,
+        1,
+        2,
+        10,
+        Arc::new(producer),
+        cancel.clone(),
+        1,
+        1000,
+        WriteOptions {
+            cancel: Some(cancel.clone()),
+            ..Default::default()
+        },
+    )
+    .expect("Write failed");
+    assert_eq!(written, 1000);
+    assert!(cancel.is_cancelled());
+}
+
+/// Cancel from the consumer callback while consuming the third chunk: the
+/// remaining chunks are not passed to the callback and all threads exit.
+#[test]
+fn cancelled_read() {
+    let filename = "tmp-cancel_read_test";
+    let _delete_file_at_exit = create_file(filename, &[1_u8; 1000]);
+    let cancel = Arc::new(CancelToken::new());
+    let consumed = Arc::new(AtomicUsize::new(0));
+    let consume = {
+        let consumed = consumed.clone();
+        move |_buffer: &[u8],
+              cancel: &Arc<CancelToken>,
+              _chunk_id: u64,
+              _num_chunks: u64,
+              offset: u64| {
+            consumed.fetch_add(1, Ordering::SeqCst);
+            if offset == 200 {
+                cancel.cancel();
+            }
+        }
+    };
+    // single producer with a single buffer: chunks are read in order and
+    // no chunk is read before the previous one has been consumed
+    let r = read_file_with_options(
+        filename,
+        1,
+        2,
+        10,
+        Arc::new(consume),
+        cancel.clone(),
+        1,
+        ReadOptions {
+            cancel: Some(cancel.clone()),
+            ..Default::default()
+        },
+    );
+    assert!(matches!(r, Err(ReadError::Cancelled)));
+    assert_eq!(consumed.load(Ordering::SeqCst), 3);
+}
+
+/// A write cancelled by `SIGINT` returns `Cancelled`; tokens created after
+/// the signal are not cancelled by it.
+#[cfg(feature = "sigint")]
+#[test]
+fn cancelled_by_sigint() {
+    use std::os::raw::c_int;
+    extern "C" {
+        fn raise(sig: c_int) -> c_int;
+    }
+    const SIGINT: c_int = 2;
+    let filename = "tmp-cancel_sigint_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let write = |cancel: Arc<CancelToken>, raise_at: Option<u64>| {
+        let producer = move |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<(), String> {
+            buffer.fill(1);
+            if Some(offset) == raise_at {
+                assert_eq!(unsafe { raise(SIGINT) }, 0);
+            }
+            Ok(())
+        };
+        write_to_file_with_options(
+            filename,
+            1,
+            2,
+            10,
+            Arc::new(producer),
+            (),
+            1,
+            1000,
+            WriteOptions {
+                cancel: Some(cancel),
+                ..Default::default()
+            },
+        )
+    };
+    let cancel = Arc::new(CancelToken::on_sigint());
+    assert!(!cancel.is_cancelled());
+    match write(cancel.clone(), Some(200)) {
+        Err(WriteError::Cancelled { written }) => assert_eq!(written, 300),
+        r => panic!("Expected cancellation, got {:?}", r),
+    }
+    assert!(cancel.is_cancelled());
+    let fresh = Arc::new(CancelToken::on_sigint());
+    assert!(!fresh.is_cancelled());
+    assert_eq!(write(fresh, None).expect("Write failed"), 1000);
+}
@@ -0,0 +1,79 @@
+mod common;
+use common::DeleteFile;
+use par_io::checkpoint::{read_checkpoint, resume_write};
+use par_io::write::{write_to_file_with_options, WriteError, WriteOptions};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+fn byte_at(offset: usize) -> u8 {
+    (offset % 251) as u8 + 1
+}
+
+/// Crash after 5 chunks, verify that the checkpoint lists exactly the chunks
+/// written, then resume and verify the final file.
+#[test]
+fn crash_and_resume() -> Result<(), String> {
+    let filename = "tmp-checkpoint_test";
+    let checkpoint = "tmp-checkpoint_test.ckpt";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let _delete_checkpoint_at_exit = DeleteFile(checkpoint.to_string());
+    let calls = Arc::new(AtomicUsize::new(0));
+    let producer =
+        |buffer: &mut Vec<u8>, calls: &Arc<AtomicUsize>, offset: u64| -> Result<(), String> {
+            calls.fetch_add(1, Ordering::SeqCst);
+            for (i, b) in buffer.iter_mut().enumerate() {
+                *b = byte_at(offset as usize + i);
+            }
+            Ok(())
+        };
+    // 3 producers, 4 chunks each, 100 bytes per chunk
+    let total_size = 1200;
+    match write_to_file_with_options(
+        filename,
+        3,
+        2,
+        4,
+        Arc::new(producer),
+        calls.clone(),
+        2,
+        total_size,
+        WriteOptions {
+            checkpoint: Some(checkpoint.to_string()),
+            crash_after: Some(5),
+            ..Default::default()
+        },
+    ) {
+        Err(WriteError::Other(msg)) => assert!(msg.contains("crash")),
+        r => panic!("Expected simulated crash, got {:?}", r),
+    }
+    let entries = read_checkpoint(checkpoint).map_err(|err| format!("{:?}", err))?;
+    assert_eq!(entries.len(), 5);
+    let data = std::fs::read(filename).map_err(|err| err.to_string())?;
+    for e in &entries {
+        let (b, end) = (e.offset as usize, (e.offset + e.size) as usize);
+        assert!((b..end).all(|o| data[o] == byte_at(o)));
+    }
+    let written: u64 = entries.iter().map(|e| e.size).sum();
+    assert_eq!(data.iter().filter(|b| **b != 0).count() as u64, written);
+
+    calls.store(0, Ordering::SeqCst);
+    let bytes = resume_write(
+        filename,
+        checkpoint,
+        3,
+        2,
+        4,
+        Arc::new(producer),
+        calls.clone(),
+        2,
+        total_size,
+        WriteOptions::default(),
+    )
+    .map_err(|err| format!("{:?}", err))?;
+    assert_eq!(bytes, total_size - written as usize);
+    assert_eq!(calls.load(Ordering::SeqCst), 12 - 5);
+    let data = std::fs::read(filename).map_err(|err| err.to_string())?;
+    let expected: Vec<u8> = (0..total_size).map(byte_at).collect();
+    assert_eq!(data, expected);
+    Ok(())
+}
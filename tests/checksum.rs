@@ -0,0 +1,112 @@
+mod common;
+use common::DeleteFile;
+use par_io::checksum::{
+    checksummed_size, read_file_checksummed, write_to_file_checksummed, CRC_SIZE,
+};
+use par_io::hash::crc32c;
+use par_io::read::ReadError;
+use std::sync::Arc;
+
+type Chunks = Vec<(u64, (u64, Vec<u8>))>;
+
+fn expected(size: u64) -> Vec<u8> {
+    (0..size).map(|i| (i % 251) as u8).collect()
+}
+
+fn write_data(filename: &str, num_producers: u64, size: usize, block_size: u64) -> usize {
+    let producer = |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<(), String> {
+        for (i, b) in buffer.iter_mut().enumerate() {
+            *b = ((offset + i as u64) % 251) as u8;
+        }
+        Ok(())
+    };
+    write_to_file_checksummed(
+        filename,
+        num_producers,
+        2,
+        2,
+        Arc::new(producer),
+        (),
+        2,
+        size,
+        block_size,
+    )
+    .expect("Write failed")
+}
+
+/// Return data read from checksummed file, sorted by offset.
+fn read_data(filename: &str, num_producers: u64, block_size: u64) -> Result<Vec<u8>, ReadError> {
+    let consumer = |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, offset: u64| {
+        (offset, buffer.to_vec())
+    };
+    let mut chunks: Chunks = read_file_checksummed(
+        filename,
+        num_producers,
+        3,
+        2,
+        Arc::new(consumer),
+        (),
+        2,
+        block_size,
+    )?;
+    chunks.sort_by_key(|(_, (offset, _))| *offset);
+    for w in chunks.windows(2) {
+        let (offset, data) = &w[0].1;
+        assert_eq!(offset + data.len() as u64, w[1].1 .0);
+    }
+    Ok(chunks.into_iter().flat_map(|(_, (_, d))| d).collect())
+}
+
+#[test]
+fn round_trip() {
+    let filename = "tmp-checksum_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    // last block shorter than the others
+    let written = write_data(filename, 3, 1000, 64);
+    assert_eq!(written as u64, checksummed_size(1000, 64));
+    let content = std::fs::read(filename).expect("Cannot read file");
+    assert_eq!(content.len() as u64, 1000 + 16 * CRC_SIZE);
+    // layout: block followed by its checksum
+    assert_eq!(content[..64], expected(64)[..]);
+    assert_eq!(content[64..68], crc32c(&expected(64)).to_le_bytes());
+    // the layout does not depend on the number of producers
+    for num_producers in [1, 2, 5] {
+        assert_eq!(
+            read_data(filename, num_producers, 64).expect("Read failed"),
+            expected(1000)
+        );
+    }
+}
+
+#[test]
+fn same_layout_for_any_producers() {
+    let filename = "tmp-checksum_layout_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    write_data(filename, 1, 5000, 100);
+    let single = std::fs::read(filename).expect("Cannot read file");
+    write_data(filename, 4, 5000, 100);
+    assert_eq!(std::fs::read(filename).expect("Cannot read file"), single);
+}
+
+#[test]
+fn corrupted_block() {
+    let filename = "tmp-checksum_corrupted_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    write_data(filename, 3, 1000, 100);
+    let mut content = std::fs::read(filename).expect("Cannot read file");
+    // flip one bit of the fourth block
+    content[3 * 104 + 10] ^= 0x10;
+    std::fs::write(filename, &content).expect("Cannot write file");
+    match read_data(filename, 2, 100) {
+        Err(ReadError::ChecksumMismatch { offset }) => assert_eq!(offset, 300),
+        r => panic!("Expected checksum mismatch, got {:?}", r.map(|d| d.len())),
+    }
+}
+
+#[test]
+fn empty_data() {
+    let filename = "tmp-checksum_empty_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    assert_eq!(write_data(filename, 2, 0, 100), 0);
+    assert!(read_data(filename, 2, 100).expect("Read failed").is_empty());
+}
@@ -0,0 +1,105 @@
+mod common;
+use common::create_file;
+use par_io::read::{BufferEvent, ChunkReader, ReadBuilder, ReadError, ReadOptions};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+
+#[test]
+fn iterate_over_chunks() {
+    let filename = "tmp-chunk_reader_test";
+    let data: Vec<u8> = (0..10_000_u32).map(|i| (i % 253) as u8).collect();
+    let _delete_file_at_exit = create_file(filename, &data);
+    let mut chunks = ChunkReader::new(filename, 3, 4, 2, ReadOptions::default())
+        .expect("Error starting read")
+        .collect::<Result<Vec<_>, _>>()
+        .expect("Error reading file");
+    assert_eq!(chunks.len(), 12);
+    chunks.sort_by_key(|(offset, _)| *offset);
+    let content: Vec<u8> = chunks.into_iter().flat_map(|(_, c)| c).collect();
+    assert_eq!(content, data);
+}
+
+/// Producers never have more chunks in flight than their buffers while the
+/// iterator is polled slowly.
+#[test]
+fn producers_do_not_read_ahead() {
+    let filename = "tmp-chunk_reader_backpressure_test";
+    let _delete_file_at_exit = create_file(filename, &[1_u8; 20_000]);
+    let in_flight = Arc::new(AtomicI64::new(0));
+    let max_in_flight = Arc::new(AtomicI64::new(0));
+    let (f, m) = (in_flight.clone(), max_in_flight.clone());
+    let hook = move |event: BufferEvent, _buffer_id: u64, _chunk_id: u64| match event {
+        BufferEvent::Dispatched => {
+            let n = f.fetch_add(1, Ordering::SeqCst) + 1;
+            m.fetch_max(n, Ordering::SeqCst);
+        }
+        BufferEvent::Recycled => {
+            f.fetch_sub(1, Ordering::SeqCst);
+        }
+        BufferEvent::Consumed => {}
+    };
+    let reader = ReadBuilder::new(filename)
+        .producers(2)
+        .chunks_per_producer(10)
+        .buffers_per_producer(2)
+        .options(ReadOptions {
+            on_buffer: Some(Arc::new(hook)),
+            ..Default::default()
+        })
+        .chunks()
+        .expect("Error starting read");
+    let mut bytes = 0;
+    for chunk in reader {
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        bytes += chunk.expect("Error reading chunk").1.len();
+    }
+    assert_eq!(bytes, 20_000);
+    assert!(max_in_flight.load(Ordering::SeqCst) <= 4);
+}
+
+#[test]
+fn drop_before_end_stops_producers() {
+    let filename = "tmp-chunk_reader_drop_test";
+    let _delete_file_at_exit = create_file(filename, &[1_u8; 10_000]);
+    let mut reader =
+        ChunkReader::new(filename, 4, 8, 1, ReadOptions::default()).expect("Error starting read");
+    assert!(reader.next().unwrap().is_ok());
+    // must not hang
+    drop(reader);
+}
+
+#[test]
+fn missing_file() {
+    match ChunkReader::new("tmp-chunk_reader_missing", 2, 2, 2, ReadOptions::default()) {
+        Err(ReadError::IO(_)) => {}
+        _ => panic!("Expected I/O error"),
+    }
+}
+
+struct Failing;
+
+impl par_io::read::ReadAt for Failing {
+    fn read_at(&self, _buffer: &mut Vec<u8>, offset: u64) -> Result<(), ReadError> {
+        Err(ReadError::Other(format!("Read failed at {}", offset)))
+    }
+}
+
+#[test]
+fn producer_error_yielded_at_end() {
+    let filename = "tmp-chunk_reader_error_test";
+    let _delete_file_at_exit = create_file(filename, &[1_u8; 1000]);
+    let results: Vec<_> = ChunkReader::new(
+        filename,
+        2,
+        2,
+        2,
+        ReadOptions {
+            source: Some(Arc::new(Failing)),
+            ..Default::default()
+        },
+    )
+    .expect("Error starting read")
+    .collect();
+    assert_eq!(results.len(), 1);
+    assert!(matches!(results[0], Err(ReadError::Other(_))));
+}
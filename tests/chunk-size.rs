@@ -0,0 +1,58 @@
+mod common;
+use common::create_file;
+use par_io::read::{read_file_with_options, ReadBuilder, ReadOptions};
+use std::sync::Arc;
+
+/// Read with a fixed chunk size and check chunk sizes, offsets, ids and the
+/// number of chunks passed to the callback.
+#[test]
+fn fixed_chunk_size() {
+    let filename = "tmp-chunk_size_test";
+    let data: Vec<u8> = (0..10_500_u32).map(|i| (i % 256) as u8).collect();
+    let _delete_file_at_exit = create_file(filename, &data);
+    let consume = |buffer: &[u8], _data: &(), chunk_id: u64, num_chunks: u64, offset: u64| {
+        (buffer.to_vec(), chunk_id, num_chunks, offset)
+    };
+    let mut v = read_file_with_options(
+        filename,
+        3,
+        2,
+        1,
+        Arc::new(consume),
+        (),
+        2,
+        ReadOptions {
+            chunk_size: Some(1000),
+            ..Default::default()
+        },
+    )
+    .expect("Error reading file");
+    assert_eq!(v.len(), 11);
+    v.sort_by_key(|(id, _)| *id);
+    for (i, (id, (buffer, chunk_id, num_chunks, offset))) in v.iter().enumerate() {
+        assert_eq!(*id, i as u64 + 1);
+        assert_eq!(chunk_id, id);
+        assert_eq!(*num_chunks, 11);
+        assert_eq!(*offset, i as u64 * 1000);
+        let expected_len = if i == 10 { 500 } else { 1000 };
+        assert_eq!(buffer.len(), expected_len);
+        assert_eq!(&buffer[..], &data[i * 1000..i * 1000 + expected_len]);
+    }
+}
+
+#[test]
+fn fixed_chunk_size_with_builder() {
+    let filename = "tmp-chunk_size_builder_test";
+    let _delete_file_at_exit = create_file(filename, &[7_u8; 4096]);
+    let consume = |buffer: &[u8], _data: &(), _chunk_id: u64, num_chunks: u64, _offset: u64| {
+        (buffer.len(), num_chunks)
+    };
+    // more producers than chunks
+    let v = ReadBuilder::new(filename)
+        .producers(8)
+        .chunk_size(1024)
+        .run(Arc::new(consume))
+        .expect("Error reading file");
+    assert_eq!(v.len(), 4);
+    assert!(v.iter().all(|(_, r)| *r == (1024, 4)));
+}
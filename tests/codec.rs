@@ -0,0 +1,98 @@
+mod common;
+use common::create_file;
+use par_io::codec::{read_records, FixedSizeRecords, NewlineDelimited};
+use par_io::read::ReadError;
+use std::sync::Arc;
+
+/// Read file made of 12 byte records and verify that each record is decoded
+/// exactly once even when chunk sizes are not a multiple of the record size.
+#[test]
+fn fixed_size_records() -> Result<(), String> {
+    const RECORD_SIZE: usize = 12;
+    let num_records = 1001_u32;
+    let bytes: Vec<u8> = (0..num_records)
+        .flat_map(|i| [i.to_le_bytes(), i.to_le_bytes(), i.to_le_bytes()].concat())
+        .collect();
+    let filename = "tmp-codec_fixed_size_test";
+    let _delete_file_at_exit = create_file(filename, &bytes);
+    let consume =
+        |records: &[Vec<u8>], _data: &(), _chunk_id: u64, _num_chunks: u64, offset: u64| {
+            assert_eq!(offset % RECORD_SIZE as u64, 0);
+            records
+                .iter()
+                .map(|r| {
+                    assert_eq!(r.len(), RECORD_SIZE);
+                    u32::from_le_bytes(r[..4].try_into().unwrap())
+                })
+                .collect::<Vec<u32>>()
+        };
+    let v = read_records(
+        filename,
+        3,
+        2,
+        4,
+        Arc::new(FixedSizeRecords(RECORD_SIZE)),
+        Arc::new(consume),
+        (),
+        2,
+    )
+    .map_err(|err| format!("{:?}", err))?;
+    let mut ids: Vec<u32> = v.into_iter().flat_map(|(_, r)| r).collect();
+    ids.sort_unstable();
+    assert_eq!(ids, (0..num_records).collect::<Vec<u32>>());
+    Ok(())
+}
+
+/// Read newline delimited text with lines spanning chunk boundaries.
+#[test]
+fn newline_delimited() -> Result<(), String> {
+    let lines: Vec<String> = (0..500)
+        .map(|i| "x".repeat(i % 37) + &i.to_string())
+        .collect();
+    let text = lines.join("\n");
+    let filename = "tmp-codec_newline_test";
+    let _delete_file_at_exit = create_file(filename, text.as_bytes());
+    let consume =
+        |records: &[String], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| {
+            records.to_vec()
+        };
+    let mut v = read_records(
+        filename,
+        4,
+        3,
+        5,
+        Arc::new(NewlineDelimited),
+        Arc::new(consume),
+        (),
+        2,
+    )
+    .map_err(|err| format!("{:?}", err))?;
+    v.sort_by_key(|(id, _)| *id);
+    let decoded: Vec<String> = v.into_iter().flat_map(|(_, r)| r).collect();
+    assert_eq!(decoded, lines);
+    Ok(())
+}
+
+/// A record size of zero is rejected before reading.
+#[test]
+fn zero_record_size() {
+    let filename = "tmp-codec_zero_size_test";
+    let _delete_file_at_exit = create_file(filename, &[1_u8; 100]);
+    let consume =
+        |records: &[Vec<u8>], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| {
+            records.len()
+        };
+    match read_records(
+        filename,
+        2,
+        2,
+        2,
+        Arc::new(FixedSizeRecords(0)),
+        Arc::new(consume),
+        (),
+        2,
+    ) {
+        Err(ReadError::Other(msg)) => assert!(msg.contains("Record size"), "{}", msg),
+        other => panic!("Unexpected result: {:?}", other.map(|_| ())),
+    }
+}
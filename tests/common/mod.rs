@@ -0,0 +1,21 @@
+//! Helpers shared by integration tests.
+#![allow(dead_code)]
+use std::io::Write;
+
+/// RAII for deleting file on exit.
+pub struct DeleteFile(pub String);
+
+impl std::ops::Drop for DeleteFile {
+    fn drop(&mut self) {
+        // the file might have never been created if the test failed early
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Create file `filename` with content `bytes` and return an object deleting
+/// the file when dropped.
+pub fn create_file(filename: &str, bytes: &[u8]) -> DeleteFile {
+    let mut file = std::fs::File::create(filename).expect("Cannot create file");
+    file.write_all(bytes).expect("Cannot write to file");
+    DeleteFile(filename.to_string())
+}
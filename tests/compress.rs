@@ -0,0 +1,273 @@
+#![cfg(feature = "zstd")]
+mod common;
+use common::DeleteFile;
+use par_io::compress::{
+    decode_frame, encode_frame, max_frame_size, read_compressed, read_frames, read_index,
+    write_compressed,
+};
+use par_io::read::{read_file_with_options, ReadError, ReadOptions};
+use par_io::write::WriteError;
+use std::sync::Arc;
+
+type Chunks = Vec<(u64, (u64, Vec<u8>))>;
+
+/// Data alternating zero-filled regions and varying bytes.
+fn data_at(offset: u64) -> u8 {
+    if (offset / 1000) % 2 == 0 {
+        0
+    } else {
+        (offset % 251) as u8
+    }
+}
+
+fn write_data(filename: &str, size: usize) -> usize {
+    let producer = |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<(), String> {
+        for (i, b) in buffer.iter_mut().enumerate() {
+            *b = data_at(offset + i as u64);
+        }
+        Ok(())
+    };
+    write_compressed(filename, 3, 2, 2, Arc::new(producer), (), 2, size)
+        .expect("Error writing compressed file")
+}
+
+/// Return data read from compressed file, sorted by offset.
+fn read_data(filename: &str) -> Vec<u8> {
+    let consumer = |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, offset: u64| {
+        (offset, buffer.to_vec())
+    };
+    let mut chunks: Chunks =
+        read_compressed(filename, 2, 3, Arc::new(consumer), (), 2).expect("Error reading file");
+    chunks.sort_by_key(|(_, (offset, _))| *offset);
+    chunks.into_iter().flat_map(|(_, (_, d))| d).collect()
+}
+
+#[test]
+fn frame_round_trip() {
+    let mut runs = vec![7_u8; 300_000];
+    runs.extend((0..200_000).map(|i| (i % 13) as u8));
+    for data in [Vec::new(), vec![1, 2, 3], vec![0; 31], runs] {
+        let mut frame = Vec::new();
+        encode_frame(&data, &mut frame);
+        assert!(frame.len() <= max_frame_size(data.len()));
+        let mut decoded = Vec::new();
+        decode_frame(&frame, &mut decoded).expect("Error decoding frame");
+        assert_eq!(decoded, data);
+    }
+}
+
+#[test]
+fn frame_rejects_invalid_data() {
+    let mut frame = Vec::new();
+    encode_frame(&[5; 100], &mut frame);
+    let mut decoded = Vec::new();
+    assert!(decode_frame(&frame[..frame.len() - 1], &mut decoded).is_err());
+    frame[0] = 0;
+    assert!(decode_frame(&frame, &mut decoded).is_err());
+}
+
+#[test]
+fn compressed_round_trip() {
+    let filename = "tmp-compress-round-trip";
+    let _delete = DeleteFile(filename.to_string());
+    let size = 100_003;
+    let file_size = write_data(filename, size);
+    assert_eq!(
+        file_size as u64,
+        std::fs::metadata(filename)
+            .expect("Cannot access file")
+            .len()
+    );
+    // zero-filled regions are stored as RLE blocks
+    assert!(file_size < size * 3 / 4);
+    let data = read_data(filename);
+    assert_eq!(data.len(), size);
+    assert!(data
+        .iter()
+        .enumerate()
+        .all(|(i, b)| *b == data_at(i as u64)));
+}
+
+#[test]
+fn read_file_decompresses() {
+    let filename = "tmp-compress-read-file";
+    let _delete = DeleteFile(filename.to_string());
+    let size = 50_001;
+    write_data(filename, size);
+    let consumer = |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, offset: u64| {
+        (offset, buffer.to_vec())
+    };
+    let options = ReadOptions {
+        compressed: true,
+        ..Default::default()
+    };
+    let mut chunks: Chunks =
+        read_file_with_options(filename, 2, 2, 4, Arc::new(consumer), (), 2, options)
+            .expect("Error reading file");
+    chunks.sort_by_key(|(_, (offset, _))| *offset);
+    let data: Vec<u8> = chunks.into_iter().flat_map(|(_, (_, d))| d).collect();
+    assert_eq!(data, read_data(filename));
+    assert_eq!(data.len(), size);
+}
+
+#[test]
+fn index_lists_frames_in_order() {
+    let filename = "tmp-compress-index";
+    let _delete = DeleteFile(filename.to_string());
+    write_data(filename, 6000);
+    let (entries, size) = read_index(filename).expect("Error reading index");
+    assert_eq!(size, 6000);
+    assert_eq!(entries.len(), 6);
+    let mut frame_offset = 0;
+    for (i, e) in entries.iter().enumerate() {
+        assert_eq!(e.offset, i as u64 * 1000);
+        assert_eq!(e.size, 1000);
+        assert_eq!(e.frame_offset, frame_offset);
+        frame_offset += e.frame_size;
+    }
+}
+
+#[test]
+fn not_compressed_file() {
+    let filename = "tmp-compress-not-compressed";
+    let _delete = common::create_file(filename, &[0; 100]);
+    assert!(read_index(filename).is_err());
+}
+
+/// Frames written by another encoder and concatenated without an index are
+/// read given their boundaries.
+#[test]
+fn read_concatenated_frames() {
+    let filename = "tmp-compress-frames";
+    let blocks: Vec<Vec<u8>> = (0..7_u8)
+        .map(|i| {
+            let mut block = vec![i; 1000 * i as usize];
+            block.extend((0..i).map(|j| j * 3));
+            block
+        })
+        .collect();
+    let mut file = Vec::new();
+    let mut frames = Vec::new();
+    for block in &blocks {
+        let offset = file.len() as u64;
+        encode_frame(block, &mut file);
+        frames.push((offset, file.len() as u64 - offset));
+    }
+    let _delete = common::create_file(filename, &file);
+    let consumer = |buffer: &[u8], _data: &(), _chunk_id: u64, num_chunks: u64, offset: u64| {
+        assert_eq!(num_chunks, 7);
+        (offset, buffer.to_vec())
+    };
+    let mut chunks: Chunks = read_frames(filename, 3, 2, &frames, Arc::new(consumer), (), 2)
+        .expect("Error reading frames");
+    chunks.sort_by_key(|(id, _)| *id);
+    for (k, (id, (offset, data))) in chunks.into_iter().enumerate() {
+        assert_eq!(id, k as u64 + 1);
+        assert_eq!(offset, frames[k].0);
+        assert_eq!(data, blocks[k]);
+    }
+    // boundaries not matching a frame
+    frames[3].1 -= 1;
+    let consumer = |_: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| ();
+    assert!(read_frames(filename, 3, 2, &frames, Arc::new(consumer), (), 2).is_err());
+}
+
+#[test]
+fn zero_counts() {
+    let filename = "tmp-compress-zero-counts";
+    let _delete = DeleteFile(filename.to_string());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<(), String> {
+        buffer.fill(1);
+        Ok(())
+    };
+    match write_compressed(filename, 2, 2, 0, Arc::new(producer), (), 2, 1000) {
+        Err(WriteError::Other(msg)) => assert_eq!(msg, "chunks_per_producer must be >= 1"),
+        r => panic!("Expected error, got {:?}", r),
+    }
+    write_data(filename, 1000);
+    let consumer =
+        |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| buffer.len();
+    match read_compressed(filename, 0, 2, Arc::new(consumer), (), 2) {
+        Err(ReadError::Other(msg)) => assert_eq!(msg, "num_producers must be >= 1"),
+        r => panic!("Expected error, got {:?}", r),
+    }
+}
+
+const TEXT: &str = "Parallel file I/O: producers generate chunks of data, consumers write them \
+at their offsets; each producer owns a fixed number of buffers, so memory \
+usage is bounded and known in advance. Chunks are compressed independently \
+so that any range of data can be read back without decompressing the rest \
+of the file, producers and consumers compress and decompress in parallel.\n";
+const TEXT_END: &str = "Reading a range only requires the frames listed in the index for that \
+range; frames written by other tools are read given their boundaries.\n";
+
+/// `TEXT + TEXT_END` repeated twice, compressed with `zstd -19`: Huffman
+/// coded literals in four streams, FSE coded sequences and a checksum.
+const REFERENCE_FRAME: [u8; 316] = [
+    0x28, 0xb5, 0x2f, 0xfd, 0x64, 0x00, 0x03, 0x75, 0x09, 0x00, 0x26, 0xd4, 0x35, 0x1a, 0x80, 0x4b,
+    0x73, 0xc0, 0x48, 0xa4, 0xa4, 0xb5, 0x2d, 0x1d, 0x67, 0x77, 0xa3, 0x24, 0x84, 0x3c, 0x1f, 0xc6,
+    0xac, 0x44, 0x7a, 0xe3, 0x38, 0xa9, 0xe8, 0x27, 0x2e, 0x00, 0x2e, 0x00, 0x2c, 0x00, 0xd7, 0x94,
+    0x98, 0x53, 0xed, 0x79, 0xb9, 0xb2, 0xfe, 0xaa, 0x74, 0x2c, 0x4d, 0x91, 0x1d, 0x07, 0x6e, 0x0f,
+    0x39, 0x31, 0xeb, 0x02, 0x9b, 0x72, 0xfc, 0xbd, 0x3c, 0x3e, 0xb1, 0xe6, 0x4f, 0x43, 0x2c, 0x19,
+    0x13, 0x10, 0x83, 0x83, 0xbf, 0x54, 0xf2, 0xfa, 0x5a, 0x97, 0x97, 0x08, 0xd7, 0x40, 0x67, 0x54,
+    0x46, 0x07, 0xc3, 0x85, 0x11, 0x9e, 0xd1, 0x11, 0x36, 0x86, 0x58, 0x95, 0x9f, 0x9c, 0x36, 0x37,
+    0xc9, 0x96, 0x2f, 0x66, 0xea, 0x80, 0xe6, 0x53, 0x6a, 0x88, 0x8f, 0x2c, 0x0d, 0x8d, 0xfe, 0x50,
+    0xc9, 0x67, 0x45, 0x07, 0xb3, 0x72, 0xee, 0x2e, 0xe8, 0x06, 0xeb, 0x04, 0xd5, 0x91, 0xb7, 0x31,
+    0x65, 0x95, 0xc3, 0x8b, 0x73, 0x88, 0xd1, 0x3d, 0x7d, 0xc4, 0x78, 0xc7, 0x4f, 0xf0, 0xd2, 0x0d,
+    0x9e, 0xf7, 0x94, 0x6d, 0xd6, 0xe2, 0x11, 0x9e, 0x39, 0x42, 0x65, 0x74, 0xd5, 0x27, 0xb3, 0xb0,
+    0xe3, 0xe7, 0x19, 0x2c, 0xf8, 0xc1, 0x00, 0x81, 0x5e, 0xe5, 0xe1, 0x1b, 0x2a, 0xc9, 0xb3, 0x2e,
+    0xc6, 0xd6, 0x79, 0x65, 0x99, 0x1b, 0xc4, 0xf8, 0x2d, 0x24, 0x4b, 0x7e, 0xac, 0xaa, 0x65, 0xfd,
+    0x72, 0xa9, 0x4f, 0xd5, 0x18, 0x4f, 0x37, 0x0b, 0x0f, 0xdd, 0x15, 0x00, 0x01, 0x83, 0xf0, 0x02,
+    0x89, 0x79, 0x55, 0x09, 0x20, 0x00, 0xfd, 0x03, 0x44, 0x59, 0xd6, 0x62, 0x30, 0xba, 0x43, 0x2f,
+    0x5b, 0xf2, 0xe2, 0xb2, 0xe0, 0x14, 0xb9, 0xaf, 0x26, 0xbc, 0x53, 0xee, 0xeb, 0xc0, 0x59, 0xa0,
+    0xfa, 0x02, 0xa1, 0x31, 0xb2, 0x62, 0x74, 0xbd, 0x08, 0xd5, 0xb6, 0x79, 0x3a, 0xb3, 0xc1, 0x04,
+    0x03, 0x9d, 0x01, 0xba, 0x12, 0x20, 0xb1, 0x66, 0x4e, 0xd6, 0x6e, 0x65, 0x5d, 0x78, 0x31, 0x7d,
+    0xd4, 0x16, 0xa2, 0x20, 0x0e, 0xd2, 0x48, 0x81, 0x45, 0x70, 0x9c, 0x31, 0x03, 0x3b, 0x0d, 0x81,
+    0x14, 0x0a, 0x5c, 0x89, 0xcf, 0x26, 0x50, 0x50, 0x08, 0x79, 0x1b, 0xb3,
+];
+
+#[test]
+fn decode_reference_frame() {
+    let text = [TEXT, TEXT_END, TEXT, TEXT_END].concat();
+    let mut decoded = Vec::new();
+    decode_frame(&REFERENCE_FRAME, &mut decoded).expect("Error decoding frame");
+    assert_eq!(decoded, text.as_bytes());
+    // corrupted checksum
+    let mut frame = REFERENCE_FRAME;
+    frame[315] ^= 1;
+    assert!(decode_frame(&frame, &mut decoded).is_err());
+}
+
+#[test]
+fn frame_compresses_text() {
+    let data = TEXT.repeat(100).into_bytes();
+    let mut frame = Vec::new();
+    encode_frame(&data, &mut frame);
+    assert!(frame.len() < data.len() / 20);
+    let mut decoded = Vec::new();
+    decode_frame(&frame, &mut decoded).expect("Error decoding frame");
+    assert_eq!(decoded, data);
+}
+
+#[test]
+fn frame_round_trip_pseudo_random() {
+    // xorshift generator, drawing from a small alphabet to create matches
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    for size in [1, 100, 4_000, 200_000] {
+        for alphabet in [2, 16, 256] {
+            let data: Vec<u8> = (0..size).map(|_| (next() % alphabet) as u8).collect();
+            let mut frame = Vec::new();
+            encode_frame(&data, &mut frame);
+            assert!(frame.len() <= max_frame_size(data.len()));
+            let mut decoded = Vec::new();
+            decode_frame(&frame, &mut decoded).expect("Error decoding frame");
+            assert_eq!(decoded, data);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+mod common;
+use common::DeleteFile;
+use par_io::write::{write_to_file_variable, write_to_file_with_options, WriteError, WriteOptions};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Options making the write of the chunk at `bad_offset` fail.
+fn failing_at(bad_offset: u64) -> WriteOptions {
+    WriteOptions {
+        offset_map: Some(Arc::new(move |offset| {
+            if offset == bad_offset {
+                i64::MAX as u64
+            } else {
+                offset
+            }
+        })),
+        detect_overlaps: false,
+        ..Default::default()
+    }
+}
+
+/// Run `f` on a separate thread and fail if it does not return in time.
+fn within_timeout<F: FnOnce() -> Result<usize, WriteError> + Send + 'static>(
+    f: F,
+) -> Result<usize, WriteError> {
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(f());
+    });
+    rx.recv_timeout(TIMEOUT).expect("Write did not return")
+}
+
+/// A failed write stops all producers and consumers, whichever consumer and
+/// chunk fails.
+#[test]
+fn fixed_chunks_consumer_error() {
+    for (num_producers, num_consumers) in [(1, 1), (4, 1), (4, 3), (2, 6), (8, 8)] {
+        for bad_chunk in [0, 5, 15] {
+            let filename = format!(
+                "tmp-consumer_error_{}_{}_{}",
+                num_producers, num_consumers, bad_chunk
+            );
+            let _delete_file_at_exit = DeleteFile(filename.clone());
+            let r = within_timeout(move || {
+                write_to_file_with_options(
+                    &filename,
+                    num_producers,
+                    num_consumers,
+                    16 / num_producers,
+                    Arc::new(|b: &mut Vec<u8>, _: &(), _| -> Result<(), String> {
+                        b.fill(1);
+                        Ok(())
+                    }),
+                    (),
+                    1,
+                    16_000,
+                    failing_at(bad_chunk * 1000),
+                )
+            });
+            assert!(matches!(r, Err(WriteError::Consumer(_))), "{:?}", r);
+        }
+    }
+}
+
+/// Producers waiting for the offset of their chunk stop when the buffers of
+/// another producer are dropped by a failed consumer.
+#[test]
+fn variable_chunks_consumer_error() {
+    for (num_producers, num_consumers) in [(1, 1), (4, 1), (4, 3), (2, 6), (8, 8)] {
+        for bad_chunk in [0, 5, 15] {
+            let filename = format!(
+                "tmp-consumer_error_variable_{}_{}_{}",
+                num_producers, num_consumers, bad_chunk
+            );
+            let _delete_file_at_exit = DeleteFile(filename.clone());
+            let r = within_timeout(move || {
+                write_to_file_variable(
+                    &filename,
+                    num_producers,
+                    num_consumers,
+                    16 / num_producers,
+                    Arc::new(|b: &mut Vec<u8>, _: &(), _| -> Result<usize, String> {
+                        b.fill(1);
+                        Ok(1000)
+                    }),
+                    (),
+                    1,
+                    1000,
+                    failing_at(bad_chunk * 1000),
+                )
+            });
+            assert!(matches!(r, Err(WriteError::Consumer(_))), "{:?}", r);
+        }
+    }
+}
+
+/// No producer callback is still running when a failed write returns.
+#[test]
+fn producers_joined_on_error() {
+    let filename = "tmp-consumer_error_joined";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let running = Arc::new(AtomicUsize::new(0));
+    let r = running.clone();
+    let producer = move |b: &mut Vec<u8>, _: &(), _| -> Result<(), String> {
+        r.fetch_add(1, Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(20));
+        b.fill(1);
+        r.fetch_sub(1, Ordering::SeqCst);
+        Ok(())
+    };
+    let r = write_to_file_with_options(
+        filename,
+        4,
+        2,
+        4,
+        Arc::new(producer),
+        (),
+        2,
+        16_000,
+        failing_at(0),
+    );
+    assert!(matches!(r, Err(WriteError::Consumer(_))), "{:?}", r);
+    assert_eq!(running.load(Ordering::SeqCst), 0);
+}
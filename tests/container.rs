@@ -0,0 +1,101 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::container::{read_container, read_header, write_container, Header, HEADER_SIZE};
+use par_io::read::ReadError;
+use std::sync::Arc;
+
+type Chunks = Vec<(u64, (u64, Vec<u8>))>;
+
+/// Return data read from container, sorted by offset.
+fn read_data(filename: &str) -> Result<Vec<u8>, ReadError> {
+    let consumer = |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, offset: u64| {
+        (offset, buffer.to_vec())
+    };
+    let mut chunks: Chunks = read_container(filename, 3, 2, 2, Arc::new(consumer), (), 2)?;
+    chunks.sort_by_key(|(_, (offset, _))| *offset);
+    Ok(chunks.into_iter().flat_map(|(_, (_, d))| d).collect())
+}
+
+#[test]
+fn round_trip() -> Result<(), String> {
+    let filename = "tmp-container_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<(), String> {
+        for (i, b) in buffer.iter_mut().enumerate() {
+            *b = ((offset + i as u64) % 251) as u8;
+        }
+        Ok(())
+    };
+    let written = write_container(filename, 3, 2, 2, Arc::new(producer), (), 2, 1000)
+        .map_err(|err| format!("{:?}", err))?;
+    assert_eq!(written, 1000);
+    let header = read_header(filename).map_err(|err| format!("{:?}", err))?;
+    assert_eq!(
+        header,
+        Header {
+            version: 1,
+            data_size: 1000
+        }
+    );
+    let data = read_data(filename).map_err(|err| format!("{:?}", err))?;
+    let expected: Vec<u8> = (0..1000_u64).map(|i| (i % 251) as u8).collect();
+    assert_eq!(data, expected);
+    Ok(())
+}
+
+/// Header bytes built by hand as written by a big-endian machine are read
+/// back independently of the host byte order.
+#[test]
+fn manual_header() -> Result<(), String> {
+    let filename = "tmp-container_manual_test";
+    let mut content = b"PARIOCNT".to_vec();
+    content.extend([0, 0, 0, 1]); // version
+    content.extend([0, 0, 0, 0]); // reserved
+    content.extend([0, 0, 0, 0, 0, 0, 0x01, 0x2C]); // 300 bytes
+    assert_eq!(content.len() as u64, HEADER_SIZE);
+    let body: Vec<u8> = (0..300).map(|i| (i % 7) as u8).collect();
+    content.extend(&body);
+    let _delete_file_at_exit = create_file(filename, &content);
+    assert_eq!(
+        read_data(filename).map_err(|err| format!("{:?}", err))?,
+        body
+    );
+    Ok(())
+}
+
+/// Headers in the wrong byte order, with a bad magic or not matching the
+/// data size are rejected.
+#[test]
+fn invalid_header() {
+    let filename = "tmp-container_invalid_test";
+    // little-endian version and size
+    let mut content = b"PARIOCNT".to_vec();
+    content.extend([1, 0, 0, 0, 0, 0, 0, 0]);
+    content.extend(10_u64.to_le_bytes());
+    content.extend([0_u8; 10]);
+    let _delete_file_at_exit = create_file(filename, &content);
+    assert!(matches!(read_data(filename), Err(ReadError::Other(_))));
+
+    let mut content = b"PARIOXXX".to_vec();
+    content.extend(
+        Header {
+            version: 1,
+            data_size: 10,
+        }
+        .to_bytes()[8..]
+            .to_vec(),
+    );
+    content.extend([0_u8; 10]);
+    std::fs::write(filename, &content).unwrap();
+    assert!(matches!(read_data(filename), Err(ReadError::Other(_))));
+
+    let mut content = Header {
+        version: 1,
+        data_size: 20,
+    }
+    .to_bytes()
+    .to_vec();
+    content.extend([0_u8; 10]);
+    std::fs::write(filename, &content).unwrap();
+    assert!(matches!(read_data(filename), Err(ReadError::Other(_))));
+}
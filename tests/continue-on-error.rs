@@ -0,0 +1,96 @@
+mod common;
+use common::DeleteFile;
+use par_io::write::{write_to_file_with_report, WriteBuilder, WriteError, WriteOptions};
+use std::sync::Arc;
+
+const BAD_OFFSET: u64 = 2000;
+
+/// Write 6 chunks of 1000 bytes, the chunk at `BAD_OFFSET` is mapped to an
+/// invalid file offset and fails.
+fn options(continue_on_error: bool) -> WriteOptions {
+    WriteOptions {
+        offset_map: Some(Arc::new(|offset| {
+            if offset == BAD_OFFSET {
+                i64::MAX as u64
+            } else {
+                offset
+            }
+        })),
+        detect_overlaps: false,
+        continue_on_error,
+        ..Default::default()
+    }
+}
+
+fn fill(buffer: &mut [u8], offset: u64) -> Result<(), String> {
+    buffer.fill((offset / 1000) as u8 + 1);
+    Ok(())
+}
+
+#[test]
+fn failed_chunks_reported() {
+    let filename = "tmp-continue_on_error";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let report = write_to_file_with_report(
+        filename,
+        2,
+        2,
+        3,
+        Arc::new(|b: &mut Vec<u8>, _: &(), offset| fill(b, offset)),
+        (),
+        2,
+        6000,
+        options(true),
+    )
+    .expect("Write failed");
+    assert!(!report.is_success());
+    assert_eq!(report.bytes_written, 5000);
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].offset, BAD_OFFSET);
+    assert_eq!(report.errors[0].size, 1000);
+    let data = std::fs::read(filename).expect("Cannot read file");
+    for (i, chunk) in data.chunks(1000).enumerate() {
+        let expected = if i as u64 * 1000 == BAD_OFFSET {
+            0
+        } else {
+            i as u8 + 1
+        };
+        assert!(chunk.iter().all(|b| *b == expected), "chunk {}", i);
+    }
+}
+
+/// Fail fast by default.
+#[test]
+fn fail_fast() {
+    let filename = "tmp-continue_on_error_fail_fast";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    match write_to_file_with_report(
+        filename,
+        2,
+        2,
+        3,
+        Arc::new(|b: &mut Vec<u8>, _: &(), offset| fill(b, offset)),
+        (),
+        2,
+        6000,
+        options(false),
+    ) {
+        Err(WriteError::Consumer(err)) => assert_eq!(err.offset, i64::MAX as u64),
+        r => panic!("Unexpected result: {:?}", r),
+    }
+}
+
+#[test]
+fn builder_continue_on_error() {
+    let filename = "tmp-continue_on_error_builder";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let report = WriteBuilder::new(filename)
+        .producers(2)
+        .chunks_per_producer(3)
+        .total_size(6000)
+        .continue_on_error(true)
+        .run_with_report(Arc::new(|b: &mut Vec<u8>, _: &(), offset| fill(b, offset)))
+        .expect("Write failed");
+    assert!(report.is_success());
+    assert_eq!(report.bytes_written, 6000);
+}
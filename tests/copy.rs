@@ -0,0 +1,73 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::copy::{copy_file, copy_files, CopyConfig, CopyError};
+
+fn data(len: usize, seed: usize) -> Vec<u8> {
+    (0..len).map(|i| ((i * 7 + seed) % 251) as u8).collect()
+}
+
+/// Files of different sizes, including empty files and files smaller than a
+/// chunk, are copied.
+#[test]
+fn copy_many_files() -> Result<(), String> {
+    let sizes = [0, 1, 10, 1000, 0, 12_345, 3, 50_000, 7];
+    let names: Vec<(String, String)> = (0..sizes.len())
+        .map(|i| (format!("tmp-copy_src_{}", i), format!("tmp-copy_dst_{}", i)))
+        .collect();
+    let mut _delete_at_exit = Vec::new();
+    for (i, (src, dst)) in names.iter().enumerate() {
+        _delete_at_exit.push(create_file(src, &data(sizes[i], i)));
+        _delete_at_exit.push(DeleteFile(dst.clone()));
+    }
+    let pairs: Vec<(&str, &str)> = names
+        .iter()
+        .map(|(s, d)| (s.as_str(), d.as_str()))
+        .collect();
+    let config = CopyConfig {
+        num_producers: 3,
+        num_consumers: 2,
+        chunks_per_producer: 4,
+        num_buffers_per_producer: 2,
+    };
+    let copied = copy_files(&pairs, config).map_err(|err| format!("{:?}", err))?;
+    assert_eq!(copied, sizes.iter().sum::<usize>());
+    for (i, (_, dst)) in names.iter().enumerate() {
+        assert_eq!(
+            std::fs::read(dst).map_err(|err| err.to_string())?,
+            data(sizes[i], i)
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn copy_single_file() -> Result<(), String> {
+    let src = "tmp-copy_file_src";
+    let dst = "tmp-copy_file_dst";
+    let _delete_src_at_exit = create_file(src, &data(100_003, 1));
+    let _delete_dst_at_exit = DeleteFile(dst.to_string());
+    let config = CopyConfig {
+        num_producers: 4,
+        num_consumers: 3,
+        chunks_per_producer: 5,
+        num_buffers_per_producer: 2,
+    };
+    let copied = copy_file(src, dst, config).map_err(|err| format!("{:?}", err))?;
+    assert_eq!(copied, 100_003);
+    assert_eq!(
+        std::fs::read(dst).map_err(|err| err.to_string())?,
+        data(100_003, 1)
+    );
+    Ok(())
+}
+
+#[test]
+fn missing_source() {
+    let src = "tmp-copy_missing_src";
+    let dst = "tmp-copy_missing_dst";
+    let _delete_dst_at_exit = DeleteFile(dst.to_string());
+    match copy_files(&[(src, dst)], CopyConfig::default()) {
+        Err(CopyError::Open { filename, .. }) => assert_eq!(filename, src),
+        r => panic!("Expected open error, got {:?}", r),
+    }
+}
@@ -0,0 +1,181 @@
+mod common;
+#[cfg(target_os = "linux")]
+use common::create_file;
+#[cfg(target_os = "linux")]
+use par_io::cpu::{Affinity, CpuReport, Worker};
+#[cfg(target_os = "linux")]
+use par_io::read::{read_file_with_options, ReadOptions};
+#[cfg(target_os = "linux")]
+use par_io::write::{write_to_file_with_options, WriteOptions};
+#[cfg(target_os = "linux")]
+use std::sync::Arc;
+
+#[cfg(target_os = "linux")]
+const CPU_SET_WORDS: usize = 16;
+
+#[cfg(target_os = "linux")]
+extern "C" {
+    fn sched_getaffinity(pid: i32, cpusetsize: usize, mask: *mut u64) -> i32;
+    fn sched_setaffinity(pid: i32, cpusetsize: usize, mask: *const u64) -> i32;
+}
+
+/// Return the CPUs the calling thread is allowed to run on.
+#[cfg(target_os = "linux")]
+fn allowed_cpus() -> Vec<usize> {
+    let mut mask = [0_u64; CPU_SET_WORDS];
+    let size = std::mem::size_of_val(&mask);
+    assert_eq!(unsafe { sched_getaffinity(0, size, mask.as_mut_ptr()) }, 0);
+    (0..CPU_SET_WORDS * 64)
+        .filter(|c| mask[c / 64] & (1 << (c % 64)) != 0)
+        .collect()
+}
+
+/// Pin the calling thread to the first CPU it is allowed to run on and
+/// return the CPU id; threads spawned afterwards inherit the affinity.
+#[cfg(target_os = "linux")]
+fn pin_current_thread() -> usize {
+    let size = CPU_SET_WORDS * 8;
+    let cpu = *allowed_cpus().first().expect("No CPU available");
+    let mut pinned = [0_u64; CPU_SET_WORDS];
+    pinned[cpu / 64] = 1 << (cpu % 64);
+    assert_eq!(unsafe { sched_setaffinity(0, size, pinned.as_ptr()) }, 0);
+    cpu
+}
+
+/// Worker threads inherit the affinity of the calling thread: all recorded
+/// CPUs must be the one the calling thread is pinned to.
+#[cfg(target_os = "linux")]
+#[test]
+fn cpus_within_affinity_set() -> Result<(), String> {
+    let data = vec![7_u8; 10000];
+    let filename = "tmp-cpu_report_test";
+    let _delete_file_at_exit = create_file(filename, &data);
+    // run in a separate thread to leave the affinity of the test thread untouched
+    let (cpu, report) = std::thread::spawn(move || -> Result<(usize, Arc<CpuReport>), String> {
+        let cpu = pin_current_thread();
+        let report = Arc::new(CpuReport::new());
+        let consume =
+            |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| {
+                buffer.len()
+            };
+        read_file_with_options(
+            filename,
+            3,
+            2,
+            2,
+            Arc::new(consume),
+            (),
+            2,
+            ReadOptions {
+                cpu_report: Some(report.clone()),
+                ..Default::default()
+            },
+        )
+        .map_err(|err| format!("{:?}", err))?;
+        Ok((cpu, report))
+    })
+    .join()
+    .map_err(|err| format!("{:?}", err))??;
+    let samples = report.samples();
+    // two samples per thread
+    assert_eq!(samples.len(), 2 * (3 + 2));
+    for w in [Worker::Producer(0), Worker::Consumer(1)] {
+        assert_eq!(samples.iter().filter(|s| s.worker == w).count(), 2);
+    }
+    assert!(samples.iter().all(|s| s.cpu == Some(cpu)));
+    Ok(())
+}
+
+/// Worker threads are pinned to the listed CPU.
+#[cfg(target_os = "linux")]
+#[test]
+fn pinned_to_listed_cpu() {
+    let filename = "tmp-cpu_affinity_cores";
+    let _delete_file_at_exit = create_file(filename, &[1; 10000]);
+    let cpu = *allowed_cpus().last().expect("No CPU available");
+    let report = Arc::new(CpuReport::new());
+    let consume =
+        |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| buffer.len();
+    read_file_with_options(
+        filename,
+        3,
+        2,
+        2,
+        Arc::new(consume),
+        (),
+        2,
+        ReadOptions {
+            cpu_report: Some(report.clone()),
+            affinity: Some(Affinity::Cores(vec![cpu])),
+            ..Default::default()
+        },
+    )
+    .expect("Read failed");
+    let produce = |buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<(), String> {
+        buffer.fill(2);
+        Ok(())
+    };
+    write_to_file_with_options(
+        filename,
+        3,
+        2,
+        2,
+        Arc::new(produce),
+        (),
+        2,
+        10000,
+        WriteOptions {
+            cpu_report: Some(report.clone()),
+            affinity: Some(Affinity::Cores(vec![cpu])),
+            ..Default::default()
+        },
+    )
+    .expect("Write failed");
+    let samples = report.samples();
+    assert_eq!(samples.len(), 4 * (3 + 2));
+    assert!(samples.iter().all(|s| s.cpu == Some(cpu)));
+}
+
+/// Producers take CPUs from the start of the allowed set, consumers from the
+/// end.
+#[cfg(target_os = "linux")]
+#[test]
+fn spread_across_allowed_cpus() {
+    let filename = "tmp-cpu_affinity_spread";
+    let _delete_file_at_exit = create_file(filename, &[1; 10000]);
+    let cpus = allowed_cpus();
+    let report = Arc::new(CpuReport::new());
+    let consume =
+        |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| buffer.len();
+    read_file_with_options(
+        filename,
+        2,
+        2,
+        2,
+        Arc::new(consume),
+        (),
+        2,
+        ReadOptions {
+            cpu_report: Some(report.clone()),
+            affinity: Some(Affinity::Spread),
+            ..Default::default()
+        },
+    )
+    .expect("Read failed");
+    for s in report.samples() {
+        let expected = match s.worker {
+            Worker::Producer(i) => cpus[i as usize % cpus.len()],
+            Worker::Consumer(i) => cpus[cpus.len() - 1 - i as usize % cpus.len()],
+        };
+        assert_eq!(s.cpu, Some(expected));
+    }
+}
+
+/// The CPU of the current thread is known on Linux only.
+#[test]
+fn current_cpu() {
+    assert_eq!(
+        par_io::cpu::current_cpu().is_some(),
+        cfg!(target_os = "linux")
+    );
+}
@@ -0,0 +1,62 @@
+mod common;
+use common::DeleteFile;
+use par_io::dedup::{read_file_dedup, write_to_file_dedup, DedupMap, DedupRef};
+use par_io::write::WriteOptions;
+use std::sync::Arc;
+
+/// Chunk `i` of 200 bytes contains bytes `i % 2 + 1`.
+fn pattern(offset: u64, len: usize) -> Vec<u8> {
+    (offset..offset + len as u64)
+        .map(|o| (o / 200 % 2 + 1) as u8)
+        .collect()
+}
+
+/// Write six chunks with only two distinct contents and verify the
+/// dedup map and that reading the file back returns the original data.
+#[test]
+fn repeated_chunks() -> Result<(), String> {
+    let filename = "tmp-dedup_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<(), String> {
+        let len = buffer.len();
+        buffer.copy_from_slice(&pattern(offset, len));
+        Ok(())
+    };
+    // single producer, consumer and buffer: chunks are written in order
+    let (written, map) = write_to_file_dedup(
+        filename,
+        1,
+        1,
+        6,
+        Arc::new(producer),
+        (),
+        1,
+        1200,
+        WriteOptions::default(),
+    )
+    .map_err(|err| format!("{:?}", err))?;
+    assert_eq!(written, 400);
+    let expected = DedupMap {
+        refs: (2..6)
+            .map(|i| DedupRef {
+                offset: i * 200,
+                size: 200,
+                source: i % 2 * 200,
+            })
+            .collect(),
+    };
+    assert_eq!(map, expected);
+    // read back with a different chunk layout
+    let consume = |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, offset: u64| {
+        (offset, buffer.to_vec())
+    };
+    let chunks = read_file_dedup(filename, Arc::new(map), 3, 2, 2, Arc::new(consume), (), 2)
+        .map_err(|err| format!("{:?}", err))?;
+    let mut data = vec![0_u8; 1200];
+    for (_, (offset, bytes)) in &chunks {
+        let offset = *offset as usize;
+        data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+    assert_eq!(data, pattern(0, 1200));
+    Ok(())
+}
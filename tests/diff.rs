@@ -0,0 +1,54 @@
+mod common;
+use common::create_file;
+use par_io::diff::{diff_files, Diff};
+
+fn data() -> Vec<u8> {
+    (0..10_000_u32).map(|i| (i % 241) as u8).collect()
+}
+
+#[test]
+fn identical_files() -> Result<(), String> {
+    let _a = create_file("tmp-diff_equal_a", &data());
+    let _b = create_file("tmp-diff_equal_b", &data());
+    let diff = diff_files("tmp-diff_equal_a", "tmp-diff_equal_b", 3, 2, 4, 2)
+        .map_err(|err| format!("{:?}", err))?;
+    assert_eq!(diff, Diff::Equal);
+    Ok(())
+}
+
+/// Differences are reported as ranges, including ranges spanning chunk
+/// boundaries.
+#[test]
+fn differing_bytes() -> Result<(), String> {
+    let mut other = data();
+    other[4321] ^= 1;
+    // 10000 bytes, 3 producers, 4 chunks per producer: 3334 is the start of
+    // the second producer region
+    for b in &mut other[3330..3340] {
+        *b ^= 0xFF;
+    }
+    let _a = create_file("tmp-diff_one_a", &data());
+    let _b = create_file("tmp-diff_one_b", &other);
+    let diff = diff_files("tmp-diff_one_a", "tmp-diff_one_b", 3, 2, 4, 2)
+        .map_err(|err| format!("{:?}", err))?;
+    assert_eq!(diff, Diff::Different(vec![3330..3340, 4321..4322]));
+    assert_eq!(diff.first_difference(), Some(3330));
+    Ok(())
+}
+
+#[test]
+fn different_sizes() -> Result<(), String> {
+    let _a = create_file("tmp-diff_size_a", &data());
+    let _b = create_file("tmp-diff_size_b", &data()[..9999]);
+    let diff = diff_files("tmp-diff_size_a", "tmp-diff_size_b", 3, 2, 4, 2)
+        .map_err(|err| format!("{:?}", err))?;
+    assert_eq!(
+        diff,
+        Diff::SizeMismatch {
+            a: 10_000,
+            b: 9_999
+        }
+    );
+    assert_eq!(diff.first_difference(), None);
+    Ok(())
+}
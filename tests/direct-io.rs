@@ -0,0 +1,50 @@
+#![cfg(target_os = "linux")]
+mod common;
+use common::DeleteFile;
+use par_io::write::{WriteBuilder, DIRECT_IO_ALIGNMENT};
+use std::sync::Arc;
+
+fn write_direct(filename: &str, total_size: usize, chunks_per_producer: u64) {
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<(), String> {
+        for (i, b) in buffer.iter_mut().enumerate() {
+            *b = ((offset as usize + i) % 251) as u8;
+        }
+        Ok(())
+    };
+    let written = WriteBuilder::new(filename)
+        .producers(2)
+        .consumers(2)
+        .chunks_per_producer(chunks_per_producer)
+        .total_size(total_size)
+        .direct_io(true)
+        .run(Arc::new(producer))
+        .expect("Error writing file");
+    assert_eq!(written, total_size);
+    let content = std::fs::read(filename).expect("Error reading file");
+    assert_eq!(
+        content,
+        (0..total_size).map(|i| (i % 251) as u8).collect::<Vec<_>>()
+    );
+}
+
+/// All chunks aligned.
+#[test]
+fn aligned_chunks() {
+    write_direct(
+        "tmp-direct_io_aligned_test",
+        8 * DIRECT_IO_ALIGNMENT as usize,
+        4,
+    );
+}
+
+/// The last chunk of each producer is not aligned and written through the
+/// page cache.
+#[test]
+fn unaligned_last_chunk() {
+    write_direct(
+        "tmp-direct_io_unaligned_test",
+        8 * DIRECT_IO_ALIGNMENT as usize + 300,
+        2,
+    );
+}
@@ -0,0 +1,73 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::read::{read_file, read_file_with_stats, ReadOptions};
+use par_io::stats::Stats;
+use par_io::write::{write_to_file, write_to_file_with_stats, WriteOptions};
+use std::sync::Arc;
+
+#[test]
+fn read_empty_file() {
+    let filename = "tmp-empty_file_read";
+    let _delete_file_at_exit = create_file(filename, &[]);
+    let consumer =
+        |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| buffer.len();
+    for (np, nc) in [(1, 1), (3, 2)] {
+        let v = read_file(filename, np, nc, 4, Arc::new(consumer), (), 2).expect("Read failed");
+        assert!(v.is_empty());
+    }
+}
+
+#[test]
+fn write_empty_file() {
+    let filename = "tmp-empty_file_write";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    // existing content is truncated
+    std::fs::write(filename, [1_u8; 100]).expect("Cannot create file");
+    let producer = |_buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<(), String> {
+        panic!("No chunk should be generated")
+    };
+    for (np, nc) in [(1, 1), (3, 2)] {
+        let written =
+            write_to_file(filename, np, nc, 4, Arc::new(producer), (), 2, 0).expect("Write failed");
+        assert_eq!(written, 0);
+        assert_eq!(std::fs::metadata(filename).expect("No file").len(), 0);
+    }
+}
+
+/// No worker threads are started for empty files.
+#[test]
+fn no_threads_for_empty_files() {
+    let filename = "tmp-empty_file_stats";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<(), String> {
+        buffer.fill(1);
+        Ok(())
+    };
+    let (written, stats) = write_to_file_with_stats(
+        filename,
+        2,
+        2,
+        2,
+        Arc::new(producer),
+        (),
+        2,
+        0,
+        WriteOptions::default(),
+    );
+    assert_eq!(written.expect("Write failed"), 0);
+    assert_eq!(stats, Stats::default());
+    let consumer =
+        |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| buffer.len();
+    let (read, stats) = read_file_with_stats(
+        filename,
+        2,
+        2,
+        2,
+        Arc::new(consumer),
+        (),
+        2,
+        ReadOptions::default(),
+    );
+    assert!(read.expect("Read failed").is_empty());
+    assert_eq!(stats, Stats::default());
+}
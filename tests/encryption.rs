@@ -0,0 +1,203 @@
+#![cfg(feature = "encryption")]
+mod common;
+use common::DeleteFile;
+use par_io::crypt::{decrypt, encrypt, encrypted_size, read_encrypted, write_encrypted};
+use par_io::read::ReadError;
+use par_io::write::WriteError;
+use std::sync::Arc;
+
+type Chunks = Vec<(u64, (u64, Vec<u8>))>;
+
+const KEY: [u8; 32] = [7; 32];
+
+fn write_data(filename: &str, size: usize, record_size: u64) -> Result<usize, String> {
+    let producer = |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<(), String> {
+        for (i, b) in buffer.iter_mut().enumerate() {
+            *b = ((offset + i as u64) % 251) as u8;
+        }
+        Ok(())
+    };
+    write_encrypted(
+        filename,
+        3,
+        2,
+        2,
+        Arc::new(producer),
+        (),
+        2,
+        size,
+        record_size,
+        KEY,
+        1,
+    )
+    .map_err(|err| format!("{:?}", err))
+}
+
+/// Return data read from encrypted file, sorted by offset.
+fn read_data(filename: &str, record_size: u64, key: [u8; 32]) -> Result<Vec<u8>, ReadError> {
+    let consumer = |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, offset: u64| {
+        (offset, buffer.to_vec())
+    };
+    let mut chunks: Chunks = read_encrypted(
+        filename,
+        2,
+        3,
+        2,
+        Arc::new(consumer),
+        (),
+        2,
+        record_size,
+        key,
+        1,
+    )?;
+    chunks.sort_by_key(|(_, (offset, _))| *offset);
+    Ok(chunks.into_iter().flat_map(|(_, (_, d))| d).collect())
+}
+
+/// RFC 8439 section 2.8.2 test vector.
+#[test]
+fn aead_test_vector() {
+    let key: Vec<u8> = (0x80..=0x9f).collect();
+    let key: [u8; 32] = key.try_into().unwrap();
+    let nonce = [7, 0, 0, 0, 0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47];
+    let aad = [
+        0x50, 0x51, 0x52, 0x53, 0xc0, 0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7,
+    ];
+    let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.".to_vec();
+    let mut data = plaintext.clone();
+    let tag = encrypt(&key, &nonce, &aad, &mut data);
+    assert_eq!(
+        data[..16],
+        [
+            0xd3, 0x1a, 0x8d, 0x34, 0x64, 0x8e, 0x60, 0xdb, 0x7b, 0x86, 0xaf, 0xbc, 0x53, 0xef,
+            0x7e, 0xc2
+        ]
+    );
+    assert_eq!(data[data.len() - 2..], [0x61, 0x16]);
+    assert_eq!(
+        tag,
+        [
+            0x1a, 0xe1, 0x0b, 0x59, 0x4f, 0x09, 0xe2, 0x6a, 0x7e, 0x90, 0x2e, 0xcb, 0xd0, 0x60,
+            0x06, 0x91
+        ]
+    );
+    let mut tampered = data.clone();
+    tampered[0] ^= 1;
+    assert!(!decrypt(&key, &nonce, &aad, &mut tampered, &tag));
+    assert!(decrypt(&key, &nonce, &aad, &mut data, &tag));
+    assert_eq!(data, plaintext);
+}
+
+#[test]
+fn round_trip() -> Result<(), String> {
+    let filename = "tmp-encryption_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    // last record shorter than the others
+    let written = write_data(filename, 1000, 64)?;
+    assert_eq!(written as u64, encrypted_size(1000, 64));
+    assert_eq!(
+        std::fs::metadata(filename)
+            .map_err(|e| e.to_string())?
+            .len(),
+        1000 + 16 * 16
+    );
+    let data = read_data(filename, 64, KEY).map_err(|err| format!("{:?}", err))?;
+    let expected: Vec<u8> = (0..1000_u64).map(|i| (i % 251) as u8).collect();
+    assert_eq!(data, expected);
+    // wrong key
+    assert!(matches!(
+        read_data(filename, 64, [8; 32]),
+        Err(ReadError::Authentication { .. })
+    ));
+    Ok(())
+}
+
+#[test]
+fn tamper() -> Result<(), String> {
+    let filename = "tmp-encryption_tamper_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    write_data(filename, 1000, 100)?;
+    let mut content = std::fs::read(filename).map_err(|e| e.to_string())?;
+    // flip one byte of the fourth record
+    content[3 * 116 + 10] ^= 0x80;
+    std::fs::write(filename, &content).map_err(|e| e.to_string())?;
+    match read_data(filename, 100, KEY) {
+        Err(ReadError::Authentication { offset }) => assert_eq!(offset, 300),
+        r => panic!(
+            "Expected authentication error, got {:?}",
+            r.map(|d| d.len())
+        ),
+    }
+    Ok(())
+}
+
+#[test]
+fn zero_counts() -> Result<(), String> {
+    let filename = "tmp-encryption_zero_counts_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<(), String> {
+        buffer.fill(1);
+        Ok(())
+    };
+    match write_encrypted(
+        filename,
+        2,
+        2,
+        0,
+        Arc::new(producer),
+        (),
+        2,
+        1000,
+        64,
+        KEY,
+        1,
+    ) {
+        Err(WriteError::Other(msg)) => assert_eq!(msg, "chunks_per_producer must be >= 1"),
+        r => panic!("Expected error, got {:?}", r),
+    }
+    write_data(filename, 1000, 64)?;
+    let consumer =
+        |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| buffer.len();
+    match read_encrypted(filename, 2, 2, 0, Arc::new(consumer), (), 2, 64, KEY, 1) {
+        Err(ReadError::Other(msg)) => assert_eq!(msg, "chunks_per_producer must be >= 1"),
+        r => panic!("Expected error, got {:?}", r),
+    }
+    Ok(())
+}
+
+/// Files truncated at a record boundary fail authentication.
+#[test]
+fn truncated() -> Result<(), String> {
+    let filename = "tmp-encryption_truncated_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    write_data(filename, 1000, 64)?;
+    let content = std::fs::read(filename).map_err(|e| e.to_string())?;
+    for num_records in [15, 5, 0] {
+        std::fs::write(filename, &content[..num_records * 80]).map_err(|e| e.to_string())?;
+        match read_data(filename, 64, KEY) {
+            Err(ReadError::Authentication { .. }) => {}
+            r => panic!(
+                "Expected authentication error with {} records, got {:?}",
+                num_records,
+                r.map(|d| d.len())
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Empty data are stored as one empty record.
+#[test]
+fn empty() -> Result<(), String> {
+    let filename = "tmp-encryption_empty_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    assert_eq!(write_data(filename, 0, 64)? as u64, encrypted_size(0, 64));
+    assert_eq!(encrypted_size(0, 64), 16);
+    let data = read_data(filename, 64, KEY).map_err(|err| format!("{:?}", err))?;
+    assert!(data.is_empty());
+    assert!(matches!(
+        read_data(filename, 64, [8; 32]),
+        Err(ReadError::Authentication { .. })
+    ));
+    Ok(())
+}
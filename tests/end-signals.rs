@@ -0,0 +1,143 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::read::{read_file_with_options, ReadOptions};
+use par_io::select::ConsumerSelector;
+use par_io::write::{write_to_file_with_options, WriteOptions};
+use std::sync::Arc;
+
+/// Route all the chunks to the first consumer, the others are never fed.
+struct First;
+
+impl ConsumerSelector for First {
+    fn select(&self, _: u64, _: usize, _: usize, _: usize, _: u64) -> usize {
+        0
+    }
+}
+
+fn data(size: usize) -> Vec<u8> {
+    (0..size).map(|i| (i % 253) as u8).collect()
+}
+
+fn read(
+    filename: &str,
+    num_producers: u64,
+    num_consumers: u64,
+    selector: Option<Arc<dyn ConsumerSelector>>,
+) -> Vec<(u64, Vec<u8>)> {
+    let consume = |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, offset: u64| {
+        (offset, buffer.to_vec())
+    };
+    let mut chunks = read_file_with_options(
+        filename,
+        num_producers,
+        num_consumers,
+        2,
+        Arc::new(consume),
+        (),
+        2,
+        ReadOptions {
+            selector,
+            ..Default::default()
+        },
+    )
+    .expect("Read failed");
+    chunks.sort_by_key(|(_, (offset, _))| *offset);
+    chunks.into_iter().map(|(_, c)| c).collect()
+}
+
+fn check_read(filename: &str, num_producers: u64, num_consumers: u64, first_only: bool) {
+    let selector: Option<Arc<dyn ConsumerSelector>> = if first_only {
+        Some(Arc::new(First))
+    } else {
+        None
+    };
+    let chunks = read(filename, num_producers, num_consumers, selector);
+    assert_eq!(chunks.len() as u64, num_producers * 2);
+    let bytes: Vec<u8> = chunks.into_iter().flat_map(|(_, c)| c).collect();
+    assert_eq!(bytes, data(7001));
+}
+
+fn check_write(filename: &str, num_producers: u64, num_consumers: u64, first_only: bool) {
+    let producer = |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<(), String> {
+        for (i, b) in buffer.iter_mut().enumerate() {
+            *b = ((offset as usize + i) % 253) as u8;
+        }
+        Ok(())
+    };
+    let selector: Option<Arc<dyn ConsumerSelector>> = if first_only {
+        Some(Arc::new(First))
+    } else {
+        None
+    };
+    let size = write_to_file_with_options(
+        filename,
+        num_producers,
+        num_consumers,
+        2,
+        Arc::new(producer),
+        (),
+        2,
+        7001,
+        WriteOptions {
+            selector,
+            ..Default::default()
+        },
+    )
+    .expect("Write failed");
+    assert_eq!(size, 7001);
+    assert_eq!(
+        std::fs::read(filename).expect("Cannot read file"),
+        data(7001)
+    );
+}
+
+/// More producers than consumers: consumers are fed by several producers.
+#[test]
+fn read_more_producers_than_consumers() {
+    let filename = "tmp-end_signals_read_many_producers";
+    let _delete_file_at_exit = create_file(filename, &data(7001));
+    for num_consumers in 1..4 {
+        check_read(filename, 7, num_consumers, false);
+    }
+}
+
+/// More consumers than producers: some consumers are never fed.
+#[test]
+fn read_more_consumers_than_producers() {
+    let filename = "tmp-end_signals_read_many_consumers";
+    let _delete_file_at_exit = create_file(filename, &data(7001));
+    check_read(filename, 1, 6, false);
+    check_read(filename, 2, 5, false);
+}
+
+/// All chunks routed to a single consumer.
+#[test]
+fn read_single_consumer_fed() {
+    let filename = "tmp-end_signals_read_first";
+    let _delete_file_at_exit = create_file(filename, &data(7001));
+    check_read(filename, 3, 4, true);
+}
+
+#[test]
+fn write_more_producers_than_consumers() {
+    let filename = "tmp-end_signals_write_many_producers";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    for num_consumers in 1..4 {
+        check_write(filename, 7, num_consumers, false);
+    }
+}
+
+#[test]
+fn write_more_consumers_than_producers() {
+    let filename = "tmp-end_signals_write_many_consumers";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    check_write(filename, 1, 6, false);
+    check_write(filename, 2, 5, false);
+}
+
+#[test]
+fn write_single_consumer_fed() {
+    let filename = "tmp-end_signals_write_first";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    check_write(filename, 3, 4, true);
+}
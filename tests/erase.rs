@@ -0,0 +1,35 @@
+mod common;
+use common::create_file;
+use par_io::erase::{erase_file, zero_file, Pass};
+
+#[test]
+fn zero() -> Result<(), String> {
+    let filename = "tmp-erase_zero_test";
+    let content: Vec<u8> = (0..1000_u64).map(|i| (i % 251) as u8 + 1).collect();
+    let _delete_file_at_exit = create_file(filename, &content);
+    let written = zero_file(filename, 3, 2, 2, 2).map_err(|err| format!("{:?}", err))?;
+    assert_eq!(written, 1000);
+    assert_eq!(
+        std::fs::read(filename).map_err(|e| e.to_string())?,
+        vec![0; 1000]
+    );
+    Ok(())
+}
+
+#[test]
+fn multi_pass() -> Result<(), String> {
+    let filename = "tmp-erase_multi_pass_test";
+    let _delete_file_at_exit = create_file(filename, &[7; 1000]);
+    let written = erase_file(filename, 2, 3, 2, 2, &[Pass::Zeros, Pass::Ones])
+        .map_err(|err| format!("{:?}", err))?;
+    assert_eq!(written, vec![1000, 1000]);
+    assert_eq!(
+        std::fs::read(filename).map_err(|e| e.to_string())?,
+        vec![0xFF; 1000]
+    );
+    erase_file(filename, 2, 3, 2, 2, &[Pass::Random]).map_err(|err| format!("{:?}", err))?;
+    let data = std::fs::read(filename).map_err(|e| e.to_string())?;
+    assert_eq!(data.len(), 1000);
+    assert!(data.iter().filter(|b| **b == 0xFF).count() < 100);
+    Ok(())
+}
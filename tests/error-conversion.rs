@@ -0,0 +1,42 @@
+use par_io::read::{read_to_vec, ReadError};
+use par_io::write::{write_slice_to_file, WriteError};
+use std::io::Write;
+
+/// `?` converts `std::io::Error` to `ReadError`.
+fn read_copy(filename: &str) -> Result<Vec<u8>, ReadError> {
+    let mut file = std::fs::File::create(filename)?;
+    file.write_all(&[5; 300])?;
+    let data = read_to_vec(filename, 2, 2, 2, 2);
+    std::fs::remove_file(filename)?;
+    data
+}
+
+/// `?` converts `std::io::Error` to `WriteError`.
+fn write_check(filename: &str) -> Result<Vec<u8>, WriteError> {
+    write_slice_to_file(filename, 2, 2, 2, &[6; 300], 2)?;
+    let data = std::fs::read(filename)?;
+    std::fs::remove_file(filename)?;
+    Ok(data)
+}
+
+#[test]
+fn read_error_from_io_error() {
+    assert_eq!(
+        read_copy("tmp-error_conversion_read").unwrap(),
+        vec![5; 300]
+    );
+    match read_copy("tmp-error_conversion_missing/file") {
+        Err(ReadError::IO(err)) => assert_eq!(err.kind(), std::io::ErrorKind::NotFound),
+        r => panic!("Unexpected result: {:?}", r),
+    }
+}
+
+#[test]
+fn write_error_from_io_error() {
+    assert_eq!(
+        write_check("tmp-error_conversion_write").unwrap(),
+        vec![6; 300]
+    );
+    let err: WriteError = std::io::Error::from(std::io::ErrorKind::NotFound).into();
+    assert!(matches!(err, WriteError::IO(_)));
+}
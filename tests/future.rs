@@ -0,0 +1,106 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::future::{read_file_async, write_to_file_async};
+use par_io::read::ReadOptions;
+use par_io::write::WriteOptions;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Minimal executor: poll the future, parking the thread until woken.
+fn block_on<F: Future>(f: F) -> F::Output {
+    let mut f = Box::pin(f);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(r) = f.as_mut().poll(&mut cx) {
+            return r;
+        }
+        thread::park();
+    }
+}
+
+#[test]
+fn write_then_read() {
+    let filename = "tmp-future_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<(), String> {
+        buffer.fill(3);
+        Ok(())
+    };
+    let write = write_to_file_async(
+        filename,
+        2,
+        2,
+        3,
+        Arc::new(producer),
+        (),
+        2,
+        9000,
+        WriteOptions::default(),
+    )
+    .expect("Cannot start write");
+    assert_eq!(block_on(write).expect("Write failed"), 9000);
+    let consumer = |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| {
+        buffer.iter().map(|b| *b as usize).sum::<usize>()
+    };
+    let read = read_file_async(
+        filename,
+        2,
+        2,
+        3,
+        Arc::new(consumer),
+        (),
+        2,
+        ReadOptions::default(),
+    )
+    .expect("Cannot start read");
+    let v = block_on(read).expect("Read failed");
+    assert_eq!(v.iter().map(|(_, s)| s).sum::<usize>(), 27_000);
+}
+
+/// The future is pending until the read completes.
+#[test]
+fn pending_until_complete() {
+    let filename = "tmp-future_pending_test";
+    let _delete_file_at_exit = create_file(filename, &[1_u8; 100]);
+    let (tx, rx) = channel::<()>();
+    // consumers wait for the test to allow them to proceed
+    let rx = Arc::new(Mutex::new(rx));
+    let consumer = |buffer: &[u8],
+                    rx: &Arc<Mutex<std::sync::mpsc::Receiver<()>>>,
+                    _c: u64,
+                    _n: u64,
+                    _o: u64| {
+        let _ = rx.lock().unwrap().recv();
+        buffer.len()
+    };
+    let mut read = read_file_async(
+        filename,
+        1,
+        1,
+        1,
+        Arc::new(consumer),
+        rx,
+        1,
+        ReadOptions::default(),
+    )
+    .expect("Cannot start read");
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    assert!(Pin::new(&mut read).poll(&mut cx).is_pending());
+    tx.send(()).unwrap();
+    let v = block_on(read).expect("Read failed");
+    assert_eq!(v, vec![(1, 100)]);
+}
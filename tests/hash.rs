@@ -0,0 +1,65 @@
+mod common;
+use common::create_file;
+use par_io::hash::{crc32c, crc32c_combine, digest, read_file_hashed, Digest, HashAlgorithm};
+use par_io::read::ReadOptions;
+use std::sync::Arc;
+
+#[test]
+fn known_vectors() {
+    assert_eq!(crc32c(b"123456789"), 0xE306_9283);
+    let expected = [
+        0xba, 0x78, 0x16, 0xbf, 0x8f, 0x01, 0xcf, 0xea, 0x41, 0x41, 0x40, 0xde, 0x5d, 0xae, 0x22,
+        0x23, 0xb0, 0x03, 0x61, 0xa3, 0x96, 0x17, 0x7a, 0x9c, 0xb4, 0x10, 0xff, 0x61, 0xf2, 0x00,
+        0x15, 0xad,
+    ];
+    assert_eq!(
+        digest(HashAlgorithm::Sha256, b"abc"),
+        Digest::Sha256(expected)
+    );
+    let data: Vec<u8> = (0..1000_u32).map(|i| (i * 7) as u8).collect();
+    let (a, b) = data.split_at(337);
+    assert_eq!(
+        crc32c_combine(crc32c(a), crc32c(b), b.len() as u64),
+        crc32c(&data)
+    );
+}
+
+fn check_read_digest(algorithm: HashAlgorithm, skip_header: u64) {
+    let filename = format!("tmp-hash_{:?}_{}_test", algorithm, skip_header);
+    let data: Vec<u8> = (0..100_003_u32).map(|i| (i % 251) as u8).collect();
+    let _delete_file_at_exit = create_file(&filename, &data);
+    let consume =
+        |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| buffer.len();
+    let (results, d) = read_file_hashed(
+        &filename,
+        4,
+        3,
+        5,
+        Arc::new(consume),
+        (),
+        2,
+        ReadOptions {
+            skip_header,
+            ..Default::default()
+        },
+        algorithm,
+    )
+    .expect("Error reading file");
+    assert_eq!(
+        results.iter().map(|(_, n)| n).sum::<usize>(),
+        data.len() - skip_header as usize
+    );
+    assert_eq!(d, digest(algorithm, &data[skip_header as usize..]));
+}
+
+#[test]
+fn crc32c_of_file() {
+    check_read_digest(HashAlgorithm::Crc32c, 0);
+    check_read_digest(HashAlgorithm::Crc32c, 17);
+}
+
+#[test]
+fn sha256_of_file() {
+    check_read_digest(HashAlgorithm::Sha256, 0);
+    check_read_digest(HashAlgorithm::Sha256, 17);
+}
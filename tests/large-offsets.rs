@@ -0,0 +1,62 @@
+#![cfg(unix)]
+mod common;
+use common::DeleteFile;
+use par_io::read::read_file_range;
+use par_io::write::{write_to_file_with_options, WriteError, WriteOptions};
+use std::sync::Arc;
+
+const FAR: u64 = 5 << 30;
+
+/// Write 2 chunks of 1000 bytes, the second one at `offset`.
+fn write_second_at(filename: &str, offset: u64) -> Result<usize, WriteError> {
+    write_to_file_with_options(
+        filename,
+        1,
+        1,
+        2,
+        Arc::new(|b: &mut Vec<u8>, _: &(), offset| -> Result<(), String> {
+            b.fill(1 + (offset / 1000) as u8);
+            Ok(())
+        }),
+        (),
+        1,
+        2000,
+        WriteOptions {
+            offset_map: Some(Arc::new(move |o| if o == 0 { 0 } else { offset })),
+            detect_overlaps: false,
+            ..Default::default()
+        },
+    )
+}
+
+/// Offsets past 4 GiB are neither truncated nor wrapped around.
+#[test]
+fn write_and_read_past_4_gib() {
+    let filename = "tmp-large_offsets";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    assert_eq!(write_second_at(filename, FAR).expect("Write failed"), 2000);
+    assert_eq!(
+        std::fs::metadata(filename).expect("No file").len(),
+        FAR + 1000
+    );
+    let consume = |buffer: &[u8], _: &(), _: u64, _: u64, _: u64| buffer.to_vec();
+    let chunks = read_file_range(filename, FAR, FAR + 1000, 2, 1, 2, Arc::new(consume), (), 1)
+        .expect("Read failed");
+    let data: Vec<u8> = chunks.into_iter().flat_map(|(_, c)| c).collect();
+    assert_eq!(data, vec![2; 1000]);
+    let head =
+        read_file_range(filename, 0, 1000, 1, 1, 1, Arc::new(consume), (), 1).expect("Read failed");
+    assert_eq!(head[0].1, vec![1; 1000]);
+}
+
+/// Writes ending past the largest file offset fail without invoking the
+/// system call.
+#[test]
+fn offset_out_of_range() {
+    let filename = "tmp-large_offsets_out_of_range";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    match write_second_at(filename, i64::MAX as u64 - 10) {
+        Err(WriteError::Consumer(err)) => assert!(err.msg.contains("out of range"), "{}", err.msg),
+        r => panic!("Unexpected result: {:?}", r),
+    }
+}
@@ -0,0 +1,83 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::latency::{Histogram, LatencyReport};
+use par_io::read::{chunk_count, read_file_with_options, ReadOptions};
+use par_io::write::{write_to_file_with_options, WriteOptions};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Percentiles are within the histogram precision.
+#[test]
+fn histogram_percentiles() {
+    let mut h = Histogram::new();
+    for i in 1..=1000_u64 {
+        h.record(Duration::from_micros(i));
+    }
+    assert_eq!(h.count(), 1000);
+    assert_eq!(h.min(), Duration::from_micros(1));
+    assert_eq!(h.max(), Duration::from_micros(1000));
+    for (p, v) in [(50.0, 500.0), (95.0, 950.0), (99.0, 990.0)] {
+        let r = h.percentile(p).as_secs_f64() * 1e6;
+        assert!((r - v).abs() / v < 0.07, "p{} = {}", p, r);
+    }
+    let mut m = Histogram::new();
+    m.merge(&h);
+    m.merge(&h);
+    assert_eq!(m.count(), 2000);
+    assert_eq!(m.percentile(50.0), h.percentile(50.0));
+}
+
+/// One sample is recorded for each chunk read and written.
+#[test]
+fn one_sample_per_chunk() -> Result<(), String> {
+    let data = vec![7_u8; 10_000];
+    let filename = "tmp-latency_test";
+    let _delete_file_at_exit = create_file(filename, &data);
+    let report = Arc::new(LatencyReport::new());
+    let consume =
+        |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| buffer.len();
+    read_file_with_options(
+        filename,
+        3,
+        2,
+        4,
+        Arc::new(consume),
+        (),
+        2,
+        ReadOptions {
+            latency_report: Some(report.clone()),
+            ..Default::default()
+        },
+    )
+    .map_err(|err| format!("{:?}", err))?;
+    let s = report.summary();
+    assert_eq!(s.count as usize, chunk_count(data.len() as u64, 3, 4));
+    assert!(s.min <= s.p50 && s.p50 <= s.p95 && s.p95 <= s.p99 && s.p99 <= s.max);
+
+    let out = "tmp-latency_write_test";
+    let _delete_out_at_exit = DeleteFile(out.to_string());
+    let report = Arc::new(LatencyReport::new());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<(), String> {
+        buffer.fill(1);
+        Ok(())
+    };
+    write_to_file_with_options(
+        out,
+        2,
+        2,
+        3,
+        Arc::new(producer),
+        (),
+        2,
+        6000,
+        WriteOptions {
+            latency_report: Some(report.clone()),
+            ..Default::default()
+        },
+    )
+    .map_err(|err| format!("{:?}", err))?;
+    let s = report.summary();
+    assert_eq!(s.count, 6);
+    assert!(s.min <= s.p50 && s.p50 <= s.p95 && s.p95 <= s.p99 && s.p99 <= s.max);
+    Ok(())
+}
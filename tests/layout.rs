@@ -0,0 +1,163 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::layout::plan;
+use par_io::read::read_file;
+use par_io::write::write_to_file;
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn even_split() {
+    let layout = plan(1200, 3, 4);
+    assert_eq!(layout.producer_chunk_size, 400);
+    assert_eq!(layout.last_producer_chunk_size, 400);
+    assert_eq!(layout.task_chunk_size, 100);
+    assert_eq!(layout.last_task_chunk_size, 100);
+    assert_eq!(layout.last_producer_task_chunk_size, 100);
+    assert_eq!(layout.last_producer_last_task_chunk_size, 100);
+    assert_eq!(layout.num_chunks, 12);
+    assert_eq!(layout.buffer_size(), 100);
+    assert_eq!(layout.num_buffers(2), 6);
+    assert_eq!(layout.num_buffers(10), 12);
+    assert_eq!(layout.buffer_memory(2), 600);
+}
+
+#[test]
+fn uneven_split() {
+    let layout = plan(1001, 3, 2);
+    assert_eq!(layout.producer_chunk_size, 334);
+    assert_eq!(layout.last_producer_chunk_size, 333);
+    assert_eq!(layout.task_chunk_size, 167);
+    assert_eq!(layout.last_task_chunk_size, 167);
+    assert_eq!(layout.last_producer_task_chunk_size, 167);
+    assert_eq!(layout.last_producer_last_task_chunk_size, 166);
+    assert_eq!(layout.num_chunks, 6);
+}
+
+/// With fewer bytes than chunks some producers generate no chunk.
+#[test]
+fn fewer_bytes_than_chunks() {
+    let layout = plan(5, 4, 2);
+    assert_eq!(layout.producer_chunk_size, 2);
+    assert_eq!(layout.last_producer_chunk_size, 0);
+    assert_eq!(layout.num_chunks, 5);
+    let regions: Vec<(u64, u64)> = (0..4)
+        .map(|i| (layout.region_offset(i), layout.region_size(i)))
+        .collect();
+    assert_eq!(regions, vec![(0, 2), (2, 2), (4, 1), (5, 0)]);
+}
+
+/// More chunks per producer than bytes per producer: chunks are one byte
+/// long and fewer chunks are generated.
+#[test]
+fn more_chunks_than_bytes() {
+    let layout = plan(10, 2, 100);
+    assert_eq!(layout.task_chunk_size, 1);
+    assert_eq!(layout.last_task_chunk_size, 0);
+    assert_eq!(layout.num_chunks, 10);
+    let filename = "tmp-layout_more_chunks";
+    let _delete_file_at_exit = create_file(filename, &[1; 10]);
+    let consumer = |buffer: &[u8], _data: &(), _id: u64, _n: u64, _offset: u64| buffer.len();
+    let chunks = read_file(filename, 2, 2, 100, Arc::new(consumer), (), 2).expect("Read failed");
+    assert_eq!(chunks.len(), 10);
+    assert!(chunks.iter().all(|(_, len)| *len == 1));
+}
+
+/// Chunks read match the planned layout.
+#[test]
+fn matches_read() {
+    let filename = "tmp-layout_read";
+    let _delete_file_at_exit = create_file(filename, &[1; 1001]);
+    let sizes = Arc::new(Mutex::new(Vec::new()));
+    let s = sizes.clone();
+    let consumer = move |buffer: &[u8], _data: &(), _id: u64, _n: u64, offset: u64| {
+        s.lock().unwrap().push((offset, buffer.len() as u64));
+    };
+    read_file(filename, 3, 2, 2, Arc::new(consumer), (), 2).expect("Read failed");
+    let mut sizes = sizes.lock().unwrap().clone();
+    sizes.sort_unstable();
+    let layout = plan(1001, 3, 2);
+    assert_eq!(sizes.len() as u64, layout.num_chunks);
+    assert_eq!(sizes[0].1, layout.task_chunk_size);
+    assert_eq!(sizes[1].1, layout.last_task_chunk_size);
+    assert_eq!(sizes[4].1, layout.last_producer_task_chunk_size);
+    assert_eq!(sizes[5].1, layout.last_producer_last_task_chunk_size);
+}
+
+/// Chunks written match the planned layout.
+#[test]
+fn matches_write() {
+    let filename = "tmp-layout_write";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let sizes = Arc::new(Mutex::new(Vec::new()));
+    let s = sizes.clone();
+    let producer = move |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<(), String> {
+        s.lock().unwrap().push((offset, buffer.len() as u64));
+        Ok(())
+    };
+    write_to_file(filename, 3, 2, 2, Arc::new(producer), (), 2, 1001).expect("Write failed");
+    let mut sizes = sizes.lock().unwrap().clone();
+    sizes.sort_unstable();
+    let layout = plan(1001, 3, 2);
+    assert_eq!(sizes.len() as u64, layout.num_chunks);
+    assert_eq!(sizes[0].1, layout.task_chunk_size);
+    assert_eq!(sizes[1].1, layout.last_task_chunk_size);
+    assert_eq!(sizes[4].1, layout.last_producer_task_chunk_size);
+    assert_eq!(sizes[5].1, layout.last_producer_last_task_chunk_size);
+}
+
+/// Chunks written cover the whole file without overlapping, for uneven
+/// sizes and counts.
+#[test]
+fn write_covers_total_size() {
+    let filename = "tmp-layout_coverage";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    for &(total_size, num_producers, chunks_per_producer) in &[
+        (1000, 3, 3),
+        (1001, 4, 2),
+        (997, 7, 5),
+        (64, 2, 8),
+        (10_007, 5, 3),
+        // more chunks than bytes per producer
+        (10, 2, 100),
+        (11, 5, 5),
+        // empty regions past the end of the data
+        (1, 3, 2),
+        (7, 5, 1),
+        (10, 12, 3),
+    ] {
+        let chunks = Arc::new(Mutex::new(Vec::new()));
+        let c = chunks.clone();
+        let producer = move |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<(), String> {
+            c.lock().unwrap().push((offset, buffer.len() as u64));
+            Ok(())
+        };
+        let written = write_to_file(
+            filename,
+            num_producers,
+            2,
+            chunks_per_producer,
+            Arc::new(producer),
+            (),
+            2,
+            total_size,
+        )
+        .expect("Write failed");
+        assert_eq!(written, total_size);
+        let mut chunks = chunks.lock().unwrap().clone();
+        chunks.sort_unstable();
+        let mut end = 0;
+        for (offset, size) in chunks {
+            assert_eq!(offset, end);
+            assert!(size > 0);
+            end += size;
+        }
+        assert_eq!(end, total_size as u64);
+        let layout = plan(total_size as u64, num_producers, chunks_per_producer);
+        assert_eq!(
+            (0..num_producers)
+                .map(|i| layout.region_size(i))
+                .sum::<u64>(),
+            total_size as u64
+        );
+    }
+}
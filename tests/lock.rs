@@ -0,0 +1,106 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::lock::{try_lock, LockPolicy};
+use par_io::read::{read_file_with_options, ReadError, ReadOptions};
+use par_io::write::{write_to_file_with_options, WriteError, WriteOptions};
+use std::fs::File;
+use std::sync::Arc;
+
+fn write(filename: &str, lock: LockPolicy) -> Result<usize, WriteError> {
+    let producer = |buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<(), String> {
+        buffer.iter_mut().for_each(|b| *b = 1);
+        Ok(())
+    };
+    write_to_file_with_options(
+        filename,
+        2,
+        2,
+        2,
+        Arc::new(producer),
+        (),
+        2,
+        100,
+        WriteOptions {
+            lock,
+            ..Default::default()
+        },
+    )
+}
+
+fn read(filename: &str, lock: LockPolicy) -> Result<usize, ReadError> {
+    let consumer = |buffer: &[u8], _data: &(), _chunk_id, _num_chunks, _offset| buffer.len();
+    let chunks = read_file_with_options(
+        filename,
+        2,
+        2,
+        2,
+        Arc::new(consumer),
+        (),
+        2,
+        ReadOptions {
+            lock,
+            ..Default::default()
+        },
+    )?;
+    Ok(chunks.iter().map(|(_, n)| n).sum())
+}
+
+#[test]
+fn second_exclusive_lock_fails() -> std::io::Result<()> {
+    let filename = "tmp-lock_test";
+    let _delete_file_at_exit = create_file(filename, &[0; 10]);
+    let first = File::open(filename)?;
+    assert!(try_lock(&first, LockPolicy::Exclusive)?);
+    let second = File::open(filename)?;
+    assert!(!try_lock(&second, LockPolicy::Exclusive)?);
+    assert!(!try_lock(&second, LockPolicy::Shared)?);
+    assert!(try_lock(&second, LockPolicy::NoLock)?);
+    // released when the file is closed
+    drop(first);
+    assert!(try_lock(&second, LockPolicy::Exclusive)?);
+    Ok(())
+}
+
+#[test]
+fn shared_locks() -> std::io::Result<()> {
+    let filename = "tmp-lock_shared_test";
+    let _delete_file_at_exit = create_file(filename, &[0; 10]);
+    let first = File::open(filename)?;
+    assert!(try_lock(&first, LockPolicy::Shared)?);
+    let second = File::open(filename)?;
+    assert!(try_lock(&second, LockPolicy::Shared)?);
+    let third = File::open(filename)?;
+    assert!(!try_lock(&third, LockPolicy::Exclusive)?);
+    Ok(())
+}
+
+#[test]
+fn write_and_read_locked_file() -> Result<(), String> {
+    let filename = "tmp-lock_write_read_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    assert_eq!(
+        write(filename, LockPolicy::Exclusive).map_err(|e| format!("{:?}", e))?,
+        100
+    );
+    assert_eq!(
+        read(filename, LockPolicy::Shared).map_err(|e| format!("{:?}", e))?,
+        100
+    );
+    let holder = File::open(filename).map_err(|e| e.to_string())?;
+    assert!(try_lock(&holder, LockPolicy::Exclusive).map_err(|e| e.to_string())?);
+    match write(filename, LockPolicy::Exclusive) {
+        Err(WriteError::Locked { filename: f }) => assert_eq!(f, filename),
+        r => panic!("Expected lock error, got {:?}", r),
+    }
+    match read(filename, LockPolicy::Shared) {
+        Err(ReadError::Locked { filename: f }) => assert_eq!(f, filename),
+        r => panic!("Expected lock error, got {:?}", r),
+    }
+    // locked file not truncated
+    drop(holder);
+    assert_eq!(
+        std::fs::read(filename).map_err(|e| e.to_string())?,
+        vec![1; 100]
+    );
+    Ok(())
+}
@@ -0,0 +1,122 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::buffer::BufferAlloc;
+use par_io::read::{read_file_with_options, ReadOptions};
+use par_io::stats::StatsReport;
+use par_io::write::{write_to_file_with_options, WriteOptions};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Allocator counting the allocated buffers.
+#[derive(Default)]
+struct Counting {
+    count: AtomicU64,
+}
+
+impl BufferAlloc for Counting {
+    fn alloc(&self, capacity: usize) -> Vec<u8> {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        Vec::with_capacity(capacity)
+    }
+}
+
+/// Read 8000 bytes in chunks of 1000 bytes with 2 producers, return the
+/// number of buffers allocated and whether the cap was exceeded.
+fn read_capped(filename: &str, max_memory_bytes: u64) -> (u64, bool) {
+    let alloc = Arc::new(Counting::default());
+    let stats = Arc::new(StatsReport::new());
+    let consumer =
+        |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| buffer.len();
+    let bytes: usize = read_file_with_options(
+        filename,
+        2,
+        2,
+        4,
+        Arc::new(consumer),
+        (),
+        1,
+        ReadOptions {
+            allocator: Some(alloc.clone()),
+            stats: Some(stats.clone()),
+            max_memory_bytes: Some(max_memory_bytes),
+            ..Default::default()
+        },
+    )
+    .expect("Read failed")
+    .iter()
+    .map(|(_, n)| n)
+    .sum();
+    assert_eq!(bytes, 8000);
+    (
+        alloc.count.load(Ordering::SeqCst),
+        stats.stats().memory_cap_exceeded,
+    )
+}
+
+/// Same as `read_capped` for writes.
+fn write_capped(filename: &str, max_memory_bytes: u64) -> (u64, bool) {
+    let alloc = Arc::new(Counting::default());
+    let stats = Arc::new(StatsReport::new());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<(), String> {
+        buffer.fill(3);
+        Ok(())
+    };
+    let bytes = write_to_file_with_options(
+        filename,
+        2,
+        2,
+        4,
+        Arc::new(producer),
+        (),
+        1,
+        8000,
+        WriteOptions {
+            allocator: Some(alloc.clone()),
+            stats: Some(stats.clone()),
+            max_memory_bytes: Some(max_memory_bytes),
+            ..Default::default()
+        },
+    )
+    .expect("Write failed");
+    assert_eq!(bytes, 8000);
+    assert_eq!(
+        std::fs::read(filename).expect("Cannot read file"),
+        vec![3; 8000]
+    );
+    (
+        alloc.count.load(Ordering::SeqCst),
+        stats.stats().memory_cap_exceeded,
+    )
+}
+
+#[test]
+fn read_buffers_from_memory_cap() {
+    let filename = "tmp-memory_cap_read";
+    let _delete_file_at_exit = create_file(filename, &[1; 8000]);
+    assert_eq!(read_capped(filename, 4000), (4, false));
+    assert_eq!(read_capped(filename, 6500), (6, false));
+    // never more than one buffer per chunk
+    assert_eq!(read_capped(filename, 100_000), (8, false));
+}
+
+#[test]
+fn read_cap_too_low() {
+    let filename = "tmp-memory_cap_read_low";
+    let _delete_file_at_exit = create_file(filename, &[1; 8000]);
+    assert_eq!(read_capped(filename, 500), (2, true));
+}
+
+#[test]
+fn write_buffers_from_memory_cap() {
+    let filename = "tmp-memory_cap_write";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    assert_eq!(write_capped(filename, 4000), (4, false));
+    assert_eq!(write_capped(filename, 100_000), (8, false));
+}
+
+#[test]
+fn write_cap_too_low() {
+    let filename = "tmp-memory_cap_write_low";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    assert_eq!(write_capped(filename, 500), (2, true));
+}
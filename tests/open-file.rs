@@ -0,0 +1,99 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::read::{read_from_file, ReadOptions};
+use par_io::write::{write_to_open_file, WriteOptions};
+use std::fs::File;
+use std::sync::Arc;
+
+#[test]
+fn read_open_file() {
+    let filename = "tmp-open_file_read";
+    let data: Vec<u8> = (0..10_000).map(|i| (i % 251) as u8).collect();
+    let _delete_file_at_exit = create_file(filename, &data);
+    let file = File::open(filename).expect("Cannot open file");
+    let consumer = |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, offset: u64| {
+        (offset, buffer.to_vec())
+    };
+    let mut chunks = read_from_file(
+        &file,
+        3,
+        2,
+        2,
+        Arc::new(consumer),
+        (),
+        2,
+        ReadOptions::default(),
+    )
+    .expect("Read failed");
+    chunks.sort_by_key(|(_, (offset, _))| *offset);
+    let read: Vec<u8> = chunks.into_iter().flat_map(|(_, (_, v))| v).collect();
+    assert_eq!(read, data);
+}
+
+/// Data is written at `data_offset` and the content after the data is kept.
+#[test]
+fn write_open_file() {
+    let filename = "tmp-open_file_write";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    std::fs::write(filename, vec![9; 3000]).expect("Cannot create file");
+    let file = File::options()
+        .write(true)
+        .open(filename)
+        .expect("Cannot open file");
+    let producer = |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<(), String> {
+        buffer.fill((offset / 500) as u8);
+        Ok(())
+    };
+    let written = write_to_open_file(
+        &file,
+        2,
+        2,
+        2,
+        Arc::new(producer),
+        (),
+        2,
+        2000,
+        WriteOptions {
+            data_offset: 100,
+            ..Default::default()
+        },
+    )
+    .expect("Write failed");
+    assert_eq!(written, 2000);
+    let data = std::fs::read(filename).expect("Cannot read file");
+    assert_eq!(data.len(), 3000);
+    assert_eq!(data[..100], [9; 100]);
+    for i in 0..2000 {
+        assert_eq!(data[100 + i], (i / 500) as u8);
+    }
+    assert_eq!(data[2100..], [9; 900]);
+}
+
+/// The file is extended to hold the data.
+#[test]
+fn write_extends_open_file() {
+    let filename = "tmp-open_file_extend";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let file = File::create(filename).expect("Cannot create file");
+    let producer = |buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<(), String> {
+        buffer.fill(4);
+        Ok(())
+    };
+    let written = write_to_open_file(
+        &file,
+        2,
+        1,
+        3,
+        Arc::new(producer),
+        (),
+        2,
+        5000,
+        WriteOptions::default(),
+    )
+    .expect("Write failed");
+    assert_eq!(written, 5000);
+    assert_eq!(
+        std::fs::read(filename).expect("Cannot read file"),
+        vec![4; 5000]
+    );
+}
@@ -0,0 +1,46 @@
+mod common;
+use common::create_file;
+use par_io::ordered::read_ordered;
+use par_io::read::ReadError;
+use std::io::Write;
+
+/// Output is byte-exact regardless of the order chunks are consumed in.
+#[test]
+fn ordered_output() {
+    let filename = "tmp-ordered_test";
+    let data: Vec<u8> = (0..10_007_u32).map(|i| (i * 7 % 256) as u8).collect();
+    let _delete_file_at_exit = create_file(filename, &data);
+    for (np, nc) in [(1, 1), (3, 2), (4, 5)] {
+        let mut sink = Vec::new();
+        let n = read_ordered(filename, np, nc, 5, 2, &mut sink).expect("Error reading file");
+        assert_eq!(n, data.len());
+        assert_eq!(sink, data);
+    }
+}
+
+/// Sink accepting a limited number of bytes.
+struct Limited(usize);
+
+impl Write for Limited {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.len() > self.0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::Other, "sink full"));
+        }
+        self.0 -= buf.len();
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn sink_error() {
+    let filename = "tmp-ordered_error_test";
+    let _delete_file_at_exit = create_file(filename, &[1_u8; 1000]);
+    let mut sink = Limited(500);
+    assert!(matches!(
+        read_ordered(filename, 2, 2, 4, 2, &mut sink),
+        Err(ReadError::IO(_))
+    ));
+}
@@ -0,0 +1,60 @@
+mod common;
+use common::DeleteFile;
+use par_io::write::{write_to_file_with_options, WriteError, WriteOptions};
+use std::sync::Arc;
+
+fn write(filename: &str, options: WriteOptions) -> Result<usize, WriteError> {
+    let producer = |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<(), String> {
+        buffer.fill((offset / 100) as u8);
+        Ok(())
+    };
+    // 8 chunks of 100 bytes
+    write_to_file_with_options(filename, 2, 2, 4, Arc::new(producer), (), 2, 800, options)
+}
+
+/// Chunks mapped in reverse order do not overlap.
+#[test]
+fn reversed_map() -> Result<(), String> {
+    let filename = "tmp-overlap_reversed_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let written = write(
+        filename,
+        WriteOptions {
+            offset_map: Some(Arc::new(|offset| 700 - offset)),
+            detect_overlaps: true,
+            ..Default::default()
+        },
+    )
+    .map_err(|err| format!("{:?}", err))?;
+    assert_eq!(written, 800);
+    let content = std::fs::read(filename).map_err(|err| err.to_string())?;
+    let expected: Vec<u8> = (0..800).map(|i| (7 - i / 100) as u8).collect();
+    assert_eq!(content, expected);
+    Ok(())
+}
+
+/// Halving offsets makes consecutive chunks overlap.
+#[test]
+fn overlapping_map() {
+    let filename = "tmp-overlap_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let options = WriteOptions {
+        offset_map: Some(Arc::new(|offset| offset / 2)),
+        detect_overlaps: true,
+        ..Default::default()
+    };
+    match write(filename, options.clone()) {
+        Err(WriteError::Other(msg)) => assert!(msg.contains("overlaps"), "{}", msg),
+        r => panic!("Expected overlap error, got {:?}", r),
+    }
+    // not detected by default
+    let written = write(
+        filename,
+        WriteOptions {
+            offset_map: options.offset_map,
+            ..Default::default()
+        },
+    )
+    .expect("Error writing file");
+    assert_eq!(written, 800);
+}
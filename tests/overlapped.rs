@@ -0,0 +1,35 @@
+#![cfg(all(feature = "overlapped", windows))]
+use par_io::read::{ReadBuilder, ReadOptions};
+use std::sync::Arc;
+
+#[test]
+fn read_through_overlapped_io() {
+    let filename = std::env::temp_dir()
+        .join("par_io_overlapped")
+        .to_str()
+        .unwrap()
+        .to_string();
+    let data: Vec<u8> = (0..100_003_u32).map(|i| (i % 251) as u8).collect();
+    std::fs::write(&filename, &data).unwrap();
+    let expected = Arc::new(data);
+    let consume = move |buffer: &[u8], expected: &Arc<Vec<u8>>, _: u64, _: u64, offset: u64| {
+        let start = offset as usize;
+        buffer == &expected[start..start + buffer.len()]
+    };
+    // small segments to keep many reads per chunk in flight
+    let results = ReadBuilder::new(&filename)
+        .producers(3)
+        .consumers(2)
+        .chunks_per_producer(4)
+        .client_data(expected)
+        .options(ReadOptions {
+            max_io_size: Some(1000),
+            ..Default::default()
+        })
+        .overlapped(true)
+        .run(Arc::new(consume))
+        .unwrap();
+    std::fs::remove_file(&filename).unwrap();
+    assert_eq!(results.len(), 12);
+    assert!(results.iter().all(|(_, ok)| *ok));
+}
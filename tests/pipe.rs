@@ -0,0 +1,126 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::pipe::{pipe, transform_file, PipeConfig, PipeError};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+fn hash_file(filename: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    std::fs::read(filename)
+        .expect("Cannot read file")
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Pipe a file through the identity transform and verify that source and
+/// destination hashes match.
+#[test]
+fn identity_transform() -> Result<(), String> {
+    let data: Vec<u8> = (0..100_000_u32).map(|i| (i % 251) as u8).collect();
+    let src = "tmp-pipe_src_test";
+    let dst = "tmp-pipe_dst_test";
+    let _delete_src_at_exit = create_file(src, &data);
+    let _delete_dst_at_exit = DeleteFile(dst.to_string());
+    let config = PipeConfig {
+        num_producers: 3,
+        num_consumers: 2,
+        chunks_per_producer: 5,
+        num_buffers_per_producer: 2,
+        num_writers: 2,
+        channel_capacity: 1,
+    };
+    let written = pipe(src, dst, Arc::new(|b: &[u8], _offset| b.to_vec()), config)
+        .map_err(|err| format!("{:?}", err))?;
+    assert_eq!(written, data.len());
+    assert_eq!(hash_file(src), hash_file(dst));
+    Ok(())
+}
+
+/// Transforms changing the chunk size are rejected.
+#[test]
+fn size_mismatch() {
+    let src = "tmp-pipe_size_src_test";
+    let dst = "tmp-pipe_size_dst_test";
+    let _delete_src_at_exit = create_file(src, &[1_u8; 1000]);
+    let _delete_dst_at_exit = DeleteFile(dst.to_string());
+    match pipe(
+        src,
+        dst,
+        Arc::new(|b: &[u8], _offset| b[1..].to_vec()),
+        PipeConfig::default(),
+    ) {
+        Err(PipeError::Size {
+            expected, actual, ..
+        }) => assert_eq!(expected, actual + 1),
+        r => panic!("Expected size error, got {:?}", r),
+    }
+}
+
+/// Transform chunks in place, the destination matches the transformed
+/// source.
+#[test]
+fn transform_in_place() {
+    let data: Vec<u8> = (0..100_000_u32).map(|i| (i % 251) as u8).collect();
+    let src = "tmp-transform_src_test";
+    let dst = "tmp-transform_dst_test";
+    let _delete_src_at_exit = create_file(src, &data);
+    let _delete_dst_at_exit = DeleteFile(dst.to_string());
+    let transform = |b: &mut [u8], _offset: u64| {
+        b.iter_mut().for_each(|x| *x = x.wrapping_add(1));
+        b.len()
+    };
+    let written =
+        transform_file(src, dst, Arc::new(transform), PipeConfig::default()).expect("Failed");
+    assert_eq!(written, data.len());
+    let expected: Vec<u8> = data.iter().map(|x| x.wrapping_add(1)).collect();
+    assert_eq!(std::fs::read(dst).expect("Cannot read file"), expected);
+}
+
+/// Bytes after the size returned by the transform read as zero.
+#[test]
+fn transform_shorter_chunks() {
+    let src = "tmp-transform_short_src_test";
+    let dst = "tmp-transform_short_dst_test";
+    let _delete_src_at_exit = create_file(src, &[1_u8; 1000]);
+    let _delete_dst_at_exit = DeleteFile(dst.to_string());
+    let config = PipeConfig {
+        num_producers: 2,
+        chunks_per_producer: 2,
+        ..Default::default()
+    };
+    let written = transform_file(
+        src,
+        dst,
+        Arc::new(|b: &mut [u8], _offset| b.len() - 50),
+        config,
+    )
+    .expect("Failed");
+    assert_eq!(written, 800);
+    let out = std::fs::read(dst).expect("Cannot read file");
+    assert_eq!(out.len(), 1000);
+    for (i, chunk) in out.chunks(250).enumerate() {
+        assert_eq!(chunk[..200], [1; 200], "chunk {}", i);
+        assert_eq!(chunk[200..], [0; 50], "chunk {}", i);
+    }
+}
+
+/// Transforms returning more bytes than they received are rejected.
+#[test]
+fn transform_size_too_large() {
+    let src = "tmp-transform_size_src_test";
+    let dst = "tmp-transform_size_dst_test";
+    let _delete_src_at_exit = create_file(src, &[1_u8; 1000]);
+    let _delete_dst_at_exit = DeleteFile(dst.to_string());
+    match transform_file(
+        src,
+        dst,
+        Arc::new(|b: &mut [u8], _offset| b.len() + 1),
+        PipeConfig::default(),
+    ) {
+        Err(PipeError::Size {
+            expected, actual, ..
+        }) => assert_eq!(expected + 1, actual),
+        r => panic!("Expected size error, got {:?}", r),
+    }
+}
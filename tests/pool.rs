@@ -0,0 +1,93 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::pool::ParIoPool;
+use par_io::read::{read_file_with_options, ReadOptions};
+use par_io::write::WriteOptions;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::thread::ThreadId;
+
+type Threads = Arc<Mutex<HashSet<ThreadId>>>;
+
+/// Threads are reused across reads.
+#[test]
+fn reuse_threads_across_reads() {
+    let filename = "tmp-pool_read_test";
+    let _delete_file_at_exit = create_file(filename, &[1_u8; 1000]);
+    let pool = ParIoPool::new(2, 3).expect("Cannot create pool");
+    let threads: Threads = Arc::new(Mutex::new(HashSet::new()));
+    let consume =
+        |buffer: &[u8], threads: &Threads, _chunk_id: u64, _num_chunks: u64, _offset: u64| {
+            threads.lock().unwrap().insert(std::thread::current().id());
+            buffer.len()
+        };
+    let consume = Arc::new(consume);
+    for _ in 0..20 {
+        let v = pool
+            .read(
+                filename,
+                4,
+                consume.clone(),
+                threads.clone(),
+                2,
+                ReadOptions::default(),
+            )
+            .expect("Error reading file");
+        assert_eq!(v.iter().map(|(_, n)| n).sum::<usize>(), 1000);
+    }
+    assert_eq!(pool.num_threads(), 5);
+    assert!(threads.lock().unwrap().len() <= 3 + 2);
+}
+
+/// Operations requesting more threads than the pool has grow the pool.
+#[test]
+fn grow_pool() {
+    let filename = "tmp-pool_grow_test";
+    let _delete_file_at_exit = create_file(filename, &[1_u8; 1000]);
+    let pool = ParIoPool::new(1, 1).expect("Cannot create pool");
+    let consume =
+        |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| buffer.len();
+    let v = read_file_with_options(
+        filename,
+        3,
+        2,
+        2,
+        Arc::new(consume),
+        (),
+        2,
+        ReadOptions {
+            pool: Some(pool.clone()),
+            ..Default::default()
+        },
+    )
+    .expect("Error reading file");
+    assert_eq!(v.iter().map(|(_, n)| n).sum::<usize>(), 1000);
+    assert_eq!(pool.num_threads(), 5);
+}
+
+#[test]
+fn write_with_pool() {
+    let pool = ParIoPool::new(2, 2).expect("Cannot create pool");
+    let producer = |buffer: &mut Vec<u8>, value: &u8, _offset: u64| -> Result<(), String> {
+        buffer.fill(*value);
+        Ok(())
+    };
+    let producer = Arc::new(producer);
+    for i in 0..10_u8 {
+        let filename = format!("tmp-pool_write_test_{}", i);
+        let _delete_file_at_exit = DeleteFile(filename.clone());
+        let written = pool
+            .write(
+                &filename,
+                3,
+                producer.clone(),
+                i,
+                2,
+                600,
+                WriteOptions::default(),
+            )
+            .expect("Error writing file");
+        assert_eq!(written, 600);
+        assert_eq!(std::fs::read(&filename).unwrap(), vec![i; 600]);
+    }
+}
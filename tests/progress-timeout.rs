@@ -0,0 +1,106 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::read::{read_file_with_options, ReadAt, ReadError, ReadOptions};
+use par_io::write::{write_to_file_with_options, WriteError, WriteOptions};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const STALL: Duration = Duration::from_secs(3);
+const TIMEOUT: Duration = Duration::from_millis(200);
+
+fn write(filename: &str, stalled_offset: Option<u64>) -> Result<usize, WriteError> {
+    let producer = move |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<(), String> {
+        if Some(offset) == stalled_offset {
+            std::thread::sleep(STALL);
+        }
+        buffer.fill(1);
+        Ok(())
+    };
+    write_to_file_with_options(
+        filename,
+        2,
+        2,
+        3,
+        Arc::new(producer),
+        (),
+        2,
+        6000,
+        WriteOptions {
+            progress_timeout: Some(TIMEOUT),
+            ..Default::default()
+        },
+    )
+}
+
+/// In-memory data blocking when reading at the given offset.
+struct Stalled {
+    offset: u64,
+}
+
+impl ReadAt for Stalled {
+    fn read_at(&self, buffer: &mut Vec<u8>, offset: u64) -> Result<(), ReadError> {
+        if offset == self.offset {
+            std::thread::sleep(STALL);
+        }
+        buffer.fill(7);
+        Ok(())
+    }
+}
+
+fn read(filename: &str, source: Option<Arc<dyn ReadAt>>) -> Result<usize, ReadError> {
+    let consumer =
+        |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| buffer.len();
+    read_file_with_options(
+        filename,
+        2,
+        2,
+        3,
+        Arc::new(consumer),
+        (),
+        2,
+        ReadOptions {
+            source,
+            progress_timeout: Some(TIMEOUT),
+            ..Default::default()
+        },
+    )
+    .map(|v| v.iter().map(|(_, n)| n).sum())
+}
+
+#[test]
+fn write_completes_within_timeout() {
+    let filename = "tmp-progress_timeout_write_ok";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    assert_eq!(write(filename, None).expect("Write failed"), 6000);
+}
+
+#[test]
+fn stalled_producer_times_out() {
+    let filename = "tmp-progress_timeout_write_stalled";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let start = Instant::now();
+    match write(filename, Some(4000)) {
+        Err(WriteError::Timeout { offset }) => assert_eq!(offset, Some(4000)),
+        r => panic!("Unexpected result: {:?}", r),
+    }
+    assert!(start.elapsed() < STALL);
+}
+
+#[test]
+fn read_completes_within_timeout() {
+    let filename = "tmp-progress_timeout_read_ok";
+    let _delete_file_at_exit = create_file(filename, &[0; 6000]);
+    assert_eq!(read(filename, None).expect("Read failed"), 6000);
+}
+
+#[test]
+fn stalled_read_times_out() {
+    let filename = "tmp-progress_timeout_read_stalled";
+    let _delete_file_at_exit = create_file(filename, &[0; 6000]);
+    let start = Instant::now();
+    match read(filename, Some(Arc::new(Stalled { offset: 1000 }))) {
+        Err(ReadError::Timeout { offset }) => assert_eq!(offset, Some(1000)),
+        r => panic!("Unexpected result: {:?}", r),
+    }
+    assert!(start.elapsed() < STALL);
+}
@@ -0,0 +1,76 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::progress::Progress;
+use par_io::read::{read_file_with_options, ReadOptions};
+use par_io::write::{write_to_file_with_options, WriteOptions};
+use std::sync::{Arc, Mutex};
+
+type Calls = Arc<Mutex<Vec<(u64, u64)>>>;
+
+/// Return callback recording its arguments.
+fn recorder() -> (Calls, Arc<Progress>) {
+    let calls: Calls = Arc::new(Mutex::new(Vec::new()));
+    let c = calls.clone();
+    let f = move |done: u64, total: u64| c.lock().unwrap().push((done, total));
+    (calls, Arc::new(f))
+}
+
+/// One call per chunk, the largest count equals the total.
+fn check(calls: &Calls, num_chunks: usize, total: u64) {
+    let mut calls = calls.lock().unwrap().clone();
+    assert_eq!(calls.len(), num_chunks);
+    assert!(calls.iter().all(|(_, t)| *t == total));
+    calls.sort();
+    assert_eq!(calls.last().unwrap().0, total);
+}
+
+#[test]
+fn read_progress() {
+    let filename = "tmp-progress_read_test";
+    let _delete_file_at_exit = create_file(filename, &[1_u8; 1000]);
+    let (calls, progress) = recorder();
+    let consume =
+        |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| buffer.len();
+    read_file_with_options(
+        filename,
+        2,
+        3,
+        4,
+        Arc::new(consume),
+        (),
+        2,
+        ReadOptions {
+            progress: Some(progress),
+            ..Default::default()
+        },
+    )
+    .expect("Error reading file");
+    check(&calls, 8, 1000);
+}
+
+#[test]
+fn write_progress() {
+    let filename = "tmp-progress_write_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let (calls, progress) = recorder();
+    let producer = |buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<(), String> {
+        buffer.fill(1);
+        Ok(())
+    };
+    write_to_file_with_options(
+        filename,
+        3,
+        2,
+        2,
+        Arc::new(producer),
+        (),
+        2,
+        900,
+        WriteOptions {
+            progress: Some(progress),
+            ..Default::default()
+        },
+    )
+    .expect("Error writing file");
+    check(&calls, 6, 900);
+}
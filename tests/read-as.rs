@@ -0,0 +1,60 @@
+mod common;
+use common::create_file;
+use par_io::read::{read_file_as, ReadError, ReadOptions};
+use std::sync::Arc;
+
+fn to_bytes(v: &[f32]) -> Vec<u8> {
+    v.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+/// Read the elements of each chunk with the index of the first element.
+fn read_f32(filename: &str, options: ReadOptions) -> Result<Vec<f32>, ReadError> {
+    let consumer = |elements: &[f32], _data: &(), _chunk_id: u64, _num_chunks: u64, first: u64| {
+        (first, elements.to_vec())
+    };
+    let mut chunks = read_file_as(filename, 3, 2, 3, Arc::new(consumer), (), 2, options)?;
+    chunks.sort_by_key(|(_, (first, _))| *first);
+    let mut next = 0;
+    let mut all = Vec::new();
+    for (_, (first, elements)) in chunks {
+        assert_eq!(first, next);
+        next += elements.len() as u64;
+        all.extend(elements);
+    }
+    Ok(all)
+}
+
+#[test]
+fn read_f32_samples() {
+    let filename = "tmp-read_as_f32";
+    // 1001 elements do not split evenly into chunks
+    let samples: Vec<f32> = (0..1001).map(|i| i as f32 * 0.5).collect();
+    let _delete_file_at_exit = create_file(filename, &to_bytes(&samples));
+    assert_eq!(read_f32(filename, ReadOptions::default()).unwrap(), samples);
+}
+
+/// Chunk size rounded up to a whole number of elements, with a header.
+#[test]
+fn read_with_chunk_size_and_header() {
+    let filename = "tmp-read_as_chunk_size";
+    let samples: Vec<f32> = (0..500).map(|i| -(i as f32)).collect();
+    let mut data = vec![0xaa; 3];
+    data.extend(to_bytes(&samples));
+    let _delete_file_at_exit = create_file(filename, &data);
+    let options = ReadOptions {
+        skip_header: 3,
+        chunk_size: Some(130),
+        ..Default::default()
+    };
+    assert_eq!(read_f32(filename, options).unwrap(), samples);
+}
+
+#[test]
+fn size_not_multiple_of_element_size() {
+    let filename = "tmp-read_as_bad_size";
+    let _delete_file_at_exit = create_file(filename, &[0; 4003]);
+    match read_f32(filename, ReadOptions::default()) {
+        Err(ReadError::Other(msg)) => assert!(msg.contains("not a multiple")),
+        r => panic!("Unexpected result: {:?}", r),
+    }
+}
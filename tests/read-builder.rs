@@ -0,0 +1,39 @@
+mod common;
+use common::create_file;
+use par_io::read::{ReadBuilder, ReadOptions};
+use std::sync::Arc;
+
+#[test]
+fn read_with_builder() {
+    let filename = "tmp-read_builder_test";
+    let _delete_file_at_exit = create_file(filename, &[2_u8; 900]);
+    let consume = |buffer: &[u8], scale: &u64, _chunk_id: u64, _num_chunks: u64, _offset: u64| {
+        buffer.iter().map(|b| *b as u64 * scale).sum::<u64>()
+    };
+    let v = ReadBuilder::new(filename)
+        .producers(3)
+        .consumers(2)
+        .chunks_per_producer(3)
+        .buffers_per_producer(3)
+        .client_data(10_u64)
+        .run(Arc::new(consume))
+        .expect("Error reading file");
+    assert_eq!(v.len(), 9);
+    assert_eq!(v.iter().map(|(_, s)| s).sum::<u64>(), 900 * 2 * 10);
+}
+
+#[test]
+fn read_with_defaults() {
+    let filename = "tmp-read_builder_defaults_test";
+    let _delete_file_at_exit = create_file(filename, &[1_u8; 1000]);
+    let consume =
+        |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| buffer.len();
+    let v = ReadBuilder::new(filename)
+        .options(ReadOptions {
+            skip_header: 100,
+            ..Default::default()
+        })
+        .run(Arc::new(consume))
+        .expect("Error reading file");
+    assert_eq!(v.iter().map(|(_, n)| n).sum::<usize>(), 900);
+}
@@ -0,0 +1,62 @@
+mod common;
+use common::create_file;
+use par_io::read::{read_file_chunks, ReadError, ReadOptions};
+use std::sync::Arc;
+
+type Chunks = Vec<(u64, (u64, Vec<u8>))>;
+
+fn read(filename: &str, np: u64, chunks: &[(u64, u64)]) -> Result<Chunks, ReadError> {
+    let consumer = |buffer: &[u8], _data: &(), _chunk_id: u64, num_chunks: u64, offset: u64| {
+        assert_eq!(num_chunks, 5);
+        (offset, buffer.to_vec())
+    };
+    read_file_chunks(
+        filename,
+        np,
+        2,
+        chunks,
+        Arc::new(consumer),
+        (),
+        2,
+        &ReadOptions::default(),
+    )
+}
+
+/// Chunks of different sizes, unsorted and overlapping, are read with the
+/// id of their position in the list.
+#[test]
+fn caller_defined_chunks() {
+    let filename = "tmp-read_chunks_test";
+    let data: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+    let _delete_file_at_exit = create_file(filename, &data);
+    let chunks = [(900, 100), (0, 1), (10, 300), (5, 20), (500, 0)];
+    for np in [1, 2, 3, 8] {
+        let mut read = read(filename, np, &chunks).expect("Read failed");
+        read.sort_by_key(|(id, _)| *id);
+        assert_eq!(read.len(), chunks.len());
+        for (k, (id, (offset, buffer))) in read.into_iter().enumerate() {
+            let (o, len) = chunks[k];
+            assert_eq!(id, k as u64 + 1);
+            assert_eq!(offset, o);
+            assert_eq!(buffer, &data[o as usize..(o + len) as usize]);
+        }
+    }
+}
+
+#[test]
+fn chunk_past_end_of_file() {
+    let filename = "tmp-read_chunks_past_end_test";
+    let _delete_file_at_exit = create_file(filename, &[1; 100]);
+    let chunks = [(0, 10), (10, 10), (90, 11), (0, 1), (1, 1)];
+    match read(filename, 2, &chunks) {
+        Err(ReadError::Other(msg)) => assert!(msg.contains("offset 90")),
+        r => panic!("Expected error, got {:?}", r.map(|r| r.len())),
+    }
+}
+
+#[test]
+fn no_chunks() {
+    let filename = "tmp-read_chunks_empty_test";
+    let _delete_file_at_exit = create_file(filename, &[1; 100]);
+    assert!(read(filename, 2, &[]).expect("Read failed").is_empty());
+}
@@ -0,0 +1,25 @@
+mod common;
+use common::create_file;
+use par_io::read::read_file_discard;
+
+#[test]
+fn discard_whole_file() {
+    let filename = "tmp-read_discard_test";
+    let _delete_file_at_exit = create_file(filename, &[7; 100_003]);
+    for (np, nc, cpp) in [(1, 1, 1), (3, 2, 5), (8, 4, 3)] {
+        assert_eq!(
+            read_file_discard(filename, np, nc, cpp, 2).expect("Read failed"),
+            100_003
+        );
+    }
+}
+
+#[test]
+fn discard_empty_file() {
+    let filename = "tmp-read_discard_empty_test";
+    let _delete_file_at_exit = create_file(filename, &[]);
+    assert_eq!(
+        read_file_discard(filename, 2, 2, 2, 2).expect("Read failed"),
+        0
+    );
+}
@@ -0,0 +1,47 @@
+mod common;
+use common::create_file;
+use par_io::read::{read_file_adaptive, Consumed, ReadError};
+use std::sync::Arc;
+
+/// Consumer extending the chunks which do not end with a newline, then
+/// returning the complete lines found in the chunk.
+#[test]
+fn extend_to_line_end() -> Result<(), String> {
+    // "aaaaaaaaaa\n" is 11 bytes long, 10 lines; chunks of 20 bytes never end
+    // with a newline
+    let data: Vec<u8> = (0..10)
+        .flat_map(|i| {
+            let mut l = vec![b'a' + i as u8; 10];
+            l.push(b'\n');
+            l
+        })
+        .collect();
+    let filename = "tmp-read_extend_test";
+    let _delete_file_at_exit = create_file(filename, &data);
+    let consume = |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| {
+        if buffer.last() != Some(&b'\n') {
+            return Consumed::NeedBytes(buffer.len() as u64 + 1);
+        }
+        Consumed::Done(buffer.len())
+    };
+    let v = read_file_adaptive(filename, 2, 2, 3, Arc::new(consume), (), 2, 20)
+        .map_err(|err| format!("{:?}", err))?;
+    assert_eq!(v.len(), 6);
+    assert!(v.iter().all(|(_, len)| *len > 0));
+    Ok(())
+}
+
+/// Requests exceeding the maximum number of extensions are reported.
+#[test]
+fn extension_limit() {
+    let filename = "tmp-read_extend_limit_test";
+    let _delete_file_at_exit = create_file(filename, &[b'x'; 100]);
+    let consume = |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| {
+        // never satisfied
+        Consumed::<()>::NeedBytes(buffer.len() as u64 + 1)
+    };
+    match read_file_adaptive(filename, 2, 2, 2, Arc::new(consume), (), 2, 3) {
+        Err(ReadError::ExtensionLimit { max_extensions, .. }) => assert_eq!(max_extensions, 3),
+        r => panic!("Expected extension limit error, got {:?}", r),
+    }
+}
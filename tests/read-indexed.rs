@@ -0,0 +1,46 @@
+mod common;
+use common::create_file;
+use par_io::read::{read_file_indexed, ReadBuilder, ReadOptions};
+use std::sync::Arc;
+
+#[test]
+fn results_indexed_by_chunk() {
+    let filename = "tmp-read_indexed_test";
+    let _delete_file_at_exit = create_file(filename, &[1_u8; 1200]);
+    let consume = |_buffer: &[u8], _data: &(), chunk_id: u64, _num_chunks: u64, offset: u64| {
+        (chunk_id, offset)
+    };
+    let v = read_file_indexed(
+        filename,
+        3,
+        2,
+        4,
+        Arc::new(consume),
+        (),
+        2,
+        ReadOptions::default(),
+    )
+    .expect("Error reading file");
+    assert_eq!(v.len(), 12);
+    for (i, r) in v.iter().enumerate() {
+        assert_eq!(*r, Some((i as u64 + 1, i as u64 * 100)));
+    }
+}
+
+#[test]
+fn chunks_not_read_are_none() {
+    let filename = "tmp-read_indexed_small_test";
+    let _delete_file_at_exit = create_file(filename, &[1_u8; 3]);
+    let consume =
+        |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| buffer.len();
+    // 2 producers, 4 chunks each but only 3 bytes: one byte chunks
+    let v = ReadBuilder::new(filename)
+        .producers(2)
+        .consumers(2)
+        .chunks_per_producer(4)
+        .run_indexed(Arc::new(consume))
+        .expect("Error reading file");
+    assert_eq!(v.len(), 8);
+    assert_eq!(v.iter().flatten().sum::<usize>(), 3);
+    assert_eq!(v.iter().filter(|r| r.is_none()).count(), 5);
+}
@@ -0,0 +1,58 @@
+mod common;
+use common::create_file;
+use par_io::read::read_file_range;
+use std::sync::Arc;
+
+type Chunks = Vec<(u64, (u64, Vec<u8>))>;
+
+/// Return the chunks read from `[start, end)`, sorted by offset.
+fn read_range(filename: &str, start: u64, end: u64, num_producers: u64) -> Chunks {
+    let consumer = |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, offset: u64| {
+        (offset, buffer.to_vec())
+    };
+    let mut chunks: Chunks = read_file_range(
+        filename,
+        start,
+        end,
+        num_producers,
+        2,
+        2,
+        Arc::new(consumer),
+        (),
+        2,
+    )
+    .expect("Error reading file");
+    chunks.retain(|(_, (_, data))| !data.is_empty());
+    chunks.sort_by_key(|(_, (offset, _))| *offset);
+    chunks
+}
+
+/// Concatenate chunks checking they are contiguous starting at `start`.
+fn data(chunks: &Chunks, start: u64) -> Vec<u8> {
+    let mut next = start;
+    let mut data = Vec::new();
+    for (_, (offset, d)) in chunks {
+        assert_eq!(*offset, next);
+        next += d.len() as u64;
+        data.extend(d);
+    }
+    data
+}
+
+#[test]
+fn range() {
+    let filename = "tmp-read_range_test";
+    let content: Vec<u8> = (0..1000_u64).map(|i| (i % 251) as u8).collect();
+    let _delete_file_at_exit = create_file(filename, &content);
+    let chunks = read_range(filename, 300, 700, 3);
+    assert_eq!(data(&chunks, 300), content[300..700]);
+    // fewer bytes than producers
+    let chunks = read_range(filename, 500, 502, 8);
+    assert_eq!(data(&chunks, 500), content[500..502]);
+    // end clamped to file size
+    let chunks = read_range(filename, 900, 5000, 3);
+    assert_eq!(data(&chunks, 900), content[900..]);
+    // empty ranges
+    assert!(read_range(filename, 400, 400, 3).is_empty());
+    assert!(read_range(filename, 2000, 3000, 3).is_empty());
+}
@@ -0,0 +1,63 @@
+mod common;
+use common::create_file;
+use par_io::read::{read_file_reduce, ReadOptions};
+use std::sync::Arc;
+
+#[test]
+fn reduce_byte_sum() {
+    let filename = "tmp-read_reduce_test";
+    let bytes: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+    let _delete_file_at_exit = create_file(filename, &bytes);
+    let consume = |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| {
+        (buffer.len(), buffer.iter().map(|b| *b as u64).sum::<u64>())
+    };
+    let (len, sum) = read_file_reduce(
+        filename,
+        3,
+        2,
+        4,
+        Arc::new(consume),
+        (),
+        2,
+        ReadOptions::default(),
+        (0_usize, 0_u64),
+        Arc::new(|(len, sum), (l, s)| (len + l, sum + s)),
+        |(l1, s1), (l2, s2)| (l1 + l2, s1 + s2),
+    )
+    .expect("Error reading file");
+    assert_eq!(len, 1000);
+    assert_eq!(sum, bytes.iter().map(|b| *b as u64).sum::<u64>());
+}
+
+#[test]
+fn reduce_skip_header() {
+    let filename = "tmp-read_reduce_header_test";
+    let _delete_file_at_exit = create_file(filename, &[1_u8; 500]);
+    let consume =
+        |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| buffer.len();
+    let chunks = read_file_reduce(
+        filename,
+        2,
+        3,
+        3,
+        Arc::new(consume),
+        (),
+        2,
+        ReadOptions {
+            skip_header: 100,
+            ..Default::default()
+        },
+        Vec::new(),
+        Arc::new(|mut v: Vec<usize>, n| {
+            v.push(n);
+            v
+        }),
+        |mut a, b| {
+            a.extend(b);
+            a
+        },
+    )
+    .expect("Error reading file");
+    assert_eq!(chunks.len(), 6);
+    assert_eq!(chunks.iter().sum::<usize>(), 400);
+}
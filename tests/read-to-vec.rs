@@ -0,0 +1,25 @@
+mod common;
+use common::create_file;
+use par_io::read::read_to_vec;
+
+#[test]
+fn read_whole_file() {
+    let filename = "tmp-read_to_vec_test";
+    let data: Vec<u8> = (0..100_003_u32).map(|i| (i % 251) as u8).collect();
+    let _delete_file_at_exit = create_file(filename, &data);
+    for (np, nc, cpp) in [(1, 1, 1), (3, 2, 5), (8, 4, 3)] {
+        assert_eq!(
+            read_to_vec(filename, np, nc, cpp, 2).expect("Read failed"),
+            data
+        );
+    }
+}
+
+#[test]
+fn read_empty_file() {
+    let filename = "tmp-read_to_vec_empty_test";
+    let _delete_file_at_exit = create_file(filename, &[]);
+    assert!(read_to_vec(filename, 2, 2, 2, 2)
+        .expect("Read failed")
+        .is_empty());
+}
@@ -0,0 +1,63 @@
+mod common;
+use common::create_file;
+use par_io::read::{read_file_try, ReadError, ReadOptions};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+#[test]
+fn consumers_succeed() {
+    let filename = "tmp-read_try_ok_test";
+    let _delete_file_at_exit = create_file(filename, &[3; 10_000]);
+    let consumer = |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| {
+        Ok::<_, String>(buffer.iter().map(|b| *b as usize).sum::<usize>())
+    };
+    let read = read_file_try(
+        filename,
+        3,
+        2,
+        4,
+        Arc::new(consumer),
+        (),
+        2,
+        ReadOptions::default(),
+    )
+    .expect("Read failed");
+    assert_eq!(read.len(), 12);
+    assert_eq!(read.iter().map(|(_, n)| n).sum::<usize>(), 30_000);
+}
+
+/// The first error stops the read and is returned with the offset of the
+/// chunk.
+#[test]
+fn first_error_stops_read() {
+    let filename = "tmp-read_try_error_test";
+    let _delete_file_at_exit = create_file(filename, &[0; 10_000]);
+    let calls = Arc::new(AtomicU64::new(0));
+    let consumer = |buffer: &[u8], calls: &Arc<AtomicU64>, _id: u64, _n: u64, offset: u64| {
+        calls.fetch_add(1, Ordering::SeqCst);
+        if offset == 0 {
+            Err(format!("Invalid chunk of {} bytes", buffer.len()))
+        } else {
+            Ok(())
+        }
+    };
+    // a single producer with one buffer reads the next chunk only after the
+    // previous one is consumed
+    match read_file_try(
+        filename,
+        1,
+        1,
+        100,
+        Arc::new(consumer),
+        calls.clone(),
+        1,
+        ReadOptions::default(),
+    ) {
+        Err(ReadError::Consumer { offset, error }) => {
+            assert_eq!(offset, 0);
+            assert!(error.contains("Invalid chunk of 100 bytes"), "{}", error);
+        }
+        r => panic!("Expected consumer error, got {:?}", r),
+    }
+    assert!(calls.load(Ordering::SeqCst) < 100);
+}
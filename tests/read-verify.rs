@@ -0,0 +1,91 @@
+mod common;
+use common::create_file;
+use par_io::read::{read_file_with_options, ReadAt, ReadError, ReadOptions};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// In-memory data source corrupting reads which include `bad_offset`:
+/// every other read when `transient` is `false`, only the first read otherwise.
+struct Flaky {
+    data: Vec<u8>,
+    bad_offset: u64,
+    transient: bool,
+    bad_reads: AtomicU64,
+    bad_chunk_offset: Mutex<Option<u64>>,
+}
+
+impl ReadAt for Flaky {
+    fn read_at(&self, buffer: &mut Vec<u8>, offset: u64) -> Result<(), ReadError> {
+        let start = offset as usize;
+        let end = start + buffer.len();
+        buffer.copy_from_slice(&self.data[start..end]);
+        if (offset..offset + buffer.len() as u64).contains(&self.bad_offset) {
+            *self.bad_chunk_offset.lock().unwrap() = Some(offset);
+            let n = self.bad_reads.fetch_add(1, Ordering::SeqCst);
+            if (self.transient && n == 0) || (!self.transient && n % 2 == 1) {
+                buffer[(self.bad_offset - offset) as usize] ^= 0xFF;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_flaky(filename: &str, source: Arc<Flaky>) -> Result<Vec<(u64, usize)>, ReadError> {
+    let consume =
+        |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| buffer.len();
+    read_file_with_options(
+        filename,
+        3,
+        2,
+        4,
+        Arc::new(consume),
+        (),
+        2,
+        ReadOptions {
+            double_read_verify: true,
+            source: Some(source),
+            ..Default::default()
+        },
+    )
+}
+
+/// Data changing across reads is reported as unstable.
+#[test]
+fn unstable_read_reported() {
+    let data: Vec<u8> = (0..5000_u32).map(|i| (i % 256) as u8).collect();
+    let filename = "tmp-read_verify_unstable_test";
+    let _delete_file_at_exit = create_file(filename, &data);
+    let source = Arc::new(Flaky {
+        data,
+        bad_offset: 2345,
+        transient: false,
+        bad_reads: AtomicU64::new(0),
+        bad_chunk_offset: Mutex::new(None),
+    });
+    match read_flaky(filename, source.clone()) {
+        Err(ReadError::Unstable { offset, attempts }) => {
+            assert_eq!(Some(offset), *source.bad_chunk_offset.lock().unwrap());
+            assert_eq!(attempts, ReadOptions::default().max_verify_retries + 1);
+        }
+        r => panic!("Expected ReadError::Unstable, got {:?}", r),
+    }
+}
+
+/// A transient mismatch is recovered by re-reading.
+#[test]
+fn transient_mismatch_retried() -> Result<(), String> {
+    let data: Vec<u8> = (0..5000_u32).map(|i| (i % 256) as u8).collect();
+    let filename = "tmp-read_verify_transient_test";
+    let _delete_file_at_exit = create_file(filename, &data);
+    let source = Arc::new(Flaky {
+        data,
+        bad_offset: 17,
+        transient: true,
+        bad_reads: AtomicU64::new(0),
+        bad_chunk_offset: Mutex::new(None),
+    });
+    let v = read_flaky(filename, source.clone()).map_err(|err| format!("{:?}", err))?;
+    assert_eq!(v.iter().map(|(_, n)| n).sum::<usize>(), 5000);
+    assert_eq!(source.bad_reads.load(Ordering::SeqCst), 4);
+    Ok(())
+}
@@ -1,4 +1,3 @@
-use par_io;
 use std::fs::File;
 use std::io::Cursor;
 use std::io::Read;
@@ -7,10 +6,8 @@ use std::io::SeekFrom;
 use std::io::Write;
 
 /// Convert reference to `Vec<T>` to `&[u8]`
-fn to_u8_slice<T: Sized>(v: &Vec<T>) -> &[u8] {
-    unsafe {
-        std::slice::from_raw_parts(v.as_ptr() as *const u8, v.len() * std::mem::size_of::<T>())
-    }
+fn to_u8_slice<T: Sized>(v: &[T]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(v.as_ptr() as *const u8, std::mem::size_of_val(v)) }
 }
 
 /*fn from_u8_slice<T: Sized>(v: &[u8]) -> &[T] {
@@ -49,6 +46,7 @@ fn read() -> Result<(), String> {
     let filename = "tmp-read_test";
     let mut file = File::options()
         .create(true)
+        .truncate(true)
         .write(true)
         .open(filename)
         .map_err(|err| err.to_string())?;
@@ -121,7 +119,7 @@ fn write() -> Result<(), String> {
     let chunks_per_producer = 3;
     let num_buffers_per_producer = 2;
     let bytes_consumed = par_io::write::write_to_file(
-        &filename,
+        filename,
         num_producers,
         num_consumers,
         chunks_per_producer,
@@ -132,14 +130,78 @@ fn write() -> Result<(), String> {
     )
     .map_err(|err| format!("{:?}", err))?;
     //4 verify result
-    let len = std::fs::metadata(&filename)
+    let len = std::fs::metadata(filename)
         .map_err(|err| err.to_string())?
         .len();
     assert_eq!(bytes_consumed, len as usize);
-    let mut file = File::open(&filename).map_err(|err| err.to_string())?;
+    let mut file = File::open(filename).map_err(|err| err.to_string())?;
     let mut buffer: Vec<u8> = vec![0_u8; len as usize];
     file.read_exact(&mut buffer)
         .map_err(|err| err.to_string())?;
     assert_eq!(buffer, *data);
     Ok(())
 }
+
+/// Verify that the vector returned by `read_file` is allocated once with
+/// the exact number of chunks.
+#[test]
+fn read_result_preallocated() -> Result<(), String> {
+    let buf: Vec<u32> = (0_u32..1111).collect();
+    let bytes = to_u8_slice(&buf);
+    let filename = "tmp-read_capacity_test";
+    let mut file = File::create(filename).map_err(|err| err.to_string())?;
+    file.write_all(bytes).map_err(|err| err.to_string())?;
+    drop(file);
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let consume =
+        |buffer: &[u8], _data: &Dummy, _chunk_id: u64, _num_chunks: u64, _offset: u64| buffer.len();
+    let (num_producers, chunks_per_producer) = (5, 3);
+    let v = par_io::read::read_file(
+        filename,
+        num_producers,
+        2,
+        chunks_per_producer,
+        std::sync::Arc::new(consume),
+        Dummy {},
+        2,
+    )
+    .map_err(|err| format!("{:?}", err))?;
+    let n = par_io::read::chunk_count(bytes.len() as u64, num_producers, chunks_per_producer);
+    assert_eq!(n, 15);
+    assert_eq!(v.len(), n);
+    // two consumers receiving 8 and 7 chunks would grow the vector to 16
+    // elements without preallocation
+    assert_eq!(v.capacity(), n);
+    Ok(())
+}
+
+/// Write data to an anonymous temporary file and read it back through the
+/// returned handle.
+#[test]
+fn write_tempfile() -> Result<(), String> {
+    let data: Vec<u8> = (0..5000_u32).map(|i| (i % 253) as u8).collect();
+    let src = std::sync::Arc::new(data);
+    let producer =
+        |buffer: &mut Vec<u8>, src: &std::sync::Arc<Vec<u8>>, offset: u64| -> Result<(), String> {
+            let start = offset as usize;
+            let end = start + buffer.len();
+            buffer.copy_from_slice(&src[start..end]);
+            Ok(())
+        };
+    let mut file = par_io::write::write_to_tempfile(
+        ".",
+        3,
+        2,
+        2,
+        std::sync::Arc::new(producer),
+        src.clone(),
+        2,
+        src.len(),
+    )
+    .map_err(|err| format!("{:?}", err))?;
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer)
+        .map_err(|err| err.to_string())?;
+    assert_eq!(buffer, *src);
+    Ok(())
+}
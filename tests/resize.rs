@@ -0,0 +1,31 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::write::{resize_file, WriteError};
+
+#[test]
+fn extend_file() {
+    let filename = "tmp-resize_extend_test";
+    let _delete_file_at_exit = create_file(filename, &[7_u8; 100]);
+    resize_file(filename, 300).expect("Error resizing file");
+    let data = std::fs::read(filename).unwrap();
+    assert_eq!(data.len(), 300);
+    assert!(data[..100].iter().all(|b| *b == 7));
+    assert!(data[100..].iter().all(|b| *b == 0));
+}
+
+#[test]
+fn truncate_file() {
+    let filename = "tmp-resize_truncate_test";
+    let _delete_file_at_exit = create_file(filename, &[7_u8; 100]);
+    resize_file(filename, 40).expect("Error resizing file");
+    assert_eq!(std::fs::read(filename).unwrap(), vec![7_u8; 40]);
+}
+
+#[test]
+fn missing_file() {
+    let filename = "tmp-resize_missing_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    assert!(matches!(resize_file(filename, 10), Err(WriteError::IO(_))));
+    // the file is not created
+    assert!(std::fs::metadata(filename).is_err());
+}
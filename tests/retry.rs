@@ -0,0 +1,112 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::read::{read_file_with_options, ReadAt, ReadError, ReadOptions};
+use par_io::retry::RetryPolicy;
+use par_io::write::{write_to_file_with_options, WriteOptions};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// In-memory data source failing the first `failures` reads at each offset.
+struct Failing {
+    data: Vec<u8>,
+    failures: u32,
+    attempts: Mutex<HashMap<u64, u32>>,
+}
+
+impl ReadAt for Failing {
+    fn read_at(&self, buffer: &mut Vec<u8>, offset: u64) -> Result<(), ReadError> {
+        let mut attempts = self.attempts.lock().unwrap();
+        let n = attempts.entry(offset).or_insert(0);
+        *n += 1;
+        if *n <= self.failures {
+            return Err(ReadError::IO(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "transient error",
+            )));
+        }
+        let start = offset as usize;
+        let end = start + buffer.len();
+        buffer.copy_from_slice(&self.data[start..end]);
+        Ok(())
+    }
+}
+
+fn read_failing(filename: &str, failures: u32, max_attempts: u32) -> Result<usize, ReadError> {
+    let data: Vec<u8> = (0..1000_u32).map(|i| (i % 256) as u8).collect();
+    let _delete_file_at_exit = create_file(filename, &data);
+    let source = Arc::new(Failing {
+        data: data.clone(),
+        failures,
+        attempts: Mutex::new(HashMap::new()),
+    });
+    let consume = |buffer: &[u8], data: &Vec<u8>, _chunk_id: u64, _num_chunks: u64, offset: u64| {
+        let start = offset as usize;
+        assert_eq!(buffer, &data[start..start + buffer.len()]);
+        buffer.len()
+    };
+    let v = read_file_with_options(
+        filename,
+        2,
+        2,
+        3,
+        Arc::new(consume),
+        data,
+        2,
+        ReadOptions {
+            source: Some(source),
+            retry: Some(RetryPolicy {
+                max_attempts,
+                backoff: Duration::from_millis(1),
+            }),
+            ..Default::default()
+        },
+    )?;
+    Ok(v.iter().map(|(_, n)| n).sum())
+}
+
+#[test]
+fn transient_read_errors_retried() {
+    let read = read_failing("tmp-retry_read_test", 2, 3).expect("Error reading file");
+    assert_eq!(read, 1000);
+}
+
+#[test]
+fn read_retries_exhausted() {
+    match read_failing("tmp-retry_exhausted_test", 2, 2) {
+        Err(ReadError::RetryExhausted {
+            attempts, error, ..
+        }) => {
+            assert_eq!(attempts, 2);
+            assert!(matches!(*error, ReadError::IO(_)));
+        }
+        r => panic!("Expected exhausted retries, got {:?}", r),
+    }
+}
+
+#[test]
+fn write_with_retry() {
+    let filename = "tmp-retry_write_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<(), String> {
+        buffer.fill(5);
+        Ok(())
+    };
+    let written = write_to_file_with_options(
+        filename,
+        2,
+        2,
+        3,
+        Arc::new(producer),
+        (),
+        2,
+        1000,
+        WriteOptions {
+            retry: Some(RetryPolicy::default()),
+            ..Default::default()
+        },
+    )
+    .expect("Error writing file");
+    assert_eq!(written, 1000);
+    assert_eq!(std::fs::read(filename).unwrap(), vec![5_u8; 1000]);
+}
@@ -0,0 +1,39 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::read::read_file_scoped;
+use par_io::write::write_to_file_scoped;
+
+/// Client data and callbacks borrow from the stack, without `Arc`.
+#[test]
+fn borrowed_client_data() -> Result<(), String> {
+    let filename = "tmp-scoped_write_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let pattern: Vec<u8> = (0..=255).collect();
+    let source: &[u8] = &pattern;
+    let producer = |buffer: &mut Vec<u8>, data: &&[u8], offset: u64| -> Result<(), String> {
+        for (i, b) in buffer.iter_mut().enumerate() {
+            *b = data[(offset as usize + i) % data.len()];
+        }
+        Ok(())
+    };
+    let written = write_to_file_scoped(filename, 3, 2, 4, &producer, source, 2, 5000)
+        .map_err(|err| format!("{:?}", err))?;
+    assert_eq!(written, 5000);
+
+    let expected: Vec<u8> = (0..5000).map(|i| pattern[i % pattern.len()]).collect();
+    let content = std::fs::read(filename).map_err(|err| err.to_string())?;
+    assert_eq!(content, expected);
+
+    let in_file = "tmp-scoped_read_test";
+    let _delete_in_file_at_exit = create_file(in_file, &expected);
+    let reference: &[u8] = &expected;
+    let consumer = |buffer: &[u8], data: &&[u8], _chunk_id: u64, _num_chunks: u64, offset: u64| {
+        let offset = offset as usize;
+        buffer == &data[offset..offset + buffer.len()]
+    };
+    let v = read_file_scoped(in_file, 3, 2, 4, &consumer, reference, 2)
+        .map_err(|err| format!("{:?}", err))?;
+    assert_eq!(v.len(), 12);
+    assert!(v.iter().all(|(_, equal)| *equal));
+    Ok(())
+}
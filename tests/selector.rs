@@ -0,0 +1,135 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::read::{read_file_with_options, ReadOptions};
+use par_io::select::ConsumerSelector;
+use par_io::write::{write_to_file_with_options, WriteOptions};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::thread::ThreadId;
+
+/// Route chunks by offset: chunk `k` of `chunk_size` bytes goes to consumer
+/// `k % num_consumers`; records the offsets seen.
+#[derive(Default)]
+struct ByOffset {
+    chunk_size: u64,
+    offsets: Mutex<Vec<u64>>,
+}
+
+impl ConsumerSelector for ByOffset {
+    fn select(
+        &self,
+        _producer_id: u64,
+        _prev_consumer: usize,
+        num_consumers: usize,
+        _num_producers: usize,
+        offset: u64,
+    ) -> usize {
+        self.offsets.lock().unwrap().push(offset);
+        (offset / self.chunk_size) as usize % num_consumers
+    }
+}
+
+/// Always select the same consumer; out of range indices wrap around.
+struct Fixed(usize);
+
+impl ConsumerSelector for Fixed {
+    fn select(&self, _: u64, _: usize, _: usize, _: usize, _: u64) -> usize {
+        self.0
+    }
+}
+
+type Seen = Arc<Mutex<Vec<(u64, ThreadId)>>>;
+
+/// Return (offset, consumer thread) for each chunk, sorted by offset.
+fn read_threads(filename: &str, selector: Arc<dyn ConsumerSelector>) -> Vec<(u64, ThreadId)> {
+    let seen: Seen = Arc::new(Mutex::new(Vec::new()));
+    let consume = |_buffer: &[u8], seen: &Seen, _chunk_id: u64, _num_chunks: u64, offset: u64| {
+        seen.lock()
+            .unwrap()
+            .push((offset, std::thread::current().id()));
+    };
+    read_file_with_options(
+        filename,
+        2,
+        3,
+        4,
+        Arc::new(consume),
+        seen.clone(),
+        2,
+        ReadOptions {
+            selector: Some(selector),
+            ..Default::default()
+        },
+    )
+    .expect("Error reading file");
+    let mut seen = seen.lock().unwrap().clone();
+    seen.sort_by_key(|(offset, _)| *offset);
+    seen
+}
+
+#[test]
+fn read_single_consumer() {
+    let filename = "tmp-selector_single_test";
+    let _delete_file_at_exit = create_file(filename, &[1_u8; 800]);
+    let seen = read_threads(filename, Arc::new(Fixed(7)));
+    assert_eq!(seen.len(), 8);
+    let threads: HashSet<ThreadId> = seen.iter().map(|(_, t)| *t).collect();
+    assert_eq!(threads.len(), 1);
+}
+
+#[test]
+fn read_by_offset() {
+    let filename = "tmp-selector_offset_test";
+    let _delete_file_at_exit = create_file(filename, &[1_u8; 800]);
+    let selector = Arc::new(ByOffset {
+        chunk_size: 100,
+        ..Default::default()
+    });
+    let seen = read_threads(filename, selector.clone());
+    // chunks k and k + 3 are consumed by the same thread
+    for k in 0..5 {
+        assert_eq!(seen[k].1, seen[k + 3].1);
+        assert_ne!(seen[k].1, seen[k + 1].1);
+    }
+    let mut offsets = selector.offsets.lock().unwrap().clone();
+    offsets.sort();
+    assert_eq!(offsets, (0..8).map(|k| k * 100).collect::<Vec<_>>());
+}
+
+#[test]
+fn write_by_offset() {
+    let filename = "tmp-selector_write_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<(), String> {
+        buffer.fill((offset / 100) as u8);
+        Ok(())
+    };
+    let selector = Arc::new(ByOffset {
+        chunk_size: 100,
+        ..Default::default()
+    });
+    let written = write_to_file_with_options(
+        filename,
+        2,
+        3,
+        4,
+        Arc::new(producer),
+        (),
+        2,
+        800,
+        WriteOptions {
+            selector: Some(selector.clone()),
+            ..Default::default()
+        },
+    )
+    .expect("Error writing file");
+    assert_eq!(written, 800);
+    let mut offsets = selector.offsets.lock().unwrap().clone();
+    offsets.sort();
+    assert_eq!(offsets, (0..8).map(|k| k * 100).collect::<Vec<_>>());
+    let content = std::fs::read(filename).expect("Error reading file");
+    assert_eq!(
+        content,
+        (0..800).map(|i| (i / 100) as u8).collect::<Vec<_>>()
+    );
+}
@@ -0,0 +1,56 @@
+mod common;
+use common::create_file;
+use par_io::read::{read_file_with_options, ReadOptions};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+type Seen = Arc<Mutex<HashSet<usize>>>;
+
+/// Read file recording the address of every buffer passed to consumers and
+/// return the number of distinct buffers and the sum of all bytes.
+fn distinct_buffers(filename: &str, num_producers: u64, options: ReadOptions) -> (usize, u64) {
+    let seen: Seen = Arc::new(Mutex::new(HashSet::new()));
+    let consume = |buffer: &[u8], seen: &Seen, _chunk_id: u64, _num_chunks: u64, _offset: u64| {
+        seen.lock().unwrap().insert(buffer.as_ptr() as usize);
+        buffer.iter().map(|b| *b as u64).sum::<u64>()
+    };
+    let v = read_file_with_options(
+        filename,
+        num_producers,
+        3,
+        4,
+        Arc::new(consume),
+        seen.clone(),
+        2,
+        options,
+    )
+    .expect("Error reading file");
+    let sum = v.iter().map(|(_, s)| s).sum();
+    let n = seen.lock().unwrap().len();
+    (n, sum)
+}
+
+/// The number of buffers in use is bounded by the pool size regardless of
+/// the number of producers.
+#[test]
+fn shared_pool_bounds_buffers() {
+    let filename = "tmp-shared_pool_test";
+    let _delete_file_at_exit = create_file(filename, &[1_u8; 4096]);
+    for num_producers in [1, 2, 8] {
+        let (n, sum) = distinct_buffers(
+            filename,
+            num_producers,
+            ReadOptions {
+                shared_pool: Some(3),
+                ..Default::default()
+            },
+        );
+        assert!(n <= 3);
+        assert_eq!(sum, 4096);
+    }
+    // without a shared pool each producer allocates its own buffers, a
+    // buffer may be recycled before the second one is used
+    let (n, sum) = distinct_buffers(filename, 8, ReadOptions::default());
+    assert!(n > 3 && n <= 16);
+    assert_eq!(sum, 4096);
+}
@@ -0,0 +1,103 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::read::{read_file, read_file_with_options, ReadError, ReadOptions};
+use par_io::write::{write_to_file_with_options, WriteOptions};
+use std::sync::Arc;
+
+type Chunks = Vec<(u64, (u64, Vec<u8>))>;
+
+/// Chunks read with multiple short `pread` calls are reassembled correctly.
+#[test]
+fn short_reads() {
+    let filename = "tmp-short_reads_test";
+    let content: Vec<u8> = (0..10_000_u64).map(|i| (i % 251) as u8).collect();
+    let _delete_file_at_exit = create_file(filename, &content);
+    for max_io_size in [1, 7, 100, 4096] {
+        let consumer =
+            |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, offset: u64| {
+                (offset, buffer.to_vec())
+            };
+        let mut chunks: Chunks = read_file_with_options(
+            filename,
+            3,
+            2,
+            2,
+            Arc::new(consumer),
+            (),
+            2,
+            ReadOptions {
+                max_io_size: Some(max_io_size),
+                ..Default::default()
+            },
+        )
+        .expect("Error reading file");
+        chunks.sort_by_key(|(_, (offset, _))| *offset);
+        let data: Vec<u8> = chunks.into_iter().flat_map(|(_, (_, d))| d).collect();
+        assert_eq!(data, content);
+    }
+}
+
+/// Chunks written with multiple short `pwrite` calls land at the right offsets.
+#[test]
+fn short_writes() {
+    let filename = "tmp-short_writes_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let expected: Vec<u8> = (0..10_000_u64).map(|i| (i % 251) as u8).collect();
+    for max_io_size in [1, 7, 100, 4096] {
+        let producer = |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<(), String> {
+            for (i, b) in buffer.iter_mut().enumerate() {
+                *b = ((offset + i as u64) % 251) as u8;
+            }
+            Ok(())
+        };
+        let written = write_to_file_with_options(
+            filename,
+            3,
+            2,
+            2,
+            Arc::new(producer),
+            (),
+            2,
+            expected.len(),
+            WriteOptions {
+                max_io_size: Some(max_io_size),
+                ..Default::default()
+            },
+        )
+        .expect("Error writing file");
+        assert_eq!(written, expected.len());
+        assert_eq!(
+            std::fs::read(filename).expect("Error reading file"),
+            expected
+        );
+    }
+}
+
+/// A file truncated during the read fails with the offset and size of the
+/// incomplete chunk.
+#[test]
+fn truncated_during_read() {
+    let filename = "tmp-short_io_truncated_test";
+    let _delete_file_at_exit = create_file(filename, &[1_u8; 4000]);
+    let name = filename.to_string();
+    // a single buffer: the second chunk is read after the first one is
+    // consumed
+    let consumer =
+        move |_buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, offset: u64| {
+            if offset == 0 {
+                std::fs::OpenOptions::new()
+                    .write(true)
+                    .open(&name)
+                    .and_then(|f| f.set_len(1500))
+                    .expect("Cannot truncate file");
+            }
+        };
+    match read_file(filename, 1, 1, 4, Arc::new(consumer), (), 1) {
+        Err(ReadError::UnexpectedEof {
+            offset,
+            expected,
+            got,
+        }) => assert_eq!((offset, expected, got), (1000, 1000, 500)),
+        r => panic!("Unexpected result: {:?}", r),
+    }
+}
@@ -0,0 +1,28 @@
+mod common;
+use common::DeleteFile;
+use par_io::write::write_to_file;
+use std::sync::Arc;
+
+/// Producers shortening chunks past the end of their data: the returned size
+/// is the number of bytes generated and the rest of the file is left zeroed.
+#[test]
+fn shortened_chunks() {
+    let filename = "tmp-short_producer_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    // the data of each of the two producers ends 300 bytes into its part
+    let end = |offset: u64| (offset / 500) * 500 + 300;
+    let producer = move |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<(), String> {
+        let available = end(offset).saturating_sub(offset) as usize;
+        buffer.truncate(available);
+        buffer.fill(1);
+        Ok(())
+    };
+    let written =
+        write_to_file(filename, 2, 2, 4, Arc::new(producer), (), 2, 1000).expect("Write failed");
+    assert_eq!(written, 600);
+    let data = std::fs::read(filename).expect("Cannot read file");
+    assert_eq!(data.len(), 1000);
+    for (i, b) in data.iter().enumerate() {
+        assert_eq!(*b, u8::from(i % 500 < 300), "offset {}", i);
+    }
+}
@@ -0,0 +1,60 @@
+mod common;
+use common::create_file;
+use par_io::read::{read_file_with_options, ReadError, ReadOptions};
+use std::sync::Arc;
+
+type Chunks = Vec<(u64, (u64, Vec<u8>))>;
+
+fn read_body(filename: &str, skip_header: u64) -> Result<Chunks, ReadError> {
+    let consumer = |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, offset: u64| {
+        (offset, buffer.to_vec())
+    };
+    let mut chunks: Chunks = read_file_with_options(
+        filename,
+        3,
+        2,
+        2,
+        Arc::new(consumer),
+        (),
+        2,
+        ReadOptions {
+            skip_header,
+            ..Default::default()
+        },
+    )?;
+    chunks.sort_by_key(|(_, (offset, _))| *offset);
+    Ok(chunks)
+}
+
+#[test]
+fn skip_header() -> Result<(), String> {
+    let filename = "tmp-skip_header_test";
+    let mut content = vec![0xFF_u8; 64];
+    content.extend((0..1000_u64).map(|i| (i % 251) as u8));
+    let _delete_file_at_exit = create_file(filename, &content);
+    let chunks = read_body(filename, 64).map_err(|err| format!("{:?}", err))?;
+    assert_eq!(chunks.first().map(|(_, (offset, _))| *offset), Some(64));
+    // offsets are file offsets and chunks are contiguous
+    let mut next = 64;
+    for (_, (offset, data)) in &chunks {
+        assert_eq!(*offset, next);
+        assert_eq!(
+            data[..],
+            content[*offset as usize..*offset as usize + data.len()]
+        );
+        next += data.len() as u64;
+    }
+    assert_eq!(next, content.len() as u64);
+    Ok(())
+}
+
+#[test]
+fn header_only() -> Result<(), String> {
+    let filename = "tmp-skip_header_only_test";
+    let _delete_file_at_exit = create_file(filename, &[0xFF_u8; 64]);
+    for skip in [64, 100] {
+        let chunks = read_body(filename, skip).map_err(|err| format!("{:?}", err))?;
+        assert!(chunks.iter().all(|(_, (_, data))| data.is_empty()));
+    }
+    Ok(())
+}
@@ -0,0 +1,128 @@
+mod common;
+use common::DeleteFile;
+use par_io::read::{spawn_read, ReadOptions};
+use par_io::write::{spawn_write, WriteOptions};
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Operations run in the background until explicitly joined.
+#[test]
+fn join_explicitly() -> Result<(), String> {
+    let filename = "tmp-spawn_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    // producers block until the test releases them
+    let (tx, rx) = channel::<()>();
+    let gate = Arc::new(Mutex::new(rx));
+    let producer =
+        |buffer: &mut Vec<u8>, gate: &Arc<Mutex<_>>, _offset: u64| -> Result<(), String> {
+            let rx: &std::sync::mpsc::Receiver<()> = &gate.lock().unwrap();
+            let _ = rx.recv_timeout(Duration::from_secs(10));
+            buffer.fill(3);
+            Ok(())
+        };
+    let handle = spawn_write(
+        filename,
+        2,
+        2,
+        2,
+        Arc::new(producer),
+        gate,
+        2,
+        1000,
+        WriteOptions::default(),
+    )
+    .map_err(|err| format!("{:?}", err))?;
+    assert!(!handle.is_finished());
+    drop(tx);
+    assert_eq!(handle.join().map_err(|err| format!("{:?}", err))?, 1000);
+
+    let consumer = |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| {
+        buffer.iter().map(|b| *b as u64).sum::<u64>()
+    };
+    let handle = spawn_read(
+        filename,
+        2,
+        2,
+        2,
+        Arc::new(consumer),
+        (),
+        2,
+        ReadOptions::default(),
+    )
+    .map_err(|err| format!("{:?}", err))?;
+    let chunks = handle.join().map_err(|err| format!("{:?}", err))?;
+    assert_eq!(chunks.iter().map(|(_, s)| s).sum::<u64>(), 3000);
+    Ok(())
+}
+
+/// Dropping the handle waits for the operation to complete.
+#[test]
+fn join_on_drop() {
+    let filename = "tmp-spawn_drop_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<(), String> {
+        std::thread::sleep(Duration::from_millis(10));
+        buffer.fill(1);
+        Ok(())
+    };
+    let handle = spawn_write(
+        filename,
+        2,
+        2,
+        2,
+        Arc::new(producer),
+        (),
+        2,
+        100,
+        WriteOptions::default(),
+    )
+    .expect("Cannot spawn write");
+    drop(handle);
+    assert_eq!(std::fs::read(filename).unwrap(), vec![1_u8; 100]);
+}
+
+/// The number of bytes written is available before joining, the client
+/// progress callback is still invoked.
+#[test]
+fn bytes_done() {
+    let filename = "tmp-spawn_bytes_done_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let (tx, rx) = channel::<()>();
+    let gate = Arc::new(Mutex::new(rx));
+    let producer =
+        |buffer: &mut Vec<u8>, gate: &Arc<Mutex<_>>, _offset: u64| -> Result<(), String> {
+            let rx: &std::sync::mpsc::Receiver<()> = &gate.lock().unwrap();
+            let _ = rx.recv_timeout(Duration::from_secs(10));
+            buffer.fill(3);
+            Ok(())
+        };
+    let reported = Arc::new(Mutex::new(0));
+    let r = reported.clone();
+    let handle = spawn_write(
+        filename,
+        2,
+        2,
+        2,
+        Arc::new(producer),
+        gate,
+        2,
+        1000,
+        WriteOptions {
+            progress: Some(Arc::new(move |done, _total| {
+                let mut r = r.lock().unwrap();
+                *r = done.max(*r);
+            })),
+            ..Default::default()
+        },
+    )
+    .expect("Cannot spawn write");
+    assert_eq!(handle.bytes_done(), 0);
+    drop(tx);
+    while !handle.is_finished() {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(handle.bytes_done(), 1000);
+    assert_eq!(handle.join().expect("Write failed"), 1000);
+    assert_eq!(*reported.lock().unwrap(), 1000);
+}
@@ -0,0 +1,69 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::read::{read_file_with_options, ReadOptions};
+use par_io::write::{write_to_file_with_options, WriteOptions};
+use std::sync::Arc;
+
+// larger than the default 2 MiB stack
+const LARGE: usize = 8 << 20;
+const STACK_SIZE: usize = 32 << 20;
+
+/// Use a stack buffer bigger than the default thread stack.
+fn large_stack_sum(data: &[u8]) -> u64 {
+    let mut buf = [0_u8; LARGE];
+    buf[..data.len()].copy_from_slice(data);
+    // prevent the buffer from being optimised away
+    unsafe { std::ptr::write_volatile(&mut buf[LARGE - 1], 0) };
+    buf.iter().map(|b| *b as u64).sum()
+}
+
+/// Callbacks allocating large buffers on the stack succeed with an increased
+/// stack size.
+#[test]
+fn large_stack_callbacks() -> Result<(), String> {
+    let filename = "tmp-stack_size_test";
+    let _delete_file_at_exit = create_file(filename, &[1_u8; 1000]);
+    let consume = |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| {
+        large_stack_sum(buffer)
+    };
+    let v = read_file_with_options(
+        filename,
+        2,
+        2,
+        2,
+        Arc::new(consume),
+        (),
+        2,
+        ReadOptions {
+            stack_size: Some(STACK_SIZE),
+            ..Default::default()
+        },
+    )
+    .map_err(|err| format!("{:?}", err))?;
+    assert_eq!(v.iter().map(|(_, s)| s).sum::<u64>(), 1000);
+
+    let out = "tmp-stack_size_write_test";
+    let _delete_out_at_exit = DeleteFile(out.to_string());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<(), String> {
+        buffer.fill(2);
+        assert_eq!(large_stack_sum(buffer), 2 * buffer.len() as u64);
+        Ok(())
+    };
+    let written = write_to_file_with_options(
+        out,
+        2,
+        2,
+        2,
+        Arc::new(producer),
+        (),
+        2,
+        1000,
+        WriteOptions {
+            stack_size: Some(STACK_SIZE),
+            ..Default::default()
+        },
+    )
+    .map_err(|err| format!("{:?}", err))?;
+    assert_eq!(written, 1000);
+    Ok(())
+}
@@ -0,0 +1,59 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::read::{read_file_with_stats, ReadOptions};
+use par_io::write::{write_to_file_with_stats, WriteOptions};
+use std::sync::Arc;
+
+#[test]
+fn write_stats() -> Result<(), String> {
+    let filename = "tmp-write_stats_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<(), String> {
+        buffer.fill(1);
+        Ok(())
+    };
+    let (written, stats) = write_to_file_with_stats(
+        filename,
+        3,
+        2,
+        4,
+        Arc::new(producer),
+        (),
+        2,
+        12_000,
+        WriteOptions::default(),
+    );
+    assert_eq!(written.map_err(|err| format!("{:?}", err))?, 12_000);
+    assert_eq!(stats.bytes, 12_000);
+    assert_eq!(stats.producer_chunks, vec![4, 4, 4]);
+    assert_eq!(stats.consumer_bytes.len(), 2);
+    assert_eq!(stats.consumer_bytes.iter().sum::<u64>(), 12_000);
+    assert!(stats.duration > std::time::Duration::ZERO);
+    assert!(stats.throughput() > 0.0);
+    Ok(())
+}
+
+#[test]
+fn read_stats() -> Result<(), String> {
+    let filename = "tmp-read_stats_test";
+    let _delete_file_at_exit = create_file(filename, &[7; 10_000]);
+    let consumer =
+        |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| buffer.len();
+    let (chunks, stats) = read_file_with_stats(
+        filename,
+        2,
+        3,
+        5,
+        Arc::new(consumer),
+        (),
+        2,
+        ReadOptions::default(),
+    );
+    let chunks = chunks.map_err(|err| format!("{:?}", err))?;
+    assert_eq!(chunks.iter().map(|(_, n)| n).sum::<usize>(), 10_000);
+    assert_eq!(stats.bytes, 10_000);
+    assert_eq!(stats.producer_chunks, vec![5, 5]);
+    assert_eq!(stats.consumer_bytes.len(), 3);
+    assert_eq!(stats.consumer_bytes.iter().sum::<u64>(), 10_000);
+    Ok(())
+}
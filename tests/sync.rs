@@ -0,0 +1,96 @@
+mod common;
+use common::DeleteFile;
+use par_io::write::{
+    write_to_file_until, write_to_file_with_options, Produced, SyncMode, WriteBuilder, WriteOptions,
+};
+use std::sync::Arc;
+
+fn write_synced(filename: &str, sync: SyncMode) {
+    let producer = |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<(), String> {
+        buffer.fill((offset / 1000) as u8);
+        Ok(())
+    };
+    let written = write_to_file_with_options(
+        filename,
+        2,
+        2,
+        2,
+        Arc::new(producer),
+        (),
+        2,
+        4000,
+        WriteOptions {
+            sync,
+            ..Default::default()
+        },
+    )
+    .expect("Write failed");
+    assert_eq!(written, 4000);
+    let data = std::fs::read(filename).expect("Cannot read file");
+    assert_eq!(
+        data,
+        (0..4000).map(|i| (i / 1000) as u8).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn sync_data() {
+    let filename = "tmp-sync_data";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    write_synced(filename, SyncMode::Data);
+}
+
+#[test]
+fn sync_all() {
+    let filename = "tmp-sync_all";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    write_synced(filename, SyncMode::All);
+}
+
+/// The file is flushed after truncation to the size of the data.
+#[test]
+fn sync_until() {
+    let filename = "tmp-sync_until";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<Produced, String> {
+        buffer.fill(5);
+        if offset == 1000 {
+            Ok(Produced::Done(10))
+        } else {
+            Ok(Produced::More)
+        }
+    };
+    let size = write_to_file_until(
+        filename,
+        1,
+        1,
+        4,
+        Arc::new(producer),
+        (),
+        2,
+        4000,
+        WriteOptions {
+            sync: SyncMode::Data,
+            ..Default::default()
+        },
+    )
+    .expect("Write failed");
+    assert_eq!(size, 1010);
+    assert_eq!(std::fs::metadata(filename).expect("No file").len(), 1010);
+}
+
+#[test]
+fn builder_sync() {
+    let filename = "tmp-sync_builder";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<(), String> {
+        buffer.fill(1);
+        Ok(())
+    };
+    let written = WriteBuilder::new(filename)
+        .total_size(1000)
+        .sync(SyncMode::All)
+        .run(Arc::new(producer))
+        .expect("Write failed");
+    assert_eq!(written, 1000);
+}
@@ -0,0 +1,45 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::read::read_file;
+use par_io::write::write_to_file;
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+
+fn current_name() -> String {
+    std::thread::current()
+        .name()
+        .unwrap_or("unnamed")
+        .to_string()
+}
+
+#[test]
+fn consumers_are_named() {
+    let filename = "tmp-thread_names_read";
+    let _delete_file_at_exit = create_file(filename, &[0; 4000]);
+    let consumer =
+        |_buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| current_name();
+    let names: BTreeSet<String> = read_file(filename, 2, 2, 4, Arc::new(consumer), (), 2)
+        .expect("Read failed")
+        .into_iter()
+        .map(|(_, name)| name)
+        .collect();
+    assert!(!names.is_empty());
+    let expected: BTreeSet<String> = (0..2).map(|i| format!("par_io-consumer-{}", i)).collect();
+    assert!(names.is_subset(&expected), "{:?}", names);
+}
+
+#[test]
+fn producers_are_named() {
+    let filename = "tmp-thread_names_write";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let names = Arc::new(Mutex::new(BTreeSet::new()));
+    let n = names.clone();
+    let producer = move |buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<(), String> {
+        n.lock().unwrap().insert(current_name());
+        buffer.fill(0);
+        Ok(())
+    };
+    write_to_file(filename, 3, 2, 2, Arc::new(producer), (), 2, 6000).expect("Write failed");
+    let expected: BTreeSet<String> = (0..3).map(|i| format!("par_io-producer-{}", i)).collect();
+    assert_eq!(*names.lock().unwrap(), expected);
+}
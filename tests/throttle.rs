@@ -0,0 +1,83 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::read::{read_file_with_options, ReadBuilder, ReadOptions};
+use par_io::write::{write_to_file_with_options, WriteOptions};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const SIZE: usize = 40_000;
+const RATE: u64 = 100_000;
+// SIZE bytes at RATE bytes per second, minus the initial burst
+const MIN_TIME: Duration = Duration::from_millis(300);
+
+#[test]
+fn read_is_throttled() {
+    let filename = "tmp-throttle_read";
+    let _delete_file_at_exit = create_file(filename, &[3; SIZE]);
+    let consumer =
+        |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| buffer.len();
+    let start = Instant::now();
+    let chunks = read_file_with_options(
+        filename,
+        4,
+        4,
+        10,
+        Arc::new(consumer),
+        (),
+        2,
+        ReadOptions {
+            max_bytes_per_sec: Some(RATE),
+            ..Default::default()
+        },
+    )
+    .expect("Read failed");
+    assert!(start.elapsed() >= MIN_TIME);
+    assert_eq!(chunks.iter().map(|(_, n)| n).sum::<usize>(), SIZE);
+}
+
+#[test]
+fn write_is_throttled() {
+    let filename = "tmp-throttle_write";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<(), String> {
+        buffer.fill(5);
+        Ok(())
+    };
+    let start = Instant::now();
+    let written = write_to_file_with_options(
+        filename,
+        4,
+        4,
+        10,
+        Arc::new(producer),
+        (),
+        2,
+        SIZE,
+        WriteOptions {
+            max_bytes_per_sec: Some(RATE),
+            ..Default::default()
+        },
+    )
+    .expect("Write failed");
+    assert!(start.elapsed() >= MIN_TIME);
+    assert_eq!(written, SIZE);
+    assert_eq!(
+        std::fs::read(filename).expect("Cannot read file"),
+        vec![5; SIZE]
+    );
+}
+
+/// A high limit does not change the result.
+#[test]
+fn builder_high_limit() {
+    let filename = "tmp-throttle_builder";
+    let _delete_file_at_exit = create_file(filename, &[1; SIZE]);
+    let consumer = |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| {
+        buffer.iter().map(|b| *b as usize).sum::<usize>()
+    };
+    let chunks = ReadBuilder::new(filename)
+        .max_bytes_per_sec(u64::MAX)
+        .run(Arc::new(consumer))
+        .expect("Read failed");
+    assert_eq!(chunks.iter().map(|(_, n)| n).sum::<usize>(), SIZE);
+}
@@ -0,0 +1,140 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::read::{read_file_with_options, ReadAt, ReadError, ReadOptions};
+use par_io::write::{write_to_file_with_options, WriteOptions};
+use std::sync::Arc;
+
+fn data_at(offset: u64) -> u8 {
+    (offset % 251) as u8
+}
+
+fn write(filename: &str, num_consumers: u64, size: usize, options: WriteOptions) -> usize {
+    let producer = |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<(), String> {
+        for (i, b) in buffer.iter_mut().enumerate() {
+            *b = data_at(offset + i as u64);
+        }
+        Ok(())
+    };
+    write_to_file_with_options(
+        filename,
+        4,
+        num_consumers,
+        25,
+        Arc::new(producer),
+        (),
+        4,
+        size,
+        WriteOptions {
+            vectored: true,
+            ..options
+        },
+    )
+    .expect("Write failed")
+}
+
+fn check_data(filename: &str, offset: usize, size: usize) {
+    let data = std::fs::read(filename).expect("Cannot read file");
+    assert_eq!(data.len(), offset + size);
+    assert!(data[offset..]
+        .iter()
+        .enumerate()
+        .all(|(i, b)| *b == data_at(i as u64)));
+}
+
+/// Small chunks written by a single consumer are coalesced.
+#[test]
+fn single_consumer() {
+    let filename = "tmp-vectored_single";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    assert_eq!(write(filename, 1, 10_007, WriteOptions::default()), 10_007);
+    check_data(filename, 0, 10_007);
+}
+
+#[test]
+fn multiple_consumers() {
+    let filename = "tmp-vectored_multiple";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    assert_eq!(write(filename, 3, 10_007, WriteOptions::default()), 10_007);
+    check_data(filename, 0, 10_007);
+}
+
+/// Short writes ending in the middle of a buffer resume at the right
+/// position, with data written after a reserved header.
+#[test]
+fn short_writes_with_data_offset() {
+    let filename = "tmp-vectored_short";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let options = WriteOptions {
+        max_io_size: Some(7),
+        data_offset: 5,
+        ..Default::default()
+    };
+    assert_eq!(write(filename, 1, 10_007, options), 10_007);
+    check_data(filename, 5, 10_007);
+}
+
+/// Read file with vectored reads, check the content of each chunk and return
+/// the number of bytes read.
+fn read(filename: &str, options: ReadOptions) -> u64 {
+    let consume = |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, offset: u64| {
+        assert!(buffer
+            .iter()
+            .enumerate()
+            .all(|(i, b)| *b == data_at(offset + i as u64)));
+        buffer.len() as u64
+    };
+    let v = read_file_with_options(
+        filename,
+        2,
+        1,
+        50,
+        Arc::new(consume),
+        (),
+        8,
+        ReadOptions {
+            vectored: true,
+            ..options
+        },
+    )
+    .expect("Read failed");
+    assert_eq!(v.len(), 100);
+    v.iter().map(|(_, n)| n).sum()
+}
+
+/// Chunks read into the buffers returned to the producer hold the data at
+/// their offset.
+#[test]
+fn vectored_reads() {
+    let filename = "tmp-vectored_read";
+    let data: Vec<u8> = (0..10_007).map(data_at).collect();
+    let _delete_file_at_exit = create_file(filename, &data);
+    assert_eq!(read(filename, ReadOptions::default()), 10_007);
+    let options = ReadOptions {
+        max_io_size: Some(7),
+        ..Default::default()
+    };
+    assert_eq!(read(filename, options), 10_007);
+}
+
+/// Buffers are filled one after the other, reading past the end of the file
+/// fails.
+#[test]
+fn read_buffers_at() {
+    let filename = "tmp-vectored_read_buffers";
+    let data: Vec<u8> = (0..100).map(data_at).collect();
+    let _delete_file_at_exit = create_file(filename, &data);
+    let file = std::fs::File::open(filename).expect("Cannot open file");
+    let (mut a, mut b, mut c) = (vec![0; 3], vec![0; 0], vec![0; 50]);
+    file.read_buffers_at(&mut [&mut a, &mut b, &mut c], 10)
+        .expect("Read failed");
+    assert_eq!(a, data[10..13]);
+    assert_eq!(c, data[13..63]);
+    let mut d = vec![0; 50];
+    match file.read_buffers_at(&mut [&mut a, &mut d], 60) {
+        Err(ReadError::UnexpectedEof { offset, got, .. }) => {
+            assert_eq!(offset, 60);
+            assert_eq!(got, 40);
+        }
+        r => panic!("Unexpected result: {:?}", r),
+    }
+}
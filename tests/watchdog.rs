@@ -0,0 +1,68 @@
+mod common;
+use common::DeleteFile;
+use par_io::watchdog::StallWarning;
+use par_io::write::{write_to_file_with_options, WriteOptions};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// A producer callback blocked until its stall is reported is reported with
+/// the offset it was invoked with.
+#[test]
+fn stalled_producer_reported() -> Result<(), String> {
+    let filename = "tmp-watchdog_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let slow_offset = 1000;
+    let stall_timeout = Duration::from_millis(50);
+    // set by the stall handler, the slow producer waits for it
+    let reported = Arc::new((Mutex::new(false), Condvar::new()));
+    let r = reported.clone();
+    let producer = move |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<(), String> {
+        if offset == slow_offset {
+            let (lock, cvar) = &*r;
+            // bounded wait so that a missing report fails instead of hanging
+            let (done, _) = cvar
+                .wait_timeout_while(lock.lock().unwrap(), Duration::from_secs(30), |done| !*done)
+                .unwrap();
+            if !*done {
+                return Err("stall not reported".to_string());
+            }
+        }
+        buffer.fill(1);
+        Ok(())
+    };
+    let warnings = Arc::new(Mutex::new(Vec::<StallWarning>::new()));
+    let w = warnings.clone();
+    let bytes = write_to_file_with_options(
+        filename,
+        2,
+        2,
+        2,
+        Arc::new(producer),
+        (),
+        2,
+        2000,
+        WriteOptions {
+            on_stall: Some((
+                stall_timeout,
+                Arc::new(move |warning: &StallWarning| {
+                    w.lock().unwrap().push(warning.clone());
+                    let (lock, cvar) = &*reported;
+                    *lock.lock().unwrap() = true;
+                    cvar.notify_all();
+                }),
+            )),
+            ..Default::default()
+        },
+    )
+    .map_err(|err| format!("{:?}", err))?;
+    assert_eq!(bytes, 2000);
+    let warnings = warnings.lock().unwrap();
+    let slow: Vec<&StallWarning> = warnings
+        .iter()
+        .filter(|w| w.offset == slow_offset)
+        .collect();
+    assert_eq!(slow.len(), 1);
+    assert_eq!(slow[0].producer_id, 1);
+    assert!(slow[0].elapsed >= stall_timeout);
+    Ok(())
+}
@@ -0,0 +1,59 @@
+#![cfg(windows)]
+mod common;
+use common::DeleteFile;
+use par_io::read::{read_file_with_options, ReadOptions};
+use par_io::write::{write_to_file_with_options, WriteOptions};
+use std::sync::Arc;
+
+type Chunks = Vec<(u64, (u64, Vec<u8>))>;
+
+/// Buffers written and read back through `seek_write` and `seek_read`,
+/// with multiple calls per chunk.
+#[test]
+fn round_trip() {
+    let filename = "tmp-windows_io_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let expected: Vec<u8> = (0..10_000_u64).map(|i| (i % 251) as u8).collect();
+    let producer = |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<(), String> {
+        for (i, b) in buffer.iter_mut().enumerate() {
+            *b = ((offset + i as u64) % 251) as u8;
+        }
+        Ok(())
+    };
+    let written = write_to_file_with_options(
+        filename,
+        3,
+        2,
+        2,
+        Arc::new(producer),
+        (),
+        2,
+        expected.len(),
+        WriteOptions {
+            max_io_size: Some(100),
+            ..Default::default()
+        },
+    )
+    .expect("Error writing file");
+    assert_eq!(written, expected.len());
+    let consumer = |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, offset: u64| {
+        (offset, buffer.to_vec())
+    };
+    let mut chunks: Chunks = read_file_with_options(
+        filename,
+        3,
+        2,
+        2,
+        Arc::new(consumer),
+        (),
+        2,
+        ReadOptions {
+            max_io_size: Some(100),
+            ..Default::default()
+        },
+    )
+    .expect("Error reading file");
+    chunks.sort_by_key(|(_, (offset, _))| *offset);
+    let data: Vec<u8> = chunks.into_iter().flat_map(|(_, (_, d))| d).collect();
+    assert_eq!(data, expected);
+}
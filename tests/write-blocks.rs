@@ -0,0 +1,43 @@
+mod common;
+use common::DeleteFile;
+use par_io::write::{write_to_file_blocks, WriteOptions};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Generate blocks covering several chunks and verify the file content and
+/// the number of producer invocations.
+#[test]
+fn blocks_sliced_into_chunks() -> Result<(), String> {
+    let filename = "tmp-write_blocks_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let calls = Arc::new(AtomicUsize::new(0));
+    let producer =
+        |buffer: &mut Vec<u8>, calls: &Arc<AtomicUsize>, offset: u64| -> Result<(), String> {
+            calls.fetch_add(1, Ordering::SeqCst);
+            for (i, b) in buffer.iter_mut().enumerate() {
+                *b = ((offset as usize + i) % 251) as u8;
+            }
+            Ok(())
+        };
+    let total_size = 10_001;
+    let written = write_to_file_blocks(
+        filename,
+        3,
+        2,
+        6,
+        4,
+        Arc::new(producer),
+        calls.clone(),
+        2,
+        total_size,
+        WriteOptions::default(),
+    )
+    .map_err(|err| format!("{:?}", err))?;
+    assert_eq!(written, total_size);
+    // 6 chunks per producer, blocks of 4 chunks: 2 blocks per producer
+    assert_eq!(calls.load(Ordering::SeqCst), 6);
+    let data = std::fs::read(filename).map_err(|err| err.to_string())?;
+    let expected: Vec<u8> = (0..total_size).map(|i| (i % 251) as u8).collect();
+    assert_eq!(data, expected);
+    Ok(())
+}
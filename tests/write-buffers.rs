@@ -0,0 +1,52 @@
+mod common;
+use common::DeleteFile;
+use par_io::write::write_to_file;
+use std::sync::{Arc, Mutex};
+
+type Sizes = Arc<Mutex<Vec<(u64, usize)>>>;
+
+/// Producers receive buffers sized to their chunks, including the buffers
+/// sent to producers before the first chunk is generated.
+#[test]
+fn producer_buffers_match_chunks() {
+    let filename = "tmp-write_buffers_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let sizes: Sizes = Arc::new(Mutex::new(Vec::new()));
+    let producer = |buffer: &mut Vec<u8>, sizes: &Sizes, offset: u64| -> Result<(), String> {
+        sizes.lock().unwrap().push((offset, buffer.len()));
+        buffer.fill(1);
+        Ok(())
+    };
+    let written = write_to_file(
+        filename,
+        3,
+        2,
+        4,
+        Arc::new(producer),
+        sizes.clone(),
+        2,
+        1000,
+    )
+    .expect("Error writing file");
+    assert_eq!(written, 1000);
+    let mut sizes = sizes.lock().unwrap().clone();
+    sizes.sort_unstable();
+    // regions of 334, 334 and 332 bytes, each split into 4 chunks
+    let mut expected = Vec::new();
+    for (region, chunks) in [
+        (0, [84, 84, 84, 82]),
+        (334, [84, 84, 84, 82]),
+        (668, [83; 4]),
+    ] {
+        let mut offset = region;
+        for len in chunks {
+            expected.push((offset, len));
+            offset += len as u64;
+        }
+    }
+    assert_eq!(sizes, expected);
+    assert_eq!(
+        std::fs::read(filename).expect("Cannot read file"),
+        vec![1; 1000]
+    );
+}
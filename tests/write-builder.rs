@@ -0,0 +1,62 @@
+mod common;
+use common::DeleteFile;
+use par_io::write::{WriteBuilder, WriteError, WriteOptions};
+use std::sync::Arc;
+
+#[test]
+fn write_with_builder() {
+    let filename = "tmp-write_builder_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let producer = |buffer: &mut Vec<u8>, base: &u8, offset: u64| -> Result<(), String> {
+        buffer.fill(base + (offset / 100) as u8);
+        Ok(())
+    };
+    let written = WriteBuilder::new(filename)
+        .producers(2)
+        .consumers(3)
+        .chunks_per_producer(4)
+        .buffers_per_producer(3)
+        .total_size(800)
+        .client_data(10_u8)
+        .run(Arc::new(producer))
+        .expect("Error writing file");
+    assert_eq!(written, 800);
+    let content = std::fs::read(filename).expect("Error reading file");
+    assert_eq!(
+        content,
+        (0..800).map(|i| 10 + (i / 100) as u8).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn write_with_defaults() {
+    let filename = "tmp-write_builder_defaults_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<(), String> {
+        buffer.fill(3);
+        Ok(())
+    };
+    let written = WriteBuilder::new(filename)
+        .total_size(1000)
+        .options(WriteOptions {
+            verify: true,
+            ..Default::default()
+        })
+        .run(Arc::new(producer))
+        .expect("Error writing file");
+    assert_eq!(written, 1000);
+    assert_eq!(std::fs::read(filename).unwrap(), vec![3_u8; 1000]);
+}
+
+#[test]
+fn missing_total_size() {
+    let filename = "tmp-write_builder_size_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let producer =
+        |_buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<(), String> { Ok(()) };
+    let r = WriteBuilder::new(filename)
+        .producers(2)
+        .run(Arc::new(producer));
+    assert!(matches!(r, Err(WriteError::Other(_))));
+    assert!(std::fs::metadata(filename).is_err());
+}
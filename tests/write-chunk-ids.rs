@@ -0,0 +1,163 @@
+mod common;
+use common::DeleteFile;
+use par_io::write::{
+    write_to_file_per_chunk, write_to_file_with_chunk_ids, WriteBuilder, WriteOptions,
+};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+type Calls = Arc<Mutex<Vec<(u64, u64, u64)>>>;
+
+/// Write `total_size` bytes, recording `(offset, chunk id, number of chunks)`
+/// for each chunk, sorted by offset.
+fn write(
+    filename: &str,
+    num_producers: u64,
+    chunks_per_producer: u64,
+    total_size: usize,
+) -> Vec<(u64, u64, u64)> {
+    let calls: Calls = Arc::new(Mutex::new(Vec::new()));
+    let recorded = calls.clone();
+    let producer = move |buffer: &mut Vec<u8>,
+                         _data: &(),
+                         offset: u64,
+                         chunk_id: u64,
+                         num_chunks: u64|
+          -> Result<(), String> {
+        buffer.fill(chunk_id as u8);
+        recorded
+            .lock()
+            .unwrap()
+            .push((offset, chunk_id, num_chunks));
+        Ok(())
+    };
+    let written = write_to_file_with_chunk_ids(
+        filename,
+        num_producers,
+        2,
+        chunks_per_producer,
+        Arc::new(producer),
+        (),
+        2,
+        total_size,
+        WriteOptions::default(),
+    )
+    .expect("Write failed");
+    assert_eq!(written, total_size);
+    let mut calls = calls.lock().unwrap().clone();
+    calls.sort();
+    calls
+}
+
+/// Chunk ids are consecutive, in file order, starting at 1.
+#[test]
+fn chunk_ids_in_file_order() {
+    let filename = "tmp-write_chunk_ids_order";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let calls = write(filename, 3, 4, 12_000);
+    assert_eq!(calls.len(), 12);
+    for (i, (offset, chunk_id, num_chunks)) in calls.iter().enumerate() {
+        assert_eq!(*offset, i as u64 * 1000);
+        assert_eq!(*chunk_id, i as u64 + 1);
+        assert_eq!(*num_chunks, 12);
+    }
+    let data = std::fs::read(filename).expect("Cannot read file");
+    assert!(data
+        .iter()
+        .enumerate()
+        .all(|(i, b)| *b as usize == i / 1000 + 1));
+}
+
+/// The number of chunks is the number of chunks actually generated when
+/// chunk sizes are rounded up.
+#[test]
+fn rounded_chunk_sizes() {
+    let filename = "tmp-write_chunk_ids_rounded";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    // producer parts of 4 bytes split into chunks of 2 bytes
+    let calls = write(filename, 2, 3, 7);
+    let ids: Vec<u64> = calls.iter().map(|(_, id, _)| *id).collect();
+    let num_chunks = ids.len() as u64;
+    assert_eq!(num_chunks, 5);
+    assert_eq!(ids, (1..=num_chunks).collect::<Vec<_>>());
+    assert!(calls.iter().all(|(_, _, n)| *n == num_chunks));
+}
+
+/// Header in the first chunk and footer in the last one, through the
+/// builder.
+#[test]
+fn builder_header_and_footer() {
+    let filename = "tmp-write_chunk_ids_builder";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let producer = |buffer: &mut Vec<u8>,
+                    _data: &(),
+                    _offset: u64,
+                    chunk_id: u64,
+                    num_chunks: u64|
+     -> Result<(), String> {
+        buffer.fill(0);
+        if chunk_id == 1 {
+            buffer[0] = b'H';
+        }
+        if chunk_id == num_chunks {
+            *buffer.last_mut().unwrap() = b'F';
+        }
+        Ok(())
+    };
+    WriteBuilder::new(filename)
+        .producers(2)
+        .chunks_per_producer(5)
+        .total_size(1000)
+        .run_with_chunk_ids(Arc::new(producer))
+        .expect("Write failed");
+    let data = std::fs::read(filename).expect("Cannot read file");
+    assert_eq!(data.len(), 1000);
+    assert_eq!(data[0], b'H');
+    assert_eq!(data[999], b'F');
+    assert!(data[1..999].iter().all(|b| *b == 0));
+}
+
+/// Each chunk is generated with its own data, indexed by chunk id; the data
+/// is created on the producer thread and need not be `Send`.
+#[test]
+fn per_chunk_data() {
+    let filename = "tmp-write_chunk_ids_per_chunk";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let fill: Vec<u8> = vec![10, 20, 30, 40, 50, 60];
+    let requested = Arc::new(Mutex::new(Vec::new()));
+    let chunk_data = {
+        let requested = requested.clone();
+        move |chunk_id: u64| {
+            requested.lock().unwrap().push(chunk_id);
+            Rc::new(fill[chunk_id as usize - 1])
+        }
+    };
+    let producer = |buffer: &mut Vec<u8>, value: &Rc<u8>, _offset: u64| -> Result<(), String> {
+        buffer.fill(**value);
+        Ok(())
+    };
+    let written = write_to_file_per_chunk(
+        filename,
+        3,
+        2,
+        2,
+        Arc::new(producer),
+        Arc::new(chunk_data),
+        2,
+        600,
+        WriteOptions::default(),
+    )
+    .expect("Write failed");
+    assert_eq!(written, 600);
+    let data = std::fs::read(filename).expect("Cannot read file");
+    for (i, chunk) in data.chunks(100).enumerate() {
+        assert!(
+            chunk.iter().all(|b| *b == 10 * (i as u8 + 1)),
+            "chunk {}",
+            i
+        );
+    }
+    let mut requested = requested.lock().unwrap().clone();
+    requested.sort_unstable();
+    assert_eq!(requested, vec![1, 2, 3, 4, 5, 6]);
+}
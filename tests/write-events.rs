@@ -0,0 +1,57 @@
+mod common;
+use common::DeleteFile;
+use par_io::write::write_to_file_with_events;
+use std::sync::Arc;
+
+// signature imposed by the producer callback type
+#[allow(clippy::ptr_arg)]
+fn producer(buffer: &mut Vec<u8>, _data: &(), offset: u64) -> Result<(), String> {
+    for (i, b) in buffer.iter_mut().enumerate() {
+        *b = ((offset + i as u64) % 251) as u8;
+    }
+    Ok(())
+}
+
+/// Collect write events and verify that exactly one event per chunk is received.
+#[test]
+fn one_event_per_chunk() -> Result<(), String> {
+    let filename = "tmp-write_events_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let total_size = 4444;
+    let (rx, h) =
+        write_to_file_with_events(filename, 4, 2, 3, Arc::new(producer), (), 2, total_size);
+    let mut events: Vec<_> = rx.iter().collect();
+    let bytes = h
+        .join()
+        .map_err(|err| format!("{:?}", err))?
+        .map_err(|err| format!("{:?}", err))?;
+    assert_eq!(bytes, total_size);
+    assert_eq!(events.len(), 12);
+    assert_eq!(
+        events.iter().map(|e| e.cumulative).max(),
+        Some(total_size as u64)
+    );
+    events.sort_by_key(|e| e.offset);
+    events.dedup_by_key(|e| e.chunk_id);
+    assert_eq!(events.len(), 12);
+    assert_eq!(
+        events.iter().map(|e| e.bytes).sum::<u64>(),
+        total_size as u64
+    );
+    Ok(())
+}
+
+/// Dropping the receiver must not prevent the write from completing.
+#[test]
+fn dropped_receiver() -> Result<(), String> {
+    let filename = "tmp-write_events_dropped_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let (rx, h) = write_to_file_with_events(filename, 3, 2, 5, Arc::new(producer), (), 2, 3000);
+    drop(rx);
+    let bytes = h
+        .join()
+        .map_err(|err| format!("{:?}", err))?
+        .map_err(|err| format!("{:?}", err))?;
+    assert_eq!(bytes, 3000);
+    Ok(())
+}
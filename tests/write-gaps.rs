@@ -0,0 +1,84 @@
+mod common;
+use common::create_file;
+use par_io::write::{write_to_file_with_gaps, OpenMode, Region, WriteError, WriteOptions};
+use std::sync::Arc;
+
+/// Write into a pre-populated file leaving the first half of each chunk
+/// untouched and verify the untouched ranges keep their old content.
+#[test]
+fn untouched_ranges_preserved() -> Result<(), String> {
+    let old = vec![0xAA_u8; 6000];
+    let filename = "tmp-write_gaps_test";
+    let _delete_file_at_exit = create_file(filename, &old);
+    let producer =
+        |buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<Vec<Region>, String> {
+            buffer.fill(0x55);
+            let keep = buffer.len() as u64 / 2;
+            Ok(vec![
+                Region::Keep(keep),
+                Region::Write(buffer.len() as u64 - keep),
+            ])
+        };
+    let chunk_size = 500;
+    let written = write_to_file_with_gaps(
+        filename,
+        3,
+        2,
+        4,
+        Arc::new(producer),
+        (),
+        2,
+        old.len(),
+        WriteOptions {
+            open_mode: OpenMode::CreateOrKeep,
+            ..Default::default()
+        },
+    )
+    .map_err(|err| format!("{:?}", err))?;
+    assert_eq!(written, old.len() / 2);
+    let data = std::fs::read(filename).map_err(|err| err.to_string())?;
+    assert_eq!(data.len(), old.len());
+    for (i, chunk) in data.chunks(chunk_size).enumerate() {
+        assert!(
+            chunk[..chunk_size / 2].iter().all(|b| *b == 0xAA),
+            "chunk {}",
+            i
+        );
+        assert!(
+            chunk[chunk_size / 2..].iter().all(|b| *b == 0x55),
+            "chunk {}",
+            i
+        );
+    }
+    Ok(())
+}
+
+/// Regions not covering the whole chunk are reported as producer errors.
+#[test]
+fn regions_must_tile_chunk() {
+    let filename = "tmp-write_gaps_tile_test";
+    let _delete_file_at_exit = create_file(filename, &[]);
+    let producer =
+        |buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<Vec<Region>, String> {
+            Ok(vec![Region::Write(buffer.len() as u64 - 1)])
+        };
+    match write_to_file_with_gaps(
+        filename,
+        2,
+        2,
+        2,
+        Arc::new(producer),
+        (),
+        2,
+        1000,
+        WriteOptions {
+            open_mode: OpenMode::CreateOrKeep,
+            ..Default::default()
+        },
+    ) {
+        Err(WriteError::Producer(err)) => {
+            assert!(err.msg.contains("Regions cover"));
+        }
+        r => panic!("Expected producer error, got {:?}", r),
+    }
+}
@@ -0,0 +1,67 @@
+mod common;
+use common::create_file;
+use par_io::write::{write_to_file_with_gaps, OpenMode, Region, WriteOptions};
+use std::sync::Arc;
+
+const SIZE: usize = 16 << 20;
+const CHUNK_SIZE: u64 = 1 << 20;
+
+/// Write a disk image where only the first 4 KiB of the first chunk hold data.
+fn write_image(filename: &str, open_mode: OpenMode) -> usize {
+    let producer = |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<Vec<Region>, String> {
+        let len = buffer.len() as u64;
+        if offset == 0 {
+            buffer[..4096].fill(0xee);
+            Ok(vec![Region::Write(4096), Region::Hole(len - 4096)])
+        } else {
+            Ok(vec![Region::Hole(len)])
+        }
+    };
+    write_to_file_with_gaps(
+        filename,
+        4,
+        2,
+        SIZE as u64 / CHUNK_SIZE / 4,
+        Arc::new(producer),
+        (),
+        2,
+        SIZE,
+        WriteOptions {
+            open_mode,
+            verify: true,
+            ..Default::default()
+        },
+    )
+    .expect("Write failed")
+}
+
+fn check_data(filename: &str) {
+    let data = std::fs::read(filename).expect("Cannot read file");
+    assert_eq!(data.len(), SIZE);
+    assert!(data[..4096].iter().all(|b| *b == 0xee));
+    assert!(data[4096..].iter().all(|b| *b == 0));
+}
+
+/// Holes are not written, the file stays sparse.
+#[test]
+fn holes_not_allocated() {
+    let filename = "tmp-write_holes_sparse";
+    let _delete_file_at_exit = create_file(filename, &[]);
+    assert_eq!(write_image(filename, OpenMode::Truncate), 4096);
+    check_data(filename);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let allocated = std::fs::metadata(filename).unwrap().blocks() * 512;
+        assert!(allocated < SIZE as u64 / 4, "{} bytes allocated", allocated);
+    }
+}
+
+/// Existing content in holes reads as zero afterwards.
+#[test]
+fn holes_clear_existing_content() {
+    let filename = "tmp-write_holes_existing";
+    let _delete_file_at_exit = create_file(filename, &vec![0x11; SIZE]);
+    assert_eq!(write_image(filename, OpenMode::CreateOrKeep), 4096);
+    check_data(filename);
+}
@@ -0,0 +1,60 @@
+mod common;
+use common::DeleteFile;
+use par_io::write::{write_reader_to_file, write_slice_to_file, WriteError, WriteOptions};
+use std::io::Cursor;
+use std::sync::Arc;
+
+fn data() -> Vec<u8> {
+    (0..50_000_u32).map(|i| (i % 251) as u8).collect()
+}
+
+#[test]
+fn write_slice() {
+    let filename = "tmp-write_slice_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let data = data();
+    let written = write_slice_to_file(filename, 3, 2, 4, &data, 2).expect("Write failed");
+    assert_eq!(written, data.len());
+    assert_eq!(std::fs::read(filename).expect("Cannot read file"), data);
+}
+
+#[test]
+fn write_reader() {
+    let filename = "tmp-write_reader_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let data: Arc<[u8]> = data().into();
+    let source = data.clone();
+    let factory = move |offset: u64, _len: usize| {
+        let mut cursor = Cursor::new(source.clone());
+        cursor.set_position(offset);
+        Ok(cursor)
+    };
+    let written = write_reader_to_file(
+        filename,
+        3,
+        2,
+        4,
+        factory,
+        2,
+        data.len(),
+        WriteOptions::default(),
+    )
+    .expect("Write failed");
+    assert_eq!(written, data.len());
+    assert_eq!(std::fs::read(filename).expect("Cannot read file"), &*data);
+}
+
+/// Readers ending before the end of the chunk fail the write.
+#[test]
+fn short_reader() {
+    let filename = "tmp-write_reader_short_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let factory = |offset: u64, len: usize| {
+        let size = if offset == 0 { len / 2 } else { len };
+        Ok(Cursor::new(vec![1_u8; size]))
+    };
+    match write_reader_to_file(filename, 2, 2, 2, factory, 2, 1000, WriteOptions::default()) {
+        Err(WriteError::Producer(err)) => assert_eq!(err.offset, 0),
+        r => panic!("Expected producer error, got {:?}", r),
+    }
+}
@@ -0,0 +1,106 @@
+mod common;
+use common::DeleteFile;
+use par_io::write::{write_to_file_until, Produced, WriteBuilder, WriteOptions};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+fn data_at(offset: u64) -> u8 {
+    (offset % 251) as u8
+}
+
+/// Write data ending `end` bytes into the file, return the size returned
+/// by the write.
+fn write_until(filename: &str, num_producers: u64, end: u64, calls: &Arc<AtomicU64>) -> usize {
+    let calls = calls.clone();
+    let producer =
+        move |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<Produced, String> {
+            calls.fetch_add(1, Ordering::SeqCst);
+            for (i, b) in buffer.iter_mut().enumerate() {
+                *b = data_at(offset + i as u64);
+            }
+            let len = buffer.len() as u64;
+            if offset <= end && end < offset + len {
+                Ok(Produced::Done((end - offset) as usize))
+            } else {
+                Ok(Produced::More)
+            }
+        };
+    write_to_file_until(
+        filename,
+        num_producers,
+        2,
+        10,
+        Arc::new(producer),
+        (),
+        2,
+        10_000 * num_producers as usize,
+        WriteOptions::default(),
+    )
+    .expect("Write failed")
+}
+
+fn check_data(filename: &str, size: usize) {
+    let data = std::fs::read(filename).expect("Cannot read file");
+    assert_eq!(data.len(), size);
+    assert!(data
+        .iter()
+        .enumerate()
+        .all(|(i, b)| *b == data_at(i as u64)));
+}
+
+/// Chunks after the end of the data are not generated.
+#[test]
+fn single_producer_ends_early() {
+    let filename = "tmp-write_until_single";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let calls = Arc::new(AtomicU64::new(0));
+    assert_eq!(write_until(filename, 1, 3300, &calls), 3300);
+    check_data(filename, 3300);
+    assert_eq!(calls.load(Ordering::SeqCst), 4);
+}
+
+/// Data generated by producers of later parts is discarded.
+#[test]
+fn multiple_producers_end_early() {
+    let filename = "tmp-write_until_multiple";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let calls = Arc::new(AtomicU64::new(0));
+    assert_eq!(write_until(filename, 3, 12_345, &calls), 12_345);
+    check_data(filename, 12_345);
+}
+
+/// Without `Produced::Done` the whole reserved size is written.
+#[test]
+fn no_early_end() {
+    let filename = "tmp-write_until_full";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let calls = Arc::new(AtomicU64::new(0));
+    assert_eq!(write_until(filename, 2, u64::MAX, &calls), 20_000);
+    check_data(filename, 20_000);
+}
+
+/// End of data at a chunk boundary, through the builder.
+#[test]
+fn builder_run_until() {
+    let filename = "tmp-write_until_builder";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<Produced, String> {
+        buffer.fill(9);
+        if offset == 500 {
+            Ok(Produced::Done(0))
+        } else {
+            Ok(Produced::More)
+        }
+    };
+    let size = WriteBuilder::new(filename)
+        .producers(1)
+        .chunks_per_producer(4)
+        .total_size(2000)
+        .run_until(Arc::new(producer))
+        .expect("Write failed");
+    assert_eq!(size, 500);
+    assert_eq!(
+        std::fs::read(filename).expect("Cannot read file"),
+        vec![9; 500]
+    );
+}
@@ -0,0 +1,80 @@
+mod common;
+use common::DeleteFile;
+use par_io::write::{write_to_file_variable, WriteBuilder, WriteError, WriteOptions};
+use std::sync::Arc;
+
+/// Chunk `k` is `k % 7` bytes with value `k`.
+fn chunk(k: u64) -> Vec<u8> {
+    vec![k as u8; (k % 7) as usize]
+}
+
+#[test]
+fn variable_sized_chunks_written_in_order() {
+    let filename = "tmp-write_variable_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), index: u64| -> Result<usize, String> {
+        let c = chunk(index);
+        buffer[..c.len()].copy_from_slice(&c);
+        Ok(c.len())
+    };
+    let written = write_to_file_variable(
+        filename,
+        3,
+        2,
+        10,
+        Arc::new(producer),
+        (),
+        2,
+        8,
+        WriteOptions::default(),
+    )
+    .expect("Error writing file");
+    let expected: Vec<u8> = (0..30).flat_map(chunk).collect();
+    assert_eq!(written, expected.len());
+    assert_eq!(std::fs::read(filename).unwrap(), expected);
+}
+
+#[test]
+fn variable_sized_chunks_with_builder() {
+    let filename = "tmp-write_variable_builder_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), index: u64| -> Result<usize, String> {
+        let c = chunk(index);
+        buffer[..c.len()].copy_from_slice(&c);
+        Ok(c.len())
+    };
+    let written = WriteBuilder::new(filename)
+        .producers(4)
+        .consumers(3)
+        .chunks_per_producer(5)
+        .run_variable(Arc::new(producer), 6)
+        .expect("Error writing file");
+    let expected: Vec<u8> = (0..20).flat_map(chunk).collect();
+    assert_eq!(written, expected.len());
+    assert_eq!(std::fs::read(filename).unwrap(), expected);
+}
+
+#[test]
+fn chunk_larger_than_maximum_size_is_an_error() {
+    let filename = "tmp-write_variable_error_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), index: u64| -> Result<usize, String> {
+        Ok(if index == 5 { buffer.len() + 1 } else { 1 })
+    };
+    let err = write_to_file_variable(
+        filename,
+        2,
+        2,
+        4,
+        Arc::new(producer),
+        (),
+        2,
+        4,
+        WriteOptions::default(),
+    )
+    .unwrap_err();
+    match err {
+        WriteError::Producer(err) => assert_eq!(err.offset, 5),
+        err => panic!("Unexpected error {:?}", err),
+    }
+}
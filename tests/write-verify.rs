@@ -0,0 +1,115 @@
+#![cfg(unix)]
+mod common;
+use common::DeleteFile;
+use par_io::read::{ReadAt, ReadError};
+use par_io::write::{write_to_file_with_report, WriteError, WriteOptions, WriteReport};
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+use std::sync::Arc;
+
+/// Read back file data flipping the first byte read at the given offsets.
+struct Corrupt {
+    file: File,
+    offsets: Vec<u64>,
+}
+
+impl ReadAt for Corrupt {
+    fn read_at(&self, buffer: &mut Vec<u8>, offset: u64) -> Result<(), ReadError> {
+        self.file
+            .read_exact_at(buffer, offset)
+            .map_err(ReadError::IO)?;
+        if self.offsets.contains(&offset) {
+            buffer[0] ^= 0xFF;
+        }
+        Ok(())
+    }
+}
+
+fn write(filename: &str, options: WriteOptions) -> Result<WriteReport, WriteError> {
+    let producer = |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<(), String> {
+        buffer.fill((offset / 100) as u8);
+        Ok(())
+    };
+    // 10 chunks of 100 bytes
+    write_to_file_with_report(filename, 2, 3, 5, Arc::new(producer), (), 2, 1000, options)
+}
+
+/// All the chunks failing verification are reported in best-effort mode.
+#[test]
+fn all_failures_reported() -> Result<(), String> {
+    let filename = "tmp-write_verify_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    // create the file before opening it for verification
+    File::create(filename).map_err(|err| err.to_string())?;
+    let bad = vec![700, 100, 400];
+    let source = Corrupt {
+        file: File::open(filename).map_err(|err| err.to_string())?,
+        offsets: bad.clone(),
+    };
+    let report = write(
+        filename,
+        WriteOptions {
+            verify: true,
+            verify_source: Some(Arc::new(source)),
+            best_effort: true,
+            ..Default::default()
+        },
+    )
+    .map_err(|err| format!("{:?}", err))?;
+    assert!(!report.is_success());
+    assert_eq!(report.bytes_written, 1000);
+    assert_eq!(report.failed_offsets, vec![100, 400, 700]);
+    Ok(())
+}
+
+/// Without best-effort mode the first failure is returned as an error;
+/// reading back from the file itself succeeds.
+#[test]
+fn stop_at_first_failure() -> Result<(), String> {
+    let filename = "tmp-write_verify_strict_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let report = write(
+        filename,
+        WriteOptions {
+            verify: true,
+            ..Default::default()
+        },
+    )
+    .map_err(|err| format!("{:?}", err))?;
+    assert!(report.is_success());
+    File::create(filename).map_err(|err| err.to_string())?;
+    let source = Corrupt {
+        file: File::open(filename).map_err(|err| err.to_string())?,
+        offsets: vec![300],
+    };
+    match write(
+        filename,
+        WriteOptions {
+            verify: true,
+            verify_source: Some(Arc::new(source)),
+            ..Default::default()
+        },
+    ) {
+        Err(WriteError::VerifyFailed { offset }) => assert_eq!(offset, 300),
+        r => panic!("Expected verification failure, got {:?}", r),
+    }
+    Ok(())
+}
+
+/// Verification enabled through the builder.
+#[test]
+fn builder_verify() {
+    let filename = "tmp-write_verify_builder_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<(), String> {
+        buffer.fill(4);
+        Ok(())
+    };
+    let written = par_io::write::WriteBuilder::new(filename)
+        .total_size(1000)
+        .verify(true)
+        .run(Arc::new(producer))
+        .expect("Write failed");
+    assert_eq!(written, 1000);
+    assert_eq!(std::fs::read(filename).unwrap(), vec![4; 1000]);
+}
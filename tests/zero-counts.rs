@@ -0,0 +1,55 @@
+mod common;
+use common::{create_file, DeleteFile};
+use par_io::read::{read_file, ReadError};
+use par_io::write::{write_to_file, WriteError};
+use std::sync::Arc;
+
+// (num_producers, num_consumers, chunks_per_producer, num_buffers_per_producer)
+const ZERO_COUNTS: [(&str, [u64; 4]); 4] = [
+    ("num_producers", [0, 2, 2, 2]),
+    ("num_consumers", [2, 0, 2, 2]),
+    ("chunks_per_producer", [2, 2, 0, 2]),
+    ("num_buffers_per_producer", [2, 2, 2, 0]),
+];
+
+fn read(filename: &str, [np, nc, cpp, nb]: [u64; 4]) -> Result<Vec<(u64, usize)>, ReadError> {
+    let consumer =
+        |buffer: &[u8], _data: &(), _chunk_id: u64, _num_chunks: u64, _offset: u64| buffer.len();
+    read_file(filename, np, nc, cpp, Arc::new(consumer), (), nb)
+}
+
+fn write(filename: &str, [np, nc, cpp, nb]: [u64; 4]) -> Result<usize, WriteError> {
+    let producer = |buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<(), String> {
+        buffer.fill(1);
+        Ok(())
+    };
+    write_to_file(filename, np, nc, cpp, Arc::new(producer), (), nb, 1000)
+}
+
+#[test]
+fn read_zero_counts() {
+    let filename = "tmp-zero_counts_read";
+    let _delete_file_at_exit = create_file(filename, &[1_u8; 1000]);
+    for (name, counts) in ZERO_COUNTS {
+        match read(filename, counts) {
+            Err(ReadError::Other(msg)) => assert_eq!(msg, format!("{} must be >= 1", name)),
+            r => panic!("Expected error for zero {}, got {:?}", name, r),
+        }
+    }
+    assert!(read(filename, [2, 2, 2, 2]).is_ok());
+}
+
+#[test]
+fn write_zero_counts() {
+    let filename = "tmp-zero_counts_write";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    for (name, counts) in ZERO_COUNTS {
+        match write(filename, counts) {
+            Err(WriteError::Other(msg)) => assert_eq!(msg, format!("{} must be >= 1", name)),
+            r => panic!("Expected error for zero {}, got {:?}", name, r),
+        }
+        // the file is not created
+        assert!(std::fs::metadata(filename).is_err());
+    }
+    assert_eq!(write(filename, [2, 2, 2, 2]).expect("Write failed"), 1000);
+}
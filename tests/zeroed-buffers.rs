@@ -0,0 +1,156 @@
+mod common;
+use common::DeleteFile;
+use par_io::write::{
+    write_to_file, write_to_file_blocks, write_to_file_variable, write_to_file_with_options,
+    WriteOptions,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Bytes not generated by the producer are written as zeros, including when
+/// buffers are reused across chunks.
+#[test]
+fn partial_producer_writes_zeros() -> Result<(), String> {
+    let filename = "tmp-zeroed_buffers_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<(), String> {
+        // odd chunks only fill the first byte, even chunks the whole buffer
+        if (offset / 500) % 2 == 0 {
+            buffer.fill(0xFF);
+        } else {
+            buffer[0] = 0xFF;
+        }
+        Ok(())
+    };
+    write_to_file(filename, 2, 2, 6, Arc::new(producer), (), 2, 6000)
+        .map_err(|err| format!("{:?}", err))?;
+    let data = std::fs::read(filename).map_err(|err| err.to_string())?;
+    for (i, chunk) in data.chunks(500).enumerate() {
+        if i % 2 == 0 {
+            assert!(chunk.iter().all(|b| *b == 0xFF), "chunk {}", i);
+        } else {
+            assert_eq!(chunk[0], 0xFF, "chunk {}", i);
+            assert!(chunk[1..].iter().all(|b| *b == 0), "chunk {}", i);
+        }
+    }
+    Ok(())
+}
+
+/// Same for producers returning the chunk size.
+#[test]
+fn partial_variable_producer_writes_zeros() -> Result<(), String> {
+    let filename = "tmp-zeroed_variable_buffers_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let producer = |buffer: &mut Vec<u8>, _data: &(), index: u64| -> Result<usize, String> {
+        if index % 2 == 0 {
+            buffer.fill(0xFF);
+        } else {
+            buffer[0] = 0xFF;
+        }
+        Ok(100)
+    };
+    write_to_file_variable(
+        filename,
+        2,
+        2,
+        4,
+        Arc::new(producer),
+        (),
+        1,
+        200,
+        WriteOptions::default(),
+    )
+    .map_err(|err| format!("{:?}", err))?;
+    let data = std::fs::read(filename).map_err(|err| err.to_string())?;
+    assert_eq!(data.len(), 800);
+    for (i, chunk) in data.chunks(100).enumerate() {
+        if i % 2 == 0 {
+            assert!(chunk.iter().all(|b| *b == 0xFF), "chunk {}", i);
+        } else {
+            assert_eq!(chunk[0], 0xFF, "chunk {}", i);
+            assert!(chunk[1..].iter().all(|b| *b == 0), "chunk {}", i);
+        }
+    }
+    Ok(())
+}
+
+/// Same for producers generating blocks of chunks: the staging block is
+/// zeroed before each block is generated.
+#[test]
+fn partial_block_producer_writes_zeros() -> Result<(), String> {
+    let filename = "tmp-zeroed_block_buffers_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    // blocks of two 500 bytes chunks
+    let producer = |buffer: &mut Vec<u8>, _data: &(), offset: u64| -> Result<(), String> {
+        if (offset / 1000) % 2 == 0 {
+            buffer.fill(0xFF);
+        } else {
+            buffer[0] = 0xFF;
+        }
+        Ok(())
+    };
+    write_to_file_blocks(
+        filename,
+        2,
+        2,
+        6,
+        2,
+        Arc::new(producer),
+        (),
+        2,
+        6000,
+        WriteOptions::default(),
+    )
+    .map_err(|err| format!("{:?}", err))?;
+    let data = std::fs::read(filename).map_err(|err| err.to_string())?;
+    assert_eq!(data.len(), 6000);
+    for (i, block) in data.chunks(1000).enumerate() {
+        if i % 2 == 0 {
+            assert!(block.iter().all(|b| *b == 0xFF), "block {}", i);
+        } else {
+            assert_eq!(block[0], 0xFF, "block {}", i);
+            assert!(block[1..].iter().all(|b| *b == 0), "block {}", i);
+        }
+    }
+    Ok(())
+}
+
+/// The recycle hook sees each written buffer; its changes do not leak into
+/// the next chunk.
+#[test]
+fn recycle_hook() -> Result<(), String> {
+    let filename = "tmp-zeroed_recycle_test";
+    let _delete_file_at_exit = DeleteFile(filename.to_string());
+    let recycled = Arc::new(AtomicU64::new(0));
+    let r = recycled.clone();
+    let producer = |buffer: &mut Vec<u8>, _data: &(), _offset: u64| -> Result<(), String> {
+        buffer[0] = 1;
+        Ok(())
+    };
+    write_to_file_with_options(
+        filename,
+        2,
+        2,
+        3,
+        Arc::new(producer),
+        (),
+        2,
+        6000,
+        WriteOptions {
+            on_recycle: Some(Arc::new(move |buffer: &mut Vec<u8>| {
+                assert_eq!(buffer[0], 1);
+                buffer.fill(0xAA);
+                r.fetch_add(1, Ordering::SeqCst);
+            })),
+            ..Default::default()
+        },
+    )
+    .map_err(|err| format!("{:?}", err))?;
+    assert_eq!(recycled.load(Ordering::SeqCst), 6);
+    let data = std::fs::read(filename).map_err(|err| err.to_string())?;
+    for (i, chunk) in data.chunks(1000).enumerate() {
+        assert_eq!(chunk[0], 1, "chunk {}", i);
+        assert!(chunk[1..].iter().all(|b| *b == 0), "chunk {}", i);
+    }
+    Ok(())
+}